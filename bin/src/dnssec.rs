@@ -23,7 +23,12 @@ use hickory_proto::rr::domain::Name;
 use hickory_proto::serialize::txt::ParseResult;
 #[cfg(feature = "dnssec")]
 use hickory_proto::{
-    dnssec::{decode_key, rdata::DNSKEY, Algorithm, KeyFormat, SigSigner},
+    dnssec::{
+        decode_key,
+        rdata::{tsig::TsigAlgorithm, DNSKEY},
+        tsig::TSigner,
+        Algorithm, KeyFormat, SigSigner,
+    },
     rr::domain::IntoName,
 };
 
@@ -167,6 +172,64 @@ impl KeyConfig {
     }
 }
 
+/// Shared secret for authenticating zone transfers and dynamic updates with TSIG
+#[derive(Deserialize, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TsigKeyConfig {
+    /// name of the key, which must match the name clients use for it
+    pub name: String,
+    /// file path to the shared secret, in raw bytes
+    pub key_path: String,
+    /// the MAC algorithm, e.g. hmac-sha256
+    pub algorithm: String,
+    /// maximum difference in seconds between the time of the client and that of the server
+    pub fudge: Option<u16>,
+}
+
+impl TsigKeyConfig {
+    /// the name of the key
+    pub fn name(&self) -> ParseResult<Name> {
+        Ok(Name::parse(&self.name, Some(&Name::root()))?)
+    }
+
+    /// path to the file with the shared secret
+    pub fn key_path(&self) -> &Path {
+        Path::new(&self.key_path)
+    }
+
+    /// algorithm of the key, see `TsigAlgorithm` for supported algorithms
+    #[cfg(feature = "dnssec")]
+    pub fn algorithm(&self) -> ParseResult<TsigAlgorithm> {
+        match TsigAlgorithm::from_name(Name::from_ascii(&self.algorithm)?) {
+            TsigAlgorithm::Unknown(_) => {
+                Err(format!("unrecognized TSIG algorithm {}", self.algorithm).into())
+            }
+            algorithm => Ok(algorithm),
+        }
+    }
+
+    /// maximum time difference between client and server, defaults to 300 seconds
+    pub fn fudge(&self) -> u16 {
+        self.fudge.unwrap_or(300)
+    }
+
+    /// Tries to read the shared secret into a TSigner
+    #[cfg(feature = "dnssec")]
+    pub fn try_into_signer(&self) -> Result<TSigner, String> {
+        let name = self
+            .name()
+            .map_err(|e| format!("bad TSIG key name: {}: {e}", self.name))?;
+        let algorithm = self
+            .algorithm()
+            .map_err(|e| format!("bad algorithm: {e}"))?;
+        let key = std::fs::read(self.key_path())
+            .map_err(|e| format!("could not read key from: {:?}: {e}", self.key_path()))?;
+
+        TSigner::new(key, algorithm, name, self.fudge())
+            .map_err(|e| format!("failed to load TSIG key: {}: {e}", self.name))
+    }
+}
+
 /// Certificate format of the file being read
 #[derive(Default, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
@@ -36,8 +36,12 @@
 #![recursion_limit = "128"]
 #![allow(clippy::redundant_clone)]
 
+#[cfg(target_family = "unix")]
+use std::collections::HashMap;
+#[cfg(any(target_family = "unix", feature = "prometheus-metrics"))]
+use std::time::Duration;
 use std::{
-    env, fmt,
+    env, fmt, fs,
     io::Error,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     path::{Path, PathBuf},
@@ -45,8 +49,18 @@ use std::{
 };
 
 use clap::Parser;
+#[cfg(target_family = "unix")]
+use futures_util::future;
 use socket2::{Domain, Socket, Type};
 use time::OffsetDateTime;
+#[cfg(target_family = "unix")]
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    signal::unix::{signal, Signal, SignalKind},
+    sync::{mpsc, oneshot},
+    time::timeout,
+};
 use tokio::{
     net::{TcpListener, UdpSocket},
     runtime,
@@ -61,8 +75,12 @@ use tracing_subscriber::{
 
 #[cfg(feature = "dns-over-tls")]
 use hickory_dns::dnssec::{self, TlsCertConfig};
-use hickory_dns::{Config, StoreConfig, ZoneConfig};
+use hickory_dns::{Config, CookieConfig, StoreConfig, ZoneConfig};
+#[cfg(target_family = "unix")]
+use hickory_proto::rr::LowerName;
 use hickory_proto::rr::Name;
+#[cfg(target_family = "unix")]
+use hickory_server::server::AccessControlHandle;
 #[cfg(feature = "blocklist")]
 use hickory_server::store::blocklist::BlocklistAuthority;
 #[cfg(feature = "resolver")]
@@ -72,12 +90,18 @@ use hickory_server::store::recursor::RecursiveAuthority;
 #[cfg(feature = "sqlite")]
 use hickory_server::store::sqlite::{SqliteAuthority, SqliteConfig};
 use hickory_server::{
-    authority::{AuthorityObject, Catalog, ZoneType},
+    authority::{AuthorityObject, Catalog, SharedCatalog, ZoneType},
     server::ServerFuture,
-    store::file::{FileAuthority, FileConfig},
+    store::{
+        file::{FileAuthority, FileConfig},
+        secondary::SecondaryAuthority,
+    },
 };
 #[cfg(feature = "dnssec")]
-use {hickory_proto::dnssec::rdata::key::KeyUsage, hickory_server::authority::DnssecAuthority};
+use {
+    hickory_proto::dnssec::{rdata::key::KeyUsage, tsig::TSigner},
+    hickory_server::authority::DnssecAuthority,
+};
 
 #[cfg(feature = "dnssec")]
 async fn load_keys<A, L>(
@@ -151,12 +175,53 @@ async fn load_keys<T>(
     Ok(())
 }
 
+/// Reads the TSIG keys of the configuration
+#[cfg(feature = "dnssec")]
+fn load_tsig_keys(config: &Config) -> Result<Vec<TSigner>, String> {
+    config
+        .tsig_keys()
+        .iter()
+        .map(|key_config| key_config.try_into_signer())
+        .collect()
+}
+
+/// Looks up the TSIG keys which a zone refers to by name
+#[cfg(feature = "dnssec")]
+fn zone_tsig_keys(
+    names: Vec<Name>,
+    tsig_keys: &[TSigner],
+    zone_name: &Name,
+) -> Result<Vec<TSigner>, String> {
+    names
+        .into_iter()
+        .map(|name| {
+            tsig_keys
+                .iter()
+                .find(|key| key.signer_name() == &name)
+                .cloned()
+                .ok_or_else(|| format!("unknown TSIG key {name} for zone {zone_name}"))
+        })
+        .collect()
+}
+
+/// The authorities of a zone, with those a reload hands the zone over to
+#[cfg_attr(not(target_family = "unix"), allow(dead_code))]
+struct LoadedZone {
+    authorities: Vec<Arc<dyn AuthorityObject>>,
+    /// Secondary authorities, which serve the zone once it is transferred
+    secondaries: Vec<Arc<SecondaryAuthority>>,
+    /// Authorities of journaled zones, which take over the journal of the previous authorities
+    #[cfg(feature = "sqlite")]
+    journaled: Vec<Arc<SqliteAuthority>>,
+}
+
 #[cfg_attr(not(feature = "dnssec"), allow(unused_mut, unused))]
 #[warn(clippy::wildcard_enum_match_arm)] // make sure all cases are handled despite of non_exhaustive
 async fn load_zone(
     zone_dir: &Path,
     zone_config: &ZoneConfig,
-) -> Result<Vec<Arc<dyn AuthorityObject>>, String> {
+    #[cfg(feature = "dnssec")] tsig_keys: &[TSigner],
+) -> Result<LoadedZone, String> {
     debug!("loading zone with config: {:#?}", zone_config);
 
     let zone_name: Name = zone_config
@@ -173,6 +238,26 @@ async fn load_zone(
         warn!("allow_update is deprecated in [[zones]] section, it belongs in [[zones.stores]]");
     }
 
+    #[cfg(feature = "dnssec")]
+    let axfr_keys: Vec<Name> = zone_tsig_keys(
+        zone_config
+            .axfr_keys()
+            .map_err(|err| format!("failed to read AXFR key names: {err}"))?,
+        tsig_keys,
+        &zone_name,
+    )?
+    .iter()
+    .map(|key| key.signer_name().clone())
+    .collect();
+    #[cfg(feature = "dnssec")]
+    let update_tsig_keys = zone_tsig_keys(
+        zone_config
+            .update_keys()
+            .map_err(|err| format!("failed to read update key names: {err}"))?,
+        tsig_keys,
+        &zone_name,
+    )?;
+
     // load the zone and insert any configured authorities in the catalog.
     debug!(
         "loading authorities for {zone_name} with stores {:?}",
@@ -180,6 +265,9 @@ async fn load_zone(
     );
 
     let mut authorities: Vec<Arc<dyn AuthorityObject>> = vec![];
+    let mut secondaries = vec![];
+    #[cfg(feature = "sqlite")]
+    let mut journaled = vec![];
     for store in &zone_config.stores {
         let authority: Arc<dyn AuthorityObject> = match store {
             #[cfg(feature = "sqlite")]
@@ -200,9 +288,17 @@ async fn load_zone(
                 )
                 .await?;
 
+                #[cfg(feature = "dnssec")]
+                {
+                    authority.set_axfr_keys(axfr_keys.clone());
+                    authority.set_update_tsig_keys(update_tsig_keys.clone());
+                }
+
                 // load any keys for the Zone, if it is a dynamic update zone, then keys are required
                 load_keys(&mut authority, zone_name_for_signer.clone(), zone_config).await?;
-                Arc::new(authority)
+                let authority = Arc::new(authority);
+                journaled.push(authority.clone());
+                authority
             }
             StoreConfig::File(config) => {
                 if zone_path.is_some() {
@@ -219,6 +315,9 @@ async fn load_zone(
                     zone_config.nx_proof_kind.clone(),
                 )?;
 
+                #[cfg(feature = "dnssec")]
+                authority.set_axfr_keys(axfr_keys.clone());
+
                 // load any keys for the Zone, if it is a dynamic update zone, then keys are required
                 load_keys(&mut authority, zone_name_for_signer.clone(), zone_config).await?;
                 Arc::new(authority)
@@ -241,6 +340,37 @@ async fn load_zone(
                 let authority = recursor.await?;
                 Arc::new(authority)
             }
+            StoreConfig::Secondary(config) => {
+                #[cfg(feature = "dnssec")]
+                let transfer_key = zone_tsig_keys(
+                    config
+                        .transfer_key()
+                        .map_err(|err| format!("failed to read transfer key name: {err}"))?
+                        .into_iter()
+                        .collect(),
+                    tsig_keys,
+                    &zone_name,
+                )?
+                .pop();
+
+                let authority = SecondaryAuthority::try_from_config(
+                    zone_name.clone(),
+                    zone_type,
+                    is_axfr_allowed,
+                    config,
+                    #[cfg(feature = "dnssec")]
+                    zone_config.nx_proof_kind.clone(),
+                    #[cfg(feature = "dnssec")]
+                    axfr_keys.clone(),
+                    #[cfg(feature = "dnssec")]
+                    transfer_key,
+                )
+                .await?;
+
+                let authority = Arc::new(authority);
+                secondaries.push(authority.clone());
+                authority
+            }
             #[cfg(feature = "blocklist")]
             StoreConfig::Blocklist(ref config) => Arc::new(
                 BlocklistAuthority::try_from_config(
@@ -269,6 +399,7 @@ async fn load_zone(
                     zone_file_path,
                     journal_file_path,
                     allow_update: zone_config.is_update_allowed(),
+                    also_notify: Vec::new(),
                 };
 
                 let mut authority = SqliteAuthority::try_from_config(
@@ -283,9 +414,17 @@ async fn load_zone(
                 )
                 .await?;
 
+                #[cfg(feature = "dnssec")]
+                {
+                    authority.set_axfr_keys(axfr_keys.clone());
+                    authority.set_update_tsig_keys(update_tsig_keys.clone());
+                }
+
                 // load any keys for the Zone, if it is a dynamic update zone, then keys are required
                 load_keys(&mut authority, zone_name_for_signer.clone(), zone_config).await?;
-                Arc::new(authority)
+                let authority = Arc::new(authority);
+                journaled.push(authority.clone());
+                authority
             }
             _ => {
                 let config = FileConfig {
@@ -304,6 +443,9 @@ async fn load_zone(
                     zone_config.nx_proof_kind.clone(),
                 )?;
 
+                #[cfg(feature = "dnssec")]
+                authority.set_axfr_keys(axfr_keys.clone());
+
                 // load any keys for the Zone, if it is a dynamic update zone, then keys are required
                 load_keys(&mut authority, zone_name_for_signer.clone(), zone_config).await?;
                 Arc::new(authority)
@@ -314,7 +456,12 @@ async fn load_zone(
     }
 
     info!("zone successfully loaded: {}", zone_config.zone()?);
-    Ok(authorities)
+    Ok(LoadedZone {
+        authorities,
+        secondaries,
+        #[cfg(feature = "sqlite")]
+        journaled,
+    })
 }
 
 /// Cli struct for all options managed with clap derive api.
@@ -369,6 +516,12 @@ struct Cli {
     #[clap(long = "https-port", value_name = "HTTPS-PORT")]
     pub(crate) https_port: Option<u16>,
 
+    /// Listening port for DNS over HTTPS queries over HTTP/1.1,
+    /// overrides any value in config file
+    #[cfg(feature = "dns-over-https-rustls")]
+    #[clap(long = "http1-port", value_name = "HTTP1-PORT")]
+    pub(crate) http1_port: Option<u16>,
+
     /// Listening port for DNS over QUIC queries,
     /// overrides any value in config file
     #[cfg(feature = "dns-over-quic")]
@@ -436,7 +589,9 @@ fn run() -> Result<(), String> {
 
     info!("loading configuration from: {config_path:?}");
 
-    let config = Config::read_config(config_path)
+    let config_toml = fs::read_to_string(config_path)
+        .map_err(|err| format!("failed to read config file from {config_path:?}: {err}"))?;
+    let config = Config::from_toml(&config_toml)
         .map_err(|err| format!("failed to read config file from {config_path:?}: {err}"))?;
     let directory_config = config.directory().to_path_buf();
     let zonedir = args.zonedir.clone();
@@ -454,19 +609,65 @@ fn run() -> Result<(), String> {
         .build()
         .map_err(|err| format!("failed to initialize Tokio runtime: {err}"))?;
 
+    // the recorder is installed before the zones are loaded, to record their serials
+    #[cfg(feature = "prometheus-metrics")]
+    if !args.validate {
+        let listen_addr = config.prometheus_listen_addr();
+        let _guard = runtime.enter();
+        metrics_exporter_prometheus::PrometheusBuilder::new()
+            .with_http_listener(listen_addr)
+            .idle_timeout(
+                metrics_util::MetricKindMask::GAUGE,
+                Some(ZONE_SERIAL_IDLE_TIMEOUT),
+            )
+            .install()
+            .map_err(|err| format!("failed to serve metrics on {listen_addr}: {err}"))?;
+        hickory_server::metrics::describe();
+        info!("serving metrics for Prometheus on http://{listen_addr}/metrics");
+    }
+
     let mut catalog: Catalog = Catalog::new();
+    catalog.set_cookies(
+        config
+            .cookies()
+            .map(CookieConfig::try_into_cookies)
+            .transpose()?,
+    );
+
+    #[cfg(feature = "dnssec")]
+    let tsig_keys = load_tsig_keys(&config)?;
+    #[cfg(feature = "dnssec")]
+    for tsig_key in &tsig_keys {
+        info!("adding TSIG key: {}", tsig_key.signer_name());
+        catalog.add_tsig_key(tsig_key.clone());
+    }
+
     // configure our server based on the config_path
+    #[cfg(all(target_family = "unix", feature = "sqlite"))]
+    let mut journaled = HashMap::new();
     for zone in config.zones() {
         let zone_name = zone
             .zone()
             .map_err(|err| format!("failed to read zone name from {config_path:?}: {err}"))?;
 
-        match runtime.block_on(load_zone(&zone_dir, zone)) {
-            Ok(authority) => catalog.upsert(zone_name.into(), authority),
+        match runtime.block_on(load_zone(
+            &zone_dir,
+            zone,
+            #[cfg(feature = "dnssec")]
+            &tsig_keys,
+        )) {
+            Ok(loaded) => {
+                #[cfg(all(target_family = "unix", feature = "sqlite"))]
+                journaled.insert(LowerName::from(&zone_name), loaded.journaled);
+                catalog.upsert(zone_name.into(), loaded.authorities);
+            }
             Err(err) => return Err(format!("could not load zone {zone_name}: {err}")),
         }
     }
 
+    #[cfg(feature = "prometheus-metrics")]
+    runtime.block_on(catalog.record_zone_serials());
+
     let v4addr = config
         .listen_addrs_ipv4()
         .map_err(|err| format!("failed to parse IPv4 addresses from {config_path:?}: {err}"))?;
@@ -496,11 +697,15 @@ fn run() -> Result<(), String> {
     let tcp_request_timeout = config.tcp_request_timeout();
 
     // now, run the server, based on the config
-    #[cfg_attr(not(feature = "dns-over-tls"), allow(unused_mut))]
-    let mut server = ServerFuture::with_access(catalog, deny_networks, allow_networks);
+    let catalog = SharedCatalog::new(catalog);
+    let mut server = ServerFuture::with_access(catalog.clone(), deny_networks, allow_networks);
+    server.set_response_rate_limit(config.rate_limit());
 
     let _guard = runtime.enter();
 
+    #[cfg(feature = "prometheus-metrics")]
+    tokio::spawn(record_zone_serials(catalog.clone()));
+
     if !args.disable_udp && !config.disable_udp() {
         // load all udp listeners
         for addr in &listen_addrs {
@@ -598,6 +803,57 @@ fn run() -> Result<(), String> {
         info!("TLS related protocols (TLS, HTTPS and QUIC) are disabled")
     }
 
+    #[cfg(feature = "dns-over-https-rustls")]
+    if let Some(http1_listen_port) = args.http1_port.or_else(|| config.http1_listen_port()) {
+        // setup HTTP/1.1 listeners
+        config_http1(
+            &mut server,
+            &config,
+            http1_listen_port,
+            &zone_dir,
+            &listen_addrs,
+        )?;
+    }
+
+    // the control socket is bound, and SIGHUP is handled, before privileges are dropped
+    #[cfg(target_family = "unix")]
+    {
+        let control = config
+            .control_socket()
+            .map(|path| bind_control_socket(path, config.user(), config.group()))
+            .transpose()?;
+        let hangup = signal(SignalKind::hangup())
+            .map_err(|err| format!("failed to register SIGHUP handler: {err}"))?;
+
+        let reloader = Reloader {
+            config_path: config_path.to_path_buf(),
+            zone_dir: zonedir,
+            catalog: catalog.clone(),
+            access: server.access_control(),
+            zones: {
+                let config_toml = parse_toml(config_path, &config_toml)?;
+                config
+                    .zones()
+                    .iter()
+                    .filter_map(|zone| zone.zone().ok())
+                    .map(|zone_name| {
+                        let source = ZoneSource::new(&config_toml, &zone_name, &zone_dir);
+                        (LowerName::from(zone_name), source)
+                    })
+                    .collect()
+            },
+            #[cfg(feature = "sqlite")]
+            journaled,
+            #[cfg(feature = "dnssec")]
+            tsig_keys: tsig_keys.clone(),
+        };
+        tokio::spawn(handle_reloads(reloader, hangup, control));
+    }
+
+    #[cfg(target_family = "unix")]
+    let terminate = signal(SignalKind::terminate())
+        .map_err(|err| format!("failed to register SIGTERM handler: {err}"))?;
+
     // Drop privileges on Unix systems if running as root.
     check_drop_privs(config.user(), config.group())?;
 
@@ -608,7 +864,23 @@ fn run() -> Result<(), String> {
     // Ideally the processing would be n-threads for receiving, which hand off to m-threads for
     //  request handling. It would generally be the case that n <= m.
     info!("server starting up, awaiting connections...");
-    match runtime.block_on(server.block_until_done()) {
+    let result = runtime.block_on(async {
+        let signal = tokio::select! {
+            result = server.block_until_done() => return result,
+            signal = shutdown_signal(
+                #[cfg(target_family = "unix")]
+                terminate,
+            ) => signal,
+        };
+
+        info!("received {signal}, shutting down");
+        server.shutdown_gracefully().await
+    });
+
+    // drops the authorities, so that their caches are written to their cache files once more
+    catalog.update(|catalog| *catalog = Catalog::new());
+
+    match result {
         Ok(()) => {
             // we're exiting for some reason...
             info!("Hickory DNS {} stopping", hickory_client::version());
@@ -628,10 +900,25 @@ fn run() -> Result<(), String> {
     Ok(())
 }
 
+/// Waits for SIGTERM, or SIGINT, returning the name of the signal
+async fn shutdown_signal(#[cfg(target_family = "unix")] mut terminate: Signal) -> &'static str {
+    #[cfg(target_family = "unix")]
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+    }
+
+    #[cfg(not(target_family = "unix"))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "SIGINT"
+    }
+}
+
 #[cfg(feature = "dns-over-tls")]
 fn config_tls(
     args: &Cli,
-    server: &mut ServerFuture<Catalog>,
+    server: &mut ServerFuture<SharedCatalog>,
     config: &Config,
     tls_cert_config: &TlsCertConfig,
     zone_dir: &Path,
@@ -674,7 +961,7 @@ fn config_tls(
 #[cfg(feature = "dns-over-https-rustls")]
 fn config_https(
     args: &Cli,
-    server: &mut ServerFuture<Catalog>,
+    server: &mut ServerFuture<SharedCatalog>,
     config: &Config,
     tls_cert_config: &TlsCertConfig,
     zone_dir: &Path,
@@ -728,10 +1015,68 @@ fn config_https(
     Ok(())
 }
 
+#[cfg(feature = "dns-over-https-rustls")]
+fn config_http1(
+    server: &mut ServerFuture<SharedCatalog>,
+    config: &Config,
+    http1_listen_port: u16,
+    zone_dir: &Path,
+    listen_addrs: &[IpAddr],
+) -> Result<(), String> {
+    let endpoint_path = config.http_endpoint();
+    let tls_cert_config = if config.http1_disable_tls() {
+        warn!("HTTP/1.1 is served without TLS, this should only be exposed to a load balancer");
+        None
+    } else {
+        Some(config.tls_cert().ok_or(
+            "an HTTP/1.1 port was specified without a tls certificate, \
+             set http1_disable_tls to serve plain HTTP",
+        )?)
+    };
+
+    for addr in listen_addrs {
+        let tls_cert = tls_cert_config
+            .map(|tls_cert_config| {
+                let tls_cert_path = tls_cert_config.path();
+                info!("loading cert for DNS over HTTP/1.1 from {tls_cert_path:?}");
+                dnssec::load_cert(zone_dir, tls_cert_config).map_err(|err| {
+                    format!("failed to load tls certificate files from {tls_cert_path:?}: {err}")
+                })
+            })
+            .transpose()?;
+
+        info!("binding HTTP/1.1 to {addr:?}");
+
+        let http1_listener = build_tcp_listener(*addr, http1_listen_port)
+            .map_err(|err| format!("failed to bind to HTTP/1.1 socket address {addr:?}: {err}"))?;
+
+        info!(
+            "listening for HTTP/1.1 on {:?}",
+            http1_listener
+                .local_addr()
+                .map_err(|err| format!("failed to lookup local address: {err}"))?
+        );
+
+        server
+            .register_http1_listener(
+                http1_listener,
+                config.tcp_request_timeout(),
+                tls_cert,
+                tls_cert_config
+                    .and_then(|tls_cert_config| tls_cert_config.endpoint_name())
+                    .map(|s| s.to_string()),
+                endpoint_path.into(),
+            )
+            .map_err(|err| format!("failed to register HTTP/1.1 listener: {err}"))?;
+    }
+
+    Ok(())
+}
+
 #[cfg(feature = "dns-over-quic")]
 fn config_quic(
     args: &Cli,
-    server: &mut ServerFuture<Catalog>,
+    server: &mut ServerFuture<SharedCatalog>,
     config: &Config,
     tls_cert_config: &TlsCertConfig,
     zone_dir: &Path,
@@ -780,6 +1125,467 @@ fn config_quic(
     Ok(())
 }
 
+/// Interval at which the serials of the zones in the catalog are recorded again
+///
+/// The exporter drops the gauges which weren't recorded for [`ZONE_SERIAL_IDLE_TIMEOUT`], so that
+///  the series of the zones removed by a reload don't outlive them.
+#[cfg(feature = "prometheus-metrics")]
+const ZONE_SERIAL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time after which the serial of a zone no longer in the catalog isn't exported anymore
+#[cfg(feature = "prometheus-metrics")]
+const ZONE_SERIAL_IDLE_TIMEOUT: Duration = Duration::from_secs(150);
+
+/// Records the serials of the zones in the catalog every [`ZONE_SERIAL_INTERVAL`], never returns
+#[cfg(feature = "prometheus-metrics")]
+async fn record_zone_serials(catalog: SharedCatalog) {
+    let mut interval = tokio::time::interval(ZONE_SERIAL_INTERVAL);
+    loop {
+        interval.tick().await;
+        catalog.load().record_zone_serials().await;
+    }
+}
+
+/// Time to wait for a command on a connection to the control socket
+#[cfg(target_family = "unix")]
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reloads the configuration and the zones of the running server
+///
+/// The listeners, user, group, DNS Cookies and Response Rate Limiting are only configured at
+///  startup, changes to them require a restart.
+#[cfg(target_family = "unix")]
+struct Reloader {
+    config_path: PathBuf,
+    /// Zone directory from the command line, which overrides the one of the configuration
+    zone_dir: Option<PathBuf>,
+    catalog: SharedCatalog,
+    access: AccessControlHandle,
+    /// Zones in the catalog, with the configuration they were loaded from
+    zones: HashMap<LowerName, ZoneSource>,
+    /// Authorities of the journaled zones in the catalog
+    #[cfg(feature = "sqlite")]
+    journaled: HashMap<LowerName, Vec<Arc<SqliteAuthority>>>,
+    /// TSIG keys of the configuration which was loaded last
+    #[cfg(feature = "dnssec")]
+    tsig_keys: Vec<TSigner>,
+}
+
+#[cfg(target_family = "unix")]
+impl Reloader {
+    /// Reads the configuration again, and replaces the zones, TSIG keys and access control of the
+    ///  server
+    ///
+    /// Zones read from zone files are always loaded again. Other zones keep their authorities if
+    ///  their configuration is unchanged, so that secondary zones are not transferred again and
+    ///  resolvers keep their cache. Zones are served from their previous authorities while they
+    ///  load, changed secondary zones until they are transferred, and all are swapped in the
+    ///  catalog at once; a zone which fails to load or to transfer keeps its previous authorities,
+    ///  if any. The previous authority of a journaled zone stops accepting updates just before the
+    ///  swap, and the updates it journaled while the zone was loading are applied to the new
+    ///  authority. Zones which are no longer configured are removed.
+    async fn reload(&mut self) -> Result<ReloadSummary, String> {
+        info!("reloading configuration from: {:?}", self.config_path);
+
+        let toml = fs::read_to_string(&self.config_path).map_err(|err| {
+            format!(
+                "failed to read config file from {:?}: {err}",
+                self.config_path
+            )
+        })?;
+        let config = Config::from_toml(&toml).map_err(|err| {
+            format!(
+                "failed to read config file from {:?}: {err}",
+                self.config_path
+            )
+        })?;
+        let config_toml = parse_toml(&self.config_path, &toml)?;
+        let zone_dir = self
+            .zone_dir
+            .clone()
+            .unwrap_or_else(|| config.directory().to_path_buf());
+        #[cfg(feature = "dnssec")]
+        let tsig_keys = load_tsig_keys(&config)?;
+
+        let mut zones = HashMap::new();
+        #[cfg(feature = "sqlite")]
+        let mut journaled = HashMap::new();
+        let mut loaded_zones = Vec::new();
+        let mut kept = 0;
+        let mut errors = Vec::new();
+        for zone in config.zones() {
+            let zone_name = match zone.zone() {
+                Ok(zone_name) => zone_name,
+                Err(err) => {
+                    errors.push(format!("failed to read zone name: {err}"));
+                    continue;
+                }
+            };
+
+            let source = ZoneSource::new(&config_toml, &zone_name, &zone_dir);
+            let name = LowerName::from(&zone_name);
+            if !is_read_from_files(zone) && self.zones.get(&name) == Some(&source) {
+                debug!("keeping unchanged zone: {zone_name}");
+                #[cfg(feature = "sqlite")]
+                if let Some(authorities) = self.journaled.remove(&name) {
+                    journaled.insert(name.clone(), authorities);
+                }
+                zones.insert(name, source);
+                kept += 1;
+                continue;
+            }
+
+            match load_zone(
+                &zone_dir,
+                zone,
+                #[cfg(feature = "dnssec")]
+                &tsig_keys,
+            )
+            .await
+            {
+                Ok(loaded) => loaded_zones.push((name, source, loaded)),
+                Err(err) => {
+                    errors.push(format!("could not load zone {zone_name}: {err}"));
+                    self.keep_previous(
+                        name,
+                        &mut zones,
+                        #[cfg(feature = "sqlite")]
+                        &mut journaled,
+                    );
+                }
+            }
+        }
+
+        // changed secondary zones are served by their previous authorities until transferred
+        let transferred = future::join_all(loaded_zones.iter().map(|(name, _, loaded)| async {
+            let serving = loaded
+                .secondaries
+                .iter()
+                .map(|secondary| secondary.wait_for_transfer());
+            !self.zones.contains_key(name)
+                || future::join_all(serving)
+                    .await
+                    .into_iter()
+                    .all(|serving| serving)
+        }))
+        .await;
+
+        let mut authorities = Vec::new();
+        for ((name, source, loaded), transferred) in loaded_zones.into_iter().zip(transferred) {
+            if !transferred {
+                errors.push(format!(
+                    "could not transfer zone {name}, keeping the previous zone"
+                ));
+                self.keep_previous(
+                    name,
+                    &mut zones,
+                    #[cfg(feature = "sqlite")]
+                    &mut journaled,
+                );
+                continue;
+            }
+
+            #[cfg(feature = "sqlite")]
+            {
+                let previous = self.journaled.get(&name).map_or(&[][..], Vec::as_slice);
+                for (authority, previous) in loaded.journaled.iter().zip(previous) {
+                    if let Err(err) = authority.take_over(previous).await {
+                        errors.push(format!("could not apply the journal of zone {name}: {err}"));
+                    }
+                }
+                journaled.insert(name.clone(), loaded.journaled);
+            }
+
+            authorities.push((name.clone(), loaded.authorities));
+            zones.insert(name, source);
+        }
+
+        let loaded = authorities.len();
+        self.catalog.update(|catalog| {
+            for removed in self.zones.keys().filter(|name| !zones.contains_key(*name)) {
+                info!("removing zone: {removed}");
+                catalog.remove(removed);
+            }
+
+            for (zone_name, authority) in authorities {
+                catalog.upsert(zone_name, authority);
+            }
+
+            #[cfg(feature = "dnssec")]
+            {
+                for tsig_key in &self.tsig_keys {
+                    catalog.remove_tsig_key(&LowerName::from(tsig_key.signer_name()));
+                }
+                for tsig_key in &tsig_keys {
+                    catalog.add_tsig_key(tsig_key.clone());
+                }
+            }
+        });
+
+        #[cfg(feature = "prometheus-metrics")]
+        self.catalog.load().record_zone_serials().await;
+
+        self.access
+            .set_networks(config.deny_networks(), config.allow_networks());
+        self.zones = zones;
+        #[cfg(feature = "sqlite")]
+        {
+            self.journaled = journaled;
+        }
+        #[cfg(feature = "dnssec")]
+        {
+            self.tsig_keys = tsig_keys;
+        }
+
+        for error in &errors {
+            error!("{error}");
+        }
+        info!(
+            "reloaded {loaded} zones, kept {kept} unchanged, {} failed",
+            errors.len()
+        );
+
+        Ok(ReloadSummary {
+            loaded,
+            kept,
+            errors,
+        })
+    }
+
+    /// Keeps the previous authorities of a zone which could not be replaced, if there are any
+    fn keep_previous(
+        &mut self,
+        name: LowerName,
+        zones: &mut HashMap<LowerName, ZoneSource>,
+        #[cfg(feature = "sqlite")] journaled: &mut HashMap<LowerName, Vec<Arc<SqliteAuthority>>>,
+    ) {
+        #[cfg(feature = "sqlite")]
+        if let Some(authorities) = self.journaled.remove(&name) {
+            journaled.insert(name.clone(), authorities);
+        }
+        if let Some(previous) = self.zones.remove(&name) {
+            zones.insert(name, previous);
+        }
+    }
+}
+
+/// The parts of the configuration a zone is loaded from, to tell whether it changed on a reload
+#[cfg(target_family = "unix")]
+#[derive(PartialEq)]
+struct ZoneSource {
+    /// The table of the zone in `zones`
+    zone: Option<toml::Value>,
+    zone_dir: PathBuf,
+    /// The TSIG keys, which the zone may refer to
+    tsig_keys: Option<toml::Value>,
+}
+
+#[cfg(target_family = "unix")]
+impl ZoneSource {
+    fn new(config: &toml::Table, zone_name: &Name, zone_dir: &Path) -> Self {
+        let zone = config
+            .get("zones")
+            .and_then(toml::Value::as_array)
+            .and_then(|zones| {
+                zones.iter().find(|zone| {
+                    zone.get("zone")
+                        .and_then(toml::Value::as_str)
+                        .and_then(|name| Name::parse(name, Some(&Name::root())).ok())
+                        .is_some_and(|name| &name == zone_name)
+                })
+            });
+
+        Self {
+            zone: zone.cloned(),
+            zone_dir: zone_dir.to_path_buf(),
+            tsig_keys: config.get("tsig_keys").cloned(),
+        }
+    }
+}
+
+/// Parses the configuration as a TOML table, to compare the zones of a reload
+#[cfg(target_family = "unix")]
+fn parse_toml(path: &Path, toml: &str) -> Result<toml::Table, String> {
+    toml.parse()
+        .map_err(|err| format!("failed to read config file from {path:?}: {err}"))
+}
+
+/// Whether the zone is read from zone files or lists, which are read again on every reload
+#[cfg(target_family = "unix")]
+fn is_read_from_files(zone: &ZoneConfig) -> bool {
+    zone.stores.iter().any(|store| match store {
+        StoreConfig::File(_) => true,
+        #[cfg(feature = "blocklist")]
+        StoreConfig::Blocklist(_) => true,
+        StoreConfig::Default => !is_journaled(zone),
+        _ => false,
+    })
+}
+
+/// Whether the zone is kept in a journal, which is to be opened by a single authority
+#[cfg(target_family = "unix")]
+fn is_journaled(zone: &ZoneConfig) -> bool {
+    zone.stores.iter().any(|store| match store {
+        #[cfg(feature = "sqlite")]
+        StoreConfig::Sqlite(_) => true,
+        StoreConfig::Default => cfg!(feature = "sqlite") && zone.is_update_allowed(),
+        _ => false,
+    })
+}
+
+/// Result of a reload, sent in response to the `reload` control command
+#[cfg(target_family = "unix")]
+struct ReloadSummary {
+    loaded: usize,
+    kept: usize,
+    errors: Vec<String>,
+}
+
+#[cfg(target_family = "unix")]
+impl fmt::Display for ReloadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "reloaded {} zones, kept {} unchanged",
+            self.loaded, self.kept
+        )?;
+        for error in &self.errors {
+            writeln!(f, "error: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Binds the control socket, replacing a socket left behind by a previous run
+///
+/// The socket is only accessible by the user the server runs as, anyone who can connect to it can
+///  reload the server. It is bound before privileges are dropped, so it is given to that user.
+#[cfg(target_family = "unix")]
+fn bind_control_socket(path: &Path, user: &str, group: &str) -> Result<UnixListener, String> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt, os::unix::fs::PermissionsExt};
+
+    if let Err(err) = fs::remove_file(path) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            return Err(format!("failed to remove control socket {path:?}: {err}"));
+        }
+    }
+
+    let listener = UnixListener::bind(path)
+        .map_err(|err| format!("failed to bind control socket {path:?}: {err}"))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .map_err(|err| format!("failed to set permissions of control socket {path:?}: {err}"))?;
+
+    if unsafe { libc::geteuid() } == 0 {
+        let (uid, gid) = lookup_user(user, group)?;
+        let Ok(path_cstring) = CString::new(path.as_os_str().as_bytes()) else {
+            return Err(format!(
+                "unable to create CString for control socket {path:?}"
+            ));
+        };
+
+        if unsafe { libc::chown(path_cstring.as_ptr(), uid, gid) } < 0 {
+            return Err(format!(
+                "failed to change owner of control socket {path:?}: {}",
+                Error::last_os_error()
+            ));
+        }
+    }
+
+    info!("listening for control commands on {path:?}");
+    Ok(listener)
+}
+
+/// A `reload` command of the control socket, answered with the result of the reload
+#[cfg(target_family = "unix")]
+type ReloadRequest = oneshot::Sender<Result<ReloadSummary, String>>;
+
+/// Reloads on SIGHUP, and on the `reload` command of the control socket
+///
+/// Commands are read from each connection in a task of its own, which sends the reload requests
+///  back to this loop, so that a slow control client doesn't hold up reloads.
+#[cfg(target_family = "unix")]
+async fn handle_reloads(mut reloader: Reloader, mut hangup: Signal, control: Option<UnixListener>) {
+    let (requests, mut received) = mpsc::channel::<ReloadRequest>(1);
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {
+                info!("received SIGHUP");
+                if let Err(err) = reloader.reload().await {
+                    error!("reload failed: {err}");
+                }
+            }
+            Some(respond) = received.recv() => {
+                info!("received reload command");
+                let result = reloader.reload().await;
+                if let Err(err) = &result {
+                    error!("reload failed: {err}");
+                }
+                let _ = respond.send(result);
+            }
+            connection = async {
+                match &control {
+                    Some(control) => control.accept().await,
+                    None => std::future::pending().await,
+                }
+            } => match connection {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_control(stream, requests.clone()));
+                }
+                Err(err) => warn!("failed to accept control connection: {err}"),
+            },
+        }
+    }
+}
+
+/// Handles a single command on a connection to the control socket
+///
+/// The only supported command is `reload`, answered with the numbers of zones reloaded and kept,
+///  and one `error:` line for each zone which failed to load.
+#[cfg(target_family = "unix")]
+async fn handle_control(stream: UnixStream, requests: mpsc::Sender<ReloadRequest>) {
+    let (read, mut write) = stream.into_split();
+    let mut command = String::new();
+    match timeout(
+        CONTROL_TIMEOUT,
+        BufReader::new(read).read_line(&mut command),
+    )
+    .await
+    {
+        Ok(Ok(_)) => (),
+        Ok(Err(err)) => {
+            warn!("failed to read control command: {err}");
+            return;
+        }
+        Err(_) => {
+            debug!("timeout reading control command");
+            return;
+        }
+    }
+
+    let response = match command.trim() {
+        "reload" => match request_reload(&requests).await {
+            Ok(summary) => summary.to_string(),
+            Err(err) => format!("error: {err}\n"),
+        },
+        command => format!("error: unknown command {command:?}\n"),
+    };
+
+    if let Err(err) = write.write_all(response.as_bytes()).await {
+        warn!("failed to write control response: {err}");
+    }
+}
+
+/// Asks [`handle_reloads`] to reload, and waits for the result
+#[cfg(target_family = "unix")]
+async fn request_reload(requests: &mpsc::Sender<ReloadRequest>) -> Result<ReloadSummary, String> {
+    let (respond, result) = oneshot::channel();
+    requests
+        .send(respond)
+        .await
+        .map_err(|_| "reloads are not handled")?;
+    result.await.map_err(|_| "reload was cancelled")?
+}
+
 fn banner() {
     #[cfg(feature = "ascii-art")]
     const HICKORY_DNS_LOGO: &str = include_str!("hickory-dns.ascii");
@@ -937,8 +1743,7 @@ fn build_udp_socket(ip: IpAddr, port: u16) -> Result<UdpSocket, Error> {
 /// halt the server.  This must be called after binding to low numbered sockets is complete.
 #[cfg(target_family = "unix")]
 fn check_drop_privs(user: &str, group: &str) -> Result<(), String> {
-    use libc::{getegid, geteuid, getgid, getgrnam, getpwnam, getuid, setgid, setuid};
-    use std::ffi::CString;
+    use libc::{getegid, geteuid, getgid, getuid, setgid, setuid};
 
     // These calls are guaranteed to succeed in a POSIX-conforming environment. In non-conforming
     // environments, implementations may return -1 to indicate a process running without an
@@ -957,41 +1762,9 @@ fn check_drop_privs(user: &str, group: &str) -> Result<(), String> {
             "running as root (uid: {uid} gid: {gid} euid: {euid} egid: {egid})...dropping privileges.",
         );
 
-        let Ok(user_cstring) = CString::new(user) else {
-            return Err(format!("unable to create CString for user {user}"));
-        };
-
-        let Ok(group_cstring) = CString::new(group) else {
-            return Err(format!(
-                "unable to create CString for group {group}. Exiting."
-            ));
-        };
-
-        // These functions must be supplied a NULL-terminated string, which is guaranteed by
-        // std::ffi::CString.  Upon success, they will return a pointer to a struct passwd or
-        // struct group, or NULL upon failure. Testing for a NULL return value is mandatory.
-        //
-        // POSIX reference: IEEE Std 1003.1-1024 getpwnam and getgrnam specifications
-        // https://pubs.opengroup.org/onlinepubs/9799919799/functions/getpwnam.html
-        // https://pubs.opengroup.org/onlinepubs/9799919799/functions/getgrnam.html
-        let (user_info, group_info) = unsafe {
-            (
-                getpwnam(user_cstring.as_ptr()),
-                getgrnam(group_cstring.as_ptr()),
-            )
-        };
-
-        if user_info.is_null() {
-            return Err(format!("unable to lookup user '{user}'. Exiting."));
-        }
-
-        if group_info.is_null() {
-            return Err(format!("unable to lookup group '{group}'. Exiting."));
-        }
+        let (user_id, group_id) = lookup_user(user, group)?;
 
-        // These functions must be supplied a gid_t (setgid) and uid_t (setuid), which are
-        // supplied by the passwd and group structs returned by getpwnam and getgrnam.
-        // The structs are tested to be valid by the calls to is_null() above.
+        // These functions must be supplied a gid_t (setgid) and uid_t (setuid).
         //
         // The call to setgid must be completed before the call to setuid is made or the
         // process will almost certainly lack the privileges necessary to switch its real gid.
@@ -999,8 +1772,7 @@ fn check_drop_privs(user: &str, group: &str) -> Result<(), String> {
         // POSIX reference: IEEE Std 1003.1-1024 setgid and setuid specifications
         // https://pubs.opengroup.org/onlinepubs/9799919799/functions/setgid.html
         // https://pubs.opengroup.org/onlinepubs/9799919799/functions/setuid.html
-        let (setgid_rc, setuid_rc) =
-            unsafe { (setgid((*group_info).gr_gid), setuid((*user_info).pw_uid)) };
+        let (setgid_rc, setuid_rc) = unsafe { (setgid(group_id), setuid(user_id)) };
 
         if setgid_rc < 0 {
             return Err("unable to set gid. Exiting.".into());
@@ -1022,3 +1794,46 @@ fn check_drop_privs(_user: &str, _group: &str) -> Result<(), String> {
     info!("hickory not running on a unix family os, not dropping privileges");
     Ok(())
 }
+
+/// Looks up the ids of the user and group the server runs as
+#[cfg(target_family = "unix")]
+fn lookup_user(user: &str, group: &str) -> Result<(libc::uid_t, libc::gid_t), String> {
+    use libc::{getgrnam, getpwnam};
+    use std::ffi::CString;
+
+    let Ok(user_cstring) = CString::new(user) else {
+        return Err(format!("unable to create CString for user {user}"));
+    };
+
+    let Ok(group_cstring) = CString::new(group) else {
+        return Err(format!(
+            "unable to create CString for group {group}. Exiting."
+        ));
+    };
+
+    // These functions must be supplied a NULL-terminated string, which is guaranteed by
+    // std::ffi::CString.  Upon success, they will return a pointer to a struct passwd or
+    // struct group, or NULL upon failure. Testing for a NULL return value is mandatory.
+    //
+    // POSIX reference: IEEE Std 1003.1-1024 getpwnam and getgrnam specifications
+    // https://pubs.opengroup.org/onlinepubs/9799919799/functions/getpwnam.html
+    // https://pubs.opengroup.org/onlinepubs/9799919799/functions/getgrnam.html
+    let (user_info, group_info) = unsafe {
+        (
+            getpwnam(user_cstring.as_ptr()),
+            getgrnam(group_cstring.as_ptr()),
+        )
+    };
+
+    if user_info.is_null() {
+        return Err(format!("unable to lookup user '{user}'. Exiting."));
+    }
+
+    if group_info.is_null() {
+        return Err(format!("unable to lookup group '{group}'. Exiting."));
+    }
+
+    // The ids are in the passwd and group structs returned by getpwnam and getgrnam, which
+    // are tested to be valid by the calls to is_null() above.
+    Ok(unsafe { ((*user_info).pw_uid, (*group_info).gr_gid) })
+}
@@ -9,6 +9,8 @@
 
 pub mod dnssec;
 
+#[cfg(feature = "prometheus-metrics")]
+use std::net::SocketAddr;
 use std::{
     fmt,
     fs::File,
@@ -20,15 +22,17 @@ use std::{
 };
 
 use cfg_if::cfg_if;
+use data_encoding::HEXLOWER_PERMISSIVE;
 use ipnet::IpNet;
 use serde::de::{self, MapAccess, SeqAccess, Visitor};
 use serde::{self, Deserialize, Deserializer};
 
 use hickory_proto::rr::Name;
 use hickory_proto::ProtoError;
-use hickory_server::authority::ZoneType;
+use hickory_server::authority::{ServerCookies, ZoneType};
 #[cfg(feature = "dnssec")]
 use hickory_server::dnssec::NxProofKind;
+use hickory_server::server::ResponseRateLimitConfig;
 #[cfg(feature = "blocklist")]
 use hickory_server::store::blocklist::BlocklistConfig;
 use hickory_server::store::file::FileConfig;
@@ -36,6 +40,7 @@ use hickory_server::store::file::FileConfig;
 use hickory_server::store::forwarder::ForwardConfig;
 #[cfg(feature = "recursor")]
 use hickory_server::store::recursor::RecursiveConfig;
+use hickory_server::store::secondary::SecondaryConfig;
 #[cfg(feature = "sqlite")]
 use hickory_server::store::sqlite::SqliteConfig;
 use hickory_server::ConfigError;
@@ -47,6 +52,8 @@ static DEFAULT_HTTPS_PORT: u16 = 443;
 static DEFAULT_QUIC_PORT: u16 = 853; // https://www.ietf.org/archive/id/draft-ietf-dprive-dnsoquic-11.html#name-reservation-of-dedicated-po
 static DEFAULT_H3_PORT: u16 = 443;
 static DEFAULT_TCP_REQUEST_TIMEOUT: u64 = 5;
+#[cfg(feature = "prometheus-metrics")]
+static DEFAULT_PROMETHEUS_PORT: u16 = 9000;
 static DEFAULT_USER: &str = "nobody";
 static DEFAULT_GROUP: &str = "nobody";
 
@@ -66,6 +73,12 @@ pub struct Config {
     tls_listen_port: Option<u16>,
     /// HTTPS port to listen on
     https_listen_port: Option<u16>,
+    /// HTTP/1.1 port to listen on for DNS-over-HTTPS, disabled unless set
+    #[cfg(feature = "dns-over-https-rustls")]
+    http1_listen_port: Option<u16>,
+    /// Serve HTTP/1.1 without TLS, for a load balancer which terminates TLS
+    #[cfg(feature = "dns-over-https-rustls")]
+    http1_disable_tls: Option<bool>,
     /// QUIC port to listen on
     quic_listen_port: Option<u16>,
     /// HTTP/3 port to listen on
@@ -114,6 +127,19 @@ pub struct Config {
     /// Networks allowed to access the server
     #[serde(default)]
     allow_networks: Vec<IpNet>,
+    /// Keys for TSIG authentication of zone transfers and dynamic updates
+    #[serde(default)]
+    tsig_keys: Vec<dnssec::TsigKeyConfig>,
+    /// DNS Cookies, disabled by default
+    cookies: Option<CookieConfig>,
+    /// Response Rate Limiting of UDP responses, disabled by default
+    rate_limit: Option<ResponseRateLimitConfig>,
+    /// Address to serve the metrics for Prometheus on
+    #[cfg(feature = "prometheus-metrics")]
+    prometheus_listen_addr: Option<SocketAddr>,
+    /// Path of the Unix socket accepting control commands, e.g. `reload`
+    #[cfg(target_family = "unix")]
+    control_socket: Option<PathBuf>,
 }
 
 impl Config {
@@ -155,6 +181,18 @@ impl Config {
         self.https_listen_port.unwrap_or(DEFAULT_HTTPS_PORT)
     }
 
+    /// port on which to listen for HTTP/1.1 connections, if any
+    #[cfg(feature = "dns-over-https-rustls")]
+    pub fn http1_listen_port(&self) -> Option<u16> {
+        self.http1_listen_port
+    }
+
+    /// get if HTTP/1.1 should be served without TLS
+    #[cfg(feature = "dns-over-https-rustls")]
+    pub fn http1_disable_tls(&self) -> bool {
+        self.http1_disable_tls.unwrap_or_default()
+    }
+
     /// port on which to listen for QUIC connections
     pub fn quic_listen_port(&self) -> u16 {
         self.quic_listen_port.unwrap_or(DEFAULT_QUIC_PORT)
@@ -248,6 +286,36 @@ impl Config {
         &self.allow_networks
     }
 
+    /// the keys for TSIG authentication, which zones refer to by name
+    pub fn tsig_keys(&self) -> &[dnssec::TsigKeyConfig] {
+        &self.tsig_keys
+    }
+
+    /// the DNS Cookies configuration, if enabled
+    pub fn cookies(&self) -> Option<&CookieConfig> {
+        self.cookies.as_ref()
+    }
+
+    /// the Response Rate Limiting configuration, if enabled
+    pub fn rate_limit(&self) -> Option<ResponseRateLimitConfig> {
+        self.rate_limit
+    }
+
+    /// address on which to serve the metrics for Prometheus, defaults to `127.0.0.1:9000`
+    #[cfg(feature = "prometheus-metrics")]
+    pub fn prometheus_listen_addr(&self) -> SocketAddr {
+        self.prometheus_listen_addr.unwrap_or(SocketAddr::from((
+            Ipv4Addr::LOCALHOST,
+            DEFAULT_PROMETHEUS_PORT,
+        )))
+    }
+
+    /// path of the Unix socket accepting control commands, disabled by default
+    #[cfg(target_family = "unix")]
+    pub fn control_socket(&self) -> Option<&Path> {
+        self.control_socket.as_deref()
+    }
+
     /// get the user name to run the server as
     #[cfg(target_family = "unix")]
     pub fn user(&self) -> &str {
@@ -269,6 +337,38 @@ impl Config {
     }
 }
 
+/// Configuration for DNS Cookies, RFC 7873
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CookieConfig {
+    /// Hex encoded 128 bit secret for the server cookies, shared by servers of an anycast
+    ///  deployment. A random secret is generated if not set.
+    pub secret: Option<String>,
+    /// Require a valid server cookie from UDP clients sending more than this many requests per
+    ///  second, 0 requires it for all UDP requests
+    pub require_threshold: Option<u32>,
+}
+
+impl CookieConfig {
+    /// Creates the server cookies
+    pub fn try_into_cookies(&self) -> Result<ServerCookies, String> {
+        let mut cookies = match &self.secret {
+            Some(secret) => {
+                let secret = HEXLOWER_PERMISSIVE
+                    .decode(secret.as_bytes())
+                    .map_err(|e| format!("bad cookie secret: {e}"))?;
+                let secret = <[u8; 16]>::try_from(secret.as_slice())
+                    .map_err(|_| "cookie secret must be 128 bits, 32 hex digits".to_string())?;
+                ServerCookies::new(secret)
+            }
+            None => ServerCookies::with_random_secret(),
+        };
+
+        cookies.set_require_threshold(self.require_threshold);
+        Ok(cookies)
+    }
+}
+
 /// Configuration for a zone
 #[derive(Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
@@ -281,8 +381,14 @@ pub struct ZoneConfig {
     pub file: Option<String>,
     /// Deprecated allow_update, this is a Store option
     pub allow_update: Option<bool>,
-    /// Allow AXFR (TODO: need auth)
+    /// Allow AXFR to everyone, see `allow_axfr_keys` to only allow authenticated transfers
     pub allow_axfr: Option<bool>,
+    /// Names of the TSIG keys, from `tsig_keys`, allowed to transfer the zone
+    #[serde(default)]
+    pub allow_axfr_keys: Vec<String>,
+    /// Names of the TSIG keys allowed to update the zone, which must also allow updates
+    #[serde(default)]
+    pub allow_update_keys: Vec<String>,
     /// Enable DnsSec TODO: should this move to StoreConfig?
     pub enable_dnssec: Option<bool>,
     /// Keys for use by the zone
@@ -331,6 +437,8 @@ impl ZoneConfig {
             file: Some(file),
             allow_update,
             allow_axfr,
+            allow_axfr_keys: Vec::new(),
+            allow_update_keys: Vec::new(),
             enable_dnssec,
             keys,
             stores: store_config_default(),
@@ -369,6 +477,22 @@ impl ZoneConfig {
         self.allow_axfr.unwrap_or(false)
     }
 
+    /// the names of the TSIG keys allowed to transfer the zone
+    pub fn axfr_keys(&self) -> Result<Vec<Name>, ProtoError> {
+        self.allow_axfr_keys
+            .iter()
+            .map(|key| Name::parse(key, Some(&Name::root())))
+            .collect()
+    }
+
+    /// the names of the TSIG keys allowed to update the zone
+    pub fn update_keys(&self) -> Result<Vec<Name>, ProtoError> {
+        self.allow_update_keys
+            .iter()
+            .map(|key| Name::parse(key, Some(&Name::root())))
+            .collect()
+    }
+
     /// declare that this zone should be signed, see keys for configuration of the keys for signing
     pub fn is_dnssec_enabled(&self) -> bool {
         cfg_if! {
@@ -403,10 +527,12 @@ pub enum StoreConfig {
     Sqlite(SqliteConfig),
     /// Forwarding Resolver
     #[cfg(feature = "resolver")]
-    Forward(ForwardConfig),
+    Forward(Box<ForwardConfig>),
     /// Recursive Resolver
     #[cfg(feature = "recursor")]
-    Recursor(RecursiveConfig),
+    Recursor(Box<RecursiveConfig>),
+    /// Secondary zone, transferred from a primary
+    Secondary(SecondaryConfig),
     /// This is used by the configuration processing code to represent a deprecated or main-block config without an associated store.
     Default,
 }
@@ -466,9 +466,15 @@ pub fn test_update_errors<A: Authority<Lookup = AuthLookup>>(mut authority: A) {
     message.add_query(Query::default());
     let bytes = message.to_vec().unwrap();
     let update = MessageRequest::from_bytes(&bytes).unwrap();
+    let request_info = RequestInfo::new(
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 53)),
+        Protocol::Udp,
+        update.header(),
+        update.query(),
+    );
 
     // this is expected to fail, i.e. updates are not allowed
-    assert!(block_on(authority.update(&update)).is_err());
+    assert!(block_on(authority.update(request_info, &update)).is_err());
 }
 
 #[allow(clippy::uninlined_format_args)]
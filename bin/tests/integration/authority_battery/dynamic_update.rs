@@ -40,8 +40,14 @@ fn update_authority<A: Authority<Lookup = AuthLookup>>(
     message.finalize(key, 1).expect("failed to sign message");
     let message = message.to_bytes().unwrap();
     let request = MessageRequest::from_bytes(&message).unwrap();
-
-    block_on(authority.update(&request))
+    let request_info = RequestInfo::new(
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 53)),
+        Protocol::Udp,
+        request.header(),
+        request.query(),
+    );
+
+    block_on(authority.update(request_info, &request))
 }
 
 pub fn test_create<A: Authority<Lookup = AuthLookup>>(mut authority: A, keys: &[SigSigner]) {
@@ -183,6 +183,124 @@ signer_name = \"ns.example.com.\"
     assert!(!config.zones()[0].keys()[1].is_zone_update_auth(),);
 }
 
+#[test]
+fn test_parse_cookies() {
+    // disabled by default
+    let config = Config::from_toml("").unwrap();
+    assert!(config.cookies().is_none());
+
+    let config = Config::from_toml("[cookies]").unwrap();
+    assert!(config.cookies().unwrap().try_into_cookies().is_ok());
+
+    let config = Config::from_toml(
+        "[cookies]\nsecret = \"e5e973e5a6b2a43f48e7dc849e37bfcf\"\nrequire_threshold = 10",
+    )
+    .unwrap();
+    let cookies = config.cookies().unwrap();
+    assert_eq!(cookies.require_threshold, Some(10));
+    assert!(cookies.try_into_cookies().is_ok());
+
+    let config = Config::from_toml("[cookies]\nsecret = \"e5e973e5\"").unwrap();
+    assert!(config.cookies().unwrap().try_into_cookies().is_err());
+}
+
+#[test]
+fn test_parse_rate_limit() {
+    // disabled by default
+    let config = Config::from_toml("").unwrap();
+    assert!(config.rate_limit().is_none());
+
+    let config = Config::from_toml("[rate_limit]\nresponses_per_second = 5\nslip = 0").unwrap();
+    let rate_limit = config.rate_limit().unwrap();
+    assert_eq!(rate_limit.responses_per_second, 5);
+    assert_eq!(rate_limit.nxdomains_per_second, None);
+    assert_eq!(rate_limit.slip, 0);
+    assert_eq!(rate_limit.window, 15);
+    assert_eq!(rate_limit.ipv4_prefix_length, 24);
+    assert!(!rate_limit.log_only);
+
+    assert!(Config::from_toml("[rate_limit]\nslip = 2").is_err());
+}
+
+#[cfg(feature = "prometheus-metrics")]
+#[test]
+fn test_parse_prometheus_listen_addr() {
+    let config = Config::from_toml("").unwrap();
+    assert_eq!(
+        config.prometheus_listen_addr(),
+        "127.0.0.1:9000".parse().unwrap()
+    );
+
+    let config = Config::from_toml("prometheus_listen_addr = \"[::1]:9153\"").unwrap();
+    assert_eq!(
+        config.prometheus_listen_addr(),
+        "[::1]:9153".parse().unwrap()
+    );
+}
+
+#[cfg(feature = "dns-over-https-rustls")]
+#[test]
+fn test_parse_http1() {
+    let config = Config::from_toml("").unwrap();
+    assert_eq!(config.http1_listen_port(), None);
+    assert!(!config.http1_disable_tls());
+
+    let config = Config::from_toml("http1_listen_port = 8080\nhttp1_disable_tls = true").unwrap();
+    assert_eq!(config.http1_listen_port(), Some(8080));
+    assert!(config.http1_disable_tls());
+}
+
+#[cfg(target_family = "unix")]
+#[test]
+fn test_parse_control_socket() {
+    let config = Config::from_toml("").unwrap();
+    assert!(config.control_socket().is_none());
+
+    let config = Config::from_toml("control_socket = \"/run/hickory-dns.sock\"").unwrap();
+    assert_eq!(
+        config.control_socket(),
+        Some(Path::new("/run/hickory-dns.sock"))
+    );
+}
+
+#[cfg(feature = "dnssec")]
+#[test]
+fn test_parse_tsig_keys() {
+    use hickory_proto::dnssec::rdata::tsig::TsigAlgorithm;
+    use hickory_proto::rr::Name;
+
+    let config = Config::from_toml(
+        "
+[[tsig_keys]]
+name = \"transfer-key\"
+key_path = \"/path/to/transfer.raw\"
+algorithm = \"hmac-sha384\"
+
+[[zones]]
+zone = \"example.com\"
+zone_type = \"Primary\"
+file = \"example.com.zone\"
+allow_axfr_keys = [\"transfer-key\"]
+",
+    )
+    .unwrap();
+
+    let key = &config.tsig_keys()[0];
+    assert_eq!(
+        key.name().unwrap(),
+        Name::parse("transfer-key.", None).unwrap()
+    );
+    assert_eq!(key.key_path(), Path::new("/path/to/transfer.raw"));
+    assert_eq!(key.algorithm().unwrap(), TsigAlgorithm::HmacSha384);
+    assert_eq!(key.fudge(), 300);
+
+    assert_eq!(
+        config.zones()[0].axfr_keys().unwrap(),
+        vec![Name::parse("transfer-key.", None).unwrap()]
+    );
+    assert!(config.zones()[0].update_keys().unwrap().is_empty());
+}
+
 #[test]
 #[cfg(feature = "dns-over-tls")]
 fn test_parse_tls() {
@@ -241,6 +359,8 @@ define_test_config!(dns_over_tls);
 define_test_config!(dnssec_with_update);
 define_test_config!(dnssec_with_update_deprecated);
 define_test_config!(example);
+define_test_config!(example_cookies);
+define_test_config!(example_rate_limit);
 define_test_config!(ipv4_and_ipv6);
 define_test_config!(ipv4_only);
 define_test_config!(ipv6_only);
@@ -248,6 +368,11 @@ define_test_config!(openssl_dnssec);
 define_test_config!(ring_dnssec);
 #[cfg(feature = "resolver")]
 define_test_config!(example_forwarder);
+#[cfg(feature = "resolver")]
+define_test_config!(example_dns64);
+define_test_config!(example_secondary);
+#[cfg(feature = "sqlite")]
+define_test_config!(example_tsig);
 
 /// Iterator that yields modified TOML tables with an extra field added, and recurses down the
 /// table's values.
@@ -27,6 +27,15 @@ use test_support::subscribe;
 
 #[test]
 fn test_example_https_toml_startup() {
+    https_toml_startup(false);
+}
+
+#[test]
+fn test_example_https_toml_startup_get() {
+    https_toml_startup(true);
+}
+
+fn https_toml_startup(use_get: bool) {
     subscribe();
 
     const ALPN_H2: &[u8] = b"h2";
@@ -65,7 +74,9 @@ fn test_example_https_toml_startup() {
         let client_config = Arc::new(client_config);
 
         let provider = TokioRuntimeProvider::new();
-        let https_builder = HttpsClientStreamBuilder::with_client_config(client_config, provider);
+        let mut https_builder =
+            HttpsClientStreamBuilder::with_client_config(client_config, provider);
+        https_builder.use_get(use_get);
         let mp = https_builder.build(addr, "ns.example.com".to_string(), "/dns-query".to_string());
         let client = Client::connect(mp);
 
@@ -386,3 +386,156 @@ fn test_deny_allow_networks_toml_startup() {
         query_a_refused(&mut io_loop, &mut client);
     })
 }
+
+#[test]
+#[cfg(target_family = "unix")]
+fn test_reload_control_command() {
+    use std::fs;
+    use std::io::Read;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use std::os::unix::net::UnixStream;
+
+    subscribe();
+    let provider = TokioRuntimeProvider::new();
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("reload.toml");
+    let control_path = dir.path().join("control.sock");
+
+    let write_config = |zones: &[String]| {
+        let mut config = format!("control_socket = \"{}\"\n", control_path.display());
+        for zone in zones {
+            config.push_str(zone);
+        }
+        fs::write(&config_path, config).unwrap();
+    };
+    let reload = || {
+        let mut control = UnixStream::connect(&control_path).unwrap();
+        control.write_all(b"reload\n").unwrap();
+        let mut response = String::new();
+        control.read_to_string(&mut response).unwrap();
+        response
+    };
+
+    let file_zone = |zone: &str, file: &str| {
+        format!("[[zones]]\nzone = \"{zone}\"\nzone_type = \"Primary\"\nfile = \"{file}\"\n")
+    };
+    let example_com = file_zone("example.com", "example.com.zone");
+    let localhost = file_zone("localhost", "default/localhost.zone");
+    let broken = file_zone("broken.example", "missing.zone");
+    // a journaled zone, with the journal in the temporary directory
+    let journaled = |journal: &str, allow_axfr: bool| {
+        format!(
+            "[[zones]]\nzone = \"example.net\"\nzone_type = \"Primary\"\nallow_axfr = {allow_axfr}\n\
+             stores = {{ type = \"sqlite\", zone_file_path = \"example.com.zone\", \
+             journal_file_path = \"{}\", allow_update = true }}\n",
+            dir.path().join(journal).display()
+        )
+    };
+
+    let mut zones = vec![example_com.clone()];
+    if cfg!(feature = "sqlite") {
+        zones.push(journaled("example.net.jrnl", false));
+    }
+    write_config(&zones);
+
+    named_test_harness(config_path.to_str().unwrap(), |socket_ports| {
+        let io_loop = Runtime::new().unwrap();
+        let addr = SocketAddr::from((
+            Ipv4Addr::LOCALHOST,
+            socket_ports.get_v4(Protocol::Tcp).expect("no tcp_port"),
+        ));
+        let (stream, sender) = TcpClientStream::new(addr, None, None, provider.clone());
+        let client = Client::new(Box::new(stream), sender, None);
+        let (mut client, bg) = io_loop.block_on(client).expect("client failed to connect");
+        hickory_proto::runtime::spawn_bg(&io_loop, bg);
+
+        let mut query = |name: &str| {
+            let name = Name::from_str(name).unwrap();
+            io_loop
+                .block_on(client.query(name, DNSClass::IN, RecordType::A))
+                .unwrap()
+                .response_code()
+        };
+        assert_eq!(query("localhost."), ResponseCode::Refused);
+
+        // only the user the server runs as can use the control socket
+        let metadata = fs::metadata(&control_path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        if unsafe { libc::geteuid() } == 0 {
+            // privileges are dropped to the default user
+            let nobody = unsafe { libc::getpwnam(b"nobody\0".as_ptr().cast()) };
+            assert!(!nobody.is_null());
+            assert_eq!(metadata.uid(), unsafe { (*nobody).pw_uid });
+        }
+
+        // a zone is added, another fails to load, and the journaled zone changes
+        let mut zones = vec![example_com.clone(), localhost.clone(), broken.clone()];
+        if cfg!(feature = "sqlite") {
+            zones.push(journaled("example.net.jrnl", true));
+        }
+        write_config(&zones);
+
+        let response = reload();
+        let loaded = if cfg!(feature = "sqlite") { 3 } else { 2 };
+        assert!(
+            response.starts_with(&format!("reloaded {loaded} zones, kept 0 unchanged\n")),
+            "{response}"
+        );
+        assert!(
+            response.contains("error: could not load zone broken.example."),
+            "{response}"
+        );
+        assert_eq!(response.lines().count(), 2, "{response}");
+
+        assert_eq!(query("www.example.com."), ResponseCode::NoError);
+        assert_eq!(query("localhost."), ResponseCode::NoError);
+        assert_eq!(query("www.broken.example."), ResponseCode::Refused);
+
+        if cfg!(feature = "sqlite") {
+            assert_eq!(query("www.example.net."), ResponseCode::NoError);
+
+            // the journaled zone fails to load, and keeps being served
+            let zones = [
+                example_com.clone(),
+                localhost.clone(),
+                journaled("missing/example.net.jrnl", true),
+            ];
+            write_config(&zones);
+
+            let response = reload();
+            assert!(
+                response.starts_with("reloaded 2 zones, kept 0 unchanged\n"),
+                "{response}"
+            );
+            assert!(
+                response.contains("error: could not load zone example.net."),
+                "{response}"
+            );
+            assert_eq!(query("www.example.net."), ResponseCode::NoError);
+        }
+
+        // a zone which becomes a secondary keeps being served until it is transferred, here from
+        //  a primary which is not listening
+        let primary = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let secondary = format!(
+            "[[zones]]\nzone = \"example.com\"\nzone_type = \"Secondary\"\n\
+             stores = {{ type = \"secondary\", primaries = [\"{primary}\"] }}\n"
+        );
+        write_config(&[secondary, localhost.clone()]);
+
+        let response = reload();
+        assert!(
+            response.starts_with("reloaded 1 zones, kept 0 unchanged\n"),
+            "{response}"
+        );
+        assert!(
+            response
+                .contains("error: could not transfer zone example.com., keeping the previous zone"),
+            "{response}"
+        );
+        assert_eq!(query("www.example.com."), ResponseCode::NoError);
+    })
+}
@@ -6,6 +6,7 @@ use std::{
     io::{stdout, BufRead, BufReader, Write},
     net::SocketAddr,
     panic::{catch_unwind, UnwindSafe},
+    path::Path,
     process::{Command, Stdio},
     str::FromStr,
     sync::*,
@@ -93,8 +94,13 @@ where
             "hickory_dns=debug,hickory_client=debug,hickory_proto=debug,hickory_resolver=debug,hickory_server=debug",
         )
         .arg("-d")
+        // an absolute path replaces the directory of the test configurations
         .arg(format!(
-            "--config={server_path}/tests/test-data/test_configs/{toml}"
+            "--config={}",
+            Path::new(&server_path)
+                .join("tests/test-data/test_configs")
+                .join(toml)
+                .display()
         ))
         .arg(format!(
             "--zonedir={server_path}/tests/test-data/test_configs"
@@ -28,6 +28,7 @@ fn sqlite(master_file_path: &str, module: &str, test_name: &str) -> SqliteAuthor
         zone_file_path: master_file_path.to_string(),
         journal_file_path: journal_path.to_str().unwrap().to_string(),
         allow_update: true,
+        also_notify: Vec::new(),
     };
 
     block_on(SqliteAuthority::try_from_config(
@@ -58,6 +59,7 @@ fn sqlite_update(master_file_path: &str, module: &str, test_name: &str) -> Sqlit
         zone_file_path: master_file_path.to_string(),
         journal_file_path: journal_path.to_str().unwrap().to_string(),
         allow_update: true,
+        also_notify: Vec::new(),
     };
 
     block_on(SqliteAuthority::try_from_config(
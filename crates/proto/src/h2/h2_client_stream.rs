@@ -45,6 +45,9 @@ pub struct HttpsClientStream {
     query_path: Arc<str>,
     name_server: SocketAddr,
     h2: SendRequest<Bytes>,
+    use_get: bool,
+    #[cfg(feature = "dns-over-odoh")]
+    odoh: Option<crate::odoh::OdohConfig>,
     is_shutdown: bool,
 }
 
@@ -64,7 +67,71 @@ impl HttpsClientStream {
         message: Bytes,
         name_server_name: Arc<str>,
         query_path: Arc<str>,
+        use_get: bool,
     ) -> Result<DnsResponse, ProtoError> {
+        // build up the http request
+        let request = if use_get {
+            crate::http::request::new_get(Version::Http2, &name_server_name, &query_path, &message)
+        } else {
+            crate::http::request::new(
+                Version::Http2,
+                &name_server_name,
+                &query_path,
+                message.remaining(),
+            )
+        };
+
+        let request =
+            request.map_err(|err| ProtoError::from(format!("bad http request: {err}")))?;
+
+        // a GET request has no body
+        let body = if use_get { None } else { Some(message) };
+        let response_bytes =
+            Self::exchange(h2, request, body, Some(crate::http::MIME_APPLICATION_DNS)).await?;
+
+        // and finally convert the bytes into a DNS message
+        DnsResponse::from_buffer(response_bytes.to_vec())
+    }
+
+    /// Encrypts the query to the target, and sends it through the proxy this stream is connected to
+    #[cfg(feature = "dns-over-odoh")]
+    async fn inner_send_odoh(
+        h2: SendRequest<Bytes>,
+        message: Bytes,
+        name_server_name: Arc<str>,
+        query_path: Arc<str>,
+        target_config: crate::odoh::OdohConfig,
+    ) -> Result<DnsResponse, ProtoError> {
+        let (message, response_key) = target_config.encrypt_query(&message)?;
+        let request = crate::http::request::new_odoh(
+            Version::Http2,
+            &name_server_name,
+            &query_path,
+            message.len(),
+        )
+        .map_err(|err| ProtoError::from(format!("bad http request: {err}")))?;
+
+        let response_bytes = Self::exchange(
+            h2,
+            request,
+            Some(Bytes::from(message)),
+            Some(crate::odoh::MIME_APPLICATION_ODOH),
+        )
+        .await?;
+
+        DnsResponse::from_buffer(response_key.decrypt_response(&response_bytes)?)
+    }
+
+    /// Sends the request with an optional body, and returns the body of the successful response
+    ///
+    /// The content type of the response must be `content_type` if one is given, a response without
+    ///  any content type is assumed to be of the expected type.
+    async fn exchange(
+        h2: SendRequest<Bytes>,
+        request: http::Request<()>,
+        body: Option<Bytes>,
+        content_type: Option<&str>,
+    ) -> Result<BytesMut, ProtoError> {
         let mut h2 = match h2.ready().await {
             Ok(h2) => h2,
             Err(err) => {
@@ -73,27 +140,18 @@ impl HttpsClientStream {
             }
         };
 
-        // build up the http request
-        let request = crate::http::request::new(
-            Version::Http2,
-            &name_server_name,
-            &query_path,
-            message.remaining(),
-        );
-
-        let request =
-            request.map_err(|err| ProtoError::from(format!("bad http request: {err}")))?;
-
         debug!("request: {:#?}", request);
 
         // Send the request
         let (response_future, mut send_stream) = h2
-            .send_request(request, false)
+            .send_request(request, body.is_none())
             .map_err(|err| ProtoError::from(format!("h2 send_request error: {err}")))?;
 
-        send_stream
-            .send_data(message, true)
-            .map_err(|e| ProtoError::from(format!("h2 send_data error: {e}")))?;
+        if let Some(body) = body {
+            send_stream
+                .send_data(body, true)
+                .map_err(|e| ProtoError::from(format!("h2 send_data error: {e}")))?;
+        }
 
         let mut response_stream = response_future
             .await
@@ -155,10 +213,10 @@ impl HttpsClientStream {
                 response_stream.status(),
                 error_string
             )));
-        } else {
+        } else if let Some(expected) = content_type {
             // verify content type
             {
-                // in the case that the ContentType is not specified, we assume it's the standard DNS format
+                // in the case that the ContentType is not specified, we assume it's the expected format
                 let content_type = response_stream
                     .headers()
                     .get(header::CONTENT_TYPE)
@@ -168,20 +226,18 @@ impl HttpsClientStream {
                             ProtoError::from(format!("ContentType header not a string: {err}"))
                         })
                     })
-                    .unwrap_or(Ok(crate::http::MIME_APPLICATION_DNS))?;
+                    .unwrap_or(Ok(expected))?;
 
-                if content_type != crate::http::MIME_APPLICATION_DNS {
+                if content_type != expected {
                     return Err(ProtoError::from(format!(
                         "ContentType unsupported (must be '{}'): '{}'",
-                        crate::http::MIME_APPLICATION_DNS,
-                        content_type
+                        expected, content_type
                     )));
                 }
             }
         };
 
-        // and finally convert the bytes into a DNS message
-        DnsResponse::from_buffer(response_bytes.to_vec())
+        Ok(response_bytes)
     }
 }
 
@@ -246,11 +302,24 @@ impl DnsRequestSender for HttpsClientStream {
             Err(err) => return err.into(),
         };
 
+        #[cfg(feature = "dns-over-odoh")]
+        if let Some(target_config) = self.odoh {
+            return Box::pin(Self::inner_send_odoh(
+                self.h2.clone(),
+                Bytes::from(bytes),
+                Arc::clone(&self.name_server_name),
+                Arc::clone(&self.query_path),
+                target_config,
+            ))
+            .into();
+        }
+
         Box::pin(Self::inner_send(
             self.h2.clone(),
             Bytes::from(bytes),
             Arc::clone(&self.name_server_name),
             Arc::clone(&self.query_path),
+            self.use_get,
         ))
         .into()
     }
@@ -289,51 +358,113 @@ pub struct HttpsClientStreamBuilder<P> {
     provider: P,
     client_config: Arc<ClientConfig>,
     bind_addr: Option<SocketAddr>,
+    use_get: bool,
+    #[cfg(feature = "dns-over-odoh")]
+    odoh: Option<crate::odoh::OdohConfig>,
 }
 
-impl<P: RuntimeProvider> HttpsClientStreamBuilder<P> {
+impl<P> HttpsClientStreamBuilder<P> {
     /// Constructs a new TlsStreamBuilder with the associated ClientConfig
     pub fn with_client_config(client_config: Arc<ClientConfig>, provider: P) -> Self {
         Self {
             provider,
             client_config,
             bind_addr: None,
+            use_get: false,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh: None,
         }
     }
 
     /// Sets the address to connect from.
-    pub fn bind_addr(&mut self, bind_addr: SocketAddr) {
+    pub fn bind_addr(&mut self, bind_addr: SocketAddr) -> &mut Self {
         self.bind_addr = Some(bind_addr);
+        self
     }
 
-    /// Creates a new HttpsStream to the specified name_server
+    /// Sends queries as `GET` requests, which HTTP caches can store, rather than `POST` (the default).
+    pub fn use_get(&mut self, use_get: bool) -> &mut Self {
+        self.use_get = use_get;
+        self
+    }
+
+    /// Sends queries as Oblivious DoH, RFC 9230, through the proxy this stream connects to
+    ///
+    /// The `http_endpoint` passed to [`Self::build`] must name the target, see
+    ///  [`proxy_path`](crate::odoh::proxy_path). Queries are encrypted to the `target_config`, which
+    ///  must be obtained from the target itself: a configuration fetched through the proxy could be
+    ///  that of the proxy.
+    #[cfg(feature = "dns-over-odoh")]
+    pub fn odoh(&mut self, target_config: crate::odoh::OdohConfig) -> &mut Self {
+        self.odoh = Some(target_config);
+        self
+    }
+
+    /// Creates a new HttpsStream with existing connection
     ///
     /// # Arguments
     ///
+    /// * `future` - A future that resolves to the TCP connection to the remote DNS resolver
     /// * `name_server` - IP and Port for the remote DNS resolver
     /// * `dns_name` - The DNS name associated with a certificate
     /// * `http_endpoint` - The HTTP endpoint where the remote DNS resolver provides service, typically `/dns-query`
-    pub fn build(
-        mut self,
+    pub fn build_with_future<S, F>(
+        self,
+        future: F,
         name_server: SocketAddr,
         dns_name: String,
         http_endpoint: String,
-    ) -> HttpsClientConnect<P::Tcp> {
+    ) -> HttpsClientConnect<S>
+    where
+        S: DnsTcpStream,
+        F: Future<Output = std::io::Result<S>> + Send + Unpin + 'static,
+    {
+        let tls = self.tls_config(dns_name, http_endpoint);
+        HttpsClientConnect(HttpsClientConnectState::TcpConnecting {
+            connect: Box::pin(future),
+            name_server,
+            tls: Some(tls),
+        })
+    }
+
+    fn tls_config(self, dns_name: String, http_endpoint: String) -> TlsConfig {
+        let mut client_config = self.client_config;
+
         // ensure the ALPN protocol is set correctly
-        if self.client_config.alpn_protocols.is_empty() {
-            let mut client_config = (*self.client_config).clone();
-            client_config.alpn_protocols = vec![ALPN_H2.to_vec()];
+        if client_config.alpn_protocols.is_empty() {
+            let mut client_cfg = (*client_config).clone();
+            client_cfg.alpn_protocols = vec![ALPN_H2.to_vec()];
 
-            self.client_config = Arc::new(client_config);
+            client_config = Arc::new(client_cfg);
         }
 
-        let tls = TlsConfig {
-            client_config: self.client_config,
+        TlsConfig {
+            client_config,
             dns_name: Arc::from(dns_name),
             http_endpoint: Arc::from(http_endpoint),
-        };
+            use_get: self.use_get,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh: self.odoh,
+        }
+    }
+}
 
+impl<P: RuntimeProvider> HttpsClientStreamBuilder<P> {
+    /// Creates a new HttpsStream to the specified name_server
+    ///
+    /// # Arguments
+    ///
+    /// * `name_server` - IP and Port for the remote DNS resolver
+    /// * `dns_name` - The DNS name associated with a certificate
+    /// * `http_endpoint` - The HTTP endpoint where the remote DNS resolver provides service, typically `/dns-query`
+    pub fn build(
+        self,
+        name_server: SocketAddr,
+        dns_name: String,
+        http_endpoint: String,
+    ) -> HttpsClientConnect<P::Tcp> {
         let connect = self.provider.connect_tcp(name_server, self.bind_addr, None);
+        let tls = self.tls_config(dns_name, http_endpoint);
         HttpsClientConnect(HttpsClientConnectState::TcpConnecting {
             connect,
             name_server,
@@ -349,9 +480,12 @@ where
 
 impl<S: DnsTcpStream> HttpsClientConnect<S> {
     /// Creates a new HttpsStream with existing connection
+    ///
+    /// This uses the default settings of [`HttpsClientStreamBuilder`], see
+    ///  [`HttpsClientStreamBuilder::build_with_future`] to change them.
     pub fn new<F>(
         future: F,
-        mut client_config: Arc<ClientConfig>,
+        client_config: Arc<ClientConfig>,
         name_server: SocketAddr,
         dns_name: String,
         http_endpoint: String,
@@ -360,25 +494,12 @@ impl<S: DnsTcpStream> HttpsClientConnect<S> {
         S: DnsTcpStream,
         F: Future<Output = std::io::Result<S>> + Send + Unpin + 'static,
     {
-        // ensure the ALPN protocol is set correctly
-        if client_config.alpn_protocols.is_empty() {
-            let mut client_cfg = (*client_config).clone();
-            client_cfg.alpn_protocols = vec![ALPN_H2.to_vec()];
-
-            client_config = Arc::new(client_cfg);
-        }
-
-        let tls = TlsConfig {
-            client_config,
-            dns_name: Arc::from(dns_name),
-            http_endpoint: Arc::from(http_endpoint),
-        };
-
-        Self(HttpsClientConnectState::TcpConnecting {
-            connect: Box::pin(future),
+        HttpsClientStreamBuilder::with_client_config(client_config, ()).build_with_future(
+            future,
             name_server,
-            tls: Some(tls),
-        })
+            dns_name,
+            http_endpoint,
+        )
     }
 }
 
@@ -397,6 +518,9 @@ struct TlsConfig {
     client_config: Arc<ClientConfig>,
     dns_name: Arc<str>,
     http_endpoint: Arc<str>,
+    use_get: bool,
+    #[cfg(feature = "dns-over-odoh")]
+    odoh: Option<crate::odoh::OdohConfig>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -425,6 +549,9 @@ where
         name_server_name: Arc<str>,
         name_server: SocketAddr,
         query_path: Arc<str>,
+        use_get: bool,
+        #[cfg(feature = "dns-over-odoh")]
+        odoh: Option<crate::odoh::OdohConfig>,
     },
     H2Handshake {
         handshake: Pin<
@@ -443,6 +570,9 @@ where
         name_server_name: Arc<str>,
         name_server: SocketAddr,
         query_path: Arc<str>,
+        use_get: bool,
+        #[cfg(feature = "dns-over-odoh")]
+        odoh: Option<crate::odoh::OdohConfig>,
     },
     Connected(Option<HttpsClientStream>),
     Errored(Option<ProtoError>),
@@ -470,6 +600,9 @@ where
                         .expect("programming error, tls should not be None here");
                     let name_server_name = Arc::clone(&tls.dns_name);
                     let query_path = Arc::clone(&tls.http_endpoint);
+                    let use_get = tls.use_get;
+                    #[cfg(feature = "dns-over-odoh")]
+                    let odoh = tls.odoh;
 
                     match ServerName::try_from(&*tls.dns_name) {
                         Ok(dns_name) => Self::TlsConnecting {
@@ -481,6 +614,9 @@ where
                                     .connect(dns_name.to_owned(), AsyncIoStdAsTokio(tcp)),
                             )),
                             query_path,
+                            use_get,
+                            #[cfg(feature = "dns-over-odoh")]
+                            odoh,
                         },
                         Err(_) => Self::Errored(Some(ProtoError::from(format!(
                             "bad dns_name: {}",
@@ -492,6 +628,9 @@ where
                     name_server_name,
                     name_server,
                     query_path,
+                    use_get,
+                    #[cfg(feature = "dns-over-odoh")]
+                    odoh,
                     tls,
                 } => {
                     let Ok(res) = ready!(tls.poll_unpin(cx)) else {
@@ -510,6 +649,9 @@ where
                         name_server_name: Arc::clone(name_server_name),
                         name_server: *name_server,
                         query_path: Arc::clone(query_path),
+                        use_get: *use_get,
+                        #[cfg(feature = "dns-over-odoh")]
+                        odoh: odoh.take(),
                         handshake: Box::pin(handshake),
                     }
                 }
@@ -517,6 +659,9 @@ where
                     name_server_name,
                     name_server,
                     query_path,
+                    use_get,
+                    #[cfg(feature = "dns-over-odoh")]
+                    odoh,
                     handshake,
                 } => {
                     let (send_request, connection) = ready!(handshake
@@ -536,6 +681,9 @@ where
                         name_server: *name_server,
                         query_path: Arc::clone(query_path),
                         h2: send_request,
+                        use_get: *use_get,
+                        #[cfg(feature = "dns-over-odoh")]
+                        odoh: odoh.take(),
                         is_shutdown: false,
                     }))
                 }
@@ -55,12 +55,44 @@ where
     }
 
     match *request.method() {
-        Method::GET => Err(format!("GET unimplemented: {}", request.method()).into()),
+        Method::GET => Ok(BytesMut::from(
+            &crate::http::request::message_from_query(request.uri())?[..],
+        )),
         Method::POST => message_from_post(request.into_body(), content_length).await,
         _ => Err(format!("bad method: {}", request.method()).into()),
     }
 }
 
+/// Given an HTTP request with an ODoH message, for either a target or a proxy, returns its body
+///
+/// The message is not decrypted, a proxy forwards it as is.
+#[cfg(feature = "dns-over-odoh")]
+pub async fn odoh_message_from<R>(
+    this_server_name: Option<Arc<str>>,
+    this_server_endpoint: Arc<str>,
+    request: Request<R>,
+) -> Result<BytesMut, HttpsError>
+where
+    R: Stream<Item = Result<Bytes, h2::Error>> + 'static + Send + Debug + Unpin,
+{
+    debug!("Received ODoH request: {:#?}", request);
+
+    crate::http::request::verify_odoh(
+        Version::Http2,
+        this_server_name.as_deref(),
+        &this_server_endpoint,
+        &request,
+    )?;
+
+    let content_length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .map(|length| Ok::<_, HttpsError>(usize::from_str(length.to_str()?)?))
+        .transpose()?;
+
+    message_from_post(request.into_body(), content_length).await
+}
+
 /// Deserialize the message from a POST message
 pub(crate) async fn message_from_post<R>(
     mut request_stream: R,
@@ -124,6 +156,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_from_get() {
+        let message = Message::new();
+        let msg_bytes = message.to_vec().unwrap();
+        let request =
+            request::new_get(Version::Http2, "ns.example.com", "/dns-query", &msg_bytes).unwrap();
+        let request = request.map(|()| TestBytesStream(vec![]));
+
+        let from_get = message_from(
+            Some(Arc::from("ns.example.com")),
+            "/dns-query".into(),
+            request,
+        );
+        let bytes = match block_on(from_get) {
+            Ok(bytes) => bytes,
+            e => panic!("{:#?}", e),
+        };
+
+        let msg_from_get = Message::from_vec(bytes.as_ref()).expect("bytes failed");
+        assert_eq!(message, msg_from_get);
+    }
+
     #[test]
     fn test_from_post() {
         let message = Message::new();
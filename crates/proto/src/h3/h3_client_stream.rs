@@ -42,6 +42,7 @@ pub struct H3ClientStream {
     query_path: Arc<str>,
     send_request: SendRequest<OpenStreams, Bytes>,
     shutdown_tx: mpsc::Sender<()>,
+    use_get: bool,
     is_shutdown: bool,
 }
 
@@ -66,14 +67,19 @@ impl H3ClientStream {
         message: Bytes,
         name_server_name: Arc<str>,
         query_path: Arc<str>,
+        use_get: bool,
     ) -> Result<DnsResponse, ProtoError> {
         // build up the http request
-        let request = crate::http::request::new(
-            Version::Http3,
-            &name_server_name,
-            &query_path,
-            message.remaining(),
-        );
+        let request = if use_get {
+            crate::http::request::new_get(Version::Http3, &name_server_name, &query_path, &message)
+        } else {
+            crate::http::request::new(
+                Version::Http3,
+                &name_server_name,
+                &query_path,
+                message.remaining(),
+            )
+        };
 
         let request =
             request.map_err(|err| ProtoError::from(format!("bad http request: {err}")))?;
@@ -86,10 +92,13 @@ impl H3ClientStream {
             .await
             .map_err(|err| ProtoError::from(format!("h3 send_request error: {err}")))?;
 
-        stream
-            .send_data(message)
-            .await
-            .map_err(|e| ProtoError::from(format!("h3 send_data error: {e}")))?;
+        // a GET request has no body
+        if !use_get {
+            stream
+                .send_data(message)
+                .await
+                .map_err(|e| ProtoError::from(format!("h3 send_data error: {e}")))?;
+        }
 
         stream
             .finish()
@@ -254,6 +263,7 @@ impl DnsRequestSender for H3ClientStream {
             Bytes::from(bytes),
             Arc::clone(&self.name_server_name),
             Arc::clone(&self.query_path),
+            self.use_get,
         ))
         .into()
     }
@@ -292,6 +302,7 @@ pub struct H3ClientStreamBuilder {
     crypto_config: rustls::ClientConfig,
     transport_config: Arc<TransportConfig>,
     bind_addr: Option<SocketAddr>,
+    use_get: bool,
 }
 
 impl H3ClientStreamBuilder {
@@ -306,6 +317,12 @@ impl H3ClientStreamBuilder {
         self.bind_addr = Some(bind_addr);
     }
 
+    /// Sends queries as `GET` requests, which HTTP caches can store, rather than `POST` (the default).
+    pub fn use_get(&mut self, use_get: bool) -> &mut Self {
+        self.use_get = use_get;
+        self
+    }
+
     /// Creates a new H3Stream to the specified name_server
     ///
     /// # Arguments
@@ -422,6 +439,7 @@ impl H3ClientStreamBuilder {
             query_path: Arc::from(query_path),
             send_request,
             shutdown_tx,
+            use_get: self.use_get,
             is_shutdown: false,
         })
     }
@@ -433,6 +451,7 @@ impl Default for H3ClientStreamBuilder {
             crypto_config: client_config().unwrap(),
             transport_config: Arc::new(super::transport()),
             bind_addr: None,
+            use_get: false,
         }
     }
 }
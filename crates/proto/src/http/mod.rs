@@ -19,6 +19,9 @@ pub mod response;
 /// Represents a version of the HTTP spec.
 #[derive(Clone, Copy, Debug)]
 pub enum Version {
+    /// HTTP/1.1 for DoH, for clients and load balancers that can't speak HTTP/2.
+    #[cfg(feature = "dns-over-https-rustls")]
+    Http1,
     /// HTTP/2 for DoH.
     #[cfg(feature = "dns-over-https-rustls")]
     Http2,
@@ -30,10 +33,20 @@ pub enum Version {
 impl Version {
     fn to_http(self) -> http::Version {
         match self {
+            #[cfg(feature = "dns-over-https-rustls")]
+            Self::Http1 => http::Version::HTTP_11,
             #[cfg(feature = "dns-over-https-rustls")]
             Self::Http2 => http::Version::HTTP_2,
             #[cfg(feature = "dns-over-h3")]
             Self::Http3 => http::Version::HTTP_3,
         }
     }
+
+    fn is_http1(self) -> bool {
+        match self {
+            #[cfg(feature = "dns-over-https-rustls")]
+            Self::Http1 => true,
+            _ => false,
+        }
+    }
 }
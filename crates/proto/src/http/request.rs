@@ -9,15 +9,19 @@
 
 use std::str::FromStr;
 
-use http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE};
-use http::{header, uri, Request, Uri};
+use data_encoding::BASE64URL_NOPAD;
+use http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, HOST};
+use http::{header, uri, Method, Request, Uri};
 use tracing::debug;
 
 use crate::error::ProtoError;
 use crate::http::error::Result;
 use crate::http::Version;
 
-/// Create a new Request for an http dns-message request
+/// The query parameter carrying the DNS message in GET requests
+const DNS_QUERY_PARAM: &str = "dns";
+
+/// Create a new POST Request for an http dns-message request
 ///
 /// ```text
 /// https://tools.ietf.org/html/draft-ietf-doh-dns-over-https-10#section-5.1
@@ -27,42 +31,17 @@ use crate::http::Version;
 /// request (as described in Section 7), encoded with base64url
 /// [RFC4648].
 /// ```
-#[allow(clippy::field_reassign_with_default)] // https://github.com/rust-lang/rust-clippy/issues/6527
 pub fn new(
     version: Version,
     name_server_name: &str,
     query_path: &str,
     message_len: usize,
 ) -> Result<Request<()>> {
-    // TODO: this is basically the GET version, but it is more expensive than POST
-    //   perhaps add an option if people want better HTTP caching options.
-
-    // let query = BASE64URL_NOPAD.encode(&message);
-    // let url = format!("/dns-query?dns={}", query);
-    // let request = Request::get(&url)
-    //     .header(header::CONTENT_TYPE, ::MIME_DNS_BINARY)
-    //     .header(header::HOST, &self.name_server_name as &str)
-    //     .header("authority", &self.name_server_name as &str)
-    //     .header(header::USER_AGENT, USER_AGENT)
-    //     .body(());
-
-    let mut parts = uri::Parts::default();
-    parts.path_and_query = Some(
-        uri::PathAndQuery::try_from(query_path)
-            .map_err(|e| ProtoError::from(format!("invalid DoH path: {e}")))?,
-    );
-    parts.scheme = Some(uri::Scheme::HTTPS);
-    parts.authority = Some(
-        uri::Authority::from_str(name_server_name)
-            .map_err(|e| ProtoError::from(format!("invalid authority: {e}")))?,
-    );
-
-    let url =
-        Uri::from_parts(parts).map_err(|e| ProtoError::from(format!("uri parse error: {e}")))?;
+    let url = https_uri(name_server_name, query_path)?;
 
     // TODO: add user agent to TypedHeaders
     let request = Request::builder()
-        .method("POST")
+        .method(Method::POST)
         .uri(url)
         .version(version.to_http())
         .header(CONTENT_TYPE, crate::http::MIME_APPLICATION_DNS)
@@ -74,12 +53,171 @@ pub fn new(
     Ok(request)
 }
 
+/// Create a new POST Request for an ODoH query, which is sent to a proxy
+///
+/// `query_path` is the path of the proxy with the target parameters, see
+///  [`proxy_path`](crate::odoh::proxy_path).
+///
+/// ```text
+/// https://www.rfc-editor.org/rfc/rfc9230#section-4.1
+/// Clients MUST set the HTTP Content-Type header to
+/// "application/oblivious-dns-message" to indicate that this request is
+/// an Oblivious DoH query intended for proxying. Clients also SHOULD set
+/// this same value for the HTTP Accept header.
+/// ```
+#[cfg(feature = "dns-over-odoh")]
+pub fn new_odoh(
+    version: Version,
+    name_server_name: &str,
+    query_path: &str,
+    message_len: usize,
+) -> Result<Request<()>> {
+    let url = https_uri(name_server_name, query_path)?;
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(url)
+        .version(version.to_http())
+        .header(CONTENT_TYPE, crate::odoh::MIME_APPLICATION_ODOH)
+        .header(ACCEPT, crate::odoh::MIME_APPLICATION_ODOH)
+        .header(CONTENT_LENGTH, message_len)
+        .body(())
+        .map_err(|e| ProtoError::from(format!("http stream errored: {e}")))?;
+
+    Ok(request)
+}
+
+/// Create a new GET Request for the `ObliviousDoHConfigs` of a target
+///
+/// This is sent to the target itself, at its well-known
+///  [`ODOH_CONFIGS_PATH`](crate::odoh::ODOH_CONFIGS_PATH), never through a proxy.
+#[cfg(feature = "dns-over-odoh")]
+pub fn new_odoh_configs(
+    version: Version,
+    name_server_name: &str,
+    query_path: &str,
+) -> Result<Request<()>> {
+    let url = https_uri(name_server_name, query_path)?;
+
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(url)
+        .version(version.to_http())
+        .body(())
+        .map_err(|e| ProtoError::from(format!("http stream errored: {e}")))?;
+
+    Ok(request)
+}
+
+/// Create a new GET Request for an http dns-message request
+///
+/// The message is carried in the `dns` query parameter, encoded with base64url without padding.
+///   GET requests are more expensive to build than POST, but are friendlier to HTTP caches.
+///
+/// ```text
+/// https://tools.ietf.org/html/rfc8484#section-4.1
+/// When the HTTP method is GET, the single variable "dns" is defined as
+/// the content of the DNS request (as described in Section 6), encoded
+/// with base64url [RFC4648].
+/// ```
+pub fn new_get(
+    version: Version,
+    name_server_name: &str,
+    query_path: &str,
+    message: &[u8],
+) -> Result<Request<()>> {
+    let separator = if query_path.contains('?') { '&' } else { '?' };
+    let path_and_query = format!(
+        "{query_path}{separator}{DNS_QUERY_PARAM}={}",
+        BASE64URL_NOPAD.encode(message)
+    );
+    let url = https_uri(name_server_name, &path_and_query)?;
+
+    // TODO: add user agent to TypedHeaders
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(url)
+        .version(version.to_http())
+        .header(ACCEPT, crate::http::MIME_APPLICATION_DNS)
+        .body(())
+        .map_err(|e| ProtoError::from(format!("http stream errored: {e}")))?;
+
+    Ok(request)
+}
+
+/// Extracts the DNS message from the `dns` query parameter of a GET request
+pub fn message_from_query(uri: &Uri) -> Result<Vec<u8>> {
+    let dns = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(DNS_QUERY_PARAM)?.strip_prefix('='))
+        .ok_or_else(|| ProtoError::from("missing dns query parameter"))?;
+
+    // padding is not permitted, but is harmless to accept
+    BASE64URL_NOPAD
+        .decode(dns.trim_end_matches('=').as_bytes())
+        .map_err(|e| ProtoError::from(format!("invalid dns query parameter: {e}")).into())
+}
+
+fn https_uri(name_server_name: &str, path_and_query: &str) -> Result<Uri> {
+    let mut parts = uri::Parts::default();
+    parts.path_and_query = Some(
+        uri::PathAndQuery::try_from(path_and_query)
+            .map_err(|e| ProtoError::from(format!("invalid DoH path: {e}")))?,
+    );
+    parts.scheme = Some(uri::Scheme::HTTPS);
+    parts.authority = Some(
+        uri::Authority::from_str(name_server_name)
+            .map_err(|e| ProtoError::from(format!("invalid authority: {e}")))?,
+    );
+
+    Uri::from_parts(parts).map_err(|e| ProtoError::from(format!("uri parse error: {e}")).into())
+}
+
 /// Verifies the request is something we know what to deal with
 pub fn verify<T>(
     version: Version,
     name_server: Option<&str>,
     query_path: &str,
     request: &Request<T>,
+) -> Result<()> {
+    verify_media_type(
+        version,
+        name_server,
+        query_path,
+        crate::http::MIME_APPLICATION_DNS,
+        request,
+    )
+}
+
+/// Verifies the request is an ODoH query, for either a target or a proxy
+#[cfg(feature = "dns-over-odoh")]
+pub fn verify_odoh<T>(
+    version: Version,
+    name_server: Option<&str>,
+    query_path: &str,
+    request: &Request<T>,
+) -> Result<()> {
+    if request.method() != Method::POST {
+        return Err(format!("bad method: {}", request.method()).into());
+    }
+
+    verify_media_type(
+        version,
+        name_server,
+        query_path,
+        crate::odoh::MIME_APPLICATION_ODOH,
+        request,
+    )
+}
+
+fn verify_media_type<T>(
+    version: Version,
+    name_server: Option<&str>,
+    query_path: &str,
+    media_type: &str,
+    request: &Request<T>,
 ) -> Result<()> {
     // Verify all HTTP parameters
     let uri = request.uri();
@@ -89,27 +227,42 @@ pub fn verify<T>(
         return Err(format!("bad path: {}, expected: {}", uri.path(), query_path,).into());
     }
 
-    // we only accept HTTPS
-    if Some(&uri::Scheme::HTTPS) != uri.scheme() {
+    // we only accept HTTPS, HTTP/1.1 may have been terminated by a load balancer and carries
+    //   no scheme in the origin-form request target
+    if !version.is_http1() && Some(&uri::Scheme::HTTPS) != uri.scheme() {
         return Err("must be HTTPS scheme".into());
     }
 
     // the authority must match our nameserver name
     if let Some(name_server) = name_server {
-        if let Some(authority) = uri.authority() {
-            if authority.host() != name_server {
-                return Err("incorrect authority".into());
+        let authority = match uri.authority() {
+            Some(authority) => Some(authority.clone()),
+            // HTTP/1.1 carries the authority in the Host header
+            None if version.is_http1() => request
+                .headers()
+                .get(HOST)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| uri::Authority::from_str(h).ok()),
+            None => None,
+        };
+
+        match authority {
+            Some(authority) if authority.host() != name_server => {
+                return Err("incorrect authority".into())
             }
-        } else {
-            return Err("no authority in HTTPS request".into());
+            Some(_) => (),
+            None => return Err("no authority in HTTPS request".into()),
         }
     }
 
-    // TODO: switch to mime::APPLICATION_DNS when that stabilizes
-    match request.headers().get(CONTENT_TYPE).map(|v| v.to_str()) {
-        Some(Ok(ctype)) if ctype == crate::http::MIME_APPLICATION_DNS => {}
-        _ => return Err("unsupported content type".into()),
-    };
+    // only POST carries a message body, GET carries the message in the query
+    if request.method() == Method::POST {
+        // TODO: switch to mime::APPLICATION_DNS when that stabilizes
+        match request.headers().get(CONTENT_TYPE).map(|v| v.to_str()) {
+            Some(Ok(ctype)) if ctype == media_type => {}
+            _ => return Err("unsupported content type".into()),
+        };
+    }
 
     // TODO: switch to mime::APPLICATION_DNS when that stabilizes
     match request.headers().get(ACCEPT).map(|v| v.to_str()) {
@@ -118,7 +271,7 @@ pub fn verify<T>(
             for mime_and_quality in ctype.split(',') {
                 let mut parts = mime_and_quality.splitn(2, ';');
                 match parts.next() {
-                    Some(mime) if mime.trim() == crate::http::MIME_APPLICATION_DNS => {
+                    Some(mime) if mime.trim() == media_type => {
                         found = true;
                         break;
                     }
@@ -140,6 +293,8 @@ pub fn verify<T>(
 
     if request.version() != version.to_http() {
         let message = match version {
+            #[cfg(feature = "dns-over-https-rustls")]
+            Version::Http1 => "only HTTP/1.1 supported",
             #[cfg(feature = "dns-over-https-rustls")]
             Version::Http2 => "only HTTP/2 supported",
             #[cfg(feature = "dns-over-h3")]
@@ -191,4 +346,105 @@ mod tests {
         )
         .is_ok());
     }
+
+    #[test]
+    #[cfg(feature = "dns-over-https-rustls")]
+    fn test_new_get_verify_h2() {
+        let message = [0, 0, 1, 0, 0, 1, 0xff, 0xfe];
+        let request = new_get(Version::Http2, "ns.example.com", "/dns-query", &message)
+            .expect("error converting to http");
+        assert_eq!(request.method(), Method::GET);
+        assert!(request.headers().get(CONTENT_TYPE).is_none());
+        assert!(verify(
+            Version::Http2,
+            Some("ns.example.com"),
+            "/dns-query",
+            &request
+        )
+        .is_ok());
+
+        assert_eq!(request.uri().query(), Some("dns=AAABAAAB__4"));
+        assert_eq!(message_from_query(request.uri()).unwrap(), message);
+    }
+
+    #[test]
+    #[cfg(feature = "dns-over-odoh")]
+    fn test_new_verify_odoh() {
+        let path = crate::odoh::proxy_path("/dns-query", "target.example.com", "/dns-query");
+        let request = new_odoh(Version::Http2, "ns.example.com", &path, 512)
+            .expect("error converting to http");
+        assert!(verify_odoh(
+            Version::Http2,
+            Some("ns.example.com"),
+            "/dns-query",
+            &request
+        )
+        .is_ok());
+        assert!(verify(
+            Version::Http2,
+            Some("ns.example.com"),
+            "/dns-query",
+            &request
+        )
+        .is_err());
+
+        let request = new(Version::Http2, "ns.example.com", "/dns-query", 512)
+            .expect("error converting to http");
+        assert!(verify_odoh(
+            Version::Http2,
+            Some("ns.example.com"),
+            "/dns-query",
+            &request
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_message_from_query() {
+        let uri = Uri::from_static("/dns-query?ct=x&dns=AAABAAAB__4=");
+        assert_eq!(
+            message_from_query(&uri).unwrap(),
+            [0, 0, 1, 0, 0, 1, 0xff, 0xfe]
+        );
+
+        let uri = Uri::from_static("/dns-query?dnsx=AAAB");
+        assert!(message_from_query(&uri).is_err());
+        let uri = Uri::from_static("/dns-query");
+        assert!(message_from_query(&uri).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "dns-over-https-rustls")]
+    fn test_verify_http1_host() {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/dns-query?dns=AAABAAAB__4")
+            .version(http::Version::HTTP_11)
+            .header(HOST, "ns.example.com:8080")
+            .header(ACCEPT, crate::http::MIME_APPLICATION_DNS)
+            .body(())
+            .unwrap();
+
+        assert!(verify(
+            Version::Http1,
+            Some("ns.example.com"),
+            "/dns-query",
+            &request
+        )
+        .is_ok());
+        assert!(verify(
+            Version::Http1,
+            Some("other.example.com"),
+            "/dns-query",
+            &request
+        )
+        .is_err());
+        assert!(verify(
+            Version::Http2,
+            Some("ns.example.com"),
+            "/dns-query",
+            &request
+        )
+        .is_err());
+    }
 }
@@ -7,12 +7,13 @@
 
 //! HTTP request creation and validation
 
-use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use http::header::{CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE};
 use http::{Response, StatusCode};
 
 use crate::error::ProtoError;
 use crate::http::error::Result;
 use crate::http::Version;
+use crate::op::{Message, ResponseCode};
 
 /// Create a new Response for an http dns-message request
 ///
@@ -39,12 +40,119 @@ use crate::http::Version;
 /// cannot generate a representation suitable for the client (HTTP status
 /// code 406, [RFC7231] Section 6.5.6), and so on.
 /// ```
-pub fn new(version: Version, message_len: usize) -> Result<Response<()>> {
+///
+/// `max_age` is the freshness lifetime of the response, in seconds, see [`max_age`].
+pub fn new(version: Version, message_len: usize, max_age: u32) -> Result<Response<()>> {
     Response::builder()
         .status(StatusCode::OK)
         .version(version.to_http())
         .header(CONTENT_TYPE, crate::http::MIME_APPLICATION_DNS)
         .header(CONTENT_LENGTH, message_len)
+        .header(CACHE_CONTROL, format!("max-age={max_age}"))
+        .body(())
+        .map_err(|e| ProtoError::from(format!("invalid response: {e}")).into())
+}
+
+/// Create a new Response for an ODoH message, or for the `ObliviousDoHConfigs` of a target
+///
+/// Encrypted responses are unique to their query, so they are never to be cached.
+#[cfg(feature = "dns-over-odoh")]
+pub fn new_odoh(version: Version, content_type: &str, message_len: usize) -> Result<Response<()>> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .version(version.to_http())
+        .header(CONTENT_TYPE, content_type)
+        .header(CONTENT_LENGTH, message_len)
+        .header(CACHE_CONTROL, "no-cache, no-store")
         .body(())
         .map_err(|e| ProtoError::from(format!("invalid response: {e}")).into())
 }
+
+/// Returns the HTTP freshness lifetime, in seconds, for a DNS response
+///
+/// This is the smallest TTL in the answer section. Negative responses use the negative caching TTL
+///   of the SOA in the authority section, and any other response is not to be cached.
+///
+/// ```text
+/// https://tools.ietf.org/html/rfc8484#section-5.1
+/// The assigned freshness lifetime of a DoH HTTP response MUST be less
+/// than or equal to the smallest TTL in the Answer section of the DNS
+/// response.  A freshness lifetime equal to the smallest TTL in the
+/// Answer section is RECOMMENDED.  For example, if a HTTP response
+/// carries three RRsets with TTLs of 30, 600, and 300, the HTTP
+/// freshness lifetime should be 30 seconds (which could be specified as
+/// "Cache-Control: max-age=30").
+///
+/// If the DNS response has no records in the Answer section, and the DNS
+/// response has an SOA record in the Authority section, the response
+/// freshness lifetime MUST NOT be greater than the MINIMUM field from
+/// that SOA record (see [RFC2308]).
+/// ```
+pub fn max_age(message: &Message) -> u32 {
+    if !matches!(
+        message.response_code(),
+        ResponseCode::NoError | ResponseCode::NXDomain
+    ) {
+        return 0;
+    }
+
+    if let Some(min_ttl) = message.answers().iter().map(|r| r.ttl()).min() {
+        return min_ttl;
+    }
+
+    message
+        .name_servers()
+        .iter()
+        .find_map(|record| record.data().as_soa().map(|soa| (record.ttl(), soa)))
+        .map(|(ttl, soa)| ttl.min(soa.minimum()))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::rr::rdata::{A, SOA};
+    use crate::rr::{Name, RData, Record};
+
+    #[test]
+    fn test_max_age() {
+        let name = Name::from_str("www.example.com.").unwrap();
+        let mut message = Message::new();
+        assert_eq!(max_age(&message), 0);
+
+        message.add_answer(Record::from_rdata(
+            name.clone(),
+            300,
+            RData::A(A::new(127, 0, 0, 1)),
+        ));
+        message.add_answer(Record::from_rdata(
+            name.clone(),
+            30,
+            RData::A(A::new(127, 0, 0, 2)),
+        ));
+        assert_eq!(max_age(&message), 30);
+
+        message.set_response_code(ResponseCode::ServFail);
+        assert_eq!(max_age(&message), 0);
+    }
+
+    #[test]
+    fn test_max_age_negative() {
+        let origin = Name::from_str("example.com.").unwrap();
+        let soa = SOA::new(origin.clone(), origin.clone(), 1, 3600, 600, 86400, 60);
+
+        let mut message = Message::new();
+        message.set_response_code(ResponseCode::NXDomain);
+        message.add_name_server(Record::from_rdata(origin.clone(), 3600, RData::SOA(soa)));
+        assert_eq!(max_age(&message), 60);
+    }
+
+    #[test]
+    #[cfg(feature = "dns-over-https-rustls")]
+    fn test_cache_control() {
+        let response = new(Version::Http2, 512, 30).unwrap();
+        assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "max-age=30");
+    }
+}
@@ -54,6 +54,8 @@ pub mod http;
 pub mod multicast;
 #[cfg(feature = "dns-over-native-tls")]
 pub mod native_tls;
+#[cfg(feature = "dns-over-odoh")]
+pub mod odoh;
 pub mod op;
 #[cfg(feature = "dns-over-openssl")]
 pub mod openssl;
@@ -0,0 +1,327 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! The subset of Hybrid Public Key Encryption, RFC 9180, needed by ODoH
+//!
+//! Only the base mode with the mandatory ODoH suite is supported: DHKEM(X25519, HKDF-SHA256),
+//!  HKDF-SHA256 and AES-128-GCM. Each context seals or opens a single message, as ODoH never
+//!  sends more than one message per encapsulation.
+
+use rand::rngs::OsRng;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_128_GCM};
+use ring::hkdf::{self, Prk, Salt, HKDF_SHA256};
+use ring::hmac;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use crate::error::ProtoError;
+
+/// DHKEM(X25519, HKDF-SHA256)
+pub(crate) const KEM_X25519_HKDF_SHA256: u16 = 0x0020;
+/// HKDF-SHA256
+pub(crate) const KDF_HKDF_SHA256: u16 = 0x0001;
+/// AES-128-GCM
+pub(crate) const AEAD_AES_128_GCM: u16 = 0x0001;
+
+/// Length of an encapsulated key, `Nenc`
+pub(crate) const N_ENC: usize = 32;
+/// Length of an AEAD key, `Nk`
+pub(crate) const N_K: usize = 16;
+/// Length of an AEAD nonce, `Nn`
+pub(crate) const N_N: usize = 12;
+/// Length of the KDF output, `Nh`
+pub(crate) const N_H: usize = 32;
+
+const VERSION_LABEL: &[u8] = b"HPKE-v1";
+const MODE_BASE: u8 = 0x00;
+
+/// An HPKE context for a single message, either for the sender or the recipient
+pub(crate) struct Context {
+    key: Zeroizing<[u8; N_K]>,
+    base_nonce: Zeroizing<[u8; N_N]>,
+    exporter_secret: Prk,
+}
+
+impl Context {
+    /// Encrypts the only message of this context
+    pub(crate) fn seal(&self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, ProtoError> {
+        seal(&*self.key, &*self.base_nonce, aad, plaintext)
+    }
+
+    /// Decrypts the only message of this context
+    pub(crate) fn open(&self, aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, ProtoError> {
+        open(&*self.key, &*self.base_nonce, aad, ciphertext)
+    }
+
+    /// Derives a secret of `len` bytes from the context, `Export` in the RFC
+    pub(crate) fn export(&self, exporter_context: &[u8], len: usize) -> Zeroizing<Vec<u8>> {
+        let mut secret = Zeroizing::new(vec![0; len]);
+        labeled_expand(
+            &hpke_suite_id(),
+            &self.exporter_secret,
+            b"sec",
+            exporter_context,
+            &mut secret,
+        );
+        secret
+    }
+}
+
+/// Encapsulates a fresh secret to `public_key`, returning the encapsulated key and the sender context
+pub(crate) fn setup_base_sender(
+    public_key: &[u8; N_ENC],
+    info: &[u8],
+) -> Result<([u8; N_ENC], Context), ProtoError> {
+    setup_base_sender_with(&StaticSecret::random_from_rng(OsRng), public_key, info)
+}
+
+fn setup_base_sender_with(
+    ephemeral: &StaticSecret,
+    public_key: &[u8; N_ENC],
+    info: &[u8],
+) -> Result<([u8; N_ENC], Context), ProtoError> {
+    let public_key = PublicKey::from(*public_key);
+    let dh = ephemeral.diffie_hellman(&public_key);
+    if !dh.was_contributory() {
+        return Err("HPKE: invalid public key".into());
+    }
+
+    let enc = PublicKey::from(ephemeral).to_bytes();
+    let shared_secret = extract_and_expand(dh.as_bytes(), &enc, public_key.as_bytes());
+    Ok((enc, key_schedule(&*shared_secret, info)))
+}
+
+/// Decapsulates `enc` with the private key, returning the recipient context
+pub(crate) fn setup_base_recipient(
+    enc: &[u8],
+    secret: &StaticSecret,
+    info: &[u8],
+) -> Result<Context, ProtoError> {
+    let enc = <[u8; N_ENC]>::try_from(enc).map_err(|_| "HPKE: bad encapsulated key length")?;
+    let dh = secret.diffie_hellman(&PublicKey::from(enc));
+    if !dh.was_contributory() {
+        return Err("HPKE: invalid encapsulated key".into());
+    }
+
+    let public_key = PublicKey::from(secret);
+    let shared_secret = extract_and_expand(dh.as_bytes(), &enc, public_key.as_bytes());
+    Ok(key_schedule(&*shared_secret, info))
+}
+
+/// `ExtractAndExpand` of DHKEM, the shared secret of the KEM
+fn extract_and_expand(dh: &[u8], enc: &[u8], public_key: &[u8]) -> Zeroizing<[u8; N_H]> {
+    let mut suite_id = b"KEM".to_vec();
+    suite_id.extend_from_slice(&KEM_X25519_HKDF_SHA256.to_be_bytes());
+
+    let kem_context = [enc, public_key].concat();
+    let eae_prk = labeled_extract(&suite_id, &[], b"eae_prk", dh);
+    let mut shared_secret = Zeroizing::new([0; N_H]);
+    labeled_expand(
+        &suite_id,
+        &eae_prk,
+        b"shared_secret",
+        &kem_context,
+        &mut *shared_secret,
+    );
+    shared_secret
+}
+
+/// `KeySchedule` for the base mode, no PSK
+fn key_schedule(shared_secret: &[u8], info: &[u8]) -> Context {
+    let suite_id = hpke_suite_id();
+
+    let psk_id_hash = labeled_extract_bytes(&suite_id, &[], b"psk_id_hash", &[]);
+    let info_hash = labeled_extract_bytes(&suite_id, &[], b"info_hash", info);
+    let key_schedule_context = [&[MODE_BASE][..], &psk_id_hash, &info_hash].concat();
+
+    let secret = labeled_extract(&suite_id, shared_secret, b"secret", &[]);
+
+    let mut key = Zeroizing::new([0; N_K]);
+    labeled_expand(&suite_id, &secret, b"key", &key_schedule_context, &mut *key);
+    let mut base_nonce = Zeroizing::new([0; N_N]);
+    labeled_expand(
+        &suite_id,
+        &secret,
+        b"base_nonce",
+        &key_schedule_context,
+        &mut *base_nonce,
+    );
+    let exporter_secret = labeled_expand_prk(&suite_id, &secret, b"exp", &key_schedule_context);
+
+    Context {
+        key,
+        base_nonce,
+        exporter_secret,
+    }
+}
+
+fn hpke_suite_id() -> Vec<u8> {
+    let mut suite_id = b"HPKE".to_vec();
+    suite_id.extend_from_slice(&KEM_X25519_HKDF_SHA256.to_be_bytes());
+    suite_id.extend_from_slice(&KDF_HKDF_SHA256.to_be_bytes());
+    suite_id.extend_from_slice(&AEAD_AES_128_GCM.to_be_bytes());
+    suite_id
+}
+
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> Prk {
+    let labeled_ikm = Zeroizing::new([VERSION_LABEL, suite_id, label, ikm].concat());
+    extract(salt, &labeled_ikm)
+}
+
+/// `LabeledExtract` of public inputs, as bytes rather than a key for `Expand`
+///
+/// The key schedule uses the output of extract as a hash of the PSK ID and the info, but ring
+/// keeps the keys it extracts opaque, so this computes the HMAC of `Extract` itself.
+fn labeled_extract_bytes(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, salt);
+    hmac::sign(&key, &[VERSION_LABEL, suite_id, label, ikm].concat())
+        .as_ref()
+        .to_vec()
+}
+
+fn labeled_expand(suite_id: &[u8], prk: &Prk, label: &[u8], info: &[u8], out: &mut [u8]) {
+    let len = u16::try_from(out.len())
+        .expect("HPKE output lengths are small")
+        .to_be_bytes();
+    expand(prk, &[&len, VERSION_LABEL, suite_id, label, info], out);
+}
+
+/// `LabeledExpand` of a secret which is itself used as a key for `Expand`
+fn labeled_expand_prk(suite_id: &[u8], prk: &Prk, label: &[u8], info: &[u8]) -> Prk {
+    let len = (N_H as u16).to_be_bytes();
+    prk.expand(&[&len, VERSION_LABEL, suite_id, label, info], HKDF_SHA256)
+        .map(Prk::from)
+        .expect("HPKE output lengths are small")
+}
+
+/// HKDF-SHA256 `Extract`
+pub(crate) fn extract(salt: &[u8], ikm: &[u8]) -> Prk {
+    Salt::new(HKDF_SHA256, salt).extract(ikm)
+}
+
+/// HKDF-SHA256 `Expand`, filling `out`
+pub(crate) fn expand(prk: &Prk, info: &[&[u8]], out: &mut [u8]) {
+    prk.expand(info, Len(out.len()))
+        .and_then(|okm| okm.fill(out))
+        .expect("HKDF output lengths are small");
+}
+
+/// The length of the output of `Expand`, which ring takes as a key type
+struct Len(usize);
+
+impl hkdf::KeyType for Len {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+/// AES-128-GCM encryption
+pub(crate) fn seal(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, ProtoError> {
+    let key = aead_key(key)?;
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "HPKE: bad nonce length")?;
+
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| "HPKE: encryption failed")?;
+    Ok(in_out)
+}
+
+/// AES-128-GCM decryption
+pub(crate) fn open(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, ProtoError> {
+    let key = aead_key(key)?;
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "HPKE: bad nonce length")?;
+
+    let mut in_out = ciphertext.to_vec();
+    let len = key
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| "HPKE: decryption failed")?
+        .len();
+    in_out.truncate(len);
+    Ok(in_out)
+}
+
+fn aead_key(key: &[u8]) -> Result<LessSafeKey, ProtoError> {
+    UnboundKey::new(&AES_128_GCM, key)
+        .map(LessSafeKey::new)
+        .map_err(|_| "HPKE: bad key length".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use data_encoding::HEXLOWER;
+
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        HEXLOWER.decode(s.as_bytes()).unwrap()
+    }
+
+    fn secret(s: &str) -> StaticSecret {
+        StaticSecret::from(<[u8; 32]>::try_from(hex(s)).unwrap())
+    }
+
+    /// RFC 9180, A.1.1, DHKEM(X25519, HKDF-SHA256), HKDF-SHA256, AES-128-GCM, base mode
+    #[test]
+    fn test_rfc9180_vector() {
+        let info = hex("4f6465206f6e2061204772656369616e2055726e");
+        let ephemeral = secret("52c4a758a802cd8b936eceea314432798d5baf2d7e9235dc084ab1b9cfa2f736");
+        let recipient = secret("4612c550263fc8ad58375df3f557aac531d26850903e55a9f23f21d8534e8ac8");
+        let public_key = PublicKey::from(&recipient).to_bytes();
+
+        let (enc, sender) = setup_base_sender_with(&ephemeral, &public_key, &info).unwrap();
+        assert_eq!(
+            enc.to_vec(),
+            hex("37fda3567bdbd628e88668c3c8d7e97d1d1253b6d4ea6d44c150f741f1bf4431")
+        );
+        assert_eq!(sender.key.to_vec(), hex("4531685d41d65f03dc48f6b8302c05b0"));
+        assert_eq!(sender.base_nonce.to_vec(), hex("56d890e5accaaf011cff4b7d"));
+
+        let ciphertext = sender
+            .seal(b"Count-0", b"Beauty is truth, truth beauty")
+            .unwrap();
+        assert_eq!(
+            ciphertext,
+            hex("f938558b5d72f1a23810b4be2ab4f84331acc02fc97babc53a52ae8218a355a96d8770ac83d07bea87e13c512a")
+        );
+
+        let recipient = setup_base_recipient(&enc, &recipient, &info).unwrap();
+        assert_eq!(
+            recipient.open(b"Count-0", &ciphertext).unwrap(),
+            b"Beauty is truth, truth beauty"
+        );
+        assert!(recipient.open(b"Count-1", &ciphertext).is_err());
+
+        // A.1.1.1, the exported values
+        for (context, exported) in [
+            (
+                "",
+                "3853fe2b4035195a573ffc53856e77058e15d9ea064de3e59f4961d0095250ee",
+            ),
+            (
+                "00",
+                "2e8f0b54673c7029649d4eb9d5e33bf1872cf76d623ff164ac185da9e88c21a5",
+            ),
+            (
+                "54657374436f6e74657874",
+                "e9e43065102c3836401bed8c3c3c75ae46be1639869391d62c61f1ec7af54931",
+            ),
+        ] {
+            assert_eq!(*sender.export(&hex(context), 32), hex(exported));
+            assert_eq!(*recipient.export(&hex(context), 32), hex(exported));
+        }
+    }
+}
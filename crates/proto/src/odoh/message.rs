@@ -0,0 +1,463 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! ODoH configurations and the encryption of queries and responses
+
+use std::fmt;
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use super::hpke::{self, AEAD_AES_128_GCM, KDF_HKDF_SHA256, KEM_X25519_HKDF_SHA256, N_ENC};
+use crate::error::ProtoError;
+use crate::serialize::binary::BinDecoder;
+
+/// The only version of `ObliviousDoHConfig` defined by RFC 9230
+const ODOH_VERSION: u16 = 0x0001;
+
+const MESSAGE_TYPE_QUERY: u8 = 0x01;
+const MESSAGE_TYPE_RESPONSE: u8 = 0x02;
+
+/// Length of the response nonce, `max(Nn, Nk)`
+const RESPONSE_NONCE_LEN: usize = 16;
+
+/// Queries are padded to a multiple of this many bytes
+const QUERY_PADDING_BLOCK: usize = 128;
+/// Responses are padded to a multiple of this many bytes, as recommended for DNS over TLS by RFC 8467
+const RESPONSE_PADDING_BLOCK: usize = 468;
+
+/// The public key configuration of an ODoH target, `ObliviousDoHConfigContents` in RFC 9230
+///
+/// Clients encrypt their queries to this key, targets publish it at
+///  [`ODOH_CONFIGS_PATH`](super::ODOH_CONFIGS_PATH).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OdohConfig {
+    public_key: [u8; N_ENC],
+}
+
+impl OdohConfig {
+    /// Creates a configuration for the X25519 public key of a target
+    pub fn new(public_key: [u8; 32]) -> Self {
+        Self { public_key }
+    }
+
+    /// The X25519 public key of the target
+    pub fn public_key(&self) -> &[u8; 32] {
+        &self.public_key
+    }
+
+    /// Returns the first supported configuration of an encoded `ObliviousDoHConfigs`
+    ///
+    /// Configurations of unknown versions or with other HPKE suites than DHKEM(X25519, HKDF-SHA256),
+    ///  HKDF-SHA256 and AES-128-GCM are skipped.
+    pub fn from_configs(configs: &[u8]) -> Result<Self, ProtoError> {
+        let mut decoder = BinDecoder::new(configs);
+        let configs = read_opaque(&mut decoder)?;
+        if !decoder.is_empty() {
+            return Err("ODoH: trailing bytes after configs".into());
+        }
+
+        let mut decoder = BinDecoder::new(configs);
+        while !decoder.is_empty() {
+            let version = decoder.read_u16()?.unverified(/*any version is checked below*/);
+            let contents = read_opaque(&mut decoder)?;
+            if version != ODOH_VERSION {
+                continue;
+            }
+
+            if let Some(config) = Self::from_contents(contents)? {
+                return Ok(config);
+            }
+        }
+
+        Err("ODoH: no supported config".into())
+    }
+
+    fn from_contents(contents: &[u8]) -> Result<Option<Self>, ProtoError> {
+        let mut decoder = BinDecoder::new(contents);
+        let kem_id = decoder.read_u16()?.unverified(/*suite is checked below*/);
+        let kdf_id = decoder.read_u16()?.unverified(/*suite is checked below*/);
+        let aead_id = decoder.read_u16()?.unverified(/*suite is checked below*/);
+        let public_key = read_opaque(&mut decoder)?;
+        if !decoder.is_empty() {
+            return Err("ODoH: trailing bytes after config contents".into());
+        }
+
+        if (kem_id, kdf_id, aead_id) != (KEM_X25519_HKDF_SHA256, KDF_HKDF_SHA256, AEAD_AES_128_GCM)
+        {
+            return Ok(None);
+        }
+
+        let public_key =
+            <[u8; N_ENC]>::try_from(public_key).map_err(|_| "ODoH: bad public key length")?;
+        Ok(Some(Self { public_key }))
+    }
+
+    /// Encodes this configuration as an `ObliviousDoHConfigs` with a single entry
+    pub fn to_configs(&self) -> Vec<u8> {
+        let contents = self.contents();
+
+        let mut config = Vec::with_capacity(contents.len() + 4);
+        config.extend_from_slice(&ODOH_VERSION.to_be_bytes());
+        push_opaque(&mut config, &contents);
+
+        let mut configs = Vec::with_capacity(config.len() + 2);
+        push_opaque(&mut configs, &config);
+        configs
+    }
+
+    /// The encoded `ObliviousDoHConfigContents`
+    fn contents(&self) -> Vec<u8> {
+        let mut contents = Vec::with_capacity(N_ENC + 8);
+        contents.extend_from_slice(&KEM_X25519_HKDF_SHA256.to_be_bytes());
+        contents.extend_from_slice(&KDF_HKDF_SHA256.to_be_bytes());
+        contents.extend_from_slice(&AEAD_AES_128_GCM.to_be_bytes());
+        push_opaque(&mut contents, &self.public_key);
+        contents
+    }
+
+    /// The identifier of this configuration, which queries carry so the target can find its key
+    pub fn key_id(&self) -> Vec<u8> {
+        let mut key_id = vec![0; hpke::N_H];
+        hpke::expand(
+            &hpke::extract(&[], &self.contents()),
+            &[b"odoh key id"],
+            &mut key_id,
+        );
+        key_id
+    }
+
+    /// Encrypts a DNS query for the target of this configuration
+    ///
+    /// Returns the encoded `ObliviousDoHMessage` and the key to decrypt the response with.
+    pub fn encrypt_query(&self, query: &[u8]) -> Result<(Vec<u8>, OdohResponseKey), ProtoError> {
+        let key_id = self.key_id();
+        let plaintext = encode_plaintext(query, QUERY_PADDING_BLOCK)?;
+
+        let (enc, context) = hpke::setup_base_sender(&self.public_key, b"odoh query")?;
+        let aad = message_aad(MESSAGE_TYPE_QUERY, &key_id);
+        let encrypted = [&enc[..], &context.seal(&aad, &plaintext)?].concat();
+
+        let response_key = OdohResponseKey {
+            secret: context.export(b"odoh response", hpke::N_K),
+            query_plaintext: plaintext,
+        };
+
+        Ok((
+            encode_message(MESSAGE_TYPE_QUERY, &key_id, &encrypted)?,
+            response_key,
+        ))
+    }
+}
+
+/// Serialized as the base64 of its `ObliviousDoHConfigs`, as published by the target
+#[cfg(feature = "serde")]
+impl Serialize for OdohConfig {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&data_encoding::BASE64.encode(&self.to_configs()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for OdohConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let configs = data_encoding::BASE64
+            .decode(s.as_bytes())
+            .map_err(de::Error::custom)?;
+        Self::from_configs(&configs).map_err(de::Error::custom)
+    }
+}
+
+/// The private key of an ODoH target
+#[derive(Clone)]
+pub struct OdohKeyPair {
+    secret: StaticSecret,
+    config: OdohConfig,
+}
+
+impl OdohKeyPair {
+    /// Generates a new random key pair
+    pub fn generate() -> Self {
+        Self::from_secret(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// Creates the key pair for an X25519 private key
+    pub fn from_private_key(private_key: [u8; 32]) -> Self {
+        Self::from_secret(StaticSecret::from(private_key))
+    }
+
+    fn from_secret(secret: StaticSecret) -> Self {
+        let config = OdohConfig::new(PublicKey::from(&secret).to_bytes());
+        Self { secret, config }
+    }
+
+    /// The configuration clients need to encrypt queries to this key pair
+    pub fn config(&self) -> &OdohConfig {
+        &self.config
+    }
+
+    /// Decrypts an encoded `ObliviousDoHMessage` query
+    ///
+    /// Returns the DNS query and the key to encrypt the response with.
+    pub fn decrypt_query(&self, message: &[u8]) -> Result<(Vec<u8>, OdohResponseKey), ProtoError> {
+        let (key_id, encrypted) = decode_message(MESSAGE_TYPE_QUERY, message)?;
+        if key_id != self.config.key_id() {
+            return Err("ODoH: unknown key id".into());
+        }
+        if encrypted.len() < N_ENC {
+            return Err("ODoH: query too short".into());
+        }
+
+        let (enc, ciphertext) = encrypted.split_at(N_ENC);
+        let context = hpke::setup_base_recipient(enc, &self.secret, b"odoh query")?;
+        let plaintext = context.open(&message_aad(MESSAGE_TYPE_QUERY, key_id), ciphertext)?;
+        let query = decode_plaintext(&plaintext)?.to_vec();
+
+        let response_key = OdohResponseKey {
+            secret: context.export(b"odoh response", hpke::N_K),
+            query_plaintext: plaintext,
+        };
+
+        Ok((query, response_key))
+    }
+}
+
+impl fmt::Debug for OdohKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OdohKeyPair")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The key a response to an ODoH query is encrypted with, derived from the query
+pub struct OdohResponseKey {
+    secret: Zeroizing<Vec<u8>>,
+    query_plaintext: Vec<u8>,
+}
+
+impl OdohResponseKey {
+    /// Encrypts a DNS response, returning the encoded `ObliviousDoHMessage`
+    pub fn encrypt_response(&self, response: &[u8]) -> Result<Vec<u8>, ProtoError> {
+        let mut nonce = [0; RESPONSE_NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+
+        let plaintext = encode_plaintext(response, RESPONSE_PADDING_BLOCK)?;
+        let (key, aead_nonce) = self.derive(&nonce);
+        let aad = message_aad(MESSAGE_TYPE_RESPONSE, &nonce);
+        let ciphertext = hpke::seal(&*key, &aead_nonce, &aad, &plaintext)?;
+
+        encode_message(MESSAGE_TYPE_RESPONSE, &nonce, &ciphertext)
+    }
+
+    /// Decrypts an encoded `ObliviousDoHMessage` response, returning the DNS response
+    pub fn decrypt_response(&self, message: &[u8]) -> Result<Vec<u8>, ProtoError> {
+        let (nonce, ciphertext) = decode_message(MESSAGE_TYPE_RESPONSE, message)?;
+        if nonce.len() != RESPONSE_NONCE_LEN {
+            return Err("ODoH: bad response nonce length".into());
+        }
+
+        let (key, aead_nonce) = self.derive(nonce);
+        let aad = message_aad(MESSAGE_TYPE_RESPONSE, nonce);
+        let plaintext = hpke::open(&*key, &aead_nonce, &aad, ciphertext)?;
+
+        Ok(decode_plaintext(&plaintext)?.to_vec())
+    }
+
+    /// Derives the AEAD key and nonce of the response
+    fn derive(&self, response_nonce: &[u8]) -> (Zeroizing<[u8; hpke::N_K]>, [u8; hpke::N_N]) {
+        let mut salt = self.query_plaintext.clone();
+        push_opaque(&mut salt, response_nonce);
+
+        let prk = hpke::extract(&salt, &self.secret);
+        let mut key = Zeroizing::new([0; hpke::N_K]);
+        hpke::expand(&prk, &[b"odoh key"], &mut *key);
+        let mut nonce = [0; hpke::N_N];
+        hpke::expand(&prk, &[b"odoh nonce"], &mut nonce);
+        (key, nonce)
+    }
+}
+
+impl fmt::Debug for OdohResponseKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OdohResponseKey").finish_non_exhaustive()
+    }
+}
+
+/// Encodes an `ObliviousDoHMessagePlaintext`, padded with zeros to a multiple of `block` bytes
+fn encode_plaintext(message: &[u8], block: usize) -> Result<Vec<u8>, ProtoError> {
+    let unpadded = message.len() + 4;
+    let padding = (block - unpadded % block) % block;
+
+    let mut plaintext = Vec::with_capacity(unpadded + padding);
+    push_opaque(&mut plaintext, message);
+    plaintext.extend_from_slice(
+        &u16::try_from(padding)
+            .expect("padding is below a block")
+            .to_be_bytes(),
+    );
+    plaintext.resize(unpadded + padding, 0);
+
+    if plaintext.len() > usize::from(u16::MAX) {
+        return Err("ODoH: message too long".into());
+    }
+    Ok(plaintext)
+}
+
+/// Decodes an `ObliviousDoHMessagePlaintext`, returning the DNS message
+fn decode_plaintext(plaintext: &[u8]) -> Result<&[u8], ProtoError> {
+    let mut decoder = BinDecoder::new(plaintext);
+    let message = read_opaque(&mut decoder)?;
+    let padding = read_opaque(&mut decoder)?;
+
+    if !decoder.is_empty() {
+        return Err("ODoH: trailing bytes after plaintext".into());
+    }
+    if padding.iter().any(|b| *b != 0) {
+        return Err("ODoH: non-zero padding".into());
+    }
+    Ok(message)
+}
+
+/// The additional authenticated data of a message, its type and key id or response nonce
+fn message_aad(message_type: u8, key_id: &[u8]) -> Vec<u8> {
+    let mut aad = vec![message_type];
+    push_opaque(&mut aad, key_id);
+    aad
+}
+
+/// Encodes an `ObliviousDoHMessage`
+fn encode_message(
+    message_type: u8,
+    key_id: &[u8],
+    encrypted: &[u8],
+) -> Result<Vec<u8>, ProtoError> {
+    if encrypted.len() > usize::from(u16::MAX) {
+        return Err("ODoH: message too long".into());
+    }
+
+    let mut message = Vec::with_capacity(key_id.len() + encrypted.len() + 5);
+    message.push(message_type);
+    push_opaque(&mut message, key_id);
+    push_opaque(&mut message, encrypted);
+    Ok(message)
+}
+
+/// Decodes an `ObliviousDoHMessage` of the expected type, returning the key id and encrypted message
+fn decode_message(message_type: u8, message: &[u8]) -> Result<(&[u8], &[u8]), ProtoError> {
+    let mut decoder = BinDecoder::new(message);
+    let found = decoder.read_u8()?.unverified(/*checked below*/);
+    if found != message_type {
+        return Err(format!("ODoH: unexpected message type: {found}").into());
+    }
+
+    let key_id = read_opaque(&mut decoder)?;
+    let encrypted = read_opaque(&mut decoder)?;
+    if !decoder.is_empty() {
+        return Err("ODoH: trailing bytes after message".into());
+    }
+    Ok((key_id, encrypted))
+}
+
+/// Reads a field prefixed with its length as a u16
+fn read_opaque<'a>(decoder: &mut BinDecoder<'a>) -> Result<&'a [u8], ProtoError> {
+    let len = decoder.read_u16()?.unverified(/*bounded by the remaining bytes*/);
+    Ok(decoder
+        .read_slice(usize::from(len))?
+        .unverified(/*opaque bytes*/))
+}
+
+/// Appends a field prefixed with its length as a u16, the caller ensures it fits
+fn push_opaque(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configs_round_trip() {
+        let key_pair = OdohKeyPair::generate();
+        let configs = key_pair.config().to_configs();
+        assert_eq!(
+            &OdohConfig::from_configs(&configs).unwrap(),
+            key_pair.config()
+        );
+    }
+
+    #[test]
+    fn test_configs_skip_unsupported() {
+        let key_pair = OdohKeyPair::from_private_key([7; 32]);
+
+        // a config of an unknown version, followed by one with an unsupported AEAD
+        let mut unsupported = vec![0xff, 0x00];
+        push_opaque(&mut unsupported, &[0; 8]);
+        unsupported.extend_from_slice(&ODOH_VERSION.to_be_bytes());
+        let mut contents = key_pair.config().contents();
+        contents[5] = 0x03;
+        push_opaque(&mut unsupported, &contents);
+
+        let mut configs = Vec::new();
+        push_opaque(&mut configs, &unsupported);
+        assert!(OdohConfig::from_configs(&configs).is_err());
+
+        let supported = key_pair.config().to_configs();
+        unsupported.extend_from_slice(&supported[2..]);
+        let mut configs = Vec::new();
+        push_opaque(&mut configs, &unsupported);
+        assert_eq!(
+            &OdohConfig::from_configs(&configs).unwrap(),
+            key_pair.config()
+        );
+    }
+
+    #[test]
+    fn test_query_response_round_trip() {
+        let key_pair = OdohKeyPair::generate();
+        let query = b"not really a dns query";
+
+        let (message, client_key) = key_pair.config().encrypt_query(query).unwrap();
+        assert_eq!(message[0], MESSAGE_TYPE_QUERY);
+
+        let (decrypted, server_key) = key_pair.decrypt_query(&message).unwrap();
+        assert_eq!(decrypted, query);
+
+        let response = b"not really a dns response";
+        let message = server_key.encrypt_response(response).unwrap();
+        assert_eq!((message.len() - 37) % RESPONSE_PADDING_BLOCK, 0);
+        assert_eq!(client_key.decrypt_response(&message).unwrap(), response);
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let key_pair = OdohKeyPair::generate();
+        let (message, _) = key_pair.config().encrypt_query(b"query").unwrap();
+
+        assert!(OdohKeyPair::generate().decrypt_query(&message).is_err());
+
+        let mut tampered = message.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(key_pair.decrypt_query(&tampered).is_err());
+
+        // a query is not a response
+        let (_, server_key) = key_pair.decrypt_query(&message).unwrap();
+        assert!(server_key.decrypt_response(&message).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Oblivious DNS over HTTPS (ODoH), RFC 9230
+//!
+//! Queries are encrypted to a target, and sent through a proxy. The proxy sees the client address
+//!  but not the query, the target sees the query but not the client address.
+
+use http::Uri;
+
+mod hpke;
+mod message;
+
+pub use self::message::{OdohConfig, OdohKeyPair, OdohResponseKey};
+
+/// The media type of ODoH queries and responses
+pub const MIME_APPLICATION_ODOH: &str = "application/oblivious-dns-message";
+
+/// The well-known path at which targets publish their `ObliviousDoHConfigs`
+pub const ODOH_CONFIGS_PATH: &str = "/.well-known/odohconfigs";
+
+/// The query parameter naming the target host in requests to a proxy
+pub const TARGET_HOST_PARAM: &str = "targethost";
+
+/// The query parameter naming the target path in requests to a proxy
+pub const TARGET_PATH_PARAM: &str = "targetpath";
+
+/// Expands the URI template of a proxy for a target, `{?targethost,targetpath}` in RFC 9230
+///
+/// # Arguments
+///
+/// * `proxy_path` - the path of the proxy, to which the target parameters are appended
+/// * `target_host` - the authority of the target, e.g. `target.example.com` or `target.example.com:8443`
+/// * `target_path` - the path of the target, typically `/dns-query`
+pub fn proxy_path(proxy_path: &str, target_host: &str, target_path: &str) -> String {
+    let separator = if proxy_path.contains('?') { '&' } else { '?' };
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair(TARGET_HOST_PARAM, target_host)
+        .append_pair(TARGET_PATH_PARAM, target_path)
+        .finish();
+
+    format!("{proxy_path}{separator}{query}")
+}
+
+/// Returns the target host and path of a request to a proxy, if it names a target
+pub fn target_from_query(uri: &Uri) -> Option<(String, String)> {
+    let mut target_host = None;
+    let mut target_path = None;
+    for (key, value) in url::form_urlencoded::parse(uri.query()?.as_bytes()) {
+        match &*key {
+            TARGET_HOST_PARAM => target_host = Some(value.into_owned()),
+            TARGET_PATH_PARAM => target_path = Some(value.into_owned()),
+            _ => (),
+        }
+    }
+
+    Some((target_host?, target_path?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_path() {
+        let path = proxy_path("/proxy", "target.example.com:8443", "/dns-query");
+        assert_eq!(
+            path,
+            "/proxy?targethost=target.example.com%3A8443&targetpath=%2Fdns-query"
+        );
+
+        let uri = Uri::try_from(path).unwrap();
+        assert_eq!(
+            target_from_query(&uri),
+            Some((
+                "target.example.com:8443".to_owned(),
+                "/dns-query".to_owned()
+            ))
+        );
+
+        // unencoded, as in the examples of RFC 9230
+        let uri = Uri::from_static("/proxy?targethost=target.example.com&targetpath=/dns-query");
+        assert_eq!(
+            target_from_query(&uri),
+            Some(("target.example.com".to_owned(), "/dns-query".to_owned()))
+        );
+
+        let uri = Uri::from_static("/dns-query?targethost=target.example.com");
+        assert_eq!(target_from_query(&uri), None);
+        assert_eq!(target_from_query(&Uri::from_static("/dns-query")), None);
+    }
+}
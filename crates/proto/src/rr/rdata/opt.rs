@@ -480,6 +480,9 @@ pub enum EdnsOption {
     /// [RFC 7871, Client Subnet, Optional](https://tools.ietf.org/html/rfc7871)
     Subnet(ClientSubnet),
 
+    /// [RFC 7873, DNS Cookies](https://tools.ietf.org/html/rfc7873)
+    Cookie(Cookie),
+
     /// Unknown, used to deal with unknown or unsupported codes
     Unknown(u16, Vec<u8>),
 }
@@ -493,6 +496,7 @@ impl EdnsOption {
             | EdnsOption::DHU(algorithms)
             | EdnsOption::N3U(algorithms) => algorithms.len(),
             EdnsOption::Subnet(subnet) => subnet.len(),
+            EdnsOption::Cookie(cookie) => cookie.len(),
             EdnsOption::Unknown(_, data) => data.len() as u16, // TODO: should we verify?
         }
     }
@@ -505,6 +509,7 @@ impl EdnsOption {
             | EdnsOption::DHU(algorithms)
             | EdnsOption::N3U(algorithms) => algorithms.is_empty(),
             EdnsOption::Subnet(subnet) => subnet.is_empty(),
+            EdnsOption::Cookie(cookie) => cookie.is_empty(),
             EdnsOption::Unknown(_, data) => data.is_empty(),
         }
     }
@@ -518,6 +523,7 @@ impl BinEncodable for EdnsOption {
             | EdnsOption::DHU(algorithms)
             | EdnsOption::N3U(algorithms) => algorithms.emit(encoder),
             EdnsOption::Subnet(subnet) => subnet.emit(encoder),
+            EdnsOption::Cookie(cookie) => cookie.emit(encoder),
             EdnsOption::Unknown(_, data) => encoder.emit_vec(data), // gah, clone needed or make a crazy api.
         }
     }
@@ -537,6 +543,7 @@ impl<'a> TryFrom<(EdnsCode, &'a [u8])> for EdnsOption {
             #[cfg(feature = "dnssec")]
             EdnsCode::N3U => Self::N3U(value.1.into()),
             EdnsCode::Subnet => Self::Subnet(value.1.try_into()?),
+            EdnsCode::Cookie => Self::Cookie(value.1.try_into()?),
             _ => Self::Unknown(value.0.into(), value.1.to_vec()),
         })
     }
@@ -552,6 +559,7 @@ impl<'a> TryFrom<&'a EdnsOption> for Vec<u8> {
             | EdnsOption::DHU(algorithms)
             | EdnsOption::N3U(algorithms) => algorithms.into(),
             EdnsOption::Subnet(subnet) => subnet.try_into()?,
+            EdnsOption::Cookie(cookie) => cookie.into(),
             EdnsOption::Unknown(_, data) => data.clone(), // gah, clone needed or make a crazy api.
         })
     }
@@ -567,6 +575,7 @@ impl<'a> From<&'a EdnsOption> for EdnsCode {
             #[cfg(feature = "dnssec")]
             EdnsOption::N3U(..) => Self::N3U,
             EdnsOption::Subnet(..) => Self::Subnet,
+            EdnsOption::Cookie(..) => Self::Cookie,
             EdnsOption::Unknown(code, _) => (*code).into(),
         }
     }
@@ -664,6 +673,24 @@ impl ClientSubnet {
         self.scope_prefix = scope_prefix;
     }
 
+    /// Returns the subnet with the address truncated to at most `source_prefix` bits, and the
+    ///  scope prefix cleared, as required in queries
+    pub fn truncate(&self, source_prefix: u8) -> Self {
+        let max_prefix = match self.address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let source_prefix = source_prefix.min(self.source_prefix).min(max_prefix);
+        let address = ipnet::IpNet::new(self.address, source_prefix)
+            .map_or(self.address, |net| net.network());
+
+        Self {
+            address,
+            source_prefix,
+            scope_prefix: 0,
+        }
+    }
+
     fn addr_len(&self) -> u16 {
         let source_prefix = self.source_prefix as u16;
         source_prefix / 8 + if source_prefix % 8 > 0 { 1 } else { 0 }
@@ -802,6 +829,119 @@ impl FromStr for ClientSubnet {
     }
 }
 
+/// [RFC 7873, DNS Cookies](https://tools.ietf.org/html/rfc7873#section-4)
+///
+/// ```text
+/// 4.  DNS Cookies
+///
+///    The DNS Cookie OPT option ... has the following format:
+///
+///                         1 1 1 1 1 1 1 1 1 1 2 2 2 2 2 2 2 2 2 2 3 3
+///     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+///    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///    |        OPTION-CODE = 10      | OPTION-LENGTH >= 16, <= 40     |
+///    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///    |                                                               |
+///    +-+-+-    Client Cookie (fixed size, 8 bytes)              -+-+-+
+///    |                                                               |
+///    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///    |                                                               |
+///    /       Server Cookie  (variable size, 8 to 32 bytes)          /
+///    /                                                               /
+///    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///
+///    When a client has not yet learned a Server Cookie, the option only
+///    contains the Client Cookie, and OPTION-LENGTH is 8.
+/// ```
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[derive(Debug, PartialOrd, PartialEq, Eq, Clone, Hash)]
+pub struct Cookie {
+    client: [u8; 8],
+    server: Option<Vec<u8>>,
+}
+
+impl Cookie {
+    /// Length of the client cookie
+    pub const CLIENT_COOKIE_LEN: usize = 8;
+    /// Minimum length of a server cookie
+    pub const MIN_SERVER_COOKIE_LEN: usize = 8;
+    /// Maximum length of a server cookie
+    pub const MAX_SERVER_COOKIE_LEN: usize = 32;
+
+    /// Construct a new Cookie, with the server cookie if one has been learned from the server
+    ///
+    /// The server cookie must be between 8 and 32 bytes long.
+    pub fn new(client: [u8; 8], server: Option<Vec<u8>>) -> ProtoResult<Self> {
+        if let Some(server) = &server {
+            if !(Self::MIN_SERVER_COOKIE_LEN..=Self::MAX_SERVER_COOKIE_LEN).contains(&server.len())
+            {
+                return Err(ProtoErrorKind::Message("invalid server cookie length").into());
+            }
+        }
+
+        Ok(Self { client, server })
+    }
+
+    /// Returns the length in bytes of the EdnsOption
+    pub fn len(&self) -> u16 {
+        (Self::CLIENT_COOKIE_LEN + self.server.as_ref().map_or(0, Vec::len)) as u16
+    }
+
+    /// Returns `true` if the length in bytes of the Cookie is 0, which is never the case
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// returns the client cookie
+    pub fn client(&self) -> &[u8; 8] {
+        &self.client
+    }
+
+    /// returns the server cookie, if present
+    pub fn server(&self) -> Option<&[u8]> {
+        self.server.as_deref()
+    }
+}
+
+impl BinEncodable for Cookie {
+    fn emit(&self, encoder: &mut BinEncoder<'_>) -> ProtoResult<()> {
+        encoder.emit_vec(&self.client)?;
+        if let Some(server) = &self.server {
+            encoder.emit_vec(server)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> From<&'a Cookie> for Vec<u8> {
+    fn from(value: &'a Cookie) -> Self {
+        let mut bytes = Self::with_capacity(value.len() as usize);
+        bytes.extend_from_slice(&value.client);
+        if let Some(server) = &value.server {
+            bytes.extend_from_slice(server);
+        }
+        bytes
+    }
+}
+
+/// A malformed cookie is an error, the server must respond with FORMERR, RFC 7873 section 5.2.2
+impl<'a> TryFrom<&'a [u8]> for Cookie {
+    type Error = ProtoError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        if value.len() < Self::CLIENT_COOKIE_LEN {
+            return Err(ProtoErrorKind::Message("invalid client cookie length").into());
+        }
+
+        let (client, server) = value.split_at(Self::CLIENT_COOKIE_LEN);
+        let mut client_cookie = [0; Self::CLIENT_COOKIE_LEN];
+        client_cookie.copy_from_slice(client);
+
+        Self::new(client_cookie, (!server.is_empty()).then(|| server.to_vec()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #![allow(clippy::dbg_macro, clippy::print_stdout)]
@@ -850,7 +990,9 @@ mod tests {
             ),
             (
                 EdnsCode::Cookie,
-                EdnsOption::Unknown(10, vec![0x0b, 0x64, 0xb4, 0xdc, 0xd7, 0xb0, 0xcc, 0x8f]),
+                EdnsOption::Cookie(
+                    Cookie::new([0x0b, 0x64, 0xb4, 0xdc, 0xd7, 0xb0, 0xcc, 0x8f], None).unwrap(),
+                ),
             ),
             (EdnsCode::Keepalive, EdnsOption::Unknown(11, vec![])),
         ];
@@ -909,4 +1051,46 @@ mod tests {
         let ecs = ClientSubnet::try_from(bytes.as_slice()).unwrap();
         assert_eq!(ecs, "172.1.1.0/24".parse().unwrap());
     }
+
+    #[test]
+    fn test_truncate_client_subnet() {
+        let ecs: ClientSubnet = "192.0.2.130/32".parse().unwrap();
+        assert_eq!(ecs.truncate(24), "192.0.2.0/24".parse().unwrap());
+        assert_eq!(ecs.truncate(25), "192.0.2.128/25".parse().unwrap());
+        assert_eq!(ecs.truncate(0), "0.0.0.0/0".parse().unwrap());
+
+        // never widens the source prefix
+        let ecs: ClientSubnet = "2001:db8:aaaa::/48".parse().unwrap();
+        assert_eq!(ecs.truncate(56), ecs);
+        assert_eq!(ecs.truncate(32), "2001:db8::/32".parse().unwrap());
+
+        let mut ecs = ecs;
+        ecs.set_scope_prefix(48);
+        assert_eq!(ecs.truncate(48).scope_prefix(), 0);
+    }
+
+    #[test]
+    fn test_read_write_cookie() {
+        let bytes: Vec<u8> = vec![
+            0x24, 0x64, 0xc4, 0xab, 0xcf, 0x10, 0xc9, 0x57, 0x01, 0x00, 0x00, 0x00, 0x5c, 0xf7,
+            0x9f, 0x11, 0x1f, 0x81, 0x30, 0xc3, 0xee, 0xe2, 0x94, 0x80,
+        ];
+        let cookie = Cookie::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(cookie.client(), &bytes[..8]);
+        assert_eq!(cookie.server(), Some(&bytes[8..]));
+        assert_eq!(cookie.len(), 24);
+        assert_eq!(Vec::<u8>::from(&cookie), bytes);
+
+        let cookie = Cookie::try_from(&bytes[..8]).unwrap();
+        assert_eq!(cookie.server(), None);
+        assert_eq!(Vec::<u8>::from(&cookie), &bytes[..8]);
+    }
+
+    #[test]
+    fn test_read_malformed_cookie() {
+        let bytes = [0u8; 41];
+        for len in [0, 7, 9, 15, 41] {
+            assert!(Cookie::try_from(&bytes[..len]).is_err(), "length: {len}");
+        }
+    }
 }
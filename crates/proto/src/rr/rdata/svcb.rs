@@ -190,6 +190,9 @@ impl SVCB {
 ///   | 6           | ipv6hint        | IPv6 address hints   | (This     |
 ///   |             |                 |                      | document) |
 ///   +-------------+-----------------+----------------------+-----------+
+///   | 7           | dohpath         | DNS over HTTPS path  | RFC 9461  |
+///   |             |                 | template             |           |
+///   +-------------+-----------------+----------------------+-----------+
 ///   | 65280-65534 | N/A             | Private Use          | (This     |
 ///   |             |                 |                      | document) |
 ///   +-------------+-----------------+----------------------+-----------+
@@ -218,6 +221,8 @@ pub enum SvcParamKey {
     EchConfigList,
     /// IPv6 address hints
     Ipv6Hint,
+    /// DNS over HTTPS path template, RFC 9461
+    DohPath,
     /// Private Use
     Key(u16),
     /// Reserved ("Invalid key")
@@ -236,6 +241,7 @@ impl From<u16> for SvcParamKey {
             4 => Self::Ipv4Hint,
             5 => Self::EchConfigList,
             6 => Self::Ipv6Hint,
+            7 => Self::DohPath,
             65280..=65534 => Self::Key(val),
             65535 => Self::Key65535,
             _ => Self::Unknown(val),
@@ -253,6 +259,7 @@ impl From<SvcParamKey> for u16 {
             SvcParamKey::Ipv4Hint => 4,
             SvcParamKey::EchConfigList => 5,
             SvcParamKey::Ipv6Hint => 6,
+            SvcParamKey::DohPath => 7,
             SvcParamKey::Key(val) => val,
             SvcParamKey::Key65535 => 65535,
             SvcParamKey::Unknown(val) => val,
@@ -286,6 +293,7 @@ impl fmt::Display for SvcParamKey {
             Self::Ipv4Hint => f.write_str("ipv4hint")?,
             Self::EchConfigList => f.write_str("ech")?,
             Self::Ipv6Hint => f.write_str("ipv6hint")?,
+            Self::DohPath => f.write_str("dohpath")?,
             Self::Key(val) => write!(f, "key{val}")?,
             Self::Key65535 => f.write_str("key65535")?,
             Self::Unknown(val) => write!(f, "unknown{val}")?,
@@ -318,6 +326,7 @@ impl std::str::FromStr for SvcParamKey {
             "ipv4hint" => Self::Ipv4Hint,
             "ech" => Self::EchConfigList,
             "ipv6hint" => Self::Ipv6Hint,
+            "dohpath" => Self::DohPath,
             "key65535" => Self::Key65535,
             _ => parse_unknown_key(s)?,
         };
@@ -424,6 +433,14 @@ pub enum SvcParamValue {
     EchConfigList(EchConfigList),
     /// See `IpHint`
     Ipv6Hint(IpHint<AAAA>),
+    ///  [RFC 9461 Service Binding Mapping for DNS Servers, Nov 2023](https://datatracker.ietf.org/doc/html/rfc9461#section-5)
+    ///
+    /// ```text
+    ///    "dohpath" is a single-valued SvcParamKey whose value (in both
+    ///    presentation format and wire format) MUST be a URI Template in
+    ///    relative form ([RFC6570], Section 1.1) encoded in UTF-8 [RFC3629].
+    /// ```
+    DohPath(String),
     /// Unparsed network data. Refer to documents on the associated key value
     ///
     /// This will be left as is when read off the wire, and encoded in bas64
@@ -471,6 +488,12 @@ impl SvcParamValue {
             SvcParamKey::Ipv4Hint => Self::Ipv4Hint(IpHint::<A>::read(&mut decoder)?),
             SvcParamKey::EchConfigList => Self::EchConfigList(EchConfigList::read(&mut decoder)?),
             SvcParamKey::Ipv6Hint => Self::Ipv6Hint(IpHint::<AAAA>::read(&mut decoder)?),
+            SvcParamKey::DohPath => {
+                let path = decoder.read_slice(len)?.unverified(/*checked as utf8 below*/);
+                let path = std::str::from_utf8(path)
+                    .map_err(|_| ProtoError::from("dohpath is not valid UTF-8"))?;
+                Self::DohPath(path.to_string())
+            }
             SvcParamKey::Key(_) | SvcParamKey::Key65535 | SvcParamKey::Unknown(_) => {
                 Self::Unknown(Unknown::read(&mut decoder)?)
             }
@@ -496,6 +519,7 @@ impl BinEncodable for SvcParamValue {
             Self::Ipv4Hint(ip_hint) => ip_hint.emit(encoder)?,
             Self::EchConfigList(ech_config) => ech_config.emit(encoder)?,
             Self::Ipv6Hint(ip_hint) => ip_hint.emit(encoder)?,
+            Self::DohPath(path) => encoder.emit_vec(path.as_bytes())?,
             Self::Unknown(unknown) => unknown.emit(encoder)?,
         }
 
@@ -518,6 +542,7 @@ impl fmt::Display for SvcParamValue {
             Self::Ipv4Hint(ip_hint) => write!(f, "{ip_hint}")?,
             Self::EchConfigList(ech_config) => write!(f, "{ech_config}")?,
             Self::Ipv6Hint(ip_hint) => write!(f, "{ip_hint}")?,
+            Self::DohPath(path) => write!(f, "\"{path}\"")?,
             Self::Unknown(unknown) => write!(f, "{unknown}")?,
         }
 
@@ -1248,6 +1273,20 @@ mod tests {
                 ),
             ],
         ));
+        test_encode_decode(SVCB::new(
+            1,
+            Name::from_utf8("dns.example.net.").unwrap(),
+            vec![
+                (
+                    SvcParamKey::Alpn,
+                    SvcParamValue::Alpn(Alpn(vec!["h2".to_string()])),
+                ),
+                (
+                    SvcParamKey::DohPath,
+                    SvcParamValue::DohPath("/dns-query{?dns}".to_string()),
+                ),
+            ],
+        ));
     }
 
     #[test]
@@ -15,6 +15,12 @@ impl SerialNumber {
     }
 }
 
+impl From<u32> for SerialNumber {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
 /// Serial Number Addition, see RFC 1982, section 3.1
 ///
 /// The result is a wrapping add.
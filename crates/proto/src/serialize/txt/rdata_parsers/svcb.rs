@@ -127,6 +127,7 @@ fn parse_value(key: SvcParamKey, value: Option<&str>) -> Result<SvcParamValue, P
         SvcParamKey::Ipv4Hint => parse_ipv4_hint(value),
         SvcParamKey::Ipv6Hint => parse_ipv6_hint(value),
         SvcParamKey::EchConfigList => parse_ech_config(value),
+        SvcParamKey::DohPath => parse_doh_path(value),
         SvcParamKey::Key(_) => parse_unknown(value),
         SvcParamKey::Key65535 | SvcParamKey::Unknown(_) => {
             Err(ParseError::from(ParseErrorKind::Message(
@@ -294,6 +295,18 @@ fn parse_ech_config(value: Option<&str>) -> Result<SvcParamValue, ParseError> {
     )))
 }
 
+///  [RFC 9461 Service Binding Mapping for DNS Servers, Nov 2023](https://datatracker.ietf.org/doc/html/rfc9461#section-5)
+///
+/// ```text
+///   "dohpath" is a single-valued SvcParamKey whose value (in both
+///   presentation format and wire format) MUST be a URI Template in
+///   relative form ([RFC6570], Section 1.1) encoded in UTF-8 [RFC3629].
+/// ```
+fn parse_doh_path(value: Option<&str>) -> Result<SvcParamValue, ParseError> {
+    let value = value.ok_or_else(|| ParseError::from("expected a value for dohpath"))?;
+    Ok(SvcParamValue::DohPath(value.to_string()))
+}
+
 ///  [RFC 9460 SVCB and HTTPS Resource Records, Nov 2023](https://datatracker.ietf.org/doc/html/rfc9460#section-2.1)
 ///
 /// ```text
@@ -437,6 +450,21 @@ mod tests {
         assert_eq!(svcb, svcb_display);
     }
 
+    #[test]
+    fn test_parsing_dohpath() {
+        let svcb: SVCB = parse_record(
+            r#"_dns.resolver.arpa. 7200 IN SVCB 1 dns.example.net. alpn=h2 dohpath=/dns-query{?dns}"#,
+        );
+
+        let (key, value) = &svcb.svc_params()[1];
+        assert_eq!(*key, SvcParamKey::DohPath);
+        assert_eq!(value.as_doh_path().unwrap(), "/dns-query{?dns}");
+
+        // round trip through the presentation format
+        let display = format!("_dns.resolver.arpa. 7200 IN SVCB {svcb}");
+        assert_eq!(svcb, parse_record::<SVCB>(&display));
+    }
+
     /// sanity check for https
     #[test]
     fn test_parsing_https() {
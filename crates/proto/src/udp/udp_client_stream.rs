@@ -9,7 +9,7 @@ use std::collections::HashSet;
 use std::fmt::{self, Display};
 use std::net::SocketAddr;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -17,7 +17,8 @@ use futures_util::{future::Future, stream::Stream};
 use tracing::{debug, trace, warn};
 
 use crate::error::ProtoError;
-use crate::op::{Message, MessageFinalizer, MessageVerifier};
+use crate::op::{Message, MessageFinalizer, MessageVerifier, ResponseCode};
+use crate::rr::rdata::opt::{Cookie, EdnsCode, EdnsOption};
 use crate::runtime::{RuntimeProvider, Time};
 use crate::udp::udp_stream::NextRandomUdpSocket;
 use crate::udp::{DnsUdpSocket, MAX_RECEIVE_BUFFER_SIZE};
@@ -32,6 +33,7 @@ pub struct UdpClientStreamBuilder<P> {
     signer: Option<Arc<dyn MessageFinalizer>>,
     bind_addr: Option<SocketAddr>,
     avoid_local_ports: Arc<HashSet<u16>>,
+    cookies: bool,
     provider: P,
 }
 
@@ -50,6 +52,7 @@ impl<P> UdpClientStreamBuilder<P> {
             signer,
             bind_addr: self.bind_addr,
             avoid_local_ports: self.avoid_local_ports,
+            cookies: self.cookies,
             provider: self.provider,
         }
     }
@@ -70,6 +73,15 @@ impl<P> UdpClientStreamBuilder<P> {
         self
     }
 
+    /// Enables or disables DNS Cookies, [RFC 7873](https://tools.ietf.org/html/rfc7873).
+    ///
+    /// When enabled, a client cookie is added to queries that use EDNS, and the server cookie from
+    /// the responses is echoed in later queries. Disabled by default.
+    pub fn with_cookies(mut self, cookies: bool) -> Self {
+        self.cookies = cookies;
+        self
+    }
+
     /// Construct a new UDP client stream.
     ///
     /// Returns a future that outputs the client stream.
@@ -80,6 +92,7 @@ impl<P> UdpClientStreamBuilder<P> {
             signer: self.signer,
             bind_addr: self.bind_addr,
             avoid_local_ports: self.avoid_local_ports.clone(),
+            cookie: self.cookies.then(|| Arc::new(ClientCookie::new())),
             provider: self.provider,
         }
     }
@@ -98,6 +111,7 @@ pub struct UdpClientStream<P> {
     signer: Option<Arc<dyn MessageFinalizer>>,
     bind_addr: Option<SocketAddr>,
     avoid_local_ports: Arc<HashSet<u16>>,
+    cookie: Option<Arc<ClientCookie>>,
     provider: P,
 }
 
@@ -110,6 +124,7 @@ impl<P: RuntimeProvider> UdpClientStream<P> {
             signer: None,
             bind_addr: None,
             avoid_local_ports: Arc::default(),
+            cookies: false,
             provider,
         }
     }
@@ -130,48 +145,22 @@ fn random_query_id() -> u16 {
 }
 
 impl<P: RuntimeProvider> DnsRequestSender for UdpClientStream<P> {
-    fn send_message(&mut self, mut message: DnsRequest) -> DnsResponseStream {
+    fn send_message(&mut self, message: DnsRequest) -> DnsResponseStream {
         if self.is_shutdown {
             panic!("can not send messages after stream is shutdown")
         }
 
-        // associated the ID for this request, b/c this connection is unique to socket port, the ID
-        //   does not need to be globally unique
-        message.set_id(random_query_id());
-
-        let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(now) => now.as_secs(),
-            Err(_) => return ProtoError::from("Current time is before the Unix epoch.").into(),
-        };
-
-        // TODO: truncates u64 to u32, error on overflow?
-        let now = now as u32;
-
-        let mut verifier = None;
-        if let Some(signer) = &self.signer {
-            if signer.should_finalize_message(&message) {
-                match message.finalize(&**signer, now) {
-                    Ok(answer_verifier) => verifier = answer_verifier,
-                    Err(e) => {
-                        debug!("could not sign message: {}", e);
-                        return e.into();
-                    }
-                }
-            }
-        }
-
         // Get an appropriate read buffer size.
         let recv_buf_size = MAX_RECEIVE_BUFFER_SIZE.min(message.max_payload() as usize);
 
-        let bytes = match message.to_vec() {
-            Ok(bytes) => bytes,
-            Err(err) => {
-                return err.into();
-            }
-        };
-
-        let message_id = message.id();
-        let message = SerialMessage::new(bytes, self.name_server);
+        let signer = self.signer.clone();
+        let cookie = self.cookie.clone();
+        let retry = cookie.as_ref().map(|_| message.clone());
+        let (message, message_id, verifier) =
+            match prepare_message(message, self.name_server, &signer, &cookie) {
+                Ok(prepared) => prepared,
+                Err(e) => return e.into(),
+            };
 
         debug!(
             "final message: {}",
@@ -187,10 +176,44 @@ impl<P: RuntimeProvider> DnsRequestSender for UdpClientStream<P> {
         P::Timer::timeout::<Pin<Box<dyn Future<Output = Result<DnsResponse, ProtoError>> + Send>>>(
             self.timeout,
             Box::pin(async move {
+                let socket = NextRandomUdpSocket::new(
+                    addr,
+                    bind_addr,
+                    avoid_local_ports.clone(),
+                    provider.clone(),
+                )
+                .await?;
+                let response = send_serial_message_inner(
+                    message,
+                    message_id,
+                    verifier,
+                    socket,
+                    recv_buf_size,
+                    cookie.as_deref(),
+                )
+                .await?;
+
+                // the server cookie has been updated from the response, retry once with it, see
+                //  RFC 7873 section 5.3
+                let retry = match retry {
+                    Some(retry) if response.response_code() == ResponseCode::BADCOOKIE => retry,
+                    _ => return Ok(response),
+                };
+
+                debug!("retrying query with new server cookie after BADCOOKIE");
+                let (message, message_id, verifier) =
+                    prepare_message(retry, addr, &signer, &cookie)?;
                 let socket =
                     NextRandomUdpSocket::new(addr, bind_addr, avoid_local_ports, provider).await?;
-                send_serial_message_inner(message, message_id, verifier, socket, recv_buf_size)
-                    .await
+                send_serial_message_inner(
+                    message,
+                    message_id,
+                    verifier,
+                    socket,
+                    recv_buf_size,
+                    cookie.as_deref(),
+                )
+                .await
             }),
         )
         .into()
@@ -226,6 +249,7 @@ pub struct UdpClientConnect<P> {
     signer: Option<Arc<dyn MessageFinalizer>>,
     bind_addr: Option<SocketAddr>,
     avoid_local_ports: Arc<HashSet<u16>>,
+    cookie: Option<Arc<ClientCookie>>,
     provider: P,
 }
 
@@ -241,17 +265,141 @@ impl<P: RuntimeProvider> Future for UdpClientConnect<P> {
             signer: self.signer.take(),
             bind_addr: self.bind_addr,
             avoid_local_ports: self.avoid_local_ports.clone(),
+            cookie: self.cookie.take(),
             provider: self.provider.clone(),
         }))
     }
 }
 
+/// Assigns a new id to the message, adds the cookie and signs it, returning the message to send
+///  along with the verifier of the response
+fn prepare_message(
+    mut message: DnsRequest,
+    name_server: SocketAddr,
+    signer: &Option<Arc<dyn MessageFinalizer>>,
+    cookie: &Option<Arc<ClientCookie>>,
+) -> Result<(SerialMessage, u16, Option<MessageVerifier>), ProtoError> {
+    // associated the ID for this request, b/c this connection is unique to socket port, the ID
+    //   does not need to be globally unique
+    message.set_id(random_query_id());
+
+    if let Some(cookie) = cookie {
+        cookie.add_to(&mut message);
+    }
+
+    let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(now) => now.as_secs(),
+        Err(_) => return Err(ProtoError::from("Current time is before the Unix epoch.")),
+    };
+
+    // TODO: truncates u64 to u32, error on overflow?
+    let now = now as u32;
+
+    let mut verifier = None;
+    if let Some(signer) = signer {
+        if signer.should_finalize_message(&message) {
+            match message.finalize(&**signer, now) {
+                Ok(answer_verifier) => verifier = answer_verifier,
+                Err(e) => {
+                    debug!("could not sign message: {}", e);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    let bytes = message.to_vec()?;
+    Ok((
+        SerialMessage::new(bytes, name_server),
+        message.id(),
+        verifier,
+    ))
+}
+
+/// The client side of DNS Cookies with a server, [RFC 7873](https://tools.ietf.org/html/rfc7873)
+///
+/// ```text
+/// 5.1.  Originating Requests
+///
+///    A DNS client that implements DNS Cookies includes one DNS COOKIE
+///    option in every request it originates, unless DNS Cookies are not
+///    enabled.  ...  The Client Cookie SHOULD be a pseudorandom function
+///    of the Client IP Address, the Server IP Address, and a secret
+///    quantity known only to the client.
+/// ```
+///
+/// Each `UdpClientStream` is for a single server, so a random client cookie per stream is
+///  specific to the server.
+struct ClientCookie {
+    client: [u8; Cookie::CLIENT_COOKIE_LEN],
+    server: Mutex<Option<Vec<u8>>>,
+}
+
+impl ClientCookie {
+    fn new() -> Self {
+        Self {
+            client: rand::random(),
+            server: Mutex::new(None),
+        }
+    }
+
+    /// Adds the cookie option to the message, if it uses EDNS
+    fn add_to(&self, message: &mut Message) {
+        let Some(edns) = message.extensions_mut() else {
+            return;
+        };
+
+        let server = self.server.lock().expect("cookie lock poisoned").clone();
+        let cookie = match Cookie::new(self.client, server) {
+            Ok(cookie) => cookie,
+            Err(_) => return,
+        };
+
+        let options = edns.options_mut();
+        options.remove(EdnsCode::Cookie);
+        options.insert(EdnsOption::Cookie(cookie));
+    }
+
+    /// Learns the server cookie of the response, returns false if the client cookie does not match
+    ///
+    /// ```text
+    /// 5.3.  Responses ...
+    ///
+    ///    If the COOKIE option is too short to contain a Server Cookie ... or
+    ///    the Client Cookie in the reply does not match that in the
+    ///    corresponding request, the reply MUST be discarded.
+    /// ```
+    fn update_from(&self, response: &Message) -> bool {
+        let Some(EdnsOption::Cookie(cookie)) = response
+            .extensions()
+            .as_ref()
+            .and_then(|edns| edns.option(EdnsCode::Cookie))
+        else {
+            // the server does not support cookies
+            return true;
+        };
+
+        if cookie.client() != &self.client {
+            return false;
+        }
+
+        match cookie.server() {
+            Some(server) => {
+                *self.server.lock().expect("cookie lock poisoned") = Some(server.to_vec());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
 async fn send_serial_message_inner<S: DnsUdpSocket + Send>(
     msg: SerialMessage,
     msg_id: u16,
     verifier: Option<MessageVerifier>,
     socket: S,
     recv_buf_size: usize,
+    cookie: Option<&ClientCookie>,
 ) -> Result<DnsResponse, ProtoError> {
     let bytes = msg.bytes();
     let addr = msg.addr();
@@ -342,6 +490,13 @@ async fn send_serial_message_inner<S: DnsUdpSocket + Send>(
                     continue;
                 }
 
+                if let Some(cookie) = cookie {
+                    if !cookie.update_from(&response) {
+                        warn!("dropped response with mismatched cookie for id: {msg_id}");
+                        continue;
+                    }
+                }
+
                 debug!("received message id: {}", response.id());
                 if let Some(mut verifier) = verifier {
                     return verifier(response_bytes);
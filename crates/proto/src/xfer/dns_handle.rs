@@ -12,6 +12,7 @@ use rand;
 use tracing::debug;
 
 use crate::op::{Message, MessageType, OpCode, Query};
+use crate::rr::rdata::opt::EdnsOption;
 use crate::xfer::{DnsRequest, DnsRequestOptions, DnsResponse, SerialMessage};
 use crate::{error::*, op::Edns};
 
@@ -77,16 +78,56 @@ fn build_message(query: Query, options: DnsRequestOptions) -> Message {
         .set_id(id)
         .set_message_type(MessageType::Query)
         .set_op_code(OpCode::Query)
-        .set_recursion_desired(options.recursion_desired);
+        .set_recursion_desired(options.recursion_desired)
+        .set_authentic_data(options.authentic_data);
 
     // Extended dns
-    if options.use_edns {
-        message
-            .extensions_mut()
-            .get_or_insert_with(Edns::new)
-            .set_max_payload(MAX_PAYLOAD_LEN)
+    if options.use_edns || options.client_subnet.is_some() {
+        let edns = message.extensions_mut().get_or_insert_with(Edns::new);
+        edns.set_max_payload(MAX_PAYLOAD_LEN)
             .set_version(0)
             .set_dnssec_ok(options.edns_set_dnssec_ok);
+
+        if let Some(client_subnet) = options.client_subnet {
+            edns.options_mut().insert(EdnsOption::Subnet(client_subnet));
+        }
     }
     message
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rr::rdata::opt::{ClientSubnet, EdnsCode};
+
+    #[test]
+    fn test_build_message_client_subnet() {
+        let client_subnet: ClientSubnet = "192.0.2.0/24".parse().unwrap();
+        let options = DnsRequestOptions {
+            client_subnet: Some(client_subnet),
+            ..DnsRequestOptions::default()
+        };
+
+        let message = build_message(Query::new(), options);
+        let edns = message.extensions().as_ref().expect("edns missing");
+        assert_eq!(
+            edns.option(EdnsCode::Subnet),
+            Some(&EdnsOption::Subnet(client_subnet))
+        );
+
+        let message = build_message(Query::new(), DnsRequestOptions::default());
+        assert!(message.extensions().is_none());
+    }
+
+    #[test]
+    fn test_build_message_authentic_data() {
+        let options = DnsRequestOptions {
+            authentic_data: true,
+            ..DnsRequestOptions::default()
+        };
+        assert!(build_message(Query::new(), options).authentic_data());
+
+        let message = build_message(Query::new(), DnsRequestOptions::default());
+        assert!(!message.authentic_data());
+    }
+}
@@ -10,6 +10,7 @@
 use std::ops::{Deref, DerefMut};
 
 use crate::op::Message;
+use crate::rr::rdata::opt::ClientSubnet;
 
 /// A set of options for expressing options to how requests should be treated
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -31,6 +32,10 @@ pub struct DnsRequestOptions {
     pub max_request_depth: usize,
     /// set recursion desired (or not) for any requests
     pub recursion_desired: bool,
+    /// Sets the AD bit, requesting the server to indicate whether the response was validated
+    pub authentic_data: bool,
+    /// When set, the EDNS Client Subnet option sent with the request, implies `use_edns`
+    pub client_subnet: Option<ClientSubnet>,
 }
 
 impl Default for DnsRequestOptions {
@@ -42,6 +47,8 @@ impl Default for DnsRequestOptions {
             use_edns: false,
             edns_set_dnssec_ok: false,
             recursion_desired: true,
+            authentic_data: false,
+            client_subnet: None,
         }
     }
 }
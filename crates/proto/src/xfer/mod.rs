@@ -6,7 +6,7 @@
 
 use std::fmt::{self, Debug, Display};
 use std::future::Future;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
@@ -316,6 +316,9 @@ pub enum Protocol {
     /// Https for DNS over HTTPS
     #[cfg(feature = "dns-over-https-rustls")]
     Https,
+    /// Oblivious DNS over HTTPS, through a proxy
+    #[cfg(feature = "dns-over-odoh")]
+    Odoh,
     /// QUIC for DNS over QUIC
     #[cfg(feature = "dns-over-quic")]
     Quic,
@@ -333,6 +336,8 @@ impl fmt::Display for Protocol {
             Self::Tls => "tls",
             #[cfg(feature = "dns-over-https-rustls")]
             Self::Https => "https",
+            #[cfg(feature = "dns-over-odoh")]
+            Self::Odoh => "odoh",
             #[cfg(feature = "dns-over-quic")]
             Self::Quic => "quic",
             #[cfg(feature = "dns-over-h3")]
@@ -353,6 +358,8 @@ impl Protocol {
             Self::Tls => false,
             #[cfg(feature = "dns-over-https-rustls")]
             Self::Https => false,
+            #[cfg(feature = "dns-over-odoh")]
+            Self::Odoh => false,
             // TODO: if you squint, this is true...
             #[cfg(feature = "dns-over-quic")]
             Self::Quic => true,
@@ -375,6 +382,8 @@ impl Protocol {
             Self::Tls => true,
             #[cfg(feature = "dns-over-https-rustls")]
             Self::Https => true,
+            #[cfg(feature = "dns-over-odoh")]
+            Self::Odoh => true,
             #[cfg(feature = "dns-over-quic")]
             Self::Quic => true,
             #[cfg(feature = "dns-over-h3")]
@@ -390,5 +399,74 @@ impl Default for Protocol {
     }
 }
 
+/// Returns whether the address is reachable on the internet, rather than only on the host or its
+/// networks
+///
+/// Addresses that are not global are never sent in an EDNS Client Subnet, [RFC 7871, section
+/// 11.3](https://tools.ietf.org/html/rfc7871#section-11.3), nor relayed to by an ODoH proxy.
+pub fn is_global(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(addr) => {
+            let [a, b, ..] = addr.octets();
+            !(addr.is_unspecified()
+                || addr.is_loopback()
+                || addr.is_private()
+                || addr.is_link_local()
+                || addr.is_broadcast()
+                || addr.is_multicast()
+                // 0.0.0.0/8, this network
+                || a == 0
+                // 100.64.0.0/10, shared address space, RFC 6598
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(addr) => {
+            if let Some(addr) = addr.to_ipv4_mapped() {
+                return is_global(IpAddr::V4(addr));
+            }
+
+            let segment = addr.segments()[0];
+            !(addr.is_unspecified()
+                || addr.is_loopback()
+                || addr.is_multicast()
+                // fc00::/7, unique local
+                || (segment & 0xfe00) == 0xfc00
+                // fe80::/10, link-local
+                || (segment & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
 #[allow(unused)] // May be unused depending on features
 pub(crate) const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn test_is_global() {
+        assert!(is_global(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))));
+        assert!(is_global(IpAddr::V6(Ipv6Addr::new(
+            0x2001, 0xdb8, 0, 0, 0, 0, 0, 1
+        ))));
+
+        assert!(!is_global(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert!(!is_global(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+        assert!(!is_global(IpAddr::V4(Ipv4Addr::new(100, 64, 0, 1))));
+        assert!(!is_global(IpAddr::V4(Ipv4Addr::new(169, 254, 0, 1))));
+        assert!(!is_global(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 1))));
+        assert!(!is_global(IpAddr::V4(Ipv4Addr::UNSPECIFIED)));
+        assert!(!is_global(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+        assert!(!is_global(IpAddr::V6(Ipv6Addr::new(
+            0xfd00, 0, 0, 0, 0, 0, 0, 1
+        ))));
+        assert!(!is_global(IpAddr::V6(Ipv6Addr::new(
+            0xfe80, 0, 0, 0, 0, 0, 0, 1
+        ))));
+        assert!(!is_global(IpAddr::V6(
+            Ipv4Addr::new(192, 168, 0, 1).to_ipv6_mapped()
+        )));
+    }
+}
@@ -27,6 +27,8 @@
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
 mod error;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 mod recursor;
 mod recursor_dns_handle;
 pub(crate) mod recursor_pool;
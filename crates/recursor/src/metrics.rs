@@ -0,0 +1,24 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Metrics of the recursor, recorded like those of [`hickory_resolver::metrics`](crate::resolver::metrics)
+//!
+//! Nothing is recorded until the application installs a recorder.
+
+use crate::resolver::metrics::{describe_cache_lookups, record_cache_lookup_in};
+
+/// Lookups in the cache of the recursor, labeled with the `result`, `hit` or `miss`
+pub const CACHE_LOOKUPS_TOTAL: &str = "hickory_recursor_cache_lookups_total";
+
+/// Describes the metrics of the recursor to the installed recorder
+pub fn describe() {
+    describe_cache_lookups(CACHE_LOOKUPS_TOTAL, "recursor");
+}
+
+pub(crate) fn record_cache_lookup(hit: bool) {
+    record_cache_lookup_in(CACHE_LOOKUPS_TOTAL, hit);
+}
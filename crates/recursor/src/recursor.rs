@@ -7,30 +7,37 @@
 
 use std::{
     collections::HashSet,
+    io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     sync::{atomic::AtomicU8, Arc},
     time::Instant,
 };
 
 use ipnet::IpNet;
+use tracing::debug;
 
-use crate::{
-    proto::op::Query,
-    recursor_dns_handle::RecursorDnsHandle,
-    resolver::{config::NameServerConfigGroup, dns_lru::TtlConfig, lookup::Lookup},
-    DnssecPolicy, Error,
-};
 #[cfg(feature = "dnssec")]
 use crate::{
     proto::{
         dnssec::TrustAnchor,
-        op::ResponseCode,
         rr::{resource::RecordRef, Record, RecordType},
         xfer::{DnsHandle as _, DnsRequestOptions, DnssecDnsHandle, FirstAnswer as _},
         ProtoError,
     },
-    resolver::dns_lru::DnsLru,
-    ErrorKind,
+    resolver::nsec_cache::NsecCache,
+};
+use crate::{
+    proto::{
+        op::{Query, ResponseCode},
+        ProtoErrorKind,
+    },
+    recursor_dns_handle::RecursorDnsHandle,
+    resolver::{
+        config::NameServerConfigGroup,
+        dns_lru::{DnsLru, TtlConfig},
+        lookup::Lookup,
+    },
+    DnssecPolicy, Error, ErrorKind,
 };
 
 /// A `Recursor` builder
@@ -49,6 +56,7 @@ pub struct RecursorBuilder {
     deny_servers: Vec<IpNet>,
     avoid_local_udp_ports: HashSet<u16>,
     ttl_config: TtlConfig,
+    aggressive_nsec: bool,
 }
 
 impl RecursorBuilder {
@@ -111,6 +119,15 @@ impl RecursorBuilder {
         self
     }
 
+    /// Answers queries from validated NSEC and NSEC3 records in the cache, when they prove that the
+    /// name or type does not exist, see [`NsecCache`](crate::resolver::nsec_cache::NsecCache)
+    ///
+    /// This only applies to validating recursors.
+    pub fn aggressive_nsec(mut self, aggressive_nsec: bool) -> Self {
+        self.aggressive_nsec = aggressive_nsec;
+        self
+    }
+
     /// Construct a new recursor using the list of NameServerConfigs for the root node list
     ///
     /// # Panics
@@ -124,8 +141,9 @@ impl RecursorBuilder {
 /// A top down recursive resolver which operates off a list of roots for initial recursive requests.
 ///
 /// This is the well known root nodes, referred to as hints in RFCs. See the IANA [Root Servers](https://www.iana.org/domains/root/servers) list.
+#[derive(Clone)]
 pub struct Recursor {
-    mode: RecursorMode,
+    mode: Arc<RecursorMode>,
 }
 
 impl Recursor {
@@ -137,7 +155,17 @@ impl Recursor {
     /// Whether the recursive resolver is a validating resolver
     pub fn is_validating(&self) -> bool {
         // matching on `NonValidating` to avoid conditional compilation (`#[cfg]`)
-        !matches!(self.mode, RecursorMode::NonValidating { .. })
+        !matches!(*self.mode, RecursorMode::NonValidating { .. })
+    }
+
+    /// Writes a snapshot of the record cache, see [`DnsLru::save`]
+    pub fn save_cache<W: io::Write>(&self, writer: W) -> io::Result<usize> {
+        self.mode.record_cache().save(writer)
+    }
+
+    /// Restores a snapshot of the record cache, see [`DnsLru::load`]
+    pub fn load_cache<R: io::Read>(&self, reader: R) -> io::Result<usize> {
+        self.mode.record_cache().load(reader)
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -155,7 +183,11 @@ impl Recursor {
             deny_servers,
             avoid_local_udp_ports,
             ttl_config,
+            aggressive_nsec,
         } = builder;
+        // only validating recursors use NSEC records
+        #[cfg(not(feature = "dnssec"))]
+        let _ = aggressive_nsec;
 
         let handle = RecursorDnsHandle::new(
             roots,
@@ -189,12 +221,15 @@ impl Recursor {
 
                 RecursorMode::Validating {
                     record_cache,
+                    nsec_cache: aggressive_nsec.then(|| NsecCache::new(record_cache_size)),
                     handle: DnssecDnsHandle::with_trust_anchor(handle, trust_anchor),
                 }
             }
         };
 
-        Ok(Self { mode })
+        Ok(Self {
+            mode: Arc::new(mode),
+        })
     }
 
     /// Perform a recursive resolution
@@ -368,7 +403,74 @@ impl Recursor {
             return Err(Error::from("query's domain name must be fully qualified"));
         }
 
-        match &self.mode {
+        let record_cache = self.mode.record_cache();
+        let serve_stale = match record_cache.serve_stale_config() {
+            Some(serve_stale) if record_cache.is_stale(&query, request_time) => *serve_stale,
+            _ => {
+                return self
+                    .mode
+                    .resolve(query, request_time, query_has_dnssec_ok)
+                    .await
+            }
+        };
+
+        // RFC 8767: the resolution continues in the background if the client response timer
+        //  fires, and refreshes the stale records in the cache once it completes
+        let mode = self.mode.clone();
+        let mut resolution = tokio::spawn({
+            let query = query.clone();
+            async move { mode.resolve(query, request_time, query_has_dnssec_ok).await }
+        });
+
+        let result = match tokio::time::timeout(
+            serve_stale.client_response_timeout,
+            &mut resolution,
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) if record_cache.get_stale(&query, Instant::now()).is_some() => {
+                Ok(Err(ErrorKind::Timeout.into()))
+            }
+            Err(_) => resolution.await,
+        }
+        .unwrap_or_else(|e| Err(Error::from(format!("resolution failed: {e}"))));
+
+        match result {
+            Err(e) if !is_negative_response(&e) => {
+                match record_cache.get_stale(&query, Instant::now()) {
+                    Some(Ok(lookup)) => {
+                        debug!("resolution of {query} failed, serving stale records: {e}");
+                        Ok(super::maybe_strip_dnssec_records(
+                            query_has_dnssec_ok,
+                            lookup,
+                            query,
+                        ))
+                    }
+                    _ => Err(e),
+                }
+            }
+            result => result,
+        }
+    }
+}
+
+impl RecursorMode {
+    fn record_cache(&self) -> &DnsLru {
+        match self {
+            Self::NonValidating { handle } => handle.record_cache(),
+            #[cfg(feature = "dnssec")]
+            Self::Validating { record_cache, .. } => record_cache,
+        }
+    }
+
+    async fn resolve(
+        &self,
+        query: Query,
+        request_time: Instant,
+        query_has_dnssec_ok: bool,
+    ) -> Result<Lookup, Error> {
+        match self {
             RecursorMode::NonValidating { handle } => {
                 handle
                     .resolve(
@@ -385,6 +487,7 @@ impl Recursor {
             RecursorMode::Validating {
                 handle,
                 record_cache,
+                nsec_cache,
             } => {
                 if let Some(Ok(lookup)) = record_cache.get(&query, request_time) {
                     let none_indeterminate = lookup
@@ -395,6 +498,10 @@ impl Recursor {
                     // if any cached record is indeterminate, fall through and perform
                     // DNSSEC validation
                     if none_indeterminate {
+                        // misses are recorded by the handle
+                        #[cfg(feature = "metrics")]
+                        crate::metrics::record_cache_lookup(true);
+
                         return Ok(super::maybe_strip_dnssec_records(
                             query_has_dnssec_ok,
                             lookup,
@@ -403,12 +510,35 @@ impl Recursor {
                     }
                 }
 
+                if let Some(nsec_cache) = nsec_cache {
+                    match nsec_cache.get(&query, request_time) {
+                        Some(Ok(lookup)) => {
+                            return Ok(super::maybe_strip_dnssec_records(
+                                query_has_dnssec_ok,
+                                lookup,
+                                query,
+                            ));
+                        }
+                        Some(Err(proto_err)) => {
+                            return Err(Error {
+                                kind: Box::new(ErrorKind::Proto(proto_err)),
+                                #[cfg(feature = "backtrace")]
+                                backtrack: None,
+                            });
+                        }
+                        None => {}
+                    }
+                }
+
                 let mut options = DnsRequestOptions::default();
                 // a validating recursor must be security aware
                 options.use_edns = true;
                 options.edns_set_dnssec_ok = true;
 
                 let response = handle.lookup(query.clone(), options).first_answer().await?;
+                if let Some(nsec_cache) = nsec_cache {
+                    nsec_cache.insert(&response, request_time);
+                }
 
                 // Return NXDomain and NoData responses in error form
                 // These need to bypass the cache lookup (and casting to a Lookup object in general)
@@ -490,6 +620,7 @@ impl Default for RecursorBuilder {
             deny_servers: vec![],
             avoid_local_udp_ports: HashSet::new(),
             ttl_config: TtlConfig::default(),
+            aggressive_nsec: false,
         }
     }
 }
@@ -504,9 +635,31 @@ enum RecursorMode {
         handle: DnssecDnsHandle<RecursorDnsHandle>,
         // this is a handle to the record cache in `RecursorDnsHandle`; not a whole separate cache
         record_cache: DnsLru,
+        nsec_cache: Option<NsecCache>,
     },
 }
 
+/// Returns true if the name servers answered that the records do not exist, as opposed to failing
+fn is_negative_response(error: &Error) -> bool {
+    let proto = match error.kind() {
+        ErrorKind::Proto(proto) => proto,
+        ErrorKind::Resolve(resolve) => match resolve.proto() {
+            Some(proto) => proto,
+            None => return false,
+        },
+        ErrorKind::Forward(_) => return true,
+        _ => return false,
+    };
+
+    matches!(
+        proto.kind(),
+        ProtoErrorKind::NoRecordsFound {
+            response_code: ResponseCode::NXDomain | ResponseCode::NoError,
+            ..
+        }
+    )
+}
+
 #[cfg(feature = "dnssec")]
 mod for_dnssec {
     use std::{
@@ -132,7 +132,13 @@ impl RecursorDnsHandle {
         depth: u8,
         cname_limit: Arc<AtomicU8>,
     ) -> Result<Lookup, Error> {
-        if let Some(lookup) = self.record_cache.get(&query, request_time) {
+        let cached = self.record_cache.get(&query, request_time);
+        #[cfg(feature = "metrics")]
+        if depth == 0 {
+            crate::metrics::record_cache_lookup(cached.is_some());
+        }
+
+        if let Some(lookup) = cached {
             let response = self
                 .resolve_cnames(
                     lookup?,
@@ -706,7 +712,6 @@ impl RecursorDnsHandle {
         }
     }
 
-    #[cfg(feature = "dnssec")]
     pub(crate) fn record_cache(&self) -> &DnsLru {
         &self.record_cache
     }
@@ -0,0 +1,220 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Implementations of the resolver traits of other libraries for [`Resolver`]
+//!
+//! Each is enabled by a feature of this crate:
+//!
+//! * `tower` - `tower_service::Service<Name>`, looking up the IP addresses of a [`Name`]
+//! * `hyper-util` - `tower_service::Service<hyper_util::client::legacy::connect::dns::Name>`,
+//!   which makes the resolver usable with `HttpConnector::new_with_resolver`
+//! * `reqwest` - `reqwest::dns::Resolve`, for `ClientBuilder::dns_resolver`
+//!
+//! Addresses are looked up with [`Resolver::lookup_ip`], and so follow the configured
+//! `LookupIpStrategy`, search domains and hosts file.
+
+use std::future::Future;
+#[cfg(any(feature = "hyper-util", feature = "reqwest"))]
+use std::net::SocketAddr;
+use std::pin::Pin;
+#[cfg(feature = "tower")]
+use std::task::{Context, Poll};
+
+#[cfg(feature = "tower")]
+use tower_service::Service;
+
+use crate::error::ResolveError;
+#[cfg(feature = "tower")]
+use crate::lookup_ip::LookupIp;
+#[cfg(any(feature = "hyper-util", feature = "reqwest"))]
+use crate::lookup_ip::LookupIpIntoIter;
+use crate::name_server::ConnectionProvider;
+#[cfg(feature = "tower")]
+use crate::Name;
+use crate::Resolver;
+
+/// The addresses of a name as socket addresses, with the port `0`
+///
+/// HTTP clients set the port of the URL themselves.
+#[cfg(any(feature = "hyper-util", feature = "reqwest"))]
+pub struct SocketAddrs(LookupIpIntoIter);
+
+#[cfg(any(feature = "hyper-util", feature = "reqwest"))]
+impl Iterator for SocketAddrs {
+    type Item = SocketAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|ip| SocketAddr::new(ip, 0))
+    }
+}
+
+#[cfg(any(feature = "hyper-util", feature = "reqwest"))]
+impl<P: ConnectionProvider> Resolver<P> {
+    /// Looks up the IP addresses of `name` as socket addresses, for the adapters
+    fn socket_addrs(
+        &self,
+        name: String,
+    ) -> Pin<Box<dyn Future<Output = Result<SocketAddrs, ResolveError>> + Send>> {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let lookup = resolver.lookup_ip(name).await?;
+            Ok(SocketAddrs(lookup.into_iter()))
+        })
+    }
+}
+
+#[cfg(feature = "tower")]
+impl<P: ConnectionProvider> Service<Name> for Resolver<P> {
+    type Response = LookupIp;
+    type Error = ResolveError;
+    type Future = Pin<Box<dyn Future<Output = Result<LookupIp, ResolveError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let resolver = self.clone();
+        Box::pin(async move { resolver.lookup_ip(name).await })
+    }
+}
+
+#[cfg(feature = "hyper-util")]
+impl<P: ConnectionProvider> Service<hyper_util::client::legacy::connect::dns::Name>
+    for Resolver<P>
+{
+    type Response = SocketAddrs;
+    type Error = ResolveError;
+    type Future = Pin<Box<dyn Future<Output = Result<SocketAddrs, ResolveError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: hyper_util::client::legacy::connect::dns::Name) -> Self::Future {
+        self.socket_addrs(name.as_str().to_owned())
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl<P: ConnectionProvider> reqwest::dns::Resolve for Resolver<P> {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let addrs = self.socket_addrs(name.as_str().to_owned());
+        Box::pin(async move {
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.await?);
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(all(test, feature = "tokio-runtime"))]
+mod tests {
+    #[cfg(any(feature = "hyper-util", feature = "reqwest"))]
+    use std::net::Ipv6Addr;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use super::*;
+    use crate::config::{LookupIpStrategy, ResolverConfig, ResolverOpts};
+    use crate::{Hosts, TokioResolver};
+
+    fn resolver(ip_strategy: LookupIpStrategy) -> TokioResolver {
+        let options = ResolverOpts {
+            ip_strategy,
+            ..ResolverOpts::default()
+        };
+
+        let mut hosts = Hosts::new();
+        hosts
+            .read_hosts_conf("127.0.0.1 www.example.test\n::1 www.example.test\n".as_bytes())
+            .unwrap();
+
+        let mut resolver = TokioResolver::tokio(ResolverConfig::new(), options);
+        resolver.set_hosts(Some(hosts));
+        resolver
+    }
+
+    const V4: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+    #[cfg(any(feature = "hyper-util", feature = "reqwest"))]
+    const V6: SocketAddr = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 0);
+
+    #[cfg(any(feature = "hyper-util", feature = "reqwest"))]
+    #[tokio::test]
+    async fn test_socket_addrs() {
+        let resolver = resolver(LookupIpStrategy::Ipv4AndIpv6);
+        let addrs = resolver
+            .socket_addrs("www.example.test".to_owned())
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(addrs, vec![V4, V6]);
+
+        let resolver = self::resolver(LookupIpStrategy::Ipv6Only);
+        let addrs = resolver
+            .socket_addrs("www.example.test".to_owned())
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(addrs, vec![V6]);
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test]
+    async fn test_tower_service() {
+        let mut resolver = resolver(LookupIpStrategy::Ipv4Only);
+        let lookup =
+            Service::<Name>::call(&mut resolver, Name::from_ascii("www.example.test").unwrap())
+                .await
+                .unwrap();
+        assert_eq!(lookup.iter().collect::<Vec<_>>(), vec![V4.ip()]);
+    }
+
+    #[cfg(feature = "hyper-util")]
+    #[tokio::test]
+    async fn test_hyper_util() {
+        use std::str::FromStr;
+
+        use hyper_util::client::legacy::connect::{dns, HttpConnector};
+
+        let mut resolver = resolver(LookupIpStrategy::Ipv4Only);
+        let name = dns::Name::from_str("www.example.test").unwrap();
+        let addrs = Service::<dns::Name>::call(&mut resolver, name)
+            .await
+            .unwrap();
+        assert_eq!(addrs.collect::<Vec<_>>(), vec![V4]);
+
+        // the resolver can be used by the connector of hyper-util
+        let _connector = HttpConnector::new_with_resolver(resolver);
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn test_reqwest() {
+        use std::str::FromStr;
+        use std::sync::Arc;
+
+        use reqwest::dns::{Name, Resolve};
+
+        let resolver = resolver(LookupIpStrategy::Ipv6Only);
+        let addrs = resolver
+            .resolve(Name::from_str("www.example.test").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(addrs.collect::<Vec<_>>(), vec![V6]);
+
+        let error = match resolver
+            .resolve(Name::from_str("www.example.invalid.").unwrap())
+            .await
+        {
+            Ok(_) => panic!("lookup without name servers should fail"),
+            Err(error) => error,
+        };
+        assert!(error.downcast_ref::<ResolveError>().is_some());
+
+        // the resolver can be used by reqwest
+        let _builder = reqwest::Client::builder().dns_resolver(Arc::new(resolver));
+    }
+}
@@ -9,33 +9,41 @@
 
 use std::{
     borrow::Cow,
+    fmt,
     future::Future,
     pin::Pin,
     sync::{
         atomic::{AtomicU8, Ordering},
         Arc,
     },
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-use futures_util::future::TryFutureExt;
+use futures_util::future::{self, Either, FutureExt, TryFutureExt};
 use once_cell::sync::Lazy;
+use tracing::debug;
 
+#[cfg(feature = "dnssec")]
+use crate::nsec_cache::NsecCache;
 use crate::{
     dns_lru::{self, DnsLru, TtlConfig},
     error::ResolveError,
     lookup::Lookup,
     proto::{
-        op::{Query, ResponseCode},
+        op::{Message, Query, ResponseCode},
         rr::{
             domain::usage::{
                 ResolverUsage, DEFAULT, INVALID, IN_ADDR_ARPA_127, IP6_ARPA_1, LOCAL,
                 LOCALHOST as LOCALHOST_usage, ONION,
             },
-            rdata::{A, AAAA, CNAME, PTR, SOA},
+            rdata::{
+                opt::{ClientSubnet, EdnsCode, EdnsOption},
+                A, AAAA, CNAME, PTR, SOA,
+            },
             resource::RecordRef,
             DNSClass, Name, RData, Record, RecordType,
         },
+        runtime::{RuntimeProvider, Spawn, Time},
         xfer::{DnsHandle, DnsRequestOptions, DnsResponse, FirstAnswer},
         {ForwardNSData, ProtoError, ProtoErrorKind},
     },
@@ -77,6 +85,9 @@ where
     client: C,
     query_depth: Arc<AtomicU8>,
     preserve_intermediates: bool,
+    stale_runtime: Option<StaleRuntime>,
+    #[cfg(feature = "dnssec")]
+    nsec_cache: Option<NsecCache>,
 }
 
 impl<C> CachingClient<C>
@@ -99,20 +110,101 @@ where
             client,
             query_depth,
             preserve_intermediates,
+            stale_runtime: None,
+            #[cfg(feature = "dnssec")]
+            nsec_cache: None,
         }
     }
 
+    /// Sets the cache of validated NSEC and NSEC3 records, which answers queries for names they
+    ///  prove not to exist, see [`NsecCache`]
+    #[cfg(feature = "dnssec")]
+    pub(crate) fn with_nsec_cache(mut self, nsec_cache: Option<NsecCache>) -> Self {
+        self.nsec_cache = nsec_cache;
+        self
+    }
+
+    /// Sets the runtime which runs the client response timer, and refreshes stale records in the
+    ///  background, see [`ServeStaleConfig`](dns_lru::ServeStaleConfig)
+    ///
+    /// Without a runtime, stale records are only served if the name servers fail.
+    pub(crate) fn with_stale_runtime(mut self, stale_runtime: Option<StaleRuntime>) -> Self {
+        self.stale_runtime = stale_runtime;
+        self
+    }
+
     /// Perform a lookup against this caching client, looking first in the cache for a result
     pub fn lookup(
         &mut self,
         query: Query,
         options: DnsRequestOptions,
     ) -> Pin<Box<dyn Future<Output = Result<Lookup, ResolveError>> + Send>> {
+        if self
+            .lru
+            .is_stale_for_subnet(&query, options.client_subnet.as_ref(), Instant::now())
+        {
+            return Box::pin(
+                Self::lookup_or_stale(query, options, self.clone()).map_err(ResolveError::from),
+            );
+        }
+
         Box::pin(
             Self::inner_lookup(query, options, self.clone(), vec![]).map_err(ResolveError::from),
         )
     }
 
+    /// Looks up a query with stale records in the cache, answering with the stale records if the
+    ///  name servers fail, or do not answer within the client response timeout
+    ///
+    /// [RFC 8767, section 5](https://tools.ietf.org/html/rfc8767#section-5), Serving Stale Data to Improve DNS Resiliency, March 2020
+    async fn lookup_or_stale(
+        query: Query,
+        options: DnsRequestOptions,
+        client: Self,
+    ) -> Result<Lookup, ProtoError> {
+        let lookup = Self::inner_lookup(query.clone(), options, client.clone(), vec![]);
+
+        let result = match (&client.stale_runtime, client.lru.serve_stale_config()) {
+            (Some(runtime), Some(serve_stale)) => {
+                let (lookup, handle) = lookup.remote_handle();
+                runtime.spawn(Box::pin(lookup));
+
+                match future::select(handle, runtime.delay(serve_stale.client_response_timeout))
+                    .await
+                {
+                    Either::Left((result, _)) => result,
+                    Either::Right((_, handle)) => {
+                        match client.lru.get_stale_for_subnet(
+                            &query,
+                            options.client_subnet.as_ref(),
+                            Instant::now(),
+                        ) {
+                            Some(stale) => {
+                                debug!("no response for {query} within the client response timeout, serving stale records");
+                                // the lookup continues in the background, and replaces the stale records
+                                handle.forget();
+                                return stale;
+                            }
+                            None => handle.await,
+                        }
+                    }
+                }
+            }
+            _ => lookup.await,
+        };
+
+        match result {
+            Err(e) if !is_negative_response(&e) => {
+                debug!("lookup of {query} failed, serving stale records: {e}");
+                client
+                    .lru
+                    .get_stale_for_subnet(&query, options.client_subnet.as_ref(), Instant::now())
+                    .unwrap_or(Err(e))
+            }
+            result => result,
+        }
+    }
+
     async fn inner_lookup(
         query: Query,
         options: DnsRequestOptions,
@@ -180,10 +272,29 @@ where
         let is_dnssec = client.client.is_verifying_dnssec();
 
         // first transition any polling that is needed (mutable refs...)
-        if let Some(cached_lookup) = client.lookup_from_cache(&query) {
+        if let Some(cached_lookup) =
+            client.lookup_from_cache(&query, options.client_subnet.as_ref())
+        {
             return cached_lookup;
         };
 
+        #[cfg(feature = "dnssec")]
+        if let Some(nsec_cache) = &client.nsec_cache {
+            match nsec_cache.get(&query, Instant::now()) {
+                Some(Ok(lookup)) => {
+                    let records = lookup
+                        .records()
+                        .iter()
+                        .filter(|record| record.record_type() == query.query_type())
+                        .map(|record| (record.clone(), record.ttl()))
+                        .collect();
+                    return client.cache(query, None, Ok(records));
+                }
+                Some(Err(e)) => return client.cache(query, None, Err(e)),
+                None => {}
+            }
+        }
+
         let response_message = client
             .client
             .lookup(query.clone(), options)
@@ -191,9 +302,19 @@ where
             .await
             .map_err(ProtoError::into);
 
+        let scope = match &response_message {
+            Ok(response) => cache_scope(options.client_subnet.as_ref(), response),
+            Err(_) => None,
+        };
+
         // TODO: technically this might be duplicating work, as name_server already performs this evaluation.
         //  we may want to create a new type, if evaluated... but this is most generic to support any impl in LookupState...
         let response_message = if let Ok(response) = response_message {
+            #[cfg(feature = "dnssec")]
+            if let Some(nsec_cache) = &client.nsec_cache {
+                nsec_cache.insert(&response, Instant::now());
+            }
+
             ProtoError::from_response(response, false)
         } else {
             response_message
@@ -249,17 +370,32 @@ where
                 next: future,
                 min_ttl: ttl,
             }) => match future.await {
-                Ok(lookup) => client.cname(lookup, query, ttl),
-                Err(e) => client.cache(query, Err(e)),
+                // the records at the end of the chain may be scoped more narrowly than the CNAME
+                Ok(lookup) => {
+                    let scope = options
+                        .client_subnet
+                        .map(|client_subnet| client_subnet.truncate(client_subnet.source_prefix()));
+                    client.cname(lookup, query, scope, ttl)
+                }
+                Err(e) => client.cache(query, scope, Err(e)),
             },
-            Ok(Records::Exists(rdata)) => client.cache(query, Ok(rdata)),
-            Err(e) => client.cache(query, Err(e)),
+            Ok(Records::Exists(rdata)) => client.cache(query, scope, Ok(rdata)),
+            Err(e) => client.cache(query, scope, Err(e)),
         }
     }
 
     /// Check if this query is already cached
-    fn lookup_from_cache(&self, query: &Query) -> Option<Result<Lookup, ProtoError>> {
-        self.lru.get(query, Instant::now())
+    fn lookup_from_cache(
+        &self,
+        query: &Query,
+        client_subnet: Option<&ClientSubnet>,
+    ) -> Option<Result<Lookup, ProtoError>> {
+        let cached = self
+            .lru
+            .get_for_subnet(query, client_subnet, Instant::now());
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_cache_lookup(cached.is_some());
+        cached
     }
 
     /// See https://tools.ietf.org/html/rfc2308
@@ -334,6 +470,20 @@ where
         let negative_ttl = response.negative_ttl();
         let response_code = response.response_code();
 
+        // the addresses of every SRV target are kept, not only those of the last one followed below
+        let srv_targets = if query.query_type().is_srv() {
+            response
+                .answers()
+                .iter()
+                .filter_map(|r| match r.data() {
+                    RData::SRV(srv) => Some(srv.target().clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
+
         // seek out CNAMES, this is only performed if the query is not a CNAME, ANY, or SRV
         // FIXME: for SRV this evaluation is inadequate. CNAME is a single chain to a single record
         //   for SRV, there could be many different targets. The search_name needs to be enhanced to
@@ -416,7 +566,7 @@ where
                         //    and it's an IP
                         if query.query_type().is_srv()
                             && r.record_type().is_ip_addr()
-                            && search_name.as_ref() == r.name()
+                            && (search_name.as_ref() == r.name() || srv_targets.contains(r.name()))
                         {
                             found_name = true;
                             Some((r, ttl))
@@ -477,26 +627,125 @@ where
     }
 
     #[allow(clippy::unnecessary_wraps)]
-    fn cname(&self, lookup: Lookup, query: Query, cname_ttl: u32) -> Result<Lookup, ProtoError> {
+    fn cname(
+        &self,
+        lookup: Lookup,
+        query: Query,
+        scope: Option<ClientSubnet>,
+        cname_ttl: u32,
+    ) -> Result<Lookup, ProtoError> {
         // this duplicates the cache entry under the original query
-        Ok(self.lru.duplicate(query, lookup, cname_ttl, Instant::now()))
+        Ok(self
+            .lru
+            .duplicate(query, scope, lookup, cname_ttl, Instant::now()))
     }
 
     fn cache(
         &self,
         query: Query,
+        scope: Option<ClientSubnet>,
         records: Result<Vec<(Record, u32)>, ProtoError>,
     ) -> Result<Lookup, ProtoError> {
         // this will put this object into an inconsistent state, but no one should call poll again...
         match records {
-            Ok(rdata) => Ok(self.lru.insert(query, rdata, Instant::now())),
-            Err(err) => Err(self.lru.negative(query, err, Instant::now())),
+            Ok(rdata) => Ok(self.lru.insert(query, scope, rdata, Instant::now())),
+            Err(err) => Err(self.lru.negative(query, scope, err, Instant::now())),
         }
     }
 
     /// Flushes/Removes all entries from the cache
     pub fn clear_cache(&self) {
         self.lru.clear();
+        #[cfg(feature = "dnssec")]
+        if let Some(nsec_cache) = &self.nsec_cache {
+            nsec_cache.clear();
+        }
+    }
+
+    /// The cache of the client
+    pub(crate) fn lru(&self) -> &DnsLru {
+        &self.lru
+    }
+
+    /// Shares the caches of `other`, keeping the records it cached while using this client
+    #[cfg(unix)]
+    #[cfg(feature = "system-config")]
+    pub(crate) fn with_caches_of(mut self, other: &Self) -> Self {
+        self.lru = other.lru.clone();
+        #[cfg(feature = "dnssec")]
+        {
+            self.nsec_cache = other.nsec_cache.clone();
+        }
+        self
+    }
+}
+
+/// Returns the client subnet the response is valid for, or `None` if it is valid for all clients
+///
+/// A response without a Client Subnet option, or with a scope prefix of 0, is valid for all
+/// clients. A scope prefix longer than the source prefix is treated as the source prefix,
+/// [RFC 7871, section 7.3.2](https://tools.ietf.org/html/rfc7871#section-7.3.2).
+fn cache_scope(client_subnet: Option<&ClientSubnet>, response: &Message) -> Option<ClientSubnet> {
+    let client_subnet = client_subnet?;
+    let edns = response.extensions().as_ref()?;
+    let Some(EdnsOption::Subnet(subnet)) = edns.option(EdnsCode::Subnet) else {
+        return None;
+    };
+
+    match subnet.scope_prefix() {
+        0 => None,
+        scope_prefix => Some(client_subnet.truncate(scope_prefix)),
+    }
+}
+
+/// Returns true if the name servers answered that the records do not exist, as opposed to failing
+fn is_negative_response(error: &ProtoError) -> bool {
+    matches!(
+        error.kind(),
+        ProtoErrorKind::NoRecordsFound {
+            response_code: ResponseCode::NXDomain | ResponseCode::NoError,
+            ..
+        }
+    )
+}
+
+/// Runtime which runs the client response timer, and refreshes stale records in the background
+#[derive(Clone)]
+pub(crate) struct StaleRuntime(Arc<dyn StaleTasks>);
+
+impl StaleRuntime {
+    pub(crate) fn new<R: RuntimeProvider>(runtime_provider: R) -> Self {
+        Self(Arc::new(runtime_provider))
+    }
+
+    fn spawn(&self, refresh: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        self.0.spawn(refresh)
+    }
+
+    fn delay(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        self.0.delay(duration)
+    }
+}
+
+impl fmt::Debug for StaleRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaleRuntime").finish_non_exhaustive()
+    }
+}
+
+trait StaleTasks: Send + Sync {
+    fn spawn(&self, refresh: Pin<Box<dyn Future<Output = ()> + Send>>);
+
+    fn delay(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+impl<R: RuntimeProvider> StaleTasks for R {
+    fn spawn(&self, refresh: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        self.create_handle().spawn_bg(refresh.map(Ok));
+    }
+
+    fn delay(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        R::Timer::delay_for(duration)
     }
 }
 
@@ -517,7 +766,7 @@ mod tests {
     use std::str::FromStr;
     use std::time::*;
 
-    use crate::proto::op::{Message, Query};
+    use crate::proto::op::{Edns, Message, Query};
     use crate::proto::rr::rdata::{NS, SRV};
     use crate::proto::rr::{Name, Record};
     use futures_executor::block_on;
@@ -557,6 +806,7 @@ mod tests {
         let query = Query::new();
         cache.insert(
             query.clone(),
+            None,
             vec![(
                 Record::from_rdata(
                     query.name().clone(),
@@ -623,6 +873,104 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_serve_stale_on_error() {
+        let mut ttl_config = dns_lru::TtlConfig::default();
+        ttl_config.with_serve_stale(Some(dns_lru::ServeStaleConfig::default()));
+        let cache = DnsLru::new(1, ttl_config);
+        let query = Query::new();
+        // the records expired a few seconds ago
+        cache.insert(
+            query.clone(),
+            None,
+            vec![(
+                Record::from_rdata(query.name().clone(), 1, RData::A(A::new(127, 0, 0, 1))),
+                1,
+            )],
+            Instant::now() - Duration::from_secs(5),
+        );
+
+        let client = mock(vec![error()]);
+        let mut client = CachingClient::with_cache(cache.clone(), client, false);
+
+        let ips = block_on(client.lookup(query.clone(), DnsRequestOptions::default())).unwrap();
+
+        assert_eq!(
+            ips.iter().cloned().collect::<Vec<_>>(),
+            vec![RData::A(A::new(127, 0, 0, 1))]
+        );
+        assert_eq!(ips.record_iter().next().unwrap().ttl(), dns_lru::STALE_TTL);
+
+        // without serve stale, the error is returned
+        let cache = DnsLru::new(1, dns_lru::TtlConfig::default());
+        cache.insert(
+            query.clone(),
+            None,
+            vec![(
+                Record::from_rdata(query.name().clone(), 1, RData::A(A::new(127, 0, 0, 1))),
+                1,
+            )],
+            Instant::now() - Duration::from_secs(5),
+        );
+
+        let client = mock(vec![error()]);
+        let mut client = CachingClient::with_cache(cache, client, false);
+
+        assert!(block_on(client.lookup(query, DnsRequestOptions::default())).is_err());
+    }
+
+    #[test]
+    fn test_cache_client_subnet_scope() {
+        let cache = DnsLru::new(10, dns_lru::TtlConfig::default());
+        let query = Query::query(Name::root(), RecordType::A);
+        let client_subnet = ClientSubnet::from_str("198.51.100.0/24").unwrap();
+        let mut options = DnsRequestOptions::default();
+        options.client_subnet = Some(client_subnet);
+
+        // the name server answers for a /16 scope
+        let mut message = v4_message().unwrap().into_message();
+        message
+            .extensions_mut()
+            .get_or_insert_with(Edns::new)
+            .options_mut()
+            .insert(EdnsOption::Subnet(ClientSubnet::new(
+                client_subnet.addr(),
+                24,
+                16,
+            )));
+        let response = DnsResponse::from_message(message).unwrap();
+
+        let client = CachingClient::with_cache(cache.clone(), mock(vec![Ok(response)]), false);
+        block_on(CachingClient::inner_lookup(
+            query.clone(),
+            options,
+            client,
+            vec![],
+        ))
+        .unwrap();
+
+        let now = Instant::now();
+        let covered = ClientSubnet::from_str("198.51.1.0/24").unwrap();
+        let other = ClientSubnet::from_str("203.0.113.0/24").unwrap();
+        assert!(cache.get_for_subnet(&query, Some(&covered), now).is_some());
+        assert!(cache.get_for_subnet(&query, Some(&other), now).is_none());
+        assert!(cache.get(&query, now).is_none());
+
+        // without a scope in the response, the records are valid for all clients
+        let cache = DnsLru::new(10, dns_lru::TtlConfig::default());
+        let client = CachingClient::with_cache(cache.clone(), mock(vec![v4_message()]), false);
+        block_on(CachingClient::inner_lookup(
+            query.clone(),
+            options,
+            client,
+            vec![],
+        ))
+        .unwrap();
+
+        assert!(cache.get_for_subnet(&query, Some(&other), now).is_some());
+        assert!(cache.get(&query, now).is_some());
+    }
+
     #[allow(clippy::unnecessary_wraps)]
     pub(crate) fn cname_message() -> Result<DnsResponse, ProtoError> {
         let mut message = Message::new();
@@ -791,6 +1139,70 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_multiple_srv_targets_response() {
+        let cache = DnsLru::new(1, dns_lru::TtlConfig::default());
+
+        let mut message = srv_message().unwrap().into_message();
+        message.add_answer(Record::from_rdata(
+            Name::from_str("_443._tcp.www.example.com.").unwrap(),
+            86400,
+            RData::SRV(SRV::new(
+                2,
+                1,
+                8443,
+                Name::from_str("backup.example.com.").unwrap(),
+            )),
+        ));
+        message.insert_additionals(vec![
+            Record::from_rdata(
+                Name::from_str("www.example.com.").unwrap(),
+                86400,
+                RData::A(A::new(127, 0, 0, 1)),
+            ),
+            Record::from_rdata(
+                Name::from_str("backup.example.com.").unwrap(),
+                86400,
+                RData::A(A::new(127, 0, 0, 2)),
+            ),
+            Record::from_rdata(
+                Name::from_str("unrelated.example.com.").unwrap(),
+                86400,
+                RData::A(A::new(127, 0, 0, 3)),
+            ),
+        ]);
+
+        let client = mock(vec![
+            error(),
+            Ok(DnsResponse::from_message(message).unwrap()),
+        ]);
+        let client = CachingClient::with_cache(cache, client, false);
+
+        let lookup = block_on(CachingClient::inner_lookup(
+            Query::query(
+                Name::from_str("_443._tcp.www.example.com.").unwrap(),
+                RecordType::SRV,
+            ),
+            DnsRequestOptions::default(),
+            client,
+            vec![],
+        ))
+        .expect("lookup failed");
+
+        let ips = lookup
+            .iter()
+            .filter(|rdata| rdata.record_type() == RecordType::A)
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ips,
+            vec![
+                RData::A(A::new(127, 0, 0, 1)),
+                RData::A(A::new(127, 0, 0, 2)),
+            ]
+        );
+    }
+
     // TODO: if we ever enable recursive lookups for SRV, here are the tests...
     // #[test]
     // fn test_recursive_srv_query() {
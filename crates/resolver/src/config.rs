@@ -20,8 +20,12 @@ use rustls::ClientConfig;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::dns_lru::ServeStaleConfig;
+#[cfg(feature = "dns-over-odoh")]
+use crate::proto::odoh::OdohConfig;
+use crate::proto::rr::rdata::opt::ClientSubnet;
 use crate::proto::rr::Name;
-use crate::proto::xfer::Protocol;
+use crate::proto::xfer::{is_global, Protocol};
 
 /// Configuration for the upstream nameservers to use for resolution
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -35,6 +39,9 @@ pub struct ResolverConfig {
     search: Vec<Name>,
     // nameservers to use for resolution.
     name_servers: NameServerConfigGroup,
+    // nameservers to use for names within specific domains
+    #[cfg_attr(feature = "serde", serde(default))]
+    domain_name_servers: Vec<DomainNameServers>,
 }
 
 impl ResolverConfig {
@@ -45,6 +52,7 @@ impl ResolverConfig {
             domain: None,
             search: vec![],
             name_servers: NameServerConfigGroup::new(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -59,6 +67,7 @@ impl ResolverConfig {
             domain: None,
             search: vec![],
             name_servers: NameServerConfigGroup::google(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -74,6 +83,7 @@ impl ResolverConfig {
             domain: None,
             search: vec![],
             name_servers: NameServerConfigGroup::google_tls(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -89,6 +99,7 @@ impl ResolverConfig {
             domain: None,
             search: vec![],
             name_servers: NameServerConfigGroup::google_https(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -104,6 +115,7 @@ impl ResolverConfig {
             domain: None,
             search: vec![],
             name_servers: NameServerConfigGroup::google_h3(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -118,6 +130,7 @@ impl ResolverConfig {
             domain: None,
             search: vec![],
             name_servers: NameServerConfigGroup::cloudflare(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -133,6 +146,7 @@ impl ResolverConfig {
             domain: None,
             search: vec![],
             name_servers: NameServerConfigGroup::cloudflare_tls(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -148,6 +162,7 @@ impl ResolverConfig {
             domain: None,
             search: vec![],
             name_servers: NameServerConfigGroup::cloudflare_https(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -162,6 +177,7 @@ impl ResolverConfig {
             domain: None,
             search: vec![],
             name_servers: NameServerConfigGroup::quad9(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -177,6 +193,7 @@ impl ResolverConfig {
             domain: None,
             search: vec![],
             name_servers: NameServerConfigGroup::quad9_tls(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -192,6 +209,7 @@ impl ResolverConfig {
             domain: None,
             search: vec![],
             name_servers: NameServerConfigGroup::quad9_https(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -211,6 +229,7 @@ impl ResolverConfig {
             domain,
             search,
             name_servers: name_servers.into(),
+            domain_name_servers: vec![],
         }
     }
 
@@ -250,10 +269,52 @@ impl ResolverConfig {
         &self.name_servers
     }
 
+    /// Adds name servers that are used instead of the default ones for names within `domain`
+    ///
+    /// This allows split DNS, e.g. to send queries for an internal domain to the name servers of a
+    /// VPN. When several domains contain a name, the longest one is used. The `options`, if set,
+    /// configure how queries are sent to these name servers, see [`DomainNameServers::options`].
+    ///
+    /// ```
+    /// use std::net::{IpAddr, Ipv4Addr};
+    /// use std::str::FromStr;
+    ///
+    /// use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig};
+    /// use hickory_resolver::proto::rr::Name;
+    ///
+    /// let mut config = ResolverConfig::default();
+    /// config.add_domain_name_servers(
+    ///     Name::from_str("corp.example.").unwrap(),
+    ///     NameServerConfigGroup::from_ips_clear(&[IpAddr::V4(Ipv4Addr::new(10, 0, 0, 53))], 53, true),
+    ///     None,
+    /// );
+    /// ```
+    pub fn add_domain_name_servers(
+        &mut self,
+        domain: Name,
+        name_servers: NameServerConfigGroup,
+        options: Option<ResolverOpts>,
+    ) {
+        self.domain_name_servers.push(DomainNameServers {
+            domain,
+            name_servers,
+            options,
+        });
+    }
+
+    /// Returns the name servers used for names within specific domains
+    pub fn domain_name_servers(&self) -> &[DomainNameServers] {
+        &self.domain_name_servers
+    }
+
     /// return the associated TlsClientConfig
     #[cfg(feature = "dns-over-rustls")]
     pub fn client_config(&self) -> Option<&Arc<rustls::ClientConfig>> {
-        self.name_servers.tls.as_ref()
+        self.name_servers.client_config()
+    }
+
+    pub(crate) fn name_server_group(&self) -> &NameServerConfigGroup {
+        &self.name_servers
     }
 
     /// adds the `rustls::ClientConf` for every configured NameServer
@@ -284,6 +345,24 @@ impl ResolverConfig {
     }
 }
 
+/// Name servers for the names within a domain, see [`ResolverConfig::add_domain_name_servers`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DomainNameServers {
+    /// The domain, including all its subdomains
+    pub domain: Name,
+    /// The name servers to use for names within the domain
+    pub name_servers: NameServerConfigGroup,
+    /// Options of the pool of these name servers, the options of the resolver are used if unset
+    ///
+    /// Only the options used to send queries to the name servers apply: `timeout`,
+    /// `avoid_local_udp_ports`, `num_concurrent_reqs`, `server_ordering_strategy`,
+    /// `shuffle_dns_servers` and `try_tcp_on_error`. All other options, e.g. `attempts`, `ndots`,
+    /// `ip_strategy`, `edns0`, `validate` or `cache_size`, are always those of the resolver.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub options: Option<ResolverOpts>,
+}
+
 impl Default for ResolverConfig {
     /// Creates a default configuration, using `8.8.8.8`, `8.8.4.4` and `2001:4860:4860::8888`, `2001:4860:4860::8844` (thank you, Google).
     ///
@@ -311,6 +390,9 @@ pub struct NameServerConfig {
     pub tls_dns_name: Option<String>,
     /// The HTTP endpoint where the DNS NameServer provides service. Only
     /// relevant to DNS-over-HTTPS. Defaults to `/dns-query` if unspecified.
+    ///
+    /// For Oblivious DoH, the name server is the proxy, and this is its path naming the target,
+    /// e.g. `/dns-query?targethost=target.example.com&targetpath=/dns-query`.
     pub http_endpoint: Option<String>,
     /// Whether to trust `NXDOMAIN` responses from upstream nameservers.
     ///
@@ -333,6 +415,13 @@ pub struct NameServerConfig {
     /// The correct ALPN for the corresponding protocol is automatically
     /// inserted if none was specificed.
     pub tls_config: Option<Arc<rustls::ClientConfig>>,
+    /// The configuration of the target of Oblivious DoH queries, required by [`Protocol::Odoh`]
+    ///
+    /// Queries are encrypted to its public key. It must be obtained from the target itself, e.g.
+    /// at its `/.well-known/odohconfigs`, and is never fetched through the proxy.
+    #[cfg(feature = "dns-over-odoh")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub odoh_config: Option<OdohConfig>,
     /// The client address (IP and port) to use for connecting to the server.
     pub bind_addr: Option<SocketAddr>,
 }
@@ -348,6 +437,8 @@ impl NameServerConfig {
             http_endpoint: None,
             #[cfg(feature = "dns-over-rustls")]
             tls_config: None,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh_config: None,
             bind_addr: None,
         }
     }
@@ -444,6 +535,8 @@ impl NameServerConfigGroup {
                 trust_negative_responses,
                 #[cfg(feature = "dns-over-rustls")]
                 tls_config: None,
+                #[cfg(feature = "dns-over-odoh")]
+                odoh_config: None,
                 bind_addr: None,
             };
             let tcp = NameServerConfig {
@@ -454,6 +547,8 @@ impl NameServerConfigGroup {
                 trust_negative_responses,
                 #[cfg(feature = "dns-over-rustls")]
                 tls_config: None,
+                #[cfg(feature = "dns-over-odoh")]
+                odoh_config: None,
                 bind_addr: None,
             };
 
@@ -485,6 +580,8 @@ impl NameServerConfigGroup {
                 trust_negative_responses,
                 #[cfg(feature = "dns-over-rustls")]
                 tls_config: None,
+                #[cfg(feature = "dns-over-odoh")]
+                odoh_config: None,
                 bind_addr: None,
             };
 
@@ -680,6 +777,12 @@ impl NameServerConfigGroup {
         }
     }
 
+    /// return the associated [`rustls::ClientConfig`]
+    #[cfg(feature = "dns-over-rustls")]
+    pub fn client_config(&self) -> Option<&Arc<ClientConfig>> {
+        self.tls.as_ref()
+    }
+
     /// add a [`rustls::ClientConfig`]
     #[cfg(feature = "dns-over-rustls")]
     pub fn with_client_config(self, client_config: Arc<ClientConfig>) -> Self {
@@ -751,6 +854,25 @@ pub enum LookupIpStrategy {
     Ipv4thenIpv6,
 }
 
+impl LookupIpStrategy {
+    /// Selects from addresses that were not looked up with this strategy, e.g. those returned in
+    /// the additional section, the ones this strategy would have returned, in its order
+    pub(crate) fn select(self, mut ipv4: Vec<IpAddr>, mut ipv6: Vec<IpAddr>) -> Vec<IpAddr> {
+        match self {
+            Self::Ipv4Only => ipv4,
+            Self::Ipv6Only => ipv6,
+            Self::Ipv4AndIpv6 => {
+                ipv4.append(&mut ipv6);
+                ipv4
+            }
+            Self::Ipv6thenIpv4 if ipv6.is_empty() => ipv4,
+            Self::Ipv6thenIpv4 => ipv6,
+            Self::Ipv4thenIpv6 if ipv4.is_empty() => ipv6,
+            Self::Ipv4thenIpv6 => ipv4,
+        }
+    }
+}
+
 impl Default for LookupIpStrategy {
     /// Returns [`LookupIpStrategy::Ipv4thenIpv6`] as the default.
     fn default() -> Self {
@@ -796,7 +918,7 @@ pub enum ResolveHosts {
 }
 
 /// Configuration for the Resolver
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
@@ -822,6 +944,11 @@ pub struct ResolverOpts {
     pub edns0: bool,
     /// Use DNSSEC to validate the request
     pub validate: bool,
+    /// Answer queries from validated NSEC and NSEC3 records in the cache, when they prove that the
+    /// name or type does not exist, see [RFC 8198](https://tools.ietf.org/html/rfc8198)
+    ///
+    /// This requires `validate`, and is only available with the `dnssec` feature.
+    pub aggressive_nsec: bool,
     /// The ip_strategy for the Resolver to use when lookup Ipv4 or Ipv6 addresses
     pub ip_strategy: LookupIpStrategy,
     /// Cache size is in number of records (some records can be large)
@@ -852,6 +979,24 @@ pub struct ResolverOpts {
     ///
     /// [`MAX_TTL`]: ../dns_lru/const.MAX_TTL.html
     pub negative_max_ttl: Option<Duration>,
+    /// Serve expired records from the cache if the name servers fail, disabled by default.
+    ///
+    /// See [`ServeStaleConfig`] and [RFC 8767](https://tools.ietf.org/html/rfc8767).
+    pub serve_stale: Option<ServeStaleConfig>,
+    /// Send the EDNS Client Subnet of the clients on whose behalf lookups are made, disabled by
+    ///  default.
+    ///
+    /// See [`ClientSubnetConfig`] and [RFC 7871](https://tools.ietf.org/html/rfc7871).
+    pub edns_client_subnet: Option<ClientSubnetConfig>,
+    /// Discover the designated resolvers of the name servers, and upgrade to the encrypted
+    ///  transports they offer, disabled by default.
+    ///
+    /// Only designated resolvers whose certificates are valid for their target name, or for the
+    ///  address of the name server when the records have no target, are used, the name servers
+    ///  without one are kept. The discovery runs in the background,
+    ///  and is repeated when the records of the designated resolvers expire. This requires the
+    ///  `dns-over-rustls` feature, see [RFC 9462](https://datatracker.ietf.org/doc/html/rfc9462).
+    pub discover_designated_resolvers: bool,
     /// Number of concurrent requests per query
     ///
     /// Where more than one nameserver is configured, this configures the resolver to send queries
@@ -867,12 +1012,27 @@ pub struct ResolverOpts {
     ///
     /// This is true by default, disabling this is useful for requesting single records, but may prevent successful resolution.
     pub recursion_desired: bool,
-    /// This is true by default, disabling this is useful for requesting single records, but may prevent successful resolution.
+    /// Set the AD bit in queries, asking the name servers to indicate whether they validated the
+    ///  response. This is false by default, and is `trust-ad` in `resolv.conf`.
+    ///
+    /// Without it, glibc also clears the AD bit of responses. There is no bit to clear here, as
+    ///  lookups return the records of the responses but not their headers.
     pub authentic_data: bool,
     /// Shuffle DNS servers before each query.
     pub shuffle_dns_servers: bool,
     /// Local UDP ports to avoid when making outgoing queries
     pub avoid_local_udp_ports: Arc<HashSet<u16>>,
+    /// Networks by which the addresses of IP lookups are ordered, `sortlist` in `resolv.conf`
+    ///
+    /// Addresses in the first network come first, then those in the second, and so on; those in
+    ///  none of the networks come last. Empty by default, keeping the order of the response.
+    pub sortlist: Vec<SortlistNetwork>,
+    /// Never query AAAA records, `no-aaaa` in `resolv.conf`
+    ///
+    /// IP lookups only look up IPv4 addresses whatever the `ip_strategy`, and lookups of AAAA
+    ///  records, e.g. [`Resolver::ipv6_lookup`](crate::Resolver::ipv6_lookup), return no records
+    ///  without sending a query. False by default.
+    pub no_aaaa: bool,
 }
 
 impl Default for ResolverOpts {
@@ -888,6 +1048,7 @@ impl Default for ResolverOpts {
             check_names: true,
             edns0: false,
             validate: false,
+            aggressive_nsec: false,
             ip_strategy: LookupIpStrategy::default(),
             cache_size: 32,
             use_hosts_file: ResolveHosts::default(),
@@ -895,6 +1056,9 @@ impl Default for ResolverOpts {
             negative_min_ttl: None,
             positive_max_ttl: None,
             negative_max_ttl: None,
+            serve_stale: None,
+            edns_client_subnet: None,
+            discover_designated_resolvers: false,
             num_concurrent_reqs: 2,
 
             // Defaults to `true` to match the behavior of dig and nslookup.
@@ -906,6 +1070,84 @@ impl Default for ResolverOpts {
             authentic_data: false,
             shuffle_dns_servers: false,
             avoid_local_udp_ports: Arc::new(HashSet::new()),
+            sortlist: Vec::new(),
+            no_aaaa: false,
+        }
+    }
+}
+
+/// A network of [`ResolverOpts::sortlist`], an address and a netmask
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SortlistNetwork {
+    /// Address of the network
+    pub addr: IpAddr,
+    /// Netmask of the network, of the same family as `addr`
+    pub mask: IpAddr,
+}
+
+impl SortlistNetwork {
+    /// Returns whether `ip` is in this network
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, self.mask, ip) {
+            (IpAddr::V4(addr), IpAddr::V4(mask), IpAddr::V4(ip)) => {
+                let mask = u32::from(mask);
+                u32::from(addr) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(addr), IpAddr::V6(mask), IpAddr::V6(ip)) => {
+                let mask = u128::from(mask);
+                u128::from(addr) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Configuration for sending the EDNS Client Subnet, [RFC 7871](https://tools.ietf.org/html/rfc7871).
+///
+/// Only a prefix of the client address is sent to the name servers, and never for private,
+/// loopback or link-local addresses. Answers are cached for the scope the name servers return.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct ClientSubnetConfig {
+    /// Number of leading bits of IPv4 client addresses sent, defaults to 24 as recommended by
+    /// RFC 7871.
+    pub ipv4_source_prefix: u8,
+    /// Number of leading bits of IPv6 client addresses sent, defaults to 56 as recommended by
+    /// RFC 7871.
+    pub ipv6_source_prefix: u8,
+}
+
+impl ClientSubnetConfig {
+    /// Returns the client subnet to send for the client, if any
+    ///
+    /// The client subnet is truncated to the configured source prefix, or the source prefix of
+    /// `client` if that is shorter. No subnet is sent if the source prefix is 0, which a client
+    /// uses to opt out, or if the address is not globally routable.
+    pub fn client_subnet(&self, client: ClientSubnet) -> Option<ClientSubnet> {
+        let source_prefix = match client.addr() {
+            IpAddr::V4(_) => self.ipv4_source_prefix,
+            IpAddr::V6(_) => self.ipv6_source_prefix,
+        };
+
+        let client_subnet = client.truncate(source_prefix);
+        if client_subnet.source_prefix() == 0 || !is_global(client.addr()) {
+            return None;
+        }
+
+        Some(client_subnet)
+    }
+}
+
+impl Default for ClientSubnetConfig {
+    fn default() -> Self {
+        Self {
+            ipv4_source_prefix: 24,
+            ipv6_source_prefix: 56,
         }
     }
 }
@@ -933,3 +1175,65 @@ pub const QUAD9_IPS: &[IpAddr] = &[
     IpAddr::V6(Ipv6Addr::new(0x2620, 0x00fe, 0, 0, 0, 0, 0, 0x00fe)),
     IpAddr::V6(Ipv6Addr::new(0x2620, 0x00fe, 0, 0, 0, 0, 0x00fe, 0x0009)),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_subnet() {
+        let config = ClientSubnetConfig::default();
+        let client_subnet = |s: &str| config.client_subnet(s.parse().unwrap());
+
+        assert_eq!(
+            client_subnet("8.8.4.4/32"),
+            Some("8.8.4.0/24".parse().unwrap())
+        );
+        assert_eq!(
+            client_subnet("2001:4860:4860::8844/128"),
+            Some("2001:4860:4860::/56".parse().unwrap())
+        );
+        // the client's own source prefix is kept if shorter
+        assert_eq!(
+            client_subnet("8.8.4.4/16"),
+            Some("8.8.0.0/16".parse().unwrap())
+        );
+        // a source prefix of 0 opts out
+        assert_eq!(client_subnet("8.8.4.4/0"), None);
+
+        for private in [
+            "10.1.2.3/32",
+            "192.168.1.1/32",
+            "172.16.0.1/32",
+            "100.64.0.1/32",
+            "127.0.0.1/32",
+            "169.254.0.1/32",
+            "0.0.0.0/32",
+            "::1/128",
+            "fd00::1/128",
+            "fe80::1/128",
+            "::ffff:10.0.0.1/128",
+        ] {
+            assert_eq!(client_subnet(private), None, "{private}");
+        }
+    }
+
+    #[test]
+    fn test_sortlist_network() {
+        let network = SortlistNetwork {
+            addr: Ipv4Addr::new(130, 155, 160, 0).into(),
+            mask: Ipv4Addr::new(255, 255, 240, 0).into(),
+        };
+        assert!(network.contains(Ipv4Addr::new(130, 155, 175, 1).into()));
+        assert!(!network.contains(Ipv4Addr::new(130, 155, 176, 1).into()));
+        assert!(!network.contains(Ipv6Addr::LOCALHOST.into()));
+
+        let network = SortlistNetwork {
+            addr: Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0).into(),
+            mask: Ipv6Addr::new(0xffff, 0xffff, 0, 0, 0, 0, 0, 0).into(),
+        };
+        assert!(network.contains(Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 1).into()));
+        assert!(!network.contains(Ipv6Addr::new(0x2001, 0xdb9, 0, 0, 0, 0, 0, 1).into()));
+        assert!(!network.contains(Ipv4Addr::LOCALHOST.into()));
+    }
+}
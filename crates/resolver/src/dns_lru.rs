@@ -7,20 +7,23 @@
 
 //! An LRU cache designed for work with DNS lookups
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
 use std::ops::RangeInclusive;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use moka::{sync::Cache, Expiry};
+use parking_lot::RwLock;
 #[cfg(feature = "serde")]
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::config;
 use crate::lookup::Lookup;
 #[cfg(feature = "dnssec")]
 use crate::proto::dnssec::rdata::RRSIG;
 use crate::proto::op::Query;
+use crate::proto::rr::rdata::opt::ClientSubnet;
 #[cfg(feature = "dnssec")]
 use crate::proto::rr::RecordData;
 use crate::proto::rr::{Record, RecordType};
@@ -33,11 +36,57 @@ use crate::proto::{ProtoError, ProtoErrorKind};
 /// upper bound on received TTLs.
 pub(crate) const MAX_TTL: u32 = 86400_u32;
 
+/// TTL of stale records, in seconds
+///
+/// This is also the time before a stale record is refreshed again after a failed attempt, see
+/// [RFC 8767, section 5](https://tools.ietf.org/html/rfc8767#section-5).
+pub const STALE_TTL: u32 = 30;
+
+/// Records are cached for the query, and the client subnet they are valid for
+///
+/// Answers to queries with an EDNS Client Subnet are only valid for clients within the scope the
+/// name servers returned, [RFC 7871, section 7.3](https://tools.ietf.org/html/rfc7871#section-7.3).
+/// Answers without a scope are valid for all clients.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    query: Query,
+    scope: Option<ClientSubnet>,
+}
+
+/// The prefix lengths of the scopes records have been cached for, by address family
+///
+/// Lookups for a client subnet only probe these prefix lengths, rather than every prefix length
+/// up to the source prefix of the client.
+#[derive(Debug, Default)]
+struct ScopePrefixes {
+    ipv4: BTreeSet<u8>,
+    ipv6: BTreeSet<u8>,
+}
+
+impl ScopePrefixes {
+    fn for_addr(&self, addr: IpAddr) -> &BTreeSet<u8> {
+        match addr {
+            IpAddr::V4(_) => &self.ipv4,
+            IpAddr::V6(_) => &self.ipv6,
+        }
+    }
+
+    fn insert(&mut self, scope: &ClientSubnet) {
+        let prefixes = match scope.addr() {
+            IpAddr::V4(_) => &mut self.ipv4,
+            IpAddr::V6(_) => &mut self.ipv6,
+        };
+        prefixes.insert(scope.source_prefix());
+    }
+}
+
 #[derive(Debug, Clone)]
 struct LruValue {
     // In the Err case, this represents an NXDomain
     lookup: Result<Lookup, ProtoError>,
     valid_until: Instant,
+    /// The value may be served stale until this time, when it is removed from the cache
+    stale_until: Instant,
 }
 
 impl LruValue {
@@ -46,6 +95,11 @@ impl LruValue {
         now <= self.valid_until
     }
 
+    /// Returns true if this value is expired, but may be served stale
+    fn is_stale(&self, now: Instant) -> bool {
+        !self.is_current(now) && now <= self.stale_until
+    }
+
     /// Returns the ttl as a Duration of time remaining.
     fn ttl(&self, now: Instant) -> Duration {
         self.valid_until.saturating_duration_since(now)
@@ -67,13 +121,15 @@ impl LruValue {
                     lookup.query().clone(),
                     Arc::from(records),
                     self.valid_until,
-                ))
+                )
+                .with_client_subnet_scope(lookup.client_subnet_scope()))
             }
             Err(e) => Err(e.clone()),
         };
         Self {
             lookup,
             valid_until: self.valid_until,
+            stale_until: self.stale_until,
         }
     }
 }
@@ -84,7 +140,8 @@ impl LruValue {
 /// in the `moka` library.
 #[derive(Clone, Debug)]
 pub struct DnsLru {
-    cache: Cache<Query, LruValue>,
+    cache: Cache<CacheKey, LruValue>,
+    scope_prefixes: Arc<RwLock<ScopePrefixes>>,
     ttl_config: Arc<TtlConfig>,
 }
 
@@ -110,6 +167,9 @@ pub struct TtlConfig {
 
     /// TTL limits applied to queries with specific query types.
     by_query_type: HashMap<RecordType, TtlBounds>,
+
+    /// Serving of expired records, disabled by default.
+    serve_stale: Option<ServeStaleConfig>,
 }
 
 impl TtlConfig {
@@ -123,6 +183,7 @@ impl TtlConfig {
                 negative_max_ttl: opts.negative_max_ttl,
             },
             by_query_type: HashMap::new(),
+            serve_stale: opts.serve_stale,
         }
     }
 
@@ -147,6 +208,7 @@ impl TtlConfig {
                 negative_max_ttl,
             },
             by_query_type: HashMap::new(),
+            serve_stale: None,
         }
     }
 
@@ -174,6 +236,17 @@ impl TtlConfig {
         self
     }
 
+    /// Enables serving expired records, see [`ServeStaleConfig`].
+    pub fn with_serve_stale(&mut self, serve_stale: Option<ServeStaleConfig>) -> &mut Self {
+        self.serve_stale = serve_stale;
+        self
+    }
+
+    /// Retrieves the configuration for serving expired records, if enabled.
+    pub fn serve_stale(&self) -> Option<&ServeStaleConfig> {
+        self.serve_stale.as_ref()
+    }
+
     /// Retrieves the minimum and maximum TTL values for positive responses.
     pub fn positive_response_ttl_bounds(&self, query_type: RecordType) -> RangeInclusive<Duration> {
         let bounds = self.by_query_type.get(&query_type).unwrap_or(&self.default);
@@ -199,6 +272,45 @@ impl TtlConfig {
     }
 }
 
+/// Configuration for serving expired records from the cache, [RFC 8767](https://tools.ietf.org/html/rfc8767).
+///
+/// If the name servers fail to answer a query, or take longer than the `client_response_timeout`,
+/// the expired records for the query are answered with a TTL of [`STALE_TTL`] seconds. The query
+/// to the name servers continues in the background, and replaces the expired records once they
+/// answer.
+///
+/// The timeout and the background queries need the runtime of the
+/// [`ConnectionProvider`](crate::name_server::ConnectionProvider), see its `runtime_provider`.
+/// Without one, expired records are only answered once the name servers fail.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct ServeStaleConfig {
+    /// How long records are kept, and may be served, after they expire. Defaults to one day.
+    ///
+    /// This is configured in seconds.
+    #[cfg_attr(feature = "serde", serde(with = "duration_secs"))]
+    pub max_stale: Duration,
+    /// How long to wait for the name servers before answering with expired records. Defaults to
+    /// 1.8 seconds, as recommended by RFC 8767.
+    ///
+    /// This is configured in milliseconds.
+    #[cfg_attr(feature = "serde", serde(with = "duration_millis"))]
+    pub client_response_timeout: Duration,
+}
+
+impl Default for ServeStaleConfig {
+    fn default() -> Self {
+        Self {
+            max_stale: Duration::from_secs(u64::from(MAX_TTL)),
+            client_response_timeout: Duration::from_millis(1800),
+        }
+    }
+}
+
 /// Minimum and maximum TTL values for positive and negative responses.
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize))]
@@ -259,17 +371,20 @@ impl DnsLru {
             .build();
         Self {
             cache,
+            scope_prefixes: Arc::default(),
             ttl_config: Arc::new(ttl_config),
         }
     }
 
     pub(crate) fn clear(&self) {
         self.cache.invalidate_all();
+        *self.scope_prefixes.write() = ScopePrefixes::default();
     }
 
     pub(crate) fn insert(
         &self,
         query: Query,
+        scope: Option<ClientSubnet>,
         records_and_ttl: Vec<(Record, u32)>,
         now: Instant,
     ) -> Lookup {
@@ -296,12 +411,14 @@ impl DnsLru {
         let valid_until = now + ttl;
 
         // insert into the LRU
-        let lookup = Lookup::new_with_deadline(query.clone(), Arc::from(records), valid_until);
-        self.cache.insert(
-            query,
+        let lookup = Lookup::new_with_deadline(query.clone(), Arc::from(records), valid_until)
+            .with_client_subnet_scope(scope.as_ref().map(ClientSubnet::source_prefix));
+        self.insert_value(
+            CacheKey { query, scope },
             LruValue {
                 lookup: Ok(lookup.clone()),
                 valid_until,
+                stale_until: self.stale_until(valid_until),
             },
         );
 
@@ -384,7 +501,7 @@ impl DnsLru {
         let mut lookup = None;
         for (query, records_and_ttl) in records {
             let is_query = original_query == query;
-            let inserted = self.insert(query, records_and_ttl, now);
+            let inserted = self.insert(query, None, records_and_ttl, now);
 
             if is_query {
                 lookup = Some(inserted)
@@ -395,15 +512,25 @@ impl DnsLru {
     }
 
     /// Generally for inserting a set of records that have already been cached, but with a different Query.
-    pub(crate) fn duplicate(&self, query: Query, lookup: Lookup, ttl: u32, now: Instant) -> Lookup {
+    pub(crate) fn duplicate(
+        &self,
+        query: Query,
+        scope: Option<ClientSubnet>,
+        lookup: Lookup,
+        ttl: u32,
+        now: Instant,
+    ) -> Lookup {
         let ttl = Duration::from_secs(u64::from(ttl));
         let valid_until = now + ttl;
+        let lookup =
+            lookup.with_client_subnet_scope(scope.as_ref().map(ClientSubnet::source_prefix));
 
-        self.cache.insert(
-            query,
+        self.insert_value(
+            CacheKey { query, scope },
             LruValue {
                 lookup: Ok(lookup.clone()),
                 valid_until,
+                stale_until: self.stale_until(valid_until),
             },
         );
 
@@ -420,7 +547,13 @@ impl DnsLru {
         }
     }
 
-    pub(crate) fn negative(&self, query: Query, mut error: ProtoError, now: Instant) -> ProtoError {
+    pub(crate) fn negative(
+        &self,
+        query: Query,
+        scope: Option<ClientSubnet>,
+        mut error: ProtoError,
+        now: Instant,
+    ) -> ProtoError {
         let ProtoError { kind, .. } = &error;
 
         // TODO: if we are getting a negative response, should we instead fallback to cache?
@@ -444,11 +577,12 @@ impl DnsLru {
             {
                 let error = error.clone();
 
-                self.cache.insert(
-                    query,
+                self.insert_value(
+                    CacheKey { query, scope },
                     LruValue {
                         lookup: Err(error),
                         valid_until,
+                        stale_until: self.stale_until(valid_until),
                     },
                 );
             }
@@ -461,7 +595,20 @@ impl DnsLru {
 
     /// Based on the query, see if there are any records available
     pub fn get(&self, query: &Query, now: Instant) -> Option<Result<Lookup, ProtoError>> {
-        let value = self.cache.get(query)?;
+        self.get_for_subnet(query, None, now)
+    }
+
+    /// Based on the query and the client subnet, see if there are any records available
+    ///
+    /// Records cached for the most specific scope covering the client subnet are returned, falling
+    /// back to records valid for all clients.
+    pub fn get_for_subnet(
+        &self,
+        query: &Query,
+        client_subnet: Option<&ClientSubnet>,
+        now: Instant,
+    ) -> Option<Result<Lookup, ProtoError>> {
+        let (_, value) = self.find(query, client_subnet)?;
         if !value.is_current(now) {
             return None;
         }
@@ -471,6 +618,107 @@ impl DnsLru {
         }
         Some(result)
     }
+
+    /// The configuration for serving expired records, if enabled
+    pub fn serve_stale_config(&self) -> Option<&ServeStaleConfig> {
+        self.ttl_config.serve_stale()
+    }
+
+    /// Returns true if the records for the query are expired, but may be served stale
+    pub fn is_stale(&self, query: &Query, now: Instant) -> bool {
+        self.is_stale_for_subnet(query, None, now)
+    }
+
+    /// Returns true if the records for the query and client subnet are expired, but may be served
+    /// stale
+    pub fn is_stale_for_subnet(
+        &self,
+        query: &Query,
+        client_subnet: Option<&ClientSubnet>,
+        now: Instant,
+    ) -> bool {
+        self.find(query, client_subnet)
+            .is_some_and(|(_, value)| value.is_stale(now))
+    }
+
+    /// Returns the records for the query, even if they are expired but may still be served stale
+    ///
+    /// Expired records are answered with a TTL of [`STALE_TTL`], and are considered current
+    /// again for that time, so the name servers are not queried for every request while they
+    /// fail, [RFC 8767, section 5](https://tools.ietf.org/html/rfc8767#section-5).
+    pub fn get_stale(&self, query: &Query, now: Instant) -> Option<Result<Lookup, ProtoError>> {
+        self.get_stale_for_subnet(query, None, now)
+    }
+
+    /// Returns the records for the query and client subnet, even if they are expired but may
+    /// still be served stale, see [`Self::get_stale`]
+    pub fn get_stale_for_subnet(
+        &self,
+        query: &Query,
+        client_subnet: Option<&ClientSubnet>,
+        now: Instant,
+    ) -> Option<Result<Lookup, ProtoError>> {
+        let (key, value) = self.find(query, client_subnet)?;
+        if value.is_stale(now) {
+            let valid_until =
+                (now + Duration::from_secs(u64::from(STALE_TTL))).min(value.stale_until);
+            self.cache.insert(
+                key,
+                LruValue {
+                    lookup: value.lookup,
+                    valid_until,
+                    stale_until: value.stale_until,
+                },
+            );
+        }
+
+        self.get_for_subnet(query, client_subnet, now)
+    }
+
+    /// Finds the cached value for the most specific scope covering the client subnet
+    fn find(
+        &self,
+        query: &Query,
+        client_subnet: Option<&ClientSubnet>,
+    ) -> Option<(CacheKey, LruValue)> {
+        let mut key = CacheKey {
+            query: query.clone(),
+            scope: None,
+        };
+
+        if let Some(client_subnet) = client_subnet {
+            let scope_prefixes = self.scope_prefixes.read();
+            let prefixes = scope_prefixes
+                .for_addr(client_subnet.addr())
+                .range(1..=client_subnet.source_prefix());
+            for prefix in prefixes.rev() {
+                key.scope = Some(client_subnet.truncate(*prefix));
+                if let Some(value) = self.cache.get(&key) {
+                    return Some((key, value));
+                }
+            }
+            key.scope = None;
+        }
+
+        let value = self.cache.get(&key)?;
+        Some((key, value))
+    }
+
+    /// Inserts the value, recording the prefix length of its scope for lookups
+    fn insert_value(&self, key: CacheKey, value: LruValue) {
+        if let Some(scope) = &key.scope {
+            self.scope_prefixes.write().insert(scope);
+        }
+        self.cache.insert(key, value);
+    }
+
+    /// Records may be served stale until this time, if enabled
+    fn stale_until(&self, valid_until: Instant) -> Instant {
+        match self.ttl_config.serve_stale() {
+            Some(serve_stale) => valid_until + serve_stale.max_stale,
+            None => valid_until,
+        }
+    }
 }
 
 /// This is an alternate deserialization function for an optional [`Duration`] that expects a single
@@ -486,29 +734,73 @@ where
     )
 }
 
+/// (De)serializes a [`Duration`] as a number of seconds
+#[cfg(feature = "serde")]
+mod duration_secs {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+/// (De)serializes a [`Duration`] as a number of milliseconds
+#[cfg(feature = "serde")]
+mod duration_millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+mod snapshot;
+
 #[cfg(feature = "serde")]
 mod ttl_config_deserialize;
 
 struct LruValueExpiry;
 
-impl Expiry<Query, LruValue> for LruValueExpiry {
+impl Expiry<CacheKey, LruValue> for LruValueExpiry {
     fn expire_after_create(
         &self,
-        _key: &Query,
+        _key: &CacheKey,
         value: &LruValue,
         created_at: Instant,
     ) -> Option<Duration> {
-        Some(value.ttl(created_at))
+        Some(value.stale_until.saturating_duration_since(created_at))
     }
 
     fn expire_after_update(
         &self,
-        _key: &Query,
+        _key: &CacheKey,
         value: &LruValue,
         updated_at: Instant,
         _duration_until_expiry: Option<Duration>,
     ) -> Option<Duration> {
-        Some(value.ttl(updated_at))
+        Some(value.stale_until.saturating_duration_since(updated_at))
     }
 }
 
@@ -536,6 +828,7 @@ mod tests {
         let value = LruValue {
             lookup: Err(ProtoErrorKind::Message("test error").into()),
             valid_until: future,
+            stale_until: future,
         };
 
         assert!(value.is_current(now));
@@ -567,7 +860,7 @@ mod tests {
         };
         let lru = DnsLru::new(1, ttls);
 
-        let rc_ips = lru.insert(query.clone(), ips_ttl, now);
+        let rc_ips = lru.insert(query.clone(), None, ips_ttl, now);
         assert_eq!(*rc_ips.iter().next().unwrap(), ips[0]);
         // the returned lookup should use the cache's min TTL, since the
         // query's TTL was below the minimum.
@@ -579,7 +872,7 @@ mod tests {
             3,
         )];
 
-        let rc_ips = lru.insert(query, ips_ttl, now);
+        let rc_ips = lru.insert(query, None, ips_ttl, now);
         assert_eq!(*rc_ips.iter().next().unwrap(), ips[0]);
         // the returned lookup should use the record's TTL, since it's
         // greater than the cache's minimum.
@@ -612,7 +905,7 @@ mod tests {
             trusted: false,
             authorities: None,
         };
-        let nx_error = lru.negative(name.clone(), err.into(), now);
+        let nx_error = lru.negative(name.clone(), None, err.into(), now);
         match nx_error.kind() {
             &ProtoErrorKind::NoRecordsFound { negative_ttl, .. } => {
                 let valid_until = negative_ttl.expect("resolve error should have a deadline");
@@ -632,7 +925,7 @@ mod tests {
             trusted: false,
             authorities: None,
         };
-        let nx_error = lru.negative(name, err.into(), now);
+        let nx_error = lru.negative(name, None, err.into(), now);
         match nx_error.kind() {
             &ProtoErrorKind::NoRecordsFound { negative_ttl, .. } => {
                 let negative_ttl = negative_ttl.expect("ProtoError should have a deadline");
@@ -667,7 +960,7 @@ mod tests {
         };
         let lru = DnsLru::new(1, ttls);
 
-        let rc_ips = lru.insert(query.clone(), ips_ttl, now);
+        let rc_ips = lru.insert(query.clone(), None, ips_ttl, now);
         assert_eq!(*rc_ips.iter().next().unwrap(), ips[0]);
         // the returned lookup should use the cache's min TTL, since the
         // query's TTL was above the maximum.
@@ -679,7 +972,7 @@ mod tests {
             59,
         )];
 
-        let rc_ips = lru.insert(query, ips_ttl, now);
+        let rc_ips = lru.insert(query, None, ips_ttl, now);
         assert_eq!(*rc_ips.iter().next().unwrap(), ips[0]);
         // the returned lookup should use the record's TTL, since it's
         // below than the cache's maximum.
@@ -712,7 +1005,7 @@ mod tests {
             trusted: false,
             authorities: None,
         };
-        let nx_error = lru.negative(name.clone(), err.into(), now);
+        let nx_error = lru.negative(name.clone(), None, err.into(), now);
         match nx_error.kind() {
             &ProtoErrorKind::NoRecordsFound { negative_ttl, .. } => {
                 let negative_ttl = negative_ttl.expect("resolve error should have a deadline");
@@ -732,7 +1025,7 @@ mod tests {
             trusted: false,
             authorities: None,
         };
-        let nx_error = lru.negative(name, err.into(), now);
+        let nx_error = lru.negative(name, None, err.into(), now);
         match nx_error.kind() {
             &ProtoErrorKind::NoRecordsFound { negative_ttl, .. } => {
                 let negative_ttl = negative_ttl.expect("resolve error should have a deadline");
@@ -757,7 +1050,7 @@ mod tests {
         let ips = [RData::A(A::new(127, 0, 0, 1))];
         let lru = DnsLru::new(1, TtlConfig::default());
 
-        let rc_ips = lru.insert(query.clone(), ips_ttl, now);
+        let rc_ips = lru.insert(query.clone(), None, ips_ttl, now);
         assert_eq!(*rc_ips.iter().next().unwrap(), ips[0]);
 
         let rc_ips = lru.get(&query, now).unwrap().expect("records should exist");
@@ -777,7 +1070,7 @@ mod tests {
         let ips = [RData::A(A::new(127, 0, 0, 1))];
         let lru = DnsLru::new(1, TtlConfig::default());
 
-        let rc_ips = lru.insert(query.clone(), ips_ttl, now);
+        let rc_ips = lru.insert(query.clone(), None, ips_ttl, now);
         assert_eq!(*rc_ips.iter().next().unwrap(), ips[0]);
 
         let ttl = lru
@@ -813,7 +1106,7 @@ mod tests {
         ];
         let lru = DnsLru::new(1, TtlConfig::default());
 
-        lru.insert(query.clone(), ips_ttl, now);
+        lru.insert(query.clone(), None, ips_ttl, now);
 
         // still valid
         let rc_ips = lru
@@ -858,7 +1151,7 @@ mod tests {
             ..TtlConfig::default()
         };
         let lru = DnsLru::new(1, ttls);
-        lru.insert(query.clone(), ips_ttl, now);
+        lru.insert(query.clone(), None, ips_ttl, now);
 
         // still valid
         let rc_ips = lru
@@ -890,6 +1183,136 @@ mod tests {
         assert!(rc_ips.is_none());
     }
 
+    #[test]
+    fn test_get_stale() {
+        let now = Instant::now();
+        let name = Name::from_str("www.example.com.").unwrap();
+        let query = Query::query(name.clone(), RecordType::A);
+        let ips_ttl = vec![(
+            Record::from_rdata(name, 1, RData::A(A::new(127, 0, 0, 1))),
+            1,
+        )];
+
+        let mut ttls = TtlConfig::default();
+        ttls.with_serve_stale(Some(ServeStaleConfig {
+            max_stale: Duration::from_secs(60),
+            ..ServeStaleConfig::default()
+        }));
+        let lru = DnsLru::new(1, ttls);
+        lru.insert(query.clone(), None, ips_ttl, now);
+
+        // current records are not stale
+        assert!(!lru.is_stale(&query, now));
+
+        // expired records are only answered stale
+        let expired = now + Duration::from_secs(2);
+        assert!(lru.get(&query, expired).is_none());
+        assert!(lru.is_stale(&query, expired));
+
+        let stale = lru
+            .get_stale(&query, expired)
+            .unwrap()
+            .expect("records should exist");
+        assert_eq!(stale.record_iter().next().unwrap().ttl(), STALE_TTL);
+        assert_eq!(
+            *stale.iter().next().unwrap(),
+            RData::A(A::new(127, 0, 0, 1))
+        );
+
+        // stale records are current for the stale TTL
+        assert!(!lru.is_stale(&query, expired));
+        assert!(lru.get(&query, expired + Duration::from_secs(1)).is_some());
+
+        // but not past the max stale window
+        let past_window = now + Duration::from_secs(62);
+        assert!(!lru.is_stale(&query, past_window));
+        assert!(lru.get_stale(&query, past_window).is_none());
+    }
+
+    #[test]
+    fn test_get_stale_disabled() {
+        let now = Instant::now();
+        let name = Name::from_str("www.example.com.").unwrap();
+        let query = Query::query(name.clone(), RecordType::A);
+        let ips_ttl = vec![(
+            Record::from_rdata(name, 1, RData::A(A::new(127, 0, 0, 1))),
+            1,
+        )];
+
+        let lru = DnsLru::new(1, TtlConfig::default());
+        lru.insert(query.clone(), None, ips_ttl, now);
+
+        let expired = now + Duration::from_secs(2);
+        assert!(!lru.is_stale(&query, expired));
+        assert!(lru.get_stale(&query, expired).is_none());
+    }
+
+    #[test]
+    fn test_get_for_subnet() {
+        let now = Instant::now();
+        let name = Name::from_str("www.example.com.").unwrap();
+        let query = Query::query(name.clone(), RecordType::A);
+        let record = |ip| {
+            vec![(
+                Record::from_rdata(name.clone(), 10, RData::A(A::new(192, 0, 2, ip))),
+                10,
+            )]
+        };
+
+        let lru = DnsLru::new(10, TtlConfig::default());
+        lru.insert(query.clone(), None, record(1), now);
+        lru.insert(
+            query.clone(),
+            Some(ClientSubnet::from_str("198.51.100.0/24").unwrap()),
+            record(2),
+            now,
+        );
+        lru.insert(
+            query.clone(),
+            Some(ClientSubnet::from_str("203.0.0.0/16").unwrap()),
+            record(3),
+            now,
+        );
+
+        let first = |client_subnet: Option<&str>| {
+            let client_subnet = client_subnet.map(|s| ClientSubnet::from_str(s).unwrap());
+            let lookup = lru
+                .get_for_subnet(&query, client_subnet.as_ref(), now)
+                .unwrap()
+                .expect("records should exist");
+            lookup.iter().next().unwrap().clone()
+        };
+
+        // the most specific scope covering the client subnet is answered
+        assert_eq!(
+            first(Some("198.51.100.0/24")),
+            RData::A(A::new(192, 0, 2, 2))
+        );
+        assert_eq!(
+            first(Some("203.0.113.0/24")),
+            RData::A(A::new(192, 0, 2, 3))
+        );
+        // otherwise the records valid for all clients
+        assert_eq!(
+            first(Some("198.51.101.0/24")),
+            RData::A(A::new(192, 0, 2, 1))
+        );
+        assert_eq!(first(None), RData::A(A::new(192, 0, 2, 1)));
+        // a scope longer than the source prefix does not cover the client
+        assert_eq!(first(Some("198.51.0.0/16")), RData::A(A::new(192, 0, 2, 1)));
+
+        // the records carry the scope they are cached for
+        let scope = |client_subnet: &str| {
+            let client_subnet = ClientSubnet::from_str(client_subnet).unwrap();
+            lru.get_for_subnet(&query, Some(&client_subnet), now)
+                .unwrap()
+                .expect("records should exist")
+                .client_subnet_scope()
+        };
+        assert_eq!(scope("203.0.113.0/24"), Some(16));
+        assert_eq!(scope("198.51.101.0/24"), None);
+    }
+
     #[test]
     fn test_insert_positive_max_ttl() {
         let now = Instant::now();
@@ -921,7 +1344,7 @@ mod tests {
             ..TtlConfig::default()
         };
         let lru = DnsLru::new(1, ttls);
-        lru.insert(query.clone(), ips_ttl, now);
+        lru.insert(query.clone(), None, ips_ttl, now);
 
         // still valid
         let rc_ips = lru
@@ -969,13 +1392,13 @@ mod tests {
         );
         let lru = DnsLru::new(2, ttl_config);
 
-        let rc_a = lru.insert(query_a.clone(), records_ttl_a, now);
+        let rc_a = lru.insert(query_a.clone(), None, records_ttl_a, now);
         assert_eq!(*rc_a.iter().next().unwrap(), rdata_a);
         // the returned lookup should use the cache's default min TTL, since the
         // response's TTL was below the minimum.
         assert_eq!(rc_a.valid_until(), now + Duration::from_secs(2));
 
-        let rc_txt = lru.insert(query_txt.clone(), records_ttl_txt, now);
+        let rc_txt = lru.insert(query_txt.clone(), None, records_ttl_txt, now);
         assert_eq!(*rc_txt.iter().next().unwrap(), rdata_txt);
         // the returned lookup should use the min TTL for TXT records, since the
         // response's TTL was below the minimum.
@@ -985,16 +1408,29 @@ mod tests {
         let records_ttl_a = vec![(Record::from_rdata(name.clone(), 1, rdata_a.clone()), 7)];
         let records_ttl_txt = vec![(Record::from_rdata(name.clone(), 1, rdata_txt.clone()), 7)];
 
-        let rc_a = lru.insert(query_a, records_ttl_a, now);
+        let rc_a = lru.insert(query_a, None, records_ttl_a, now);
         assert_eq!(*rc_a.iter().next().unwrap(), rdata_a);
         // the returned lookup should use the record's TTL, since it's
         // greater than the default min TTL.
         assert_eq!(rc_a.valid_until(), now + Duration::from_secs(7));
 
-        let rc_txt = lru.insert(query_txt, records_ttl_txt, now);
+        let rc_txt = lru.insert(query_txt, None, records_ttl_txt, now);
         assert_eq!(*rc_txt.iter().next().unwrap(), rdata_txt);
         // the returned lookup should use the record's TTL, since it's
         // greater than the min TTL for TXT records.
         assert_eq!(rc_txt.valid_until(), now + Duration::from_secs(7));
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serve_stale_config_deserialize() {
+        let config =
+            toml::from_str::<ServeStaleConfig>("max_stale = 3600\nclient_response_timeout = 500")
+                .unwrap();
+        assert_eq!(config.max_stale, Duration::from_secs(3600));
+        assert_eq!(config.client_response_timeout, Duration::from_millis(500));
+
+        let config = toml::from_str::<ServeStaleConfig>("").unwrap();
+        assert_eq!(config, ServeStaleConfig::default());
+    }
 }
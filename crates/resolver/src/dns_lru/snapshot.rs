@@ -0,0 +1,277 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Snapshots of the cache, so it can be restored after a restart
+
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::{CacheKey, DnsLru, LruValue};
+use crate::lookup::Lookup;
+use crate::proto::op::Query;
+use crate::proto::rr::rdata::opt::ClientSubnet;
+use crate::proto::rr::Record;
+use crate::proto::serialize::binary::{BinDecodable, BinDecoder, BinEncodable, BinEncoder};
+use crate::proto::ProtoError;
+
+/// Leading bytes of every snapshot
+const MAGIC: &[u8] = b"HDNSLRU";
+
+/// Version of the snapshot format, incremented on incompatible changes
+const VERSION: u8 = 1;
+
+impl DnsLru {
+    /// Writes a snapshot of the cached records, returning the number of cached queries written
+    ///
+    /// Only current positive answers are written, along with the time they remain valid. Negative
+    /// answers, and records that may only be served stale, are left out. DNSSEC proofs are not
+    /// preserved.
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<usize> {
+        let (bytes, count) = self
+            .snapshot(Instant::now(), SystemTime::now())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+        writer.write_all(&bytes)?;
+        Ok(count)
+    }
+
+    /// Restores a snapshot written by [`Self::save`], returning the number of cached queries
+    /// restored
+    ///
+    /// The remaining TTLs of the records are reduced by the time passed since the snapshot was
+    /// written, and records that have expired in the meantime are dropped.
+    pub fn load<R: Read>(&self, mut reader: R) -> io::Result<usize> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        self.restore(&bytes, Instant::now(), SystemTime::now())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn snapshot(
+        &self,
+        now: Instant,
+        system_now: SystemTime,
+    ) -> Result<(Vec<u8>, usize), ProtoError> {
+        let mut bytes = Vec::new();
+        let mut encoder = BinEncoder::new(&mut bytes);
+        encoder.emit_vec(MAGIC)?;
+        encoder.emit_u8(VERSION)?;
+        encoder.emit_vec(&unix_time(system_now).to_be_bytes())?;
+
+        let mut count = 0;
+        for (key, value) in self.cache.iter() {
+            let Ok(lookup) = &value.lookup else {
+                continue;
+            };
+
+            let ttl = u32::try_from(value.ttl(now).as_secs()).unwrap_or(u32::MAX);
+            if !value.is_current(now) || ttl == 0 {
+                continue;
+            }
+
+            key.query.emit(&mut encoder)?;
+            match &key.scope {
+                Some(scope) => {
+                    encoder.emit_u8(1)?;
+                    scope.emit(&mut encoder)?;
+                }
+                None => encoder.emit_u8(0)?,
+            }
+            encoder.emit_u32(ttl)?;
+
+            let records = lookup.records();
+            let len = u16::try_from(records.len())
+                .map_err(|_| ProtoError::from("too many records in cached lookup"))?;
+            encoder.emit_u16(len)?;
+            for record in records {
+                record.emit(&mut encoder)?;
+            }
+
+            count += 1;
+        }
+
+        Ok((bytes, count))
+    }
+
+    fn restore(
+        &self,
+        bytes: &[u8],
+        now: Instant,
+        system_now: SystemTime,
+    ) -> Result<usize, ProtoError> {
+        let mut decoder = BinDecoder::new(bytes);
+        let magic = decoder.read_slice(MAGIC.len())?.unverified(/*checked below*/);
+        if magic != MAGIC {
+            return Err(ProtoError::from("not a cache snapshot"));
+        }
+
+        let version = decoder.read_u8()?.unverified(/*checked below*/);
+        if version != VERSION {
+            return Err(ProtoError::from(format!(
+                "unsupported cache snapshot version: {version}"
+            )));
+        }
+
+        let saved_at = decoder.read_slice(8)?.unverified(/*any time is valid*/);
+        // UNWRAP: the slice is 8 bytes long
+        let saved_at = u64::from_be_bytes(saved_at.try_into().unwrap());
+        // if the clock went backwards, assume no time has passed
+        let downtime = unix_time(system_now).saturating_sub(saved_at);
+
+        let mut count = 0;
+        while !decoder.is_empty() {
+            let query = Query::read(&mut decoder)?;
+            let scope = match decoder.read_u8()?.unverified(/*checked as a bool*/) {
+                0 => None,
+                1 => Some(ClientSubnet::read(&mut decoder)?),
+                _ => return Err(ProtoError::from("invalid client subnet in cache snapshot")),
+            };
+            let ttl = decoder.read_u32()?.unverified(/*any ttl is valid*/);
+
+            let len = decoder.read_u16()?.unverified(/*bounded by the snapshot size*/);
+            let records = (0..len)
+                .map(|_| Record::read(&mut decoder))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let Some(ttl) = u64::from(ttl).checked_sub(downtime).filter(|ttl| *ttl > 0) else {
+                continue;
+            };
+
+            let valid_until = now + Duration::from_secs(ttl);
+            let lookup = Lookup::new_with_deadline(query.clone(), Arc::from(records), valid_until)
+                .with_client_subnet_scope(scope.as_ref().map(ClientSubnet::source_prefix));
+            self.insert_value(
+                CacheKey { query, scope },
+                LruValue {
+                    lookup: Ok(lookup),
+                    valid_until,
+                    stale_until: self.stale_until(valid_until),
+                },
+            );
+
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+/// Seconds since the Unix epoch
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::dns_lru::TtlConfig;
+    use crate::proto::rr::rdata::A;
+    use crate::proto::rr::{Name, RData, RecordType};
+    use crate::proto::ProtoErrorKind;
+
+    fn query(name: &str) -> Query {
+        Query::query(Name::from_str(name).unwrap(), RecordType::A)
+    }
+
+    fn record(name: &str, ttl: u32) -> (Record, u32) {
+        let record = Record::from_rdata(
+            Name::from_str(name).unwrap(),
+            ttl,
+            RData::A(A::new(127, 0, 0, 1)),
+        );
+        (record, ttl)
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+
+        let lru = DnsLru::new(8, TtlConfig::default());
+        let short = query("short.example.com.");
+        let long = query("long.example.com.");
+        let scoped = query("scoped.example.com.");
+        let negative = query("negative.example.com.");
+        let scope = ClientSubnet::from_str("192.0.2.0/24").unwrap();
+
+        lru.insert(
+            short.clone(),
+            None,
+            vec![record("short.example.com.", 60)],
+            now,
+        );
+        lru.insert(
+            long.clone(),
+            None,
+            vec![record("long.example.com.", 600)],
+            now,
+        );
+        lru.insert(
+            scoped.clone(),
+            Some(scope),
+            vec![record("scoped.example.com.", 600)],
+            now,
+        );
+        lru.negative(
+            negative.clone(),
+            None,
+            ProtoError::from(ProtoErrorKind::NoRecordsFound {
+                query: Box::new(negative.clone()),
+                soa: None,
+                ns: None,
+                negative_ttl: Some(600),
+                response_code: crate::proto::op::ResponseCode::NXDomain,
+                trusted: true,
+                authorities: None,
+            }),
+            now,
+        );
+
+        let (bytes, count) = lru.snapshot(now, system_now).unwrap();
+        assert_eq!(count, 3);
+
+        // restore after two minutes of downtime
+        let restored = DnsLru::new(8, TtlConfig::default());
+        let count = restored
+            .restore(&bytes, now, system_now + Duration::from_secs(120))
+            .unwrap();
+        assert_eq!(count, 2);
+
+        assert!(restored.get(&short, now).is_none());
+        assert!(restored.get(&negative, now).is_none());
+
+        let lookup = restored.get(&long, now).unwrap().unwrap();
+        assert_eq!(lookup.valid_until(), now + Duration::from_secs(480));
+        assert_eq!(lookup.records()[0].ttl(), 480);
+
+        // scoped answers are only restored for their scope
+        assert!(restored.get(&scoped, now).is_none());
+        let client = ClientSubnet::from_str("192.0.2.1/32").unwrap();
+        assert!(restored
+            .get_for_subnet(&scoped, Some(&client), now)
+            .is_some());
+    }
+
+    #[test]
+    fn test_restore_invalid() {
+        let lru = DnsLru::new(8, TtlConfig::default());
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+
+        assert!(lru.restore(b"garbage", now, system_now).is_err());
+
+        let (mut bytes, _) = lru.snapshot(now, system_now).unwrap();
+        bytes[MAGIC.len()] = VERSION + 1;
+        assert!(lru.restore(&bytes, now, system_now).is_err());
+    }
+}
@@ -24,6 +24,7 @@ impl From<TtlConfigMap> for TtlConfig {
         Self {
             default,
             by_query_type,
+            serve_stale: None,
         }
     }
 }
@@ -71,6 +71,41 @@ where
     ))
 }
 
+/// Sends Oblivious DoH queries through the proxy at `socket_addr`, encrypted to the
+/// `target_config` of the target named by `http_endpoint`
+#[cfg(feature = "dns-over-odoh")]
+#[allow(clippy::type_complexity)]
+pub(crate) fn new_odoh_stream_with_future<S, F>(
+    future: F,
+    socket_addr: SocketAddr,
+    dns_name: String,
+    http_endpoint: String,
+    target_config: crate::proto::odoh::OdohConfig,
+    client_config: Option<Arc<rustls::ClientConfig>>,
+) -> DnsExchangeConnect<HttpsClientConnect<S>, HttpsClientStream, TokioTime>
+where
+    S: DnsTcpStream + Send + 'static,
+    F: Future<Output = std::io::Result<S>> + Send + Unpin + 'static,
+{
+    let client_config = if let Some(client_config) = client_config {
+        client_config
+    } else {
+        match CLIENT_CONFIG.clone() {
+            Ok(client_config) => client_config,
+            Err(error) => return DnsExchange::error(error),
+        }
+    };
+
+    let mut https_builder = HttpsClientStreamBuilder::with_client_config(client_config, ());
+    https_builder.odoh(target_config);
+    DnsExchange::connect(https_builder.build_with_future(
+        future,
+        socket_addr,
+        dns_name,
+        http_endpoint,
+    ))
+}
+
 #[cfg(any(feature = "webpki-roots", feature = "native-certs"))]
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,384 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Connection racing over both address families, Happy Eyeballs version 2, [RFC 8305](https://datatracker.ietf.org/doc/html/rfc8305)
+//!
+//! See [`Resolver::connect_tcp`](crate::Resolver::connect_tcp).
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use tracing::debug;
+
+use crate::error::ResolveError;
+use crate::proto::runtime::Time;
+
+/// The time to wait for the AAAA answer once the A answer is received, RFC 8305 section 3
+const RESOLUTION_DELAY: Duration = Duration::from_millis(50);
+
+/// The time to wait for a connection attempt before starting the next one, RFC 8305 section 5
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// The addresses of one family, as looked up
+pub(crate) type IpsFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<IpAddr>, ResolveError>> + Send + 'a>>;
+
+type Delay = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+type Attempt<S> = Pin<Box<dyn Future<Output = (SocketAddr, io::Result<S>)> + Send>>;
+
+/// Races connections to the addresses of both families, returning the first one established
+///
+/// Connecting starts with the first answer for IPv6, or [`RESOLUTION_DELAY`] after the first
+/// answer for IPv4 if the IPv6 one is still missing. The addresses are tried alternating between
+/// the families, IPv6 first, and each attempt gets [`CONNECTION_ATTEMPT_DELAY`] before the next
+/// one is started alongside it, or less if it fails. Addresses that arrive late are added to
+/// those still to be tried.
+pub(crate) struct HappyEyeballs<'a, S, C, T> {
+    port: u16,
+    connect: C,
+    ipv6: Option<IpsFuture<'a>>,
+    ipv4: Option<IpsFuture<'a>>,
+    ipv6_addrs: VecDeque<IpAddr>,
+    ipv4_addrs: VecDeque<IpAddr>,
+    prefer_ipv6: bool,
+    started: bool,
+    resolution_delay: Option<Delay>,
+    attempt_delay: Option<Delay>,
+    attempts: FuturesUnordered<Attempt<S>>,
+    error: Option<ResolveError>,
+    time: PhantomData<fn() -> T>,
+}
+
+impl<'a, S: 'static, C, T> HappyEyeballs<'a, S, C, T>
+where
+    C: FnMut(SocketAddr) -> Pin<Box<dyn Future<Output = io::Result<S>> + Send>>,
+    T: Time,
+{
+    /// Races connections to `port` of the addresses returned by the lookups
+    ///
+    /// A family that is not to be used is passed as `None`.
+    pub(crate) fn new(
+        port: u16,
+        connect: C,
+        ipv6: Option<IpsFuture<'a>>,
+        ipv4: Option<IpsFuture<'a>>,
+    ) -> Self {
+        Self {
+            port,
+            connect,
+            ipv6,
+            ipv4,
+            ipv6_addrs: VecDeque::new(),
+            ipv4_addrs: VecDeque::new(),
+            prefer_ipv6: true,
+            started: false,
+            resolution_delay: None,
+            attempt_delay: None,
+            attempts: FuturesUnordered::new(),
+            error: None,
+            time: PhantomData,
+        }
+    }
+
+    /// Polls the lookups, adding their addresses to those to be tried
+    fn poll_lookups(&mut self, cx: &mut Context<'_>) {
+        if let Some(Poll::Ready(result)) = self.ipv6.as_mut().map(|ipv6| ipv6.as_mut().poll(cx)) {
+            self.ipv6 = None;
+            match result {
+                Ok(ips) => self.ipv6_addrs.extend(ips),
+                Err(e) => {
+                    debug!("AAAA lookup failed: {e}");
+                    self.error = Some(e);
+                }
+            }
+        }
+
+        if let Some(Poll::Ready(result)) = self.ipv4.as_mut().map(|ipv4| ipv4.as_mut().poll(cx)) {
+            self.ipv4 = None;
+            match result {
+                Ok(ips) => self.ipv4_addrs.extend(ips),
+                Err(e) => {
+                    debug!("A lookup failed: {e}");
+                    self.error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// Returns whether connecting may start, waiting briefly for IPv6 addresses if only IPv4
+    /// addresses are known so far
+    fn poll_started(&mut self, cx: &mut Context<'_>) -> bool {
+        if self.started || self.ipv6.is_none() {
+            self.started = true;
+            self.resolution_delay = None;
+            return true;
+        }
+
+        if self.ipv4_addrs.is_empty() {
+            return false;
+        }
+
+        let delay = self
+            .resolution_delay
+            .get_or_insert_with(|| T::delay_for(RESOLUTION_DELAY));
+        if delay.as_mut().poll(cx).is_ready() {
+            self.started = true;
+            self.resolution_delay = None;
+        }
+
+        self.started
+    }
+
+    /// Returns the next address to try, alternating between the families
+    fn next_addr(&mut self) -> Option<SocketAddr> {
+        let (preferred, other) = match self.prefer_ipv6 {
+            true => (&mut self.ipv6_addrs, &mut self.ipv4_addrs),
+            false => (&mut self.ipv4_addrs, &mut self.ipv6_addrs),
+        };
+
+        let ip = preferred.pop_front().or_else(|| other.pop_front())?;
+        self.prefer_ipv6 = ip.is_ipv4();
+        Some(SocketAddr::new(ip, self.port))
+    }
+}
+
+impl<S: 'static, C, T> Future for HappyEyeballs<'_, S, C, T>
+where
+    C: FnMut(SocketAddr) -> Pin<Box<dyn Future<Output = io::Result<S>> + Send>> + Unpin,
+    T: Time,
+{
+    type Output = Result<(S, SocketAddr), ResolveError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.poll_lookups(cx);
+
+        if this.poll_started(cx) {
+            loop {
+                while let Poll::Ready(Some((addr, result))) = this.attempts.poll_next_unpin(cx) {
+                    match result {
+                        Ok(stream) => return Poll::Ready(Ok((stream, addr))),
+                        Err(e) => {
+                            debug!("failed to connect to {addr}: {e}");
+                            this.error = Some(e.into());
+                            // the next attempt starts right away
+                            this.attempt_delay = None;
+                        }
+                    }
+                }
+
+                if let Some(delay) = &mut this.attempt_delay {
+                    match delay.as_mut().poll(cx) {
+                        Poll::Ready(()) => this.attempt_delay = None,
+                        Poll::Pending => break,
+                    }
+                }
+
+                let Some(addr) = this.next_addr() else {
+                    break;
+                };
+
+                debug!("connecting to {addr}");
+                let connect = (this.connect)(addr);
+                this.attempts
+                    .push(Box::pin(async move { (addr, connect.await) }));
+                this.attempt_delay = Some(T::delay_for(CONNECTION_ATTEMPT_DELAY));
+            }
+        }
+
+        let exhausted = this.ipv6.is_none()
+            && this.ipv4.is_none()
+            && this.ipv6_addrs.is_empty()
+            && this.ipv4_addrs.is_empty()
+            && this.attempts.is_empty();
+        if exhausted {
+            return Poll::Ready(Err(this
+                .error
+                .take()
+                .unwrap_or_else(|| "no addresses to connect to".into())));
+        }
+
+        Poll::Pending
+    }
+}
+
+#[cfg(all(test, feature = "tokio-runtime"))]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::sync::{Arc, Mutex};
+
+    use tokio::time::{sleep, Instant};
+
+    use super::*;
+    use crate::proto::runtime::TokioTime;
+
+    const V6_A: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+    const V6_B: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2));
+    const V4_A: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const V4_B: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    fn lookup(delay: u64, ips: Vec<IpAddr>) -> Option<IpsFuture<'static>> {
+        Some(Box::pin(async move {
+            sleep(Duration::from_millis(delay)).await;
+            Ok(ips)
+        }))
+    }
+
+    /// Connects to the addresses that are given a delay, after it, and fails for the others
+    /// after 10ms, recording the order of the attempts
+    #[allow(clippy::type_complexity)]
+    fn connector(
+        delays: Vec<(IpAddr, u64)>,
+        attempts: Arc<Mutex<Vec<IpAddr>>>,
+    ) -> impl FnMut(SocketAddr) -> Pin<Box<dyn Future<Output = io::Result<SocketAddr>> + Send>> + Unpin
+    {
+        move |addr| {
+            attempts.lock().unwrap().push(addr.ip());
+            let delay = delays
+                .iter()
+                .find(|(ip, _)| *ip == addr.ip())
+                .map(|(_, delay)| *delay);
+
+            Box::pin(async move {
+                match delay {
+                    Some(delay) => {
+                        sleep(Duration::from_millis(delay)).await;
+                        Ok(addr)
+                    }
+                    None => {
+                        sleep(Duration::from_millis(10)).await;
+                        Err(io::ErrorKind::ConnectionRefused.into())
+                    }
+                }
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_prefers_ipv6() {
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let connect = connector(vec![(V6_A, 5), (V4_A, 5)], attempts.clone());
+
+        let (stream, addr) = HappyEyeballs::<_, _, TokioTime>::new(
+            443,
+            connect,
+            lookup(0, vec![V6_A]),
+            lookup(0, vec![V4_A]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(addr, SocketAddr::new(V6_A, 443));
+        assert_eq!(stream, addr);
+        assert_eq!(*attempts.lock().unwrap(), vec![V6_A]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_resolution_delay() {
+        // the AAAA answer arrives within the resolution delay
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let connect = connector(vec![(V6_A, 5), (V4_A, 5)], attempts.clone());
+        let (_, addr) = HappyEyeballs::<_, _, TokioTime>::new(
+            443,
+            connect,
+            lookup(20, vec![V6_A]),
+            lookup(0, vec![V4_A]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(addr.ip(), V6_A);
+
+        // the AAAA answer arrives too late
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let connect = connector(vec![(V6_A, 5), (V4_A, 5)], attempts.clone());
+        let start = Instant::now();
+        let (_, addr) = HappyEyeballs::<_, _, TokioTime>::new(
+            443,
+            connect,
+            lookup(100, vec![V6_A]),
+            lookup(0, vec![V4_A]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(addr.ip(), V4_A);
+        assert_eq!(start.elapsed(), RESOLUTION_DELAY + Duration::from_millis(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_staggered_attempts() {
+        // the IPv6 address hangs, the IPv4 one is tried after the connection attempt delay
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let connect = connector(vec![(V6_A, 10_000), (V4_A, 5)], attempts.clone());
+        let start = Instant::now();
+        let (_, addr) = HappyEyeballs::<_, _, TokioTime>::new(
+            443,
+            connect,
+            lookup(0, vec![V6_A]),
+            lookup(0, vec![V4_A]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(addr.ip(), V4_A);
+        assert_eq!(
+            start.elapsed(),
+            CONNECTION_ATTEMPT_DELAY + Duration::from_millis(5)
+        );
+        assert_eq!(*attempts.lock().unwrap(), vec![V6_A, V4_A]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interleaves_families() {
+        // failed attempts start the next one right away
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let connect = connector(vec![(V4_B, 5)], attempts.clone());
+        let start = Instant::now();
+        let (_, addr) = HappyEyeballs::<_, _, TokioTime>::new(
+            443,
+            connect,
+            lookup(0, vec![V6_A, V6_B]),
+            lookup(0, vec![V4_A, V4_B]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(addr.ip(), V4_B);
+        assert_eq!(start.elapsed(), Duration::from_millis(35));
+        assert_eq!(*attempts.lock().unwrap(), vec![V6_A, V4_A, V6_B, V4_B]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_all_fail() {
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let connect = connector(vec![], attempts.clone());
+        let ipv6: Option<IpsFuture<'static>> = Some(Box::pin(async {
+            Err(ResolveError::from("no AAAA records"))
+        }));
+
+        let error =
+            HappyEyeballs::<_, _, TokioTime>::new(443, connect, ipv6, lookup(0, vec![V4_A]))
+                .await
+                .unwrap_err();
+
+        assert!(error.to_string().contains("refused"), "{error}");
+        assert_eq!(*attempts.lock().unwrap(), vec![V4_A]);
+
+        let connect = connector(vec![], attempts.clone());
+        let error = HappyEyeballs::<_, _, TokioTime>::new(443, connect, None, lookup(0, vec![]))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("no addresses"), "{error}");
+    }
+}
@@ -194,12 +194,12 @@ impl Hosts {
 }
 
 #[cfg(unix)]
-fn hosts_path() -> &'static str {
+pub(crate) fn hosts_path() -> &'static str {
     "/etc/hosts"
 }
 
 #[cfg(windows)]
-fn hosts_path() -> std::path::PathBuf {
+pub(crate) fn hosts_path() -> std::path::PathBuf {
     let system_root =
         std::env::var_os("SystemRoot").expect("Environtment variable SystemRoot not found");
     let system_root = Path::new(&system_root);
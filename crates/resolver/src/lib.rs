@@ -215,6 +215,8 @@ pub use hickory_proto as proto;
 // reexports from proto
 pub use proto::rr::{IntoName, Name};
 
+#[cfg(any(feature = "tower", feature = "hyper-util", feature = "reqwest"))]
+pub mod adapters;
 pub mod caching_client;
 pub mod config;
 pub mod dns_lru;
@@ -224,12 +226,18 @@ pub use error::{ResolveError, ResolveErrorKind};
 mod h2;
 #[cfg(feature = "dns-over-h3")]
 mod h3;
+mod happy_eyeballs;
 mod hosts;
 pub use hosts::Hosts;
 pub mod lookup;
 pub mod lookup_ip;
+pub mod lookup_service;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 // TODO: consider #[doc(hidden)]
 pub mod name_server;
+#[cfg(feature = "dnssec")]
+pub mod nsec_cache;
 #[cfg(feature = "tokio-runtime")]
 use name_server::TokioConnectionProvider;
 #[cfg(feature = "dns-over-quic")]
@@ -245,6 +253,9 @@ pub mod system_conf;
 mod tests;
 #[cfg(feature = "dns-over-tls")]
 mod tls;
+#[cfg(unix)]
+#[cfg(feature = "system-config")]
+pub mod watcher;
 
 #[doc(hidden)]
 #[deprecated(since = "0.25.0", note = "use `Resolver` instead")]
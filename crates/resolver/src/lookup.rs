@@ -9,6 +9,7 @@
 
 use std::{
     cmp::min,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
     pin::Pin,
     slice::Iter,
     sync::Arc,
@@ -21,9 +22,11 @@ use futures_util::{
     stream::Stream,
     FutureExt,
 };
+use rand::Rng;
 
 use crate::{
     caching_client::CachingClient,
+    config::LookupIpStrategy,
     dns_lru::MAX_TTL,
     error::*,
     hosts::Hosts,
@@ -51,6 +54,7 @@ pub struct Lookup {
     query: Query,
     records: Arc<[Record]>,
     valid_until: Instant,
+    client_subnet_scope: Option<u8>,
 }
 
 impl Lookup {
@@ -67,6 +71,7 @@ impl Lookup {
             query,
             records,
             valid_until,
+            client_subnet_scope: None,
         }
     }
 
@@ -76,6 +81,7 @@ impl Lookup {
             query,
             records,
             valid_until,
+            client_subnet_scope: None,
         }
     }
 
@@ -115,6 +121,20 @@ impl Lookup {
         self.valid_until
     }
 
+    /// Returns the scope prefix length of the client subnet the records are valid for, if they
+    ///  were looked up with an EDNS Client Subnet
+    ///
+    /// A scope of 0 means the records are valid for all clients,
+    ///  [RFC 7871, section 7.2.1](https://tools.ietf.org/html/rfc7871#section-7.2.1).
+    pub fn client_subnet_scope(&self) -> Option<u8> {
+        self.client_subnet_scope
+    }
+
+    pub(crate) fn with_client_subnet_scope(mut self, client_subnet_scope: Option<u8>) -> Self {
+        self.client_subnet_scope = client_subnet_scope;
+        self
+    }
+
     #[doc(hidden)]
     pub fn is_empty(&self) -> bool {
         self.records.is_empty()
@@ -136,9 +156,10 @@ impl Lookup {
         records.extend_from_slice(&self.records);
         records.extend_from_slice(&other.records);
 
-        // Choose the sooner deadline of the two lookups.
+        // Choose the sooner deadline of the two lookups, and the narrower scope.
         let valid_until = min(self.valid_until(), other.valid_until());
         Self::new_with_deadline(self.query.clone(), Arc::from(records), valid_until)
+            .with_client_subnet_scope(self.client_subnet_scope.max(other.client_subnet_scope))
     }
 
     /// Add new records to this lookup, without creating a new Lookup
@@ -409,6 +430,34 @@ impl SrvLookup {
         LookupIpIter(self.0.iter())
     }
 
+    /// Returns the SRV records in the order in which their targets should be tried, see RFC 2782
+    ///
+    /// Records are sorted by priority, lowest first, and records of the same priority are shuffled
+    ///  by a weighted random selection. Records with the target `.` are left out, they state that
+    ///  the service is not available at this domain.
+    pub fn ordered(&self) -> Vec<rdata::SRV> {
+        order_srvs(self.iter().cloned().collect(), &mut rand::thread_rng())
+    }
+
+    /// Returns the addresses of `target` that were returned along with the SRV records
+    ///
+    /// These are taken from the additional section of the response, and are filtered and ordered
+    ///  according to `strategy`. If none are returned, the addresses of the target need to be
+    ///  looked up separately.
+    pub fn target_ips(&self, target: &Name, strategy: LookupIpStrategy) -> Vec<IpAddr> {
+        let mut ipv4 = Vec::new();
+        let mut ipv6 = Vec::new();
+        for record in self.0.records().iter().filter(|r| r.name() == target) {
+            match record.data() {
+                RData::A(ip) => ipv4.push(IpAddr::from(Ipv4Addr::from(*ip))),
+                RData::AAAA(ip) => ipv6.push(IpAddr::from(Ipv6Addr::from(*ip))),
+                _ => (),
+            }
+        }
+
+        strategy.select(ipv4, ipv6)
+    }
+
     /// Return a reference to the inner lookup
     ///
     /// This can be useful for getting all records from the request
@@ -417,6 +466,44 @@ impl SrvLookup {
     }
 }
 
+/// Orders SRV records by priority, and by a weighted random selection within a priority
+///
+/// This follows the algorithm of RFC 2782: of the remaining records of a priority, with those of
+///  weight 0 first, the first one whose running sum of weights is at least a random number
+///  between 0 and the sum of all their weights is selected next.
+fn order_srvs<R: Rng + ?Sized>(mut srvs: Vec<rdata::SRV>, rng: &mut R) -> Vec<rdata::SRV> {
+    srvs.retain(|srv| !srv.target().is_root());
+    // stable, so records of weight 0 stay in the order of the response
+    srvs.sort_by_key(|srv| (srv.priority(), srv.weight() != 0));
+
+    let mut ordered = Vec::with_capacity(srvs.len());
+    let mut srvs = srvs.into_iter().peekable();
+    while let Some(first) = srvs.next() {
+        let priority = first.priority();
+        let mut group = vec![first];
+        while let Some(srv) = srvs.next_if(|srv| srv.priority() == priority) {
+            group.push(srv);
+        }
+
+        while !group.is_empty() {
+            let total = group.iter().map(|srv| u32::from(srv.weight())).sum::<u32>();
+            let selected = rng.gen_range(0..=total);
+
+            let mut running = 0;
+            let index = group
+                .iter()
+                .position(|srv| {
+                    running += u32::from(srv.weight());
+                    running >= selected
+                })
+                .unwrap_or_default();
+            ordered.push(group.remove(index));
+        }
+    }
+
+    ordered
+}
+
 impl From<Lookup> for SrvLookup {
     fn from(lookup: Lookup) -> Self {
         Self(lookup)
@@ -603,7 +690,6 @@ lookup_type!(NsLookup, NsLookupIter, NsLookupIntoIter, RData::NS, NS);
 
 #[cfg(test)]
 mod tests {
-    use std::net::{IpAddr, Ipv4Addr};
     use std::str::FromStr;
     use std::sync::{Arc, Mutex};
 
@@ -774,6 +860,112 @@ mod tests {
         assert_eq!(lookup.next(), None);
     }
 
+    fn srv(priority: u16, weight: u16, target: &str) -> rdata::SRV {
+        rdata::SRV::new(priority, weight, 443, Name::from_str(target).unwrap())
+    }
+
+    #[test]
+    fn test_order_srvs() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(2782);
+        let srvs = vec![
+            srv(20, 10, "c.example.com."),
+            srv(10, 0, "a.example.com."),
+            srv(10, 0, "."),
+            srv(30, 0, "d.example.com."),
+            srv(20, 0, "b.example.com."),
+        ];
+
+        for _ in 0..100 {
+            let ordered = order_srvs(srvs.clone(), &mut rng);
+            let targets = ordered
+                .iter()
+                .map(|srv| srv.target().to_string())
+                .collect::<Vec<_>>();
+
+            assert_eq!(targets.len(), 4);
+            assert_eq!(targets[0], "a.example.com.");
+            assert_eq!(targets[3], "d.example.com.");
+        }
+
+        // the heavier record should be first most of the time
+        let srvs = vec![
+            srv(10, 1, "light.example.com."),
+            srv(10, 99, "heavy.example.com."),
+        ];
+        let heavy_first = (0..1000)
+            .filter(|_| {
+                order_srvs(srvs.clone(), &mut rng)[0]
+                    .target()
+                    .eq(&Name::from_str("heavy.example.com.").unwrap())
+            })
+            .count();
+        assert!(heavy_first > 900, "heavy first {heavy_first} times");
+        assert!(heavy_first < 1000, "light never first");
+
+        // a lone record for the root means the service is not available
+        assert!(order_srvs(vec![srv(0, 0, ".")], &mut rng).is_empty());
+    }
+
+    #[test]
+    fn test_srv_target_ips() {
+        let target = Name::from_str("www.example.com.").unwrap();
+        let lookup = SrvLookup(Lookup::new_with_max_ttl(
+            Query::query(
+                Name::from_str("_443._tcp.example.com.").unwrap(),
+                RecordType::SRV,
+            ),
+            Arc::from([
+                Record::from_rdata(
+                    Name::from_str("_443._tcp.example.com.").unwrap(),
+                    80,
+                    RData::SRV(srv(10, 0, "www.example.com.")),
+                ),
+                Record::from_rdata(
+                    target.clone(),
+                    80,
+                    RData::AAAA(AAAA::from(Ipv6Addr::LOCALHOST)),
+                ),
+                Record::from_rdata(target.clone(), 80, RData::A(A::new(127, 0, 0, 1))),
+                Record::from_rdata(
+                    Name::from_str("other.example.com.").unwrap(),
+                    80,
+                    RData::A(A::new(127, 0, 0, 2)),
+                ),
+            ]),
+        ));
+
+        let v4 = IpAddr::from(Ipv4Addr::LOCALHOST);
+        let v6 = IpAddr::from(Ipv6Addr::LOCALHOST);
+        assert_eq!(
+            lookup.target_ips(&target, LookupIpStrategy::Ipv4Only),
+            vec![v4]
+        );
+        assert_eq!(
+            lookup.target_ips(&target, LookupIpStrategy::Ipv6Only),
+            vec![v6]
+        );
+        assert_eq!(
+            lookup.target_ips(&target, LookupIpStrategy::Ipv4AndIpv6),
+            vec![v4, v6]
+        );
+        assert_eq!(
+            lookup.target_ips(&target, LookupIpStrategy::Ipv6thenIpv4),
+            vec![v6]
+        );
+        assert_eq!(
+            lookup.target_ips(&target, LookupIpStrategy::Ipv4thenIpv6),
+            vec![v4]
+        );
+        assert!(lookup
+            .target_ips(
+                &Name::from_str("missing.example.com.").unwrap(),
+                LookupIpStrategy::Ipv4thenIpv6
+            )
+            .is_empty());
+    }
+
     #[test]
     #[cfg(feature = "dnssec")]
     fn test_dnssec_lookup() {
@@ -797,6 +989,7 @@ mod tests {
             query: Query::default(),
             records: Arc::from([a1.clone(), a2.clone()]),
             valid_until: Instant::now(),
+            client_subnet_scope: None,
         };
 
         let mut lookup = lookup.dnssec_iter();
@@ -24,7 +24,7 @@ use crate::proto::rr::{Name, RData, Record, RecordType};
 use crate::proto::xfer::{DnsHandle, DnsRequestOptions};
 
 use crate::caching_client::CachingClient;
-use crate::config::LookupIpStrategy;
+use crate::config::{LookupIpStrategy, SortlistNetwork};
 use crate::dns_lru::MAX_TTL;
 use crate::error::*;
 use crate::hosts::Hosts;
@@ -60,6 +60,30 @@ impl LookupIp {
     pub fn as_lookup(&self) -> &Lookup {
         &self.0
     }
+
+    /// Orders the addresses by the first network of `sortlist` they are in, see
+    ///  [`ResolverOpts::sortlist`](crate::config::ResolverOpts::sortlist)
+    pub(crate) fn sort_by_networks(self, sortlist: &[SortlistNetwork]) -> Self {
+        if sortlist.is_empty() {
+            return self;
+        }
+
+        // the sort is stable, other records such as CNAMEs keep their place before the addresses
+        let mut records = self.0.records().to_vec();
+        records.sort_by_key(|record| match record.data().ip_addr() {
+            Some(ip) => sortlist
+                .iter()
+                .position(|network| network.contains(ip))
+                .unwrap_or(sortlist.len()),
+            None => 0,
+        });
+
+        Self(Lookup::new_with_deadline(
+            self.0.query().clone(),
+            Arc::from(records),
+            self.0.valid_until(),
+        ))
+    }
 }
 
 impl From<Lookup> for LookupIp {
@@ -718,4 +742,59 @@ pub(crate) mod tests {
             vec![Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)]
         );
     }
+
+    #[test]
+    fn test_sort_by_networks() {
+        let name = Name::from_ascii("www.example.com.").unwrap();
+        let record = |ip: IpAddr| Record::from_rdata(name.clone(), 86400, RData::from(ip));
+        let lookup = LookupIp::from(Lookup::new_with_max_ttl(
+            Query::query(name.clone(), RecordType::A),
+            Arc::from([
+                Record::from_rdata(
+                    name.clone(),
+                    86400,
+                    RData::CNAME(crate::proto::rr::rdata::CNAME(name.clone())),
+                ),
+                record(Ipv4Addr::new(192, 0, 2, 1).into()),
+                record(Ipv4Addr::new(198, 51, 100, 1).into()),
+                record(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1).into()),
+                record(Ipv4Addr::new(203, 0, 113, 1).into()),
+            ]),
+        ));
+
+        let sortlist = [
+            SortlistNetwork {
+                addr: Ipv4Addr::new(203, 0, 113, 0).into(),
+                mask: Ipv4Addr::new(255, 255, 255, 0).into(),
+            },
+            SortlistNetwork {
+                addr: Ipv4Addr::new(198, 51, 0, 0).into(),
+                mask: Ipv4Addr::new(255, 255, 0, 0).into(),
+            },
+        ];
+        let sorted = lookup.clone().sort_by_networks(&sortlist);
+        assert_eq!(
+            sorted.iter().collect::<Vec<_>>(),
+            vec![
+                IpAddr::from(Ipv4Addr::new(203, 0, 113, 1)),
+                Ipv4Addr::new(198, 51, 100, 1).into(),
+                Ipv4Addr::new(192, 0, 2, 1).into(),
+                Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1).into(),
+            ]
+        );
+        assert!(matches!(
+            sorted.as_lookup().records()[0].data(),
+            RData::CNAME(_)
+        ));
+
+        // without a sortlist the order of the response is kept
+        assert_eq!(
+            lookup
+                .clone()
+                .sort_by_networks(&[])
+                .iter()
+                .collect::<Vec<_>>(),
+            lookup.iter().collect::<Vec<_>>()
+        );
+    }
 }
@@ -0,0 +1,509 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Resolution of the endpoints of an HTTPS service from its HTTPS records, [RFC 9460](https://datatracker.ietf.org/doc/html/rfc9460)
+//!
+//! See [`Resolver::lookup_service_endpoints`].
+
+use std::net::{IpAddr, SocketAddr};
+
+use rand::seq::SliceRandom;
+use tracing::debug;
+
+use crate::config::LookupIpStrategy;
+use crate::error::ResolveError;
+use crate::lookup::Lookup;
+use crate::name_server::ConnectionProvider;
+use crate::proto::rr::rdata::svcb::{Mandatory, SvcParamKey, SvcParamValue, SVCB};
+use crate::proto::rr::{Name, RData, RecordType};
+use crate::proto::ProtoError;
+use crate::Resolver;
+
+/// The maximum number of AliasMode records followed, RFC 9460 section 3 asks for a limit of at most 8
+const MAX_ALIAS_CHAIN: usize = 8;
+
+/// The port of HTTPS, for which the HTTPS records are at the host name itself
+const HTTPS_PORT: u16 = 443;
+
+/// The protocol every HTTPS endpoint supports unless its record has `no-default-alpn`
+const DEFAULT_ALPN: &str = "http/1.1";
+
+/// An endpoint of an HTTPS service, ready to be connected to
+///
+/// The endpoints of a service are returned in the order in which they should be tried.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceEndpoint {
+    priority: Option<u16>,
+    target: Name,
+    port: u16,
+    alpn: Vec<String>,
+    ech_config_list: Option<Vec<u8>>,
+    ips: Vec<IpAddr>,
+}
+
+impl ServiceEndpoint {
+    /// The SvcPriority of the record of this endpoint
+    ///
+    /// This is `None` for the origin itself, which is returned when the service has no usable
+    /// HTTPS records.
+    pub fn priority(&self) -> Option<u16> {
+        self.priority
+    }
+
+    /// The effective TargetName of the record, the name whose addresses are used
+    pub fn target(&self) -> &Name {
+        &self.target
+    }
+
+    /// The port to connect to
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The protocol identifiers offered by this endpoint, see `alpn` in RFC 9460
+    ///
+    /// This includes `http/1.1`, unless the record has `no-default-alpn`. It is empty for the
+    /// origin, as its protocols are not known.
+    pub fn alpn(&self) -> &[String] {
+        &self.alpn
+    }
+
+    /// The ECHConfigList for Encrypted Client Hello, if the endpoint offers it
+    pub fn ech_config_list(&self) -> Option<&[u8]> {
+        self.ech_config_list.as_deref()
+    }
+
+    /// The addresses of the target, in the order of the configured `LookupIpStrategy`
+    pub fn ips(&self) -> &[IpAddr] {
+        &self.ips
+    }
+
+    /// The addresses to connect to, the addresses of the target with the port
+    pub fn socket_addrs(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.ips.iter().map(|ip| SocketAddr::new(*ip, self.port))
+    }
+
+    /// The endpoint of the origin itself, used when the service has no usable HTTPS records
+    pub(crate) fn origin(host: Name, port: u16, ips: Vec<IpAddr>) -> Self {
+        Self {
+            priority: None,
+            target: host,
+            port,
+            alpn: Vec::new(),
+            ech_config_list: None,
+            ips,
+        }
+    }
+}
+
+/// Resolves the endpoints of the HTTPS service at `host` and `port`, see [`Resolver::lookup_service_endpoints`]
+pub(crate) async fn lookup_service_endpoints<P: ConnectionProvider>(
+    resolver: &Resolver<P>,
+    host: Name,
+    port: u16,
+) -> Result<Vec<ServiceEndpoint>, ResolveError> {
+    let strategy = resolver.options().ip_strategy;
+
+    let mut name = query_name(&host, port)?;
+    let mut aliases = 0;
+    let services = loop {
+        let lookup = match resolver.lookup(name.clone(), RecordType::HTTPS).await {
+            Ok(lookup) => lookup,
+            Err(e) => {
+                debug!("no HTTPS records for {name}: {e}");
+                break Vec::new();
+            }
+        };
+
+        match alias_target(&lookup) {
+            Some(target) if target.is_root() => {
+                debug!("HTTPS service at {name} is not available");
+                return Ok(Vec::new());
+            }
+            Some(_) if aliases == MAX_ALIAS_CHAIN => {
+                debug!("too many HTTPS aliases for {host}, the last being {name}");
+                break Vec::new();
+            }
+            Some(target) => {
+                aliases += 1;
+                name = target;
+            }
+            None => break service_records(&lookup),
+        }
+    };
+
+    // clients must not fall back to connecting without ECH if every endpoint offers it
+    let ech_required = !services.is_empty()
+        && services.iter().all(|(_, svcb)| {
+            svcb.svc_params()
+                .iter()
+                .any(|(key, _)| *key == SvcParamKey::EchConfigList)
+        });
+
+    let mut endpoints = Vec::new();
+    for (owner, svcb) in services {
+        let Some(mut endpoint) = endpoint(&owner, &svcb, port) else {
+            continue;
+        };
+
+        let ips = match resolver.lookup_ip(endpoint.target.clone()).await {
+            Ok(lookup) => lookup.iter().collect(),
+            Err(e) => {
+                debug!("failed to look up addresses of {}: {e}", endpoint.target);
+                Vec::new()
+            }
+        };
+
+        endpoint.ips = merge_hints(strategy, ips, &svcb);
+        if endpoint.ips.is_empty() {
+            continue;
+        }
+
+        endpoints.push(endpoint);
+    }
+
+    if !endpoints.is_empty() || ech_required {
+        return Ok(endpoints);
+    }
+
+    let lookup = resolver.lookup_ip(host.clone()).await?;
+    Ok(vec![ServiceEndpoint::origin(
+        host,
+        port,
+        lookup.iter().collect(),
+    )])
+}
+
+/// Returns the name of the HTTPS records of the service at `host` and `port`, RFC 9460 section 9.1
+fn query_name(host: &Name, port: u16) -> Result<Name, ProtoError> {
+    if port == HTTPS_PORT {
+        return Ok(host.clone());
+    }
+
+    host.prepend_label("_https")?
+        .prepend_label(format!("_{port}"))
+}
+
+/// Returns the TargetName of an AliasMode record in the lookup, picked at random if there are several
+fn alias_target(lookup: &Lookup) -> Option<Name> {
+    let targets = https_records(lookup)
+        .filter(|(_, svcb)| svcb.svc_priority() == 0)
+        .map(|(_, svcb)| svcb.target_name())
+        .collect::<Vec<_>>();
+
+    targets
+        .choose(&mut rand::thread_rng())
+        .map(|target| (*target).clone())
+}
+
+/// Returns the ServiceMode records in the lookup, with their owner names, in the order in which
+/// they should be tried
+fn service_records(lookup: &Lookup) -> Vec<(Name, SVCB)> {
+    let mut services = https_records(lookup)
+        .filter(|(_, svcb)| svcb.svc_priority() > 0)
+        .map(|(owner, svcb)| (owner.clone(), svcb.clone()))
+        .collect::<Vec<_>>();
+
+    // records of the same priority are shuffled, RFC 9460 section 2.4.1
+    services.shuffle(&mut rand::thread_rng());
+    services.sort_by_key(|(_, svcb)| svcb.svc_priority());
+    services
+}
+
+fn https_records(lookup: &Lookup) -> impl Iterator<Item = (&Name, &SVCB)> {
+    lookup
+        .records()
+        .iter()
+        .filter_map(|record| match record.data() {
+            RData::HTTPS(https) => Some((record.name(), &https.0)),
+            _ => None,
+        })
+}
+
+/// Returns the endpoint of a ServiceMode record, without its addresses, if the record is usable
+fn endpoint(owner: &Name, svcb: &SVCB, port: u16) -> Option<ServiceEndpoint> {
+    /// Keys of the SvcParams that are used for HTTPS records
+    const SUPPORTED_KEYS: &[SvcParamKey] = &[
+        SvcParamKey::Alpn,
+        SvcParamKey::NoDefaultAlpn,
+        SvcParamKey::Port,
+        SvcParamKey::Ipv4Hint,
+        SvcParamKey::EchConfigList,
+        SvcParamKey::Ipv6Hint,
+    ];
+
+    let target = if svcb.target_name().is_root() {
+        owner.clone()
+    } else {
+        svcb.target_name().clone()
+    };
+
+    let mut endpoint = ServiceEndpoint {
+        priority: Some(svcb.svc_priority()),
+        target,
+        port,
+        alpn: Vec::new(),
+        ech_config_list: None,
+        ips: Vec::new(),
+    };
+
+    let mut no_default_alpn = false;
+    for (key, value) in svcb.svc_params() {
+        match value {
+            SvcParamValue::Mandatory(mandatory) => {
+                // records that require keys that are not understood must be ignored
+                if !mandatory_supported(mandatory, SUPPORTED_KEYS) {
+                    debug!("unsupported mandatory keys in HTTPS record of {owner}: {mandatory}");
+                    return None;
+                }
+            }
+            SvcParamValue::Alpn(alpn) => endpoint.alpn.clone_from(&alpn.0),
+            SvcParamValue::NoDefaultAlpn => no_default_alpn = true,
+            SvcParamValue::Port(port) => endpoint.port = *port,
+            SvcParamValue::EchConfigList(ech) => endpoint.ech_config_list = Some(ech.0.clone()),
+            SvcParamValue::Ipv4Hint(_) | SvcParamValue::Ipv6Hint(_) => {}
+            _ => debug!("ignoring SvcParam in HTTPS record of {owner}: {key}"),
+        }
+    }
+
+    if !no_default_alpn && !endpoint.alpn.iter().any(|alpn| alpn == DEFAULT_ALPN) {
+        endpoint.alpn.push(DEFAULT_ALPN.to_string());
+    }
+
+    if endpoint.alpn.is_empty() {
+        debug!("no protocols in HTTPS record of {owner}");
+        return None;
+    }
+
+    Some(endpoint)
+}
+
+/// Returns whether all keys of the mandatory SvcParam are `supported`
+///
+/// Clients must ignore records whose mandatory keys they do not understand, RFC 9460 section 8.
+pub(crate) fn mandatory_supported(mandatory: &Mandatory, supported: &[SvcParamKey]) -> bool {
+    mandatory.0.iter().all(|key| supported.contains(key))
+}
+
+/// Merges the address hints of a record with the addresses that were looked up for its target
+///
+/// Hints of an address family are only used if no addresses of that family were looked up, as
+/// the looked up addresses are authoritative, RFC 9460 section 7.3.
+fn merge_hints(strategy: LookupIpStrategy, ips: Vec<IpAddr>, svcb: &SVCB) -> Vec<IpAddr> {
+    let (mut ipv4, mut ipv6): (Vec<_>, Vec<_>) = ips.into_iter().partition(IpAddr::is_ipv4);
+
+    let use_ipv4_hints = ipv4.is_empty();
+    let use_ipv6_hints = ipv6.is_empty();
+    for (_, value) in svcb.svc_params() {
+        match value {
+            SvcParamValue::Ipv4Hint(hint) if use_ipv4_hints => {
+                ipv4.extend(hint.0.iter().map(|a| IpAddr::V4(a.0)))
+            }
+            SvcParamValue::Ipv6Hint(hint) if use_ipv6_hints => {
+                ipv6.extend(hint.0.iter().map(|aaaa| IpAddr::V6(aaaa.0)))
+            }
+            _ => {}
+        }
+    }
+
+    strategy.select(ipv4, ipv6)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::proto::op::Query;
+    use crate::proto::rr::rdata::svcb::{Alpn, EchConfigList, IpHint, Mandatory};
+    use crate::proto::rr::rdata::{A, AAAA, HTTPS};
+    use crate::proto::rr::Record;
+
+    fn name(name: &str) -> Name {
+        Name::from_str(name).unwrap()
+    }
+
+    fn https(
+        owner: &str,
+        priority: u16,
+        target: &str,
+        params: Vec<(SvcParamKey, SvcParamValue)>,
+    ) -> Record {
+        Record::from_rdata(
+            name(owner),
+            300,
+            RData::HTTPS(HTTPS(SVCB::new(priority, name(target), params))),
+        )
+    }
+
+    fn lookup(records: Vec<Record>) -> Lookup {
+        Lookup::new_with_max_ttl(
+            Query::query(name("example.com."), RecordType::HTTPS),
+            Arc::from(records),
+        )
+    }
+
+    #[test]
+    fn test_query_name() {
+        let host = name("www.example.com.");
+        assert_eq!(query_name(&host, 443).unwrap(), host);
+        assert_eq!(
+            query_name(&host, 8443).unwrap(),
+            name("_8443._https.www.example.com.")
+        );
+    }
+
+    #[test]
+    fn test_alias_target() {
+        let aliased = lookup(vec![
+            https("example.com.", 1, ".", vec![]),
+            https("example.com.", 0, "svc.example.net.", vec![]),
+        ]);
+        assert_eq!(alias_target(&aliased), Some(name("svc.example.net.")));
+
+        let services = lookup(vec![https("example.com.", 1, ".", vec![])]);
+        assert_eq!(alias_target(&services), None);
+    }
+
+    #[test]
+    fn test_service_records_order() {
+        let services = lookup(vec![
+            https("example.com.", 3, "c.example.com.", vec![]),
+            https("example.com.", 0, "alias.example.com.", vec![]),
+            https("example.com.", 1, "a.example.com.", vec![]),
+            https("example.com.", 2, "b.example.com.", vec![]),
+        ]);
+
+        let targets = service_records(&services)
+            .into_iter()
+            .map(|(_, svcb)| svcb.target_name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            vec![
+                name("a.example.com."),
+                name("b.example.com."),
+                name("c.example.com.")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let record = https(
+            "svc2.example.net.",
+            1,
+            ".",
+            vec![
+                (
+                    SvcParamKey::Alpn,
+                    SvcParamValue::Alpn(Alpn(vec!["h3".to_string(), "h2".to_string()])),
+                ),
+                (SvcParamKey::Port, SvcParamValue::Port(8002)),
+                (
+                    SvcParamKey::EchConfigList,
+                    SvcParamValue::EchConfigList(EchConfigList(vec![1, 2, 3])),
+                ),
+            ],
+        );
+        let RData::HTTPS(https) = record.data() else {
+            unreachable!()
+        };
+
+        let endpoint = endpoint(record.name(), https, 443).unwrap();
+        assert_eq!(endpoint.priority(), Some(1));
+        assert_eq!(endpoint.target(), &name("svc2.example.net."));
+        assert_eq!(endpoint.port(), 8002);
+        assert_eq!(endpoint.alpn(), ["h3", "h2", "http/1.1"]);
+        assert_eq!(endpoint.ech_config_list(), Some(&[1, 2, 3][..]));
+    }
+
+    #[test]
+    fn test_endpoint_no_default_alpn() {
+        let owner = name("example.com.");
+        let svcb = SVCB::new(
+            1,
+            name("svc.example.com."),
+            vec![
+                (
+                    SvcParamKey::Alpn,
+                    SvcParamValue::Alpn(Alpn(vec!["h3".to_string()])),
+                ),
+                (SvcParamKey::NoDefaultAlpn, SvcParamValue::NoDefaultAlpn),
+            ],
+        );
+
+        let endpoint = endpoint(&owner, &svcb, 8443).unwrap();
+        assert_eq!(endpoint.target(), &name("svc.example.com."));
+        assert_eq!(endpoint.port(), 8443);
+        assert_eq!(endpoint.alpn(), ["h3"]);
+        assert_eq!(endpoint.ech_config_list(), None);
+
+        // no protocols at all
+        let svcb = SVCB::new(
+            1,
+            name("svc.example.com."),
+            vec![(SvcParamKey::NoDefaultAlpn, SvcParamValue::NoDefaultAlpn)],
+        );
+        assert!(super::endpoint(&owner, &svcb, 443).is_none());
+
+        // a mandatory key that is not understood
+        let svcb = SVCB::new(
+            1,
+            name("svc.example.com."),
+            vec![(
+                SvcParamKey::Mandatory,
+                SvcParamValue::Mandatory(Mandatory(vec![SvcParamKey::Key(65000)])),
+            )],
+        );
+        assert!(super::endpoint(&owner, &svcb, 443).is_none());
+    }
+
+    #[test]
+    fn test_merge_hints() {
+        let svcb = SVCB::new(
+            1,
+            Name::root(),
+            vec![
+                (
+                    SvcParamKey::Ipv4Hint,
+                    SvcParamValue::Ipv4Hint(IpHint(vec![A::new(192, 0, 2, 1)])),
+                ),
+                (
+                    SvcParamKey::Ipv6Hint,
+                    SvcParamValue::Ipv6Hint(IpHint(vec![AAAA::new(
+                        0x2001, 0xdb8, 0, 0, 0, 0, 0, 1,
+                    )])),
+                ),
+            ],
+        );
+
+        let looked_up = IpAddr::from(Ipv4Addr::new(192, 0, 2, 2));
+        let v4_hint = IpAddr::from(Ipv4Addr::new(192, 0, 2, 1));
+        let v6_hint = IpAddr::from(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+
+        // the looked up addresses replace the hints of their family
+        assert_eq!(
+            merge_hints(LookupIpStrategy::Ipv4AndIpv6, vec![looked_up], &svcb),
+            vec![looked_up, v6_hint]
+        );
+        assert_eq!(
+            merge_hints(LookupIpStrategy::Ipv4thenIpv6, vec![looked_up], &svcb),
+            vec![looked_up]
+        );
+        assert_eq!(
+            merge_hints(LookupIpStrategy::Ipv6Only, vec![], &svcb),
+            vec![v6_hint]
+        );
+        assert_eq!(
+            merge_hints(LookupIpStrategy::Ipv4thenIpv6, vec![], &svcb),
+            vec![v4_hint]
+        );
+    }
+}
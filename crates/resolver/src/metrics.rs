@@ -0,0 +1,42 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Metrics of the resolver, recorded with the [`metrics`] facade
+//!
+//! Nothing is recorded until the application installs a recorder.
+
+use metrics::{counter, describe_counter};
+
+/// Lookups in the cache of the resolver, labeled with the `result`, `hit` or `miss`
+pub const CACHE_LOOKUPS_TOTAL: &str = "hickory_resolver_cache_lookups_total";
+
+/// Describes the metrics of the resolver to the installed recorder
+pub fn describe() {
+    describe_cache_lookups(CACHE_LOOKUPS_TOTAL, "resolver");
+}
+
+pub(crate) fn record_cache_lookup(hit: bool) {
+    record_cache_lookup_in(CACHE_LOOKUPS_TOTAL, hit);
+}
+
+/// Describes the counter `name` of the lookups in the cache of `owner`
+///
+/// The recursor counts the lookups in its own cache with a counter of its own.
+#[doc(hidden)]
+pub fn describe_cache_lookups(name: &'static str, owner: &str) {
+    describe_counter!(
+        name,
+        format!("Number of lookups in the cache of the {owner}")
+    );
+}
+
+/// Counts a lookup in a cache with the counter `name`, see [`describe_cache_lookups`]
+#[doc(hidden)]
+pub fn record_cache_lookup_in(name: &'static str, hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    counter!(name, "result" => result).increment(1);
+}
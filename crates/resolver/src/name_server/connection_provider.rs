@@ -73,6 +73,12 @@ pub trait ConnectionProvider: 'static + Clone + Send + Sync + Unpin {
         config: &NameServerConfig,
         options: &ResolverOpts,
     ) -> Result<Self::FutureConn, io::Error>;
+
+    /// The runtime of the connections, used to spawn background tasks of the resolver, e.g. the
+    /// refresh of stale records. Returns `None` by default, which disables these tasks.
+    fn runtime_provider(&self) -> Option<&Self::RuntimeProvider> {
+        None
+    }
 }
 
 #[cfg(feature = "dns-over-tls")]
@@ -296,6 +302,43 @@ impl<P: RuntimeProvider> ConnectionProvider for GenericConnector<P> {
                 );
                 ConnectionConnect::Https(exchange)
             }
+            #[cfg(feature = "dns-over-odoh")]
+            (Protocol::Odoh, _) => {
+                let socket_addr = config.socket_addr;
+                let tls_dns_name = config.tls_dns_name.clone().unwrap_or_default();
+                let http_endpoint = config
+                    .http_endpoint
+                    .clone()
+                    .filter(|endpoint| {
+                        endpoint
+                            .parse()
+                            .is_ok_and(|uri| proto::odoh::target_from_query(&uri).is_some())
+                    })
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "the http_endpoint of an ODoH proxy must name the target",
+                        )
+                    })?;
+                let target_config = config.odoh_config.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "ODoH requires the odoh_config of the target",
+                    )
+                })?;
+                let client_config = config.tls_config.clone();
+                let tcp_future = self.runtime_provider.connect_tcp(socket_addr, None, None);
+
+                let exchange = crate::h2::new_odoh_stream_with_future(
+                    tcp_future,
+                    socket_addr,
+                    tls_dns_name,
+                    http_endpoint,
+                    target_config,
+                    client_config,
+                );
+                ConnectionConnect::Https(exchange)
+            }
             #[cfg(feature = "dns-over-quic")]
             (Protocol::Quic, Some(binder)) => {
                 let socket_addr = config.socket_addr;
@@ -353,6 +396,10 @@ impl<P: RuntimeProvider> ConnectionProvider for GenericConnector<P> {
             spawner: self.runtime_provider.create_handle(),
         })
     }
+
+    fn runtime_provider(&self) -> Option<&Self::RuntimeProvider> {
+        Some(&self.runtime_provider)
+    }
 }
 
 /// A stream of response to a DNS request.
@@ -0,0 +1,814 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Discovery of Designated Resolvers, [RFC 9462](https://datatracker.ietf.org/doc/html/rfc9462)
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_util::future::{join_all, FutureExt};
+use parking_lot::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::config::{NameServerConfig, ResolverConfig, ResolverOpts};
+use crate::lookup_service::mandatory_supported;
+use crate::name_server::{ConnectionProvider, NameServer};
+use crate::proto::op::Query;
+use crate::proto::rr::rdata::svcb::{SvcParamKey, SvcParamValue, SVCB};
+use crate::proto::rr::{Name, RData, Record, RecordType};
+use crate::proto::runtime::{RuntimeProvider, Spawn, Time};
+use crate::proto::xfer::{DnsHandle, DnsRequestOptions, FirstAnswer, Protocol};
+
+/// The name under which resolvers publish their designated resolvers, RFC 9462 section 4
+const RESOLVER_ARPA: &str = "_dns.resolver.arpa.";
+
+/// Time allowed for the discovery and verification of the designated resolver of a name server
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before the discovery is repeated when no designated resolver was found, e.g. because
+///  the network was not up yet, doubled after each discovery which finds none
+const DISCOVERY_RETRY: Duration = Duration::from_secs(30);
+
+/// Maximum delay before the discovery is repeated when no designated resolver was found
+const MAX_DISCOVERY_RETRY: Duration = Duration::from_secs(3600);
+
+/// The name servers of a pool, upgraded to the designated resolvers of those that have one
+///
+/// The designated resolvers are discovered in the background, until then the unencrypted name
+/// servers are used. The discovery is repeated once the SVCB records of the designated resolvers
+/// expire, the previous designated resolvers are used in the meantime. If none were found, it is
+/// retried after a delay which grows with each discovery that finds none.
+pub(crate) struct DesignatedResolvers<P: ConnectionProvider> {
+    config: ResolverConfig,
+    options: ResolverOpts,
+    conn_provider: P,
+    datagram_conns: Arc<[NameServer<P>]>,
+    stream_conns: Arc<[NameServer<P>]>,
+    state: Mutex<State<P>>,
+}
+
+/// The datagram and stream name servers of a pool
+type Conns<P> = (Arc<[NameServer<P>]>, Arc<[NameServer<P>]>);
+
+struct State<P: ConnectionProvider> {
+    datagram_conns: Arc<[NameServer<P>]>,
+    stream_conns: Arc<[NameServer<P>]>,
+    /// When to discover the designated resolvers again, `None` during a discovery
+    expires: Option<Instant>,
+    /// Delay before the next discovery if this one finds no designated resolvers
+    retry: Duration,
+}
+
+impl<P: ConnectionProvider> State<P> {
+    /// Schedules the next discovery, once the SVCB records expire after `ttl`, or after the retry
+    ///  delay if no designated resolvers were found
+    fn schedule(&mut self, ttl: Option<Duration>, now: Instant) {
+        let delay = match ttl {
+            Some(ttl) => {
+                self.retry = DISCOVERY_RETRY;
+                ttl
+            }
+            None => {
+                let retry = self.retry;
+                self.retry = (retry * 2).min(MAX_DISCOVERY_RETRY);
+                debug!("no designated resolvers found, discovering again in {retry:?}");
+                retry
+            }
+        };
+
+        self.expires = Some(now + delay);
+    }
+}
+
+impl<P: ConnectionProvider> DesignatedResolvers<P> {
+    /// Starts the discovery of the designated resolvers of the unencrypted name servers of `config`
+    ///
+    /// Returns `None` if the connection provider has no runtime to run the discovery on.
+    pub(crate) fn discover(
+        config: ResolverConfig,
+        options: ResolverOpts,
+        conn_provider: P,
+        datagram_conns: Arc<[NameServer<P>]>,
+        stream_conns: Arc<[NameServer<P>]>,
+    ) -> Option<Arc<Self>> {
+        if conn_provider.runtime_provider().is_none() {
+            warn!("discover_designated_resolvers requires a connection provider with a runtime");
+            return None;
+        }
+
+        let designated = Arc::new(Self {
+            config,
+            options,
+            conn_provider,
+            state: Mutex::new(State {
+                datagram_conns: datagram_conns.clone(),
+                stream_conns: stream_conns.clone(),
+                expires: None,
+                retry: DISCOVERY_RETRY,
+            }),
+            datagram_conns,
+            stream_conns,
+        });
+
+        designated.spawn_discovery();
+        Some(designated)
+    }
+
+    /// Returns the datagram and stream name servers to send requests to
+    ///
+    /// This starts a new discovery if the designated resolvers expired.
+    pub(crate) fn conns(self: &Arc<Self>) -> Conns<P> {
+        let mut state = self.state.lock();
+        if state
+            .expires
+            .is_some_and(|expires| expires <= Instant::now())
+        {
+            state.expires = None;
+            self.spawn_discovery();
+        }
+
+        (state.datagram_conns.clone(), state.stream_conns.clone())
+    }
+
+    fn spawn_discovery(self: &Arc<Self>) {
+        let Some(runtime_provider) = self.conn_provider.runtime_provider() else {
+            return;
+        };
+
+        let this = Arc::clone(self);
+        runtime_provider.create_handle().spawn_bg(async move {
+            let (designated, ttl) =
+                discover(&this.config, &this.options, &this.conn_provider).await;
+
+            let mut state = this.state.lock();
+            state.datagram_conns = upgrade(&this.datagram_conns, &designated, true);
+            state.stream_conns = upgrade(&this.stream_conns, &designated, false);
+            state.schedule(ttl, Instant::now());
+            Ok(())
+        });
+    }
+}
+
+/// Replaces the name servers that have a designated resolver by it
+///
+/// Requests to an upgraded name server must never fall back to its unencrypted transports, so
+/// the designated resolvers are only added to the datagram name servers, which are tried first,
+/// and the upgraded name servers are removed from the stream name servers.
+fn upgrade<P: ConnectionProvider>(
+    conns: &[NameServer<P>],
+    designated: &HashMap<IpAddr, NameServer<P>>,
+    add_designated: bool,
+) -> Arc<[NameServer<P>]> {
+    let mut upgraded = HashSet::new();
+    conns
+        .iter()
+        .filter_map(|conn| {
+            let ip = conn.config().socket_addr.ip();
+            match designated.get(&ip) {
+                None => Some(conn.clone()),
+                Some(designated) if add_designated && upgraded.insert(ip) => {
+                    Some(designated.clone())
+                }
+                Some(_) => None,
+            }
+        })
+        .collect()
+}
+
+/// Discovers the designated resolvers of the unencrypted name servers of `config`, concurrently
+///
+/// Returns the designated resolver of each name server that has one, by the address of the name
+/// server, and the time until their SVCB records expire.
+async fn discover<P: ConnectionProvider>(
+    config: &ResolverConfig,
+    options: &ResolverOpts,
+    conn_provider: &P,
+) -> (HashMap<IpAddr, NameServer<P>>, Option<Duration>) {
+    let mut seen = HashSet::new();
+    let discoveries = config
+        .name_servers()
+        .iter()
+        .filter(|ns_config| ns_config.protocol == Protocol::Udp)
+        .filter(|ns_config| seen.insert(ns_config.socket_addr.ip()))
+        .map(|ns_config| {
+            let ip = ns_config.socket_addr.ip();
+            let discovery = discover_one(
+                ns_config.clone(),
+                config.clone(),
+                options.clone(),
+                conn_provider.clone(),
+            );
+
+            <P::RuntimeProvider as RuntimeProvider>::Timer::timeout(DISCOVERY_TIMEOUT, discovery)
+                .map(move |result| {
+                    result.unwrap_or_else(|_| {
+                        debug!("timed out discovering the designated resolvers of {ip}");
+                        None
+                    })
+                })
+        });
+
+    let mut designated = HashMap::new();
+    let mut min_ttl = None;
+    for (ip, name_server, ttl) in join_all(discoveries).await.into_iter().flatten() {
+        designated.insert(ip, name_server);
+        min_ttl = Some(min_ttl.map_or(ttl, |min_ttl: u32| min_ttl.min(ttl)));
+    }
+
+    (
+        designated,
+        min_ttl.map(|ttl| Duration::from_secs(u64::from(ttl))),
+    )
+}
+
+/// Discovers the designated resolver of the unencrypted name server
+///
+/// The designated resolver is verified before use, following
+/// [RFC 9462, section 4.2](https://datatracker.ietf.org/doc/html/rfc9462#section-4.2): the
+/// TLS connection to the designated resolver is authenticated against its target name, or the IP
+/// address of the unencrypted resolver if the SVCB record has none. Returns the address of the
+/// unencrypted resolver, its designated resolver and the TTL of the SVCB records.
+async fn discover_one<P: ConnectionProvider>(
+    ns_config: NameServerConfig,
+    config: ResolverConfig,
+    options: ResolverOpts,
+    conn_provider: P,
+) -> Option<(IpAddr, NameServer<P>, u32)> {
+    let ip = ns_config.socket_addr.ip();
+    let unencrypted = NameServer::new(ns_config, options.clone(), conn_provider.clone());
+    let advertised = match query_svcb(&unencrypted).await {
+        Ok(advertised) => advertised,
+        Err(e) => {
+            debug!("no designated resolvers for {ip}: {e}");
+            return None;
+        }
+    };
+
+    let target_addrs = target_addrs(&unencrypted, ip, &advertised).await;
+    for ns_config in designated_configs(ip, &advertised.svcbs, &target_addrs, &config) {
+        let name_server = NameServer::new(ns_config, options.clone(), conn_provider.clone());
+
+        // a response over the encrypted connection means the certificate was verified
+        match query_svcb(&name_server).await {
+            Ok(_) => {}
+            Err(e) if e.is_no_records_found() => {}
+            Err(e) => {
+                debug!("failed to verify designated resolver of {ip}: {e}");
+                continue;
+            }
+        }
+
+        info!("using designated resolver of {ip}: {name_server:?}");
+        return Some((ip, name_server, advertised.ttl));
+    }
+
+    None
+}
+
+/// The designated resolvers advertised by a name server
+struct Advertised {
+    svcbs: Vec<SVCB>,
+    /// The minimum TTL of the SVCB records
+    ttl: u32,
+    /// The records of the additional section, which may hold the addresses of the targets
+    additionals: Vec<Record>,
+}
+
+/// Returns the SVCB records of the designated resolvers advertised by `name_server`
+async fn query_svcb<P: ConnectionProvider>(
+    name_server: &NameServer<P>,
+) -> Result<Advertised, crate::proto::ProtoError> {
+    // UNWRAP: the name is a valid constant
+    let name = Name::from_ascii(RESOLVER_ARPA).unwrap();
+
+    let response = name_server
+        .lookup(
+            Query::query(name, RecordType::SVCB),
+            DnsRequestOptions::default(),
+        )
+        .first_answer()
+        .await?;
+
+    let mut ttl = u32::MAX;
+    let svcbs = response
+        .answers()
+        .iter()
+        .filter_map(|record| match record.data() {
+            RData::SVCB(svcb) => {
+                ttl = ttl.min(record.ttl());
+                Some(svcb.clone())
+            }
+            _ => None,
+        })
+        .collect();
+
+    Ok(Advertised {
+        svcbs,
+        ttl,
+        additionals: response.additionals().to_vec(),
+    })
+}
+
+/// Looks up the addresses of the targets of the SVCB records without hints, in the address family
+/// of the unencrypted resolver at `ip`
+///
+/// The addresses in the additional section of the SVCB response are used, the others are queried
+/// from the unencrypted resolver.
+async fn target_addrs<P: ConnectionProvider>(
+    unencrypted: &NameServer<P>,
+    ip: IpAddr,
+    advertised: &Advertised,
+) -> HashMap<Name, Vec<IpAddr>> {
+    let record_type = if ip.is_ipv4() {
+        RecordType::A
+    } else {
+        RecordType::AAAA
+    };
+
+    let mut target_addrs = HashMap::new();
+    for svcb in &advertised.svcbs {
+        let Some(target) = target_name(svcb) else {
+            continue;
+        };
+        if !hints(ip, svcb).is_empty() || target_addrs.contains_key(target) {
+            continue;
+        }
+
+        let mut addrs = addrs_of(target, record_type, &advertised.additionals);
+        if addrs.is_empty() {
+            let query = Query::query(target.clone(), record_type);
+            match unencrypted
+                .lookup(query, DnsRequestOptions::default())
+                .first_answer()
+                .await
+            {
+                Ok(response) => addrs = addrs_of(target, record_type, response.answers()),
+                Err(e) => debug!("failed to look up designated resolver {target} of {ip}: {e}"),
+            }
+        }
+
+        target_addrs.insert(target.clone(), addrs);
+    }
+
+    target_addrs
+}
+
+/// Returns the addresses of `name` in `records`, following CNAME records
+fn addrs_of(name: &Name, record_type: RecordType, records: &[Record]) -> Vec<IpAddr> {
+    let mut name = name;
+    for _ in 0..records.len() {
+        let cname = records.iter().find_map(|record| match record.data() {
+            RData::CNAME(cname) if record.name() == name => Some(&cname.0),
+            _ => None,
+        });
+        match cname {
+            Some(cname) => name = cname,
+            None => break,
+        }
+    }
+
+    records
+        .iter()
+        .filter(|record| record.record_type() == record_type && record.name() == name)
+        .filter_map(|record| record.data().ip_addr())
+        .collect()
+}
+
+/// Returns the name of the designated resolver, or `None` if the target of the record is its
+/// owner name, `_dns.resolver.arpa`
+fn target_name(svcb: &SVCB) -> Option<&Name> {
+    Some(svcb.target_name()).filter(|target| !target.is_root())
+}
+
+/// Returns the name server configs of the designated resolvers of the resolver at `ip`, in
+/// order of preference
+///
+/// The addresses of a designated resolver are its hints, otherwise those of its target in
+/// `target_addrs`. A designated resolver without target is assumed to share the address of the
+/// unencrypted resolver.
+fn designated_configs(
+    ip: IpAddr,
+    svcbs: &[SVCB],
+    target_addrs: &HashMap<Name, Vec<IpAddr>>,
+    config: &ResolverConfig,
+) -> Vec<NameServerConfig> {
+    let mut svcbs = svcbs
+        .iter()
+        .filter(|svcb| svcb.svc_priority() > 0)
+        .collect::<Vec<_>>();
+    svcbs.sort_by_key(|svcb| svcb.svc_priority());
+
+    let mut configs = Vec::new();
+    for svcb in svcbs {
+        let target = target_name(svcb);
+        let mut addrs = hints(ip, svcb);
+        if addrs.is_empty() {
+            addrs = match target {
+                Some(target) => target_addrs.get(target).cloned().unwrap_or_default(),
+                None => vec![ip],
+            };
+        }
+
+        // the certificate must be valid for the name of the designated resolver, or else for the
+        // address of the unencrypted resolver
+        let tls_dns_name = match target {
+            Some(target) => {
+                let mut target = target.clone();
+                target.set_fqdn(false);
+                target.to_ascii()
+            }
+            None => ip.to_string(),
+        };
+
+        for endpoint in endpoints(svcb) {
+            // the authority of the requests would be an IPv6 address, which the https client
+            // doesn't support
+            if endpoint.http_endpoint.is_some() && target.is_none() && ip.is_ipv6() {
+                continue;
+            }
+
+            for addr in &addrs {
+                let mut ns_config =
+                    NameServerConfig::new(SocketAddr::new(*addr, endpoint.port), endpoint.protocol);
+                ns_config.tls_dns_name = Some(tls_dns_name.clone());
+                ns_config.http_endpoint.clone_from(&endpoint.http_endpoint);
+                ns_config.tls_config = config.client_config().cloned();
+                configs.push(ns_config);
+            }
+        }
+    }
+
+    configs
+}
+
+/// An encrypted transport offered by a designated resolver
+#[derive(Debug, PartialEq, Eq)]
+struct Endpoint {
+    protocol: Protocol,
+    port: u16,
+    http_endpoint: Option<String>,
+}
+
+/// Returns the supported endpoints of a designated resolver, see
+/// [RFC 9461](https://datatracker.ietf.org/doc/html/rfc9461)
+fn endpoints(svcb: &SVCB) -> Vec<Endpoint> {
+    /// Keys of the SvcParams that are used for designated resolvers
+    const SUPPORTED_KEYS: &[SvcParamKey] = &[
+        SvcParamKey::Alpn,
+        SvcParamKey::NoDefaultAlpn,
+        SvcParamKey::Port,
+        SvcParamKey::Ipv4Hint,
+        SvcParamKey::Ipv6Hint,
+        SvcParamKey::DohPath,
+    ];
+
+    let mut alpns = None;
+    let mut port = None;
+    let mut doh_path = None;
+    for (key, value) in svcb.svc_params() {
+        match value {
+            SvcParamValue::Mandatory(mandatory) => {
+                // ignore records that require keys that are not understood
+                if !mandatory_supported(mandatory, SUPPORTED_KEYS) {
+                    debug!("unsupported mandatory keys in designated resolver: {mandatory}");
+                    return Vec::new();
+                }
+            }
+            SvcParamValue::Alpn(alpn) => alpns = Some(&alpn.0),
+            SvcParamValue::Port(value) => port = Some(*value),
+            // the path is a URI template with a dns variable, which the https client adds itself
+            SvcParamValue::DohPath(template) => {
+                doh_path = template
+                    .strip_suffix("{?dns}")
+                    .filter(|path| path.starts_with('/'))
+                    .map(ToString::to_string)
+            }
+            SvcParamValue::NoDefaultAlpn
+            | SvcParamValue::Ipv4Hint(_)
+            | SvcParamValue::Ipv6Hint(_) => {}
+            _ => debug!("ignoring SvcParam in designated resolver: {key}"),
+        }
+    }
+
+    // there is no default alpn for DNS servers, RFC 9461 section 4.1
+    let Some(alpns) = alpns else {
+        return Vec::new();
+    };
+
+    let mut endpoints = Vec::new();
+    for alpn in alpns {
+        let (protocol, default_port, is_http) = match alpn.as_str() {
+            "dot" => (Protocol::Tls, 853, false),
+            #[cfg(feature = "dns-over-https-rustls")]
+            "h2" => (Protocol::Https, 443, true),
+            #[cfg(feature = "dns-over-h3")]
+            "h3" => (Protocol::H3, 443, true),
+            #[cfg(feature = "dns-over-quic")]
+            "doq" => (Protocol::Quic, 853, false),
+            _ => continue,
+        };
+
+        // DNS over HTTPS is only offered when the path is present
+        let http_endpoint = match (is_http, &doh_path) {
+            (false, _) => None,
+            (true, Some(path)) => Some(path.clone()),
+            (true, None) => continue,
+        };
+
+        endpoints.push(Endpoint {
+            protocol,
+            port: port.unwrap_or(default_port),
+            http_endpoint,
+        });
+    }
+
+    endpoints
+}
+
+/// Returns the address hints of a designated resolver, in the address family of the unencrypted
+/// resolver at `ip`
+fn hints(ip: IpAddr, svcb: &SVCB) -> Vec<IpAddr> {
+    svcb.svc_params()
+        .iter()
+        .flat_map(|(_, value)| match value {
+            SvcParamValue::Ipv4Hint(hint) if ip.is_ipv4() => {
+                hint.0.iter().map(|a| IpAddr::V4(a.0)).collect()
+            }
+            SvcParamValue::Ipv6Hint(hint) if ip.is_ipv6() => {
+                hint.0.iter().map(|aaaa| IpAddr::V6(aaaa.0)).collect()
+            }
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+    use crate::name_server::TokioConnectionProvider;
+    use crate::proto::rr::rdata::svcb::{Alpn, IpHint, Mandatory};
+    use crate::proto::rr::rdata::{A, AAAA, CNAME};
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    fn svcb(priority: u16, params: Vec<(SvcParamKey, SvcParamValue)>) -> SVCB {
+        SVCB::new(
+            priority,
+            Name::from_ascii("dns.example.net.").unwrap(),
+            params,
+        )
+    }
+
+    fn alpn(alpns: &[&str]) -> (SvcParamKey, SvcParamValue) {
+        (
+            SvcParamKey::Alpn,
+            SvcParamValue::Alpn(Alpn(alpns.iter().map(ToString::to_string).collect())),
+        )
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let endpoints = endpoints(&svcb(
+            1,
+            vec![
+                alpn(&["dot", "unknown"]),
+                (SvcParamKey::Port, SvcParamValue::Port(8853)),
+            ],
+        ));
+
+        assert_eq!(
+            endpoints,
+            vec![Endpoint {
+                protocol: Protocol::Tls,
+                port: 8853,
+                http_endpoint: None,
+            }]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "dns-over-https-rustls")]
+    fn test_endpoints_doh() {
+        let doh_path = (
+            SvcParamKey::DohPath,
+            SvcParamValue::DohPath("/dns-query{?dns}".to_string()),
+        );
+
+        assert_eq!(
+            endpoints(&svcb(1, vec![alpn(&["h2"]), doh_path.clone()])),
+            vec![Endpoint {
+                protocol: Protocol::Https,
+                port: 443,
+                http_endpoint: Some("/dns-query".to_string()),
+            }]
+        );
+
+        // DoH requires the path
+        assert!(endpoints(&svcb(1, vec![alpn(&["h2"])])).is_empty());
+
+        // and a name or an IPv4 resolver
+        let ipv6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let svcbs = [SVCB::new(1, Name::root(), vec![alpn(&["h2"]), doh_path])];
+        assert!(
+            designated_configs(ipv6, &svcbs, &HashMap::new(), &ResolverConfig::new()).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_endpoints_unsupported() {
+        // no alpn
+        assert!(endpoints(&svcb(1, vec![])).is_empty());
+
+        // unknown mandatory key
+        let mandatory = (
+            SvcParamKey::Mandatory,
+            SvcParamValue::Mandatory(Mandatory(vec![SvcParamKey::Key(65333)])),
+        );
+        assert!(endpoints(&svcb(1, vec![mandatory, alpn(&["dot"])])).is_empty());
+    }
+
+    #[test]
+    fn test_hints() {
+        let ipv4 = Ipv4Addr::new(192, 0, 2, 53);
+        let ipv6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 53);
+        let svcb = svcb(
+            1,
+            vec![
+                alpn(&["dot"]),
+                (
+                    SvcParamKey::Ipv4Hint,
+                    SvcParamValue::Ipv4Hint(IpHint(vec![A(ipv4)])),
+                ),
+                (
+                    SvcParamKey::Ipv6Hint,
+                    SvcParamValue::Ipv6Hint(IpHint(vec![AAAA(ipv6)])),
+                ),
+            ],
+        );
+
+        assert_eq!(hints(IP, &svcb), vec![IpAddr::V4(ipv4)]);
+
+        let unencrypted = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        assert_eq!(hints(unencrypted, &svcb), vec![IpAddr::V6(ipv6)]);
+
+        assert!(hints(IP, &self::svcb(1, vec![alpn(&["dot"])])).is_empty());
+    }
+
+    #[test]
+    fn test_addrs_of() {
+        let name = |name| Name::from_ascii(name).unwrap();
+        let target = name("dns.example.net.");
+        let records = [
+            Record::from_rdata(
+                target.clone(),
+                300,
+                RData::CNAME(CNAME(name("dns.example.com."))),
+            ),
+            Record::from_rdata(
+                name("dns.example.com."),
+                300,
+                RData::A(A::new(192, 0, 2, 53)),
+            ),
+            Record::from_rdata(
+                name("other.example.com."),
+                300,
+                RData::A(A::new(192, 0, 2, 54)),
+            ),
+        ];
+
+        assert_eq!(
+            addrs_of(&target, RecordType::A, &records),
+            vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 53))]
+        );
+        assert!(addrs_of(&target, RecordType::AAAA, &records).is_empty());
+    }
+
+    #[test]
+    fn test_designated_configs() {
+        let svcbs = [
+            svcb(
+                2,
+                vec![
+                    alpn(&["dot"]),
+                    (SvcParamKey::Port, SvcParamValue::Port(8853)),
+                ],
+            ),
+            // alias mode is not used for designated resolvers
+            svcb(0, vec![]),
+            svcb(1, vec![alpn(&["dot"])]),
+        ];
+
+        // the addresses of the target are used without hints
+        let designated = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 53));
+        let target_addrs = HashMap::from([(
+            Name::from_ascii("dns.example.net.").unwrap(),
+            vec![designated],
+        )]);
+
+        let configs = designated_configs(IP, &svcbs, &target_addrs, &ResolverConfig::new());
+        let addrs = configs
+            .iter()
+            .map(|ns_config| ns_config.socket_addr)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            addrs,
+            vec![
+                SocketAddr::new(designated, 853),
+                SocketAddr::new(designated, 8853)
+            ]
+        );
+        // and the certificate is verified against its name
+        assert!(configs.iter().all(|ns_config| {
+            ns_config.protocol == Protocol::Tls
+                && ns_config.tls_dns_name.as_deref() == Some("dns.example.net")
+        }));
+
+        // a target without addresses is not used
+        assert!(designated_configs(IP, &svcbs, &HashMap::new(), &ResolverConfig::new()).is_empty());
+
+        // without target, the designated resolver is at the address of the unencrypted resolver
+        let svcbs = [SVCB::new(1, Name::root(), vec![alpn(&["dot"])])];
+        let configs = designated_configs(IP, &svcbs, &HashMap::new(), &ResolverConfig::new());
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].socket_addr, SocketAddr::new(IP, 853));
+        assert_eq!(configs[0].tls_dns_name.as_deref(), Some("192.0.2.1"));
+    }
+
+    #[test]
+    fn test_schedule() {
+        let mut state = State::<TokioConnectionProvider> {
+            datagram_conns: Arc::from([]),
+            stream_conns: Arc::from([]),
+            expires: None,
+            retry: DISCOVERY_RETRY,
+        };
+        let now = Instant::now();
+
+        // failed discoveries are retried with a growing delay
+        state.schedule(None, now);
+        assert_eq!(state.expires, Some(now + DISCOVERY_RETRY));
+        state.schedule(None, now);
+        assert_eq!(state.expires, Some(now + DISCOVERY_RETRY * 2));
+        for _ in 0..10 {
+            state.schedule(None, now);
+        }
+        assert_eq!(state.expires, Some(now + MAX_DISCOVERY_RETRY));
+
+        // designated resolvers are discovered again once their records expire
+        let ttl = Duration::from_secs(300);
+        state.schedule(Some(ttl), now);
+        assert_eq!(state.expires, Some(now + ttl));
+        state.schedule(None, now);
+        assert_eq!(state.expires, Some(now + DISCOVERY_RETRY));
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let name_server = |ip: IpAddr, port, protocol| {
+            NameServer::new(
+                NameServerConfig::new(SocketAddr::new(ip, port), protocol),
+                ResolverOpts::default(),
+                TokioConnectionProvider::default(),
+            )
+        };
+        let addrs = |conns: Arc<[NameServer<TokioConnectionProvider>]>| {
+            conns
+                .iter()
+                .map(|conn| (conn.config().socket_addr, conn.config().protocol))
+                .collect::<Vec<_>>()
+        };
+
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        let datagram_conns = [
+            name_server(IP, 53, Protocol::Udp),
+            name_server(other, 53, Protocol::Udp),
+        ];
+        let stream_conns = [
+            name_server(IP, 53, Protocol::Tcp),
+            name_server(other, 53, Protocol::Tcp),
+        ];
+        let designated = HashMap::from([(IP, name_server(IP, 853, Protocol::Tls))]);
+
+        // the name server without a designated resolver is kept
+        assert_eq!(
+            addrs(upgrade(&datagram_conns, &designated, true)),
+            vec![
+                (SocketAddr::new(IP, 853), Protocol::Tls),
+                (SocketAddr::new(other, 53), Protocol::Udp),
+            ]
+        );
+        // and the upgraded one is not queried unencrypted over TCP
+        assert_eq!(
+            addrs(upgrade(&stream_conns, &designated, false)),
+            vec![(SocketAddr::new(other, 53), Protocol::Tcp)]
+        );
+    }
+}
@@ -8,6 +8,8 @@
 //! A module with associated items for working with nameservers
 
 mod connection_provider;
+#[cfg(feature = "dns-over-rustls")]
+pub(crate) mod ddr;
 #[allow(clippy::module_inception)]
 mod name_server;
 mod name_server_pool;
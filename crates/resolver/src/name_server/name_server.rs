@@ -166,6 +166,12 @@ where
     pub fn trust_nx_responses(&self) -> bool {
         self.config.trust_negative_responses
     }
+
+    /// The configuration of this name server
+    #[cfg(any(all(test, feature = "tokio-runtime"), feature = "dns-over-rustls"))]
+    pub(crate) fn config(&self) -> &NameServerConfig {
+        &self.config
+    }
 }
 
 impl<P> DnsHandle for NameServer<P>
@@ -251,6 +257,8 @@ mod tests {
             trust_negative_responses: false,
             #[cfg(feature = "dns-over-rustls")]
             tls_config: None,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh_config: None,
             bind_addr: None,
         };
         let io_loop = Runtime::new().unwrap();
@@ -290,6 +298,8 @@ mod tests {
             trust_negative_responses: false,
             #[cfg(feature = "dns-over-rustls")]
             tls_config: None,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh_config: None,
             bind_addr: None,
         };
         let io_loop = Runtime::new().unwrap();
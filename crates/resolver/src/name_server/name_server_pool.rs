@@ -23,7 +23,10 @@ use tracing::debug;
 
 use crate::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts, ServerOrderingStrategy};
 use crate::name_server::connection_provider::{ConnectionProvider, GenericConnector};
+#[cfg(feature = "dns-over-rustls")]
+use crate::name_server::ddr::DesignatedResolvers;
 use crate::name_server::name_server::NameServer;
+use crate::proto::rr::LowerName;
 use crate::proto::runtime::{RuntimeProvider, Time};
 use crate::proto::xfer::{DnsHandle, DnsRequest, DnsResponse, FirstAnswer};
 use crate::proto::{ProtoError, ProtoErrorKind};
@@ -42,6 +45,10 @@ pub struct NameServerPool<P: ConnectionProvider + Send + 'static> {
     options: ResolverOpts,
     datagram_index: Arc<AtomicUsize>,
     stream_index: Arc<AtomicUsize>,
+    #[cfg(feature = "dns-over-rustls")]
+    designated: Option<Arc<DesignatedResolvers<P>>>,
+    /// Pools for names within specific domains, taking precedence over this one
+    domains: Arc<[(LowerName, Self)]>,
 }
 
 impl<P> NameServerPool<P>
@@ -53,15 +60,39 @@ where
         options: ResolverOpts,
         conn_provider: P,
     ) -> Self {
-        let datagram_conns = config
-            .name_servers()
+        let domains = config
+            .domain_name_servers()
+            .iter()
+            .map(|domain| {
+                let options = domain.options.clone().unwrap_or_else(|| options.clone());
+                let pool = Self::from_group_with_provider(
+                    &domain.name_servers,
+                    options,
+                    conn_provider.clone(),
+                );
+                (LowerName::from(&domain.domain), pool)
+            })
+            .collect::<Vec<_>>();
+
+        let mut pool =
+            Self::from_group_with_provider(config.name_server_group(), options, conn_provider);
+        pool.domains = Arc::from(domains);
+        pool
+    }
+
+    fn from_group_with_provider(
+        name_servers: &NameServerConfigGroup,
+        options: ResolverOpts,
+        conn_provider: P,
+    ) -> Self {
+        let datagram_conns = name_servers
             .iter()
             .filter(|ns_config| ns_config.protocol.is_datagram())
             .map(|ns_config| {
                 #[cfg(feature = "dns-over-rustls")]
                 let ns_config = {
                     let mut ns_config = ns_config.clone();
-                    ns_config.tls_config = config.client_config().cloned();
+                    ns_config.tls_config = name_servers.client_config().cloned();
                     ns_config
                 };
                 #[cfg(not(feature = "dns-over-rustls"))]
@@ -71,15 +102,14 @@ where
             })
             .collect();
 
-        let stream_conns = config
-            .name_servers()
+        let stream_conns = name_servers
             .iter()
             .filter(|ns_config| ns_config.protocol.is_stream())
             .map(|ns_config| {
                 #[cfg(feature = "dns-over-rustls")]
                 let ns_config = {
                     let mut ns_config = ns_config.clone();
-                    ns_config.tls_config = config.client_config().cloned();
+                    ns_config.tls_config = name_servers.client_config().cloned();
                     ns_config
                 };
                 #[cfg(not(feature = "dns-over-rustls"))]
@@ -95,6 +125,9 @@ where
             options,
             datagram_index: Arc::from(AtomicUsize::new(0)),
             stream_index: Arc::from(AtomicUsize::new(0)),
+            #[cfg(feature = "dns-over-rustls")]
+            designated: None,
+            domains: Arc::from([]),
         }
     }
 
@@ -121,6 +154,9 @@ where
             options,
             datagram_index: Arc::from(AtomicUsize::new(0)),
             stream_index: Arc::from(AtomicUsize::new(0)),
+            #[cfg(feature = "dns-over-rustls")]
+            designated: None,
+            domains: Arc::from([]),
         }
     }
 
@@ -136,6 +172,9 @@ where
             options,
             datagram_index: Arc::from(AtomicUsize::new(0)),
             stream_index: Arc::from(AtomicUsize::new(0)),
+            #[cfg(feature = "dns-over-rustls")]
+            designated: None,
+            domains: Arc::from([]),
         }
     }
 
@@ -152,9 +191,43 @@ where
             options,
             datagram_index: Arc::from(AtomicUsize::new(0)),
             stream_index: Arc::from(AtomicUsize::new(0)),
+            #[cfg(feature = "dns-over-rustls")]
+            designated: None,
+            domains: Arc::from([]),
         }
     }
 
+    /// Upgrades the name servers of `config` to their designated resolvers, see RFC 9462
+    ///
+    /// The designated resolvers are discovered in the background, requests are sent to the
+    /// unencrypted name servers until then. The name servers without designated resolvers are kept,
+    /// see [`DesignatedResolvers`].
+    #[cfg(feature = "dns-over-rustls")]
+    pub(crate) fn with_designated_resolvers(
+        mut self,
+        config: &ResolverConfig,
+        conn_provider: P,
+    ) -> Self {
+        self.designated = DesignatedResolvers::discover(
+            config.clone(),
+            self.options.clone(),
+            conn_provider,
+            Arc::clone(&self.datagram_conns),
+            Arc::clone(&self.stream_conns),
+        );
+        self
+    }
+
+    /// Returns the pool for the longest domain containing the name of the request, if any
+    fn domain_pool(&self, request: &DnsRequest) -> Option<&Self> {
+        let name = LowerName::from(request.queries().first()?.name());
+        self.domains
+            .iter()
+            .filter(|(domain, _)| domain.zone_of(&name))
+            .max_by_key(|(domain, _)| domain.num_labels())
+            .map(|(_, pool)| pool)
+    }
+
     async fn try_send(
         opts: ResolverOpts,
         conns: Arc<[NameServer<P>]>,
@@ -195,10 +268,25 @@ where
     type Response = Pin<Box<dyn Stream<Item = Result<DnsResponse, ProtoError>> + Send>>;
 
     fn send<R: Into<DnsRequest>>(&self, request: R) -> Self::Response {
-        let opts = self.options.clone();
         let request = request.into();
-        let datagram_conns = Arc::clone(&self.datagram_conns);
-        let stream_conns = Arc::clone(&self.stream_conns);
+        if let Some(pool) = self.domain_pool(&request) {
+            return pool.send(request);
+        }
+
+        let opts = self.options.clone();
+        #[cfg(feature = "dns-over-rustls")]
+        let (datagram_conns, stream_conns) = match &self.designated {
+            Some(designated) => designated.conns(),
+            None => (
+                Arc::clone(&self.datagram_conns),
+                Arc::clone(&self.stream_conns),
+            ),
+        };
+        #[cfg(not(feature = "dns-over-rustls"))]
+        let (datagram_conns, stream_conns) = (
+            Arc::clone(&self.datagram_conns),
+            Arc::clone(&self.stream_conns),
+        );
         let datagram_index = Arc::clone(&self.datagram_index);
         let stream_index = Arc::clone(&self.stream_index);
         // TODO: remove this clone, return the Message in the error?
@@ -417,14 +505,15 @@ mod tests {
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
     use std::str::FromStr;
 
+    use futures_util::future;
     use tokio::runtime::Runtime;
 
     use super::*;
     use crate::config::NameServerConfig;
     use crate::name_server::connection_provider::TokioConnectionProvider;
     use crate::name_server::GenericNameServer;
-    use crate::proto::op::Query;
-    use crate::proto::rr::{Name, RecordType};
+    use crate::proto::op::{Message, MessageType, Query};
+    use crate::proto::rr::{rdata::A, Name, RData, Record, RecordType};
     use crate::proto::runtime::TokioRuntimeProvider;
     use crate::proto::xfer::{DnsHandle, DnsRequestOptions, Protocol};
 
@@ -441,6 +530,8 @@ mod tests {
             trust_negative_responses: false,
             #[cfg(feature = "dns-over-rustls")]
             tls_config: None,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh_config: None,
             bind_addr: None,
         };
 
@@ -452,6 +543,8 @@ mod tests {
             trust_negative_responses: false,
             #[cfg(feature = "dns-over-rustls")]
             tls_config: None,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh_config: None,
             bind_addr: None,
         };
 
@@ -502,6 +595,166 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_domain_pool() {
+        let domain_servers =
+            |ip: Ipv4Addr| NameServerConfigGroup::from_ips_clear(&[IpAddr::V4(ip)], 53, true);
+        let domain_opts = |secs| {
+            Some(ResolverOpts {
+                timeout: Duration::from_secs(secs),
+                ..ResolverOpts::default()
+            })
+        };
+
+        let mut config = ResolverConfig::new();
+        config.add_domain_name_servers(
+            Name::from_str("example.com.").unwrap(),
+            domain_servers(Ipv4Addr::new(10, 0, 0, 1)),
+            domain_opts(2),
+        );
+        config.add_domain_name_servers(
+            Name::from_str("corp.example.com.").unwrap(),
+            domain_servers(Ipv4Addr::new(10, 0, 0, 2)),
+            domain_opts(3),
+        );
+        config.add_domain_name_servers(
+            Name::from_str("10.in-addr.arpa.").unwrap(),
+            domain_servers(Ipv4Addr::new(10, 0, 0, 3)),
+            None,
+        );
+
+        let pool = GenericNameServerPool::from_config_with_provider(
+            &config,
+            ResolverOpts {
+                timeout: Duration::from_secs(4),
+                ..ResolverOpts::default()
+            },
+            TokioConnectionProvider::default(),
+        );
+
+        // the name server and its timeout of the pool used for a name
+        let domain_pool = |name: &str| {
+            let query = Query::query(Name::from_str(name).unwrap(), RecordType::A);
+            let request = DnsRequest::new(
+                crate::proto::op::Message::new().add_query(query).clone(),
+                DnsRequestOptions::default(),
+            );
+            pool.domain_pool(&request).map(|pool| {
+                let name_server = &pool.datagram_conns[0];
+                (
+                    name_server.config().socket_addr.ip(),
+                    pool.options.timeout.as_secs(),
+                )
+            })
+        };
+        let server = |last| IpAddr::V4(Ipv4Addr::new(10, 0, 0, last));
+
+        assert_eq!(domain_pool("www.example.com."), Some((server(1), 2)));
+        assert_eq!(domain_pool("EXAMPLE.com."), Some((server(1), 2)));
+        assert_eq!(domain_pool("www.corp.example.com."), Some((server(2), 3)));
+        assert_eq!(domain_pool("notcorp.example.com."), Some((server(1), 2)));
+        // domains without options use those of the resolver
+        assert_eq!(domain_pool("1.0.0.10.in-addr.arpa."), Some((server(3), 4)));
+        assert_eq!(domain_pool("www.example.org."), None);
+        assert_eq!(domain_pool("com."), None);
+    }
+
+    #[test]
+    fn test_domain_pool_send() {
+        let io_loop = Runtime::new().unwrap();
+        let server = |last| IpAddr::V4(Ipv4Addr::new(10, 0, 0, last));
+
+        let mut config = ResolverConfig::new();
+        for name_server in NameServerConfigGroup::from_ips_clear(&[server(1)], 53, true).iter() {
+            config.add_name_server(name_server.clone());
+        }
+        config.add_domain_name_servers(
+            Name::from_str("corp.example.com.").unwrap(),
+            NameServerConfigGroup::from_ips_clear(&[server(2)], 53, true),
+            Some(ResolverOpts {
+                timeout: Duration::from_secs(3),
+                ..ResolverOpts::default()
+            }),
+        );
+
+        let provider = RecordingProvider::default();
+        let pool = NameServerPool::from_config_with_provider(
+            &config,
+            ResolverOpts {
+                timeout: Duration::from_secs(4),
+                ..ResolverOpts::default()
+            },
+            provider.clone(),
+        );
+        let lookup = |name: &str| {
+            let query = Query::query(Name::from_str(name).unwrap(), RecordType::A);
+            io_loop
+                .block_on(
+                    pool.lookup(query, DnsRequestOptions::default())
+                        .first_answer(),
+                )
+                .expect("lookup failed");
+            provider.0.lock().unwrap().pop()
+        };
+
+        // the request is sent to the name server of the domain, with the timeout of the domain
+        assert_eq!(
+            lookup("www.corp.example.com."),
+            Some((server(2), Duration::from_secs(3)))
+        );
+        assert_eq!(
+            lookup("www.example.com."),
+            Some((server(1), Duration::from_secs(4)))
+        );
+    }
+
+    /// Records the name servers connected to, with the timeout of the connections
+    #[derive(Clone, Default)]
+    struct RecordingProvider(Arc<std::sync::Mutex<Vec<(IpAddr, Duration)>>>);
+
+    impl ConnectionProvider for RecordingProvider {
+        type Conn = AnswerHandle;
+        type FutureConn = future::Ready<Result<AnswerHandle, ProtoError>>;
+        type RuntimeProvider = TokioRuntimeProvider;
+
+        fn new_connection(
+            &self,
+            config: &NameServerConfig,
+            options: &ResolverOpts,
+        ) -> Result<Self::FutureConn, std::io::Error> {
+            self.0
+                .lock()
+                .unwrap()
+                .push((config.socket_addr.ip(), options.timeout));
+            Ok(future::ready(Ok(AnswerHandle)))
+        }
+    }
+
+    /// Answers every query with an address
+    #[derive(Clone)]
+    struct AnswerHandle;
+
+    impl DnsHandle for AnswerHandle {
+        type Response = Pin<Box<dyn Stream<Item = Result<DnsResponse, ProtoError>> + Send>>;
+
+        fn send<R: Into<DnsRequest> + Unpin + Send + 'static>(&self, request: R) -> Self::Response {
+            let request = request.into();
+            let mut message = Message::new();
+            message
+                .set_id(request.id())
+                .set_message_type(MessageType::Response);
+            for query in request.queries() {
+                message.add_query(query.clone());
+                message.add_answer(Record::from_rdata(
+                    query.name().clone(),
+                    300,
+                    RData::A(A::new(192, 0, 2, 1)),
+                ));
+            }
+            Box::pin(once(future::ready(DnsResponse::from_message(message))))
+        }
+    }
+
     #[test]
     fn test_multi_use_conns() {
         let io_loop = Runtime::new().unwrap();
@@ -515,6 +768,8 @@ mod tests {
             trust_negative_responses: false,
             #[cfg(feature = "dns-over-rustls")]
             tls_config: None,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh_config: None,
             bind_addr: None,
         };
 
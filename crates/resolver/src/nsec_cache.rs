@@ -0,0 +1,927 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Aggressive use of DNSSEC-validated cache, see [RFC 8198](https://tools.ietf.org/html/rfc8198)
+//!
+//! Validated NSEC and NSEC3 records prove that no names exist in the ranges between their owner
+//! and next names. Once cached, they answer queries for other names within these ranges without
+//! asking the name servers, which defeats random subdomain attacks against the authoritative
+//! servers.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use tracing::debug;
+
+use crate::lookup::Lookup;
+use crate::proto::dnssec::rdata::{DNSSECRData, NSEC, NSEC3};
+use crate::proto::dnssec::Nsec3HashAlgorithm;
+use crate::proto::op::{Query, ResponseCode};
+use crate::proto::rr::rdata::SOA;
+use crate::proto::rr::{DNSClass, Name, RData, Record, RecordType};
+use crate::proto::xfer::DnsResponse;
+use crate::proto::{ProtoError, ProtoErrorKind};
+
+/// NSEC3 records with more iterations are not cached, as every lookup would have to hash the name
+/// that many times, see [RFC 9276](https://tools.ietf.org/html/rfc9276#section-3.2)
+const MAX_NSEC3_ITERATIONS: u16 = 100;
+
+/// DNAME records redirect all names below their owner, see [RFC 6672](https://tools.ietf.org/html/rfc6672)
+const DNAME: RecordType = RecordType::Unknown(39);
+
+/// A cache of validated NSEC and NSEC3 records, and of the wildcard records they allow to expand
+///
+/// Only records with a [`Proof::Secure`](crate::proto::dnssec::Proof::Secure) are cached, NSEC3
+/// records with the Opt-Out flag are never used.
+#[derive(Clone)]
+pub struct NsecCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl fmt::Debug for NsecCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NsecCache")
+            .field("len", &self.inner.lock().len)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Inner {
+    zones: HashMap<Name, Zone>,
+    len: usize,
+    max_size: usize,
+}
+
+#[derive(Default)]
+struct Zone {
+    soa: Option<Cached>,
+    nsecs: BTreeMap<Name, Cached>,
+    nsec3s: BTreeMap<Vec<u8>, Cached>,
+    nsec3_params: Option<(Nsec3HashAlgorithm, Vec<u8>, u16)>,
+    wildcards: HashMap<(Name, RecordType), Cached>,
+}
+
+/// A record set along with its RRSIGs
+struct Cached {
+    records: Vec<Record>,
+    valid_until: Instant,
+}
+
+/// What the cached records prove about a query
+enum Answer<'a> {
+    NxDomain(Vec<&'a Cached>),
+    NoData(Vec<&'a Cached>),
+    Wildcard(&'a Cached, Vec<&'a Cached>),
+}
+
+impl NsecCache {
+    /// Creates a cache holding up to `max_size` record sets
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                zones: HashMap::new(),
+                len: 0,
+                max_size,
+            })),
+        }
+    }
+
+    /// Caches the validated NSEC and NSEC3 records of a response, along with the SOA and any
+    /// records expanded from a wildcard
+    pub fn insert(&self, response: &DnsResponse, now: Instant) {
+        let negative_ttl = response.negative_ttl();
+        let mut inner = self.inner.lock();
+
+        for record in response.name_servers() {
+            if !record.proof().is_secure() {
+                continue;
+            }
+
+            let Some((zone, rrsigs)) = signed_by(record, response.name_servers()) else {
+                continue;
+            };
+
+            match record.data() {
+                RData::SOA(_) if record.name() == &zone => {
+                    let cached = Cached::new(record, rrsigs, record.ttl(), now);
+                    inner.zone(zone).soa = Some(cached);
+                }
+                RData::DNSSEC(DNSSECRData::NSEC(_)) => {
+                    let ttl = negative_ttl.map_or(record.ttl(), |ttl| ttl.min(record.ttl()));
+                    let cached = Cached::new(record, rrsigs, ttl, now);
+                    inner.insert_nsec(zone, record.name().clone(), cached, now);
+                }
+                RData::DNSSEC(DNSSECRData::NSEC3(nsec3)) => {
+                    if nsec3.opt_out() || nsec3.iterations() > MAX_NSEC3_ITERATIONS {
+                        continue;
+                    }
+
+                    let Some(hash) = hashed_owner(record.name(), &zone) else {
+                        continue;
+                    };
+
+                    let ttl = negative_ttl.map_or(record.ttl(), |ttl| ttl.min(record.ttl()));
+                    let cached = Cached::new(record, rrsigs, ttl, now);
+                    inner.insert_nsec3(zone, nsec3, hash, cached, now);
+                }
+                _ => {}
+            }
+        }
+
+        // RRSIGs with fewer labels than their owner name sign records expanded from a wildcard
+        for rrsig in response.answers() {
+            let Some(sig) = rrsig.data().as_dnssec().and_then(DNSSECRData::as_rrsig) else {
+                continue;
+            };
+
+            let labels = usize::from(sig.num_labels());
+            if labels >= rrsig.name().num_labels() as usize {
+                continue;
+            }
+
+            let Ok(wildcard) = rrsig.name().trim_to(labels).prepend_label("*") else {
+                continue;
+            };
+
+            let mut records = response
+                .answers()
+                .iter()
+                .filter(|r| r.name() == rrsig.name() && r.record_type() == sig.type_covered())
+                .filter(|r| r.proof().is_secure())
+                .cloned()
+                .collect::<Vec<_>>();
+            let Some(ttl) = records.iter().map(Record::ttl).min() else {
+                continue;
+            };
+
+            records.push(rrsig.clone());
+            for record in &mut records {
+                record.set_name(wildcard.clone());
+            }
+
+            let cached = Cached {
+                records,
+                valid_until: now + Duration::from_secs(u64::from(ttl)),
+            };
+            inner.insert_wildcard(
+                sig.signer_name().clone(),
+                (wildcard, sig.type_covered()),
+                cached,
+                now,
+            );
+        }
+    }
+
+    /// Answers the query from the cached records, if they prove that the name or type does not
+    /// exist, or that it is expanded from a cached wildcard
+    ///
+    /// Denials are returned as [`ProtoErrorKind::NoRecordsFound`] errors, carrying the SOA of the
+    /// zone and the NSEC or NSEC3 records of the proof.
+    pub fn get(&self, query: &Query, now: Instant) -> Option<Result<Lookup, ProtoError>> {
+        if query.query_class() != DNSClass::IN {
+            return None;
+        }
+
+        let inner = self.inner.lock();
+        let name = query.name();
+
+        // only the closest enclosing zone may prove anything about the name
+        let (apex, zone) = (0..=name.num_labels()).rev().find_map(|labels| {
+            let apex = name.trim_to(labels as usize);
+            inner.zones.get_key_value(&apex)
+        })?;
+
+        let soa = zone.soa.as_ref().filter(|soa| soa.is_current(now))?;
+        let answer = match zone.nsec3_params {
+            Some(_) => zone.prove_nsec3(apex, query, now),
+            None => zone.prove_nsec(apex, query, now),
+        }?;
+
+        let (response_code, proof) = match answer {
+            Answer::NxDomain(proof) => (ResponseCode::NXDomain, proof),
+            Answer::NoData(proof) => (ResponseCode::NoError, proof),
+            Answer::Wildcard(wildcard, proof) => {
+                debug!("answering {query} from a cached wildcard");
+                let valid_until = proof
+                    .iter()
+                    .map(|cached| cached.valid_until)
+                    .fold(wildcard.valid_until, Instant::min);
+                let ttl = valid_until.saturating_duration_since(now).as_secs() as u32;
+
+                let records = wildcard
+                    .records
+                    .iter()
+                    .map(|record| {
+                        let mut record = record.clone();
+                        record.set_name(name.clone()).set_ttl(ttl);
+                        record
+                    })
+                    .collect::<Vec<_>>();
+
+                return Some(Ok(Lookup::new_with_deadline(
+                    query.clone(),
+                    Arc::from(records),
+                    valid_until,
+                )));
+            }
+        };
+
+        debug!("answering {query} with {response_code} from cached NSEC records");
+        let valid_until = proof
+            .iter()
+            .map(|cached| cached.valid_until)
+            .fold(soa.valid_until, Instant::min);
+        let negative_ttl = valid_until.saturating_duration_since(now).as_secs() as u32;
+
+        let soa_record = soa.records.first().and_then(|record| {
+            let soa = record.data().as_soa()?.clone();
+            Some(Record::<SOA>::from_rdata(
+                record.name().clone(),
+                negative_ttl,
+                soa,
+            ))
+        })?;
+
+        let mut authorities = soa.records.clone();
+        for cached in proof {
+            authorities.extend(cached.records.iter().cloned());
+        }
+
+        Some(Err(ProtoError::from(ProtoErrorKind::NoRecordsFound {
+            query: Box::new(query.clone()),
+            soa: Some(Box::new(soa_record)),
+            ns: None,
+            negative_ttl: Some(negative_ttl),
+            response_code,
+            trusted: true,
+            authorities: Some(Arc::from(authorities)),
+        })))
+    }
+
+    /// Removes all cached records
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.zones.clear();
+        inner.len = 0;
+    }
+}
+
+impl Inner {
+    fn zone(&mut self, apex: Name) -> &mut Zone {
+        self.zones.entry(apex).or_default()
+    }
+
+    /// Makes room for another record set, returning false if the cache is full
+    fn reserve(&mut self, now: Instant) -> bool {
+        if self.len < self.max_size {
+            return true;
+        }
+
+        let mut len = 0;
+        self.zones.retain(|_, zone| {
+            zone.nsecs.retain(|_, cached| cached.is_current(now));
+            zone.nsec3s.retain(|_, cached| cached.is_current(now));
+            zone.wildcards.retain(|_, cached| cached.is_current(now));
+            len += zone.len();
+            zone.len() > 0 || zone.soa.as_ref().is_some_and(|soa| soa.is_current(now))
+        });
+        self.len = len;
+
+        self.len < self.max_size
+    }
+
+    fn insert_nsec(&mut self, apex: Name, owner: Name, cached: Cached, now: Instant) {
+        if !apex.zone_of(&owner) || !self.reserve(now) {
+            return;
+        }
+
+        if self.zone(apex).nsecs.insert(owner, cached).is_none() {
+            self.len += 1;
+        }
+    }
+
+    fn insert_nsec3(
+        &mut self,
+        apex: Name,
+        nsec3: &NSEC3,
+        hash: Vec<u8>,
+        cached: Cached,
+        now: Instant,
+    ) {
+        if !self.reserve(now) {
+            return;
+        }
+
+        let params = (
+            nsec3.hash_algorithm(),
+            nsec3.salt().to_vec(),
+            nsec3.iterations(),
+        );
+
+        let zone = self.zone(apex);
+        let mut removed = 0;
+        if zone.nsec3_params.as_ref() != Some(&params) {
+            // the zone was signed again with new parameters
+            removed = zone.nsec3s.len();
+            zone.nsec3s.clear();
+            zone.nsec3_params = Some(params);
+        }
+
+        let added = usize::from(zone.nsec3s.insert(hash, cached).is_none());
+        self.len = self.len + added - removed;
+    }
+
+    fn insert_wildcard(
+        &mut self,
+        apex: Name,
+        key: (Name, RecordType),
+        cached: Cached,
+        now: Instant,
+    ) {
+        if !apex.zone_of(&key.0) || !self.reserve(now) {
+            return;
+        }
+
+        if self.zone(apex).wildcards.insert(key, cached).is_none() {
+            self.len += 1;
+        }
+    }
+}
+
+impl Zone {
+    fn len(&self) -> usize {
+        self.nsecs.len() + self.nsec3s.len() + self.wildcards.len()
+    }
+
+    /// See [RFC 4035, section 5.4](https://tools.ietf.org/html/rfc4035#section-5.4)
+    fn prove_nsec(&self, apex: &Name, query: &Query, now: Instant) -> Option<Answer<'_>> {
+        let name = query.name();
+        let query_type = query.query_type();
+
+        if let Some(matching) = self.nsecs.get(name).filter(|c| c.is_current(now)) {
+            let types = matching.nsec()?.type_bit_maps();
+            return no_data(apex, name, query_type, types).then(|| Answer::NoData(vec![matching]));
+        }
+
+        let covering = self.covering_nsec(name, now)?;
+        let (owner, nsec) = (covering.records[0].name(), covering.nsec()?);
+        if is_cut(apex, owner, nsec.type_bit_maps()) && owner.zone_of(name) {
+            return None;
+        }
+
+        // the name is an empty non-terminal if the next name is below it
+        let next = nsec.next_domain_name();
+        if name.zone_of(next) {
+            return Some(Answer::NoData(vec![covering]));
+        }
+
+        // the closest encloser is the longest existing ancestor of the name
+        let closest_encloser = [owner, next]
+            .into_iter()
+            .map(|other| common_ancestor(name, other))
+            .max_by_key(Name::num_labels)?;
+        let wildcard = closest_encloser.prepend_label("*").ok()?;
+
+        if let Some(matching) = self.nsecs.get(&wildcard).filter(|c| c.is_current(now)) {
+            let types = matching.nsec()?.type_bit_maps();
+            return self.wildcard(&wildcard, query_type, types, vec![covering, matching], now);
+        }
+
+        let wildcard_covering = self.covering_nsec(&wildcard, now)?;
+        let mut proof = vec![covering];
+        if !std::ptr::eq(covering, wildcard_covering) {
+            proof.push(wildcard_covering);
+        }
+
+        Some(Answer::NxDomain(proof))
+    }
+
+    /// See [RFC 5155, section 8](https://tools.ietf.org/html/rfc5155#section-8)
+    fn prove_nsec3(&self, apex: &Name, query: &Query, now: Instant) -> Option<Answer<'_>> {
+        let (algorithm, salt, iterations) = self.nsec3_params.as_ref()?;
+        let hash = |name: &Name| -> Option<Vec<u8>> {
+            let digest = algorithm.hash(salt, name, *iterations).ok()?;
+            Some(digest.as_ref().to_vec())
+        };
+        let matching = |name: &Name| -> Option<&Cached> {
+            self.nsec3s
+                .get(&hash(name)?)
+                .filter(|cached| cached.is_current(now))
+        };
+
+        let name = query.name();
+        let query_type = query.query_type();
+
+        if let Some(matching) = matching(name) {
+            let types = matching.nsec3()?.type_bit_maps();
+            return no_data(apex, name, query_type, types).then(|| Answer::NoData(vec![matching]));
+        }
+
+        // find the closest encloser, the next closer name below it must be covered
+        let mut next_closer = name.clone();
+        let (closest_encloser, encloser) = loop {
+            if next_closer == *apex {
+                return None;
+            }
+
+            let encloser_name = next_closer.base_name();
+            if let Some(encloser) = matching(&encloser_name) {
+                break (encloser_name, encloser);
+            }
+            next_closer = encloser_name;
+        };
+
+        if is_cut(apex, &closest_encloser, encloser.nsec3()?.type_bit_maps()) {
+            return None;
+        }
+
+        let next_closer = self.covering_nsec3(&hash(&next_closer)?, now)?;
+        let wildcard = closest_encloser.prepend_label("*").ok()?;
+
+        if let Some(matching) = matching(&wildcard) {
+            let types = matching.nsec3()?.type_bit_maps();
+            let proof = vec![encloser, next_closer, matching];
+            return self.wildcard(&wildcard, query_type, types, proof, now);
+        }
+
+        let wildcard_covering = self.covering_nsec3(&hash(&wildcard)?, now)?;
+        Some(Answer::NxDomain(vec![
+            encloser,
+            next_closer,
+            wildcard_covering,
+        ]))
+    }
+
+    /// Answers from an existing wildcard, which has the record `types`
+    fn wildcard<'a>(
+        &'a self,
+        wildcard: &Name,
+        query_type: RecordType,
+        types: &[RecordType],
+        mut proof: Vec<&'a Cached>,
+        now: Instant,
+    ) -> Option<Answer<'a>> {
+        if types.contains(&RecordType::CNAME) {
+            return None;
+        }
+
+        if !types.contains(&query_type) {
+            return Some(Answer::NoData(proof));
+        }
+
+        // the wildcard itself is only needed to prove that no closer match exists
+        proof.pop();
+        let records = self
+            .wildcards
+            .get(&(wildcard.clone(), query_type))
+            .filter(|cached| cached.is_current(now))?;
+        Some(Answer::Wildcard(records, proof))
+    }
+
+    /// Returns the NSEC record whose range contains `name`
+    fn covering_nsec(&self, name: &Name, now: Instant) -> Option<&Cached> {
+        let (owner, cached) = self.nsecs.range(..name.clone()).next_back()?;
+        let next = cached.nsec()?.next_domain_name();
+
+        // the last NSEC of the zone wraps around to the apex
+        let covers = name < next || next <= owner;
+        (covers && cached.is_current(now)).then_some(cached)
+    }
+
+    /// Returns the NSEC3 record whose range contains `hash`
+    fn covering_nsec3(&self, hash: &[u8], now: Instant) -> Option<&Cached> {
+        let (owner, cached) = self
+            .nsec3s
+            .range(..hash.to_vec())
+            .next_back()
+            .or_else(|| self.nsec3s.iter().next_back())?;
+        let next = cached.nsec3()?.next_hashed_owner_name();
+
+        let covers = if owner.as_slice() < next {
+            owner.as_slice() < hash && hash < next
+        } else {
+            // the last NSEC3 of the zone wraps around to the first
+            owner.as_slice() < hash || hash < next
+        };
+        (covers && cached.is_current(now)).then_some(cached)
+    }
+}
+
+impl Cached {
+    fn new(record: &Record, rrsigs: Vec<Record>, ttl: u32, now: Instant) -> Self {
+        let mut records = vec![record.clone()];
+        records.extend(rrsigs);
+
+        Self {
+            records,
+            valid_until: now + Duration::from_secs(u64::from(ttl)),
+        }
+    }
+
+    fn is_current(&self, now: Instant) -> bool {
+        now < self.valid_until
+    }
+
+    fn nsec(&self) -> Option<&NSEC> {
+        self.records.first()?.data().as_dnssec()?.as_nsec()
+    }
+
+    fn nsec3(&self) -> Option<&NSEC3> {
+        self.records.first()?.data().as_dnssec()?.as_nsec3()
+    }
+}
+
+/// Returns the zone which signed the record, along with the RRSIGs
+fn signed_by(record: &Record, section: &[Record]) -> Option<(Name, Vec<Record>)> {
+    let rrsigs = section
+        .iter()
+        .filter(|r| r.name() == record.name())
+        .filter(|r| {
+            r.data()
+                .as_dnssec()
+                .and_then(DNSSECRData::as_rrsig)
+                .is_some_and(|sig| sig.type_covered() == record.record_type())
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let rrsig = rrsigs.first()?.data().as_dnssec()?.as_rrsig()?;
+    Some((rrsig.signer_name().clone(), rrsigs))
+}
+
+/// Decodes the hash in the first label of an NSEC3 owner name, `<base32-hash>.<apex>`
+fn hashed_owner(owner: &Name, apex: &Name) -> Option<Vec<u8>> {
+    if owner.base_name() != *apex {
+        return None;
+    }
+
+    let label = owner.iter().next()?;
+    data_encoding::BASE32_DNSSEC
+        .decode(&label.to_ascii_lowercase())
+        .ok()
+}
+
+/// Whether a record matching the name proves that there is no data of the query type
+fn no_data(apex: &Name, name: &Name, query_type: RecordType, types: &[RecordType]) -> bool {
+    if types.contains(&query_type) || types.contains(&RecordType::CNAME) {
+        return false;
+    }
+
+    match query_type {
+        // the DS records of a zone are in the parent
+        RecordType::DS => name != apex,
+        // names at a delegation are answered with a referral
+        _ => !is_cut(apex, name, types),
+    }
+}
+
+/// Whether the names below `name` are delegated or redirected elsewhere
+fn is_cut(apex: &Name, name: &Name, types: &[RecordType]) -> bool {
+    let delegation =
+        name != apex && types.contains(&RecordType::NS) && !types.contains(&RecordType::SOA);
+    delegation || types.contains(&DNAME)
+}
+
+/// The longest name that is an ancestor of, or equal to, both names
+fn common_ancestor(name: &Name, other: &Name) -> Name {
+    let mut labels = name.num_labels().min(other.num_labels()) as usize;
+    while name.trim_to(labels) != other.trim_to(labels) {
+        labels -= 1;
+    }
+
+    name.trim_to(labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::proto::dnssec::rdata::{DNSSECRData, RRSIG};
+    use crate::proto::dnssec::{Algorithm, Proof};
+    use crate::proto::op::Message;
+    use crate::proto::rr::rdata::A;
+
+    fn name(name: &str) -> Name {
+        Name::from_str(name).unwrap()
+    }
+
+    fn rrsig(owner: &str, type_covered: RecordType, labels: u8) -> Record {
+        let sig = RRSIG::new(
+            type_covered,
+            Algorithm::ECDSAP256SHA256,
+            labels,
+            300,
+            0,
+            0,
+            0,
+            name("example.com."),
+            vec![],
+        );
+
+        Record::from_rdata(name(owner), 300, RData::DNSSEC(DNSSECRData::RRSIG(sig)))
+    }
+
+    fn secure(mut record: Record) -> Record {
+        record.set_proof(Proof::Secure);
+        record
+    }
+
+    fn soa() -> [Record; 2] {
+        let soa = SOA::new(
+            name("ns.example.com."),
+            name("hostmaster.example.com."),
+            1,
+            3600,
+            600,
+            86400,
+            120,
+        );
+
+        [
+            secure(Record::from_rdata(
+                name("example.com."),
+                300,
+                RData::SOA(soa),
+            )),
+            rrsig("example.com.", RecordType::SOA, 2),
+        ]
+    }
+
+    fn nsec(owner: &str, next: &str, types: &[RecordType]) -> [Record; 2] {
+        let nsec = NSEC::new(name(next), types.to_vec());
+        let labels = name(owner).num_labels();
+
+        [
+            secure(Record::from_rdata(
+                name(owner),
+                300,
+                RData::DNSSEC(DNSSECRData::NSEC(nsec)),
+            )),
+            rrsig(owner, RecordType::NSEC, labels),
+        ]
+    }
+
+    fn response(answers: Vec<Record>, name_servers: Vec<Record>) -> DnsResponse {
+        let mut message = Message::new();
+        message.insert_answers(answers);
+        message.insert_name_servers(name_servers);
+        DnsResponse::from_message(message).unwrap()
+    }
+
+    fn query(name_: &str, query_type: RecordType) -> Query {
+        Query::query(name(name_), query_type)
+    }
+
+    fn response_code(result: Option<Result<Lookup, ProtoError>>) -> Option<ResponseCode> {
+        match result?.unwrap_err().kind() {
+            ProtoErrorKind::NoRecordsFound { response_code, .. } => Some(*response_code),
+            kind => panic!("unexpected error: {kind}"),
+        }
+    }
+
+    #[test]
+    fn test_nsec() {
+        let cache = NsecCache::new(32);
+        let now = Instant::now();
+
+        // example.com. has A records at b.example.com., d.example.com. is delegated
+        let mut name_servers = soa().to_vec();
+        name_servers.extend(nsec(
+            "example.com.",
+            "b.example.com.",
+            &[RecordType::SOA, RecordType::NS, RecordType::NSEC],
+        ));
+        name_servers.extend(nsec(
+            "b.example.com.",
+            "d.example.com.",
+            &[RecordType::A, RecordType::NSEC],
+        ));
+        name_servers.extend(nsec(
+            "d.example.com.",
+            "f.g.example.com.",
+            &[RecordType::NS, RecordType::NSEC],
+        ));
+        cache.insert(&response(vec![], name_servers), now);
+
+        let get = |name: &str, query_type| response_code(cache.get(&query(name, query_type), now));
+
+        // covered by the apex and b.example.com. NSEC, no wildcard at *.example.com.
+        assert_eq!(
+            get("a.example.com.", RecordType::A),
+            Some(ResponseCode::NXDomain)
+        );
+        assert_eq!(
+            get("c.example.com.", RecordType::A),
+            Some(ResponseCode::NXDomain)
+        );
+        assert_eq!(
+            get("B.example.com.", RecordType::AAAA),
+            Some(ResponseCode::NoError)
+        );
+        assert_eq!(
+            get("example.com.", RecordType::MX),
+            Some(ResponseCode::NoError)
+        );
+        // g.example.com. is an empty non-terminal
+        assert_eq!(
+            get("g.example.com.", RecordType::A),
+            Some(ResponseCode::NoError)
+        );
+
+        // existing records
+        assert_eq!(get("b.example.com.", RecordType::A), None);
+        // delegated names
+        assert_eq!(get("d.example.com.", RecordType::A), None);
+        assert_eq!(get("x.d.example.com.", RecordType::A), None);
+        assert_eq!(
+            get("d.example.com.", RecordType::DS),
+            Some(ResponseCode::NoError)
+        );
+        // not covered by any cached NSEC
+        assert_eq!(get("h.example.com.", RecordType::A), None);
+        assert_eq!(get("example.org.", RecordType::A), None);
+
+        // negative answers have the SOA and the proof
+        let err = cache
+            .get(&query("a.example.com.", RecordType::A), now)
+            .unwrap()
+            .unwrap_err();
+        let ProtoErrorKind::NoRecordsFound {
+            soa,
+            negative_ttl,
+            authorities,
+            ..
+        } = err.kind()
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(soa.as_ref().unwrap().name(), &name("example.com."));
+        assert_eq!(*negative_ttl, Some(120));
+        assert_eq!(authorities.as_ref().unwrap().len(), 4);
+
+        // expired records are not used
+        let later = now + Duration::from_secs(121);
+        assert!(cache
+            .get(&query("a.example.com.", RecordType::A), later)
+            .is_none());
+    }
+
+    #[test]
+    fn test_nsec_wildcard() {
+        let cache = NsecCache::new(32);
+        let now = Instant::now();
+
+        // *.example.com. has an A record
+        let mut name_servers = soa().to_vec();
+        name_servers.extend(nsec(
+            "example.com.",
+            "*.example.com.",
+            &[RecordType::SOA, RecordType::NS, RecordType::NSEC],
+        ));
+        name_servers.extend(nsec(
+            "*.example.com.",
+            "example.com.",
+            &[RecordType::A, RecordType::TXT, RecordType::NSEC],
+        ));
+        let answers = vec![
+            secure(Record::from_rdata(
+                name("a.example.com."),
+                60,
+                RData::A(A::new(192, 0, 2, 1)),
+            )),
+            rrsig("a.example.com.", RecordType::A, 2),
+        ];
+        cache.insert(&response(answers, name_servers), now);
+
+        let lookup = cache
+            .get(&query("b.example.com.", RecordType::A), now)
+            .unwrap()
+            .unwrap();
+        let records = lookup.records();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|r| r.name() == &name("b.example.com.")));
+        assert_eq!(records[0].data(), &RData::A(A::new(192, 0, 2, 1)));
+        assert_eq!(records[0].ttl(), 60);
+
+        // the wildcard has no AAAA records
+        assert_eq!(
+            response_code(cache.get(&query("b.example.com.", RecordType::AAAA), now)),
+            Some(ResponseCode::NoError)
+        );
+        // the TXT records of the wildcard were never cached
+        assert!(cache
+            .get(&query("b.example.com.", RecordType::TXT), now)
+            .is_none());
+    }
+
+    #[test]
+    fn test_nsec3() {
+        let cache = NsecCache::new(32);
+        let now = Instant::now();
+
+        let hash = |name_: &str| {
+            let digest = Nsec3HashAlgorithm::SHA1.hash(&[], &name(name_), 0).unwrap();
+            digest.as_ref().to_vec()
+        };
+
+        // a chain of two NSEC3 records for example.com. and b.example.com. covers all other hashes
+        let mut hashes = [
+            (hash("example.com."), vec![RecordType::SOA, RecordType::NS]),
+            (hash("b.example.com."), vec![RecordType::A]),
+        ];
+        hashes.sort();
+
+        let mut name_servers = soa().to_vec();
+        for (index, (hashed, types)) in hashes.iter().enumerate() {
+            let next = hashes[(index + 1) % hashes.len()].0.clone();
+            let nsec3 = NSEC3::new(
+                Nsec3HashAlgorithm::SHA1,
+                false,
+                0,
+                vec![],
+                next,
+                types.clone(),
+            );
+            let owner = format!(
+                "{}.example.com.",
+                data_encoding::BASE32_DNSSEC.encode(hashed)
+            );
+            name_servers.push(secure(Record::from_rdata(
+                name(&owner),
+                300,
+                RData::DNSSEC(DNSSECRData::NSEC3(nsec3)),
+            )));
+            name_servers.push(rrsig(&owner, RecordType::NSEC3, 3));
+        }
+        cache.insert(&response(vec![], name_servers), now);
+
+        let get = |name: &str, query_type| response_code(cache.get(&query(name, query_type), now));
+        assert_eq!(
+            get("a.example.com.", RecordType::A),
+            Some(ResponseCode::NXDomain)
+        );
+        assert_eq!(
+            get("x.y.example.com.", RecordType::A),
+            Some(ResponseCode::NXDomain)
+        );
+        assert_eq!(
+            get("b.example.com.", RecordType::AAAA),
+            Some(ResponseCode::NoError)
+        );
+        assert_eq!(get("b.example.com.", RecordType::A), None);
+        // b.example.com. is the closest encloser of the names below it
+        assert_eq!(
+            get("x.b.example.com.", RecordType::A),
+            Some(ResponseCode::NXDomain)
+        );
+    }
+
+    #[test]
+    fn test_insecure_not_cached() {
+        let cache = NsecCache::new(32);
+        let now = Instant::now();
+
+        let mut name_servers = soa().to_vec();
+        let [mut record, rrsig] = nsec(
+            "example.com.",
+            "b.example.com.",
+            &[RecordType::SOA, RecordType::NSEC],
+        );
+        record.set_proof(Proof::Insecure);
+        name_servers.extend([record, rrsig]);
+        cache.insert(&response(vec![], name_servers), now);
+
+        assert!(cache
+            .get(&query("a.example.com.", RecordType::A), now)
+            .is_none());
+    }
+
+    #[test]
+    fn test_max_size() {
+        let cache = NsecCache::new(1);
+        let now = Instant::now();
+
+        let mut name_servers = soa().to_vec();
+        name_servers.extend(nsec(
+            "example.com.",
+            "b.example.com.",
+            &[RecordType::SOA, RecordType::NSEC],
+        ));
+        name_servers.extend(nsec(
+            "b.example.com.",
+            "d.example.com.",
+            &[RecordType::A, RecordType::NSEC],
+        ));
+        cache.insert(&response(vec![], name_servers), now);
+
+        assert_eq!(cache.inner.lock().len, 1);
+    }
+}
@@ -7,25 +7,33 @@
 
 //! Structs for creating and using a AsyncResolver
 use std::fmt;
-use std::net::IpAddr;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
+use futures_util::stream::{self, Stream, StreamExt, TryStreamExt};
 use tracing::{debug, trace};
 
-use crate::caching_client::CachingClient;
-use crate::config::{ResolveHosts, ResolverConfig, ResolverOpts};
+use crate::caching_client::{CachingClient, StaleRuntime};
+use crate::config::{LookupIpStrategy, ResolveHosts, ResolverConfig, ResolverOpts};
 use crate::dns_lru::{self, DnsLru};
 use crate::error::ResolveError;
+use crate::happy_eyeballs::{HappyEyeballs, IpsFuture};
 use crate::hosts::Hosts;
 use crate::lookup::{self, Lookup, LookupEither, LookupFuture};
 use crate::lookup_ip::{LookupIp, LookupIpFuture};
+use crate::lookup_service::{self, ServiceEndpoint};
 #[cfg(feature = "tokio-runtime")]
 use crate::name_server::TokioConnectionProvider;
 use crate::name_server::{ConnectionProvider, NameServerPool};
-use crate::proto::op::Query;
+use crate::proto::op::{Query, ResponseCode};
 use crate::proto::rr::domain::usage::ONION;
+use crate::proto::rr::rdata::opt::ClientSubnet;
+use crate::proto::rr::rdata::SRV;
 use crate::proto::rr::{IntoName, Name, RData, Record, RecordType};
+use crate::proto::runtime::RuntimeProvider;
 use crate::proto::xfer::{DnsRequestOptions, RetryDnsHandle};
+use crate::proto::ProtoError;
 
 /// An asynchronous resolver for DNS generic over async Runtimes.
 ///
@@ -137,6 +145,16 @@ impl<R: ConnectionProvider> Resolver<R> {
         self.client_cache.clear_cache();
     }
 
+    /// Writes a snapshot of the cache, see [`DnsLru::save`]
+    pub fn save_cache<W: io::Write>(&self, writer: W) -> io::Result<usize> {
+        self.client_cache.lru().save(writer)
+    }
+
+    /// Restores a snapshot of the cache, see [`DnsLru::load`]
+    pub fn load_cache<Rd: io::Read>(&self, reader: Rd) -> io::Result<usize> {
+        self.client_cache.lru().load(reader)
+    }
+
     /// Read the config for this resolver.
     pub fn config(&self) -> &ResolverConfig {
         &self.config
@@ -157,8 +175,33 @@ impl<P: ConnectionProvider> Resolver<P> {
     /// * `options` - basic lookup options for the resolver
     /// * `conn_provider` - connection provider, for DNS connections, I/O, and timers
     pub fn new_with_conn(config: ResolverConfig, options: ResolverOpts, conn_provider: P) -> Self {
+        let stale_runtime = options
+            .serve_stale
+            .and(conn_provider.runtime_provider())
+            .map(|runtime_provider| StaleRuntime::new(runtime_provider.clone()));
+        if options.serve_stale.is_some() && stale_runtime.is_none() {
+            tracing::warn!(
+                "serve_stale requires a connection provider with a runtime for the client response timeout and the refresh of stale records"
+            );
+        }
+        #[cfg(not(feature = "dns-over-rustls"))]
+        if options.discover_designated_resolvers {
+            tracing::warn!(
+                "discover_designated_resolvers option is only available with 'dns-over-rustls' feature"
+            );
+        }
+
+        #[cfg(feature = "dns-over-rustls")]
+        let designated_conn_provider = options
+            .discover_designated_resolvers
+            .then(|| conn_provider.clone());
         let pool =
             NameServerPool::from_config_with_provider(&config, options.clone(), conn_provider);
+        #[cfg(feature = "dns-over-rustls")]
+        let pool = match designated_conn_provider {
+            Some(conn_provider) => pool.with_designated_resolvers(&config, conn_provider),
+            None => pool,
+        };
         let either;
         let client = RetryDnsHandle::new(pool, options.attempts);
         if options.validate {
@@ -185,9 +228,22 @@ impl<P: ConnectionProvider> Resolver<P> {
 
         trace!("handle passed back");
         let lru = DnsLru::new(options.cache_size, dns_lru::TtlConfig::from_opts(&options));
+        let client_cache = CachingClient::with_cache(lru, either, options.preserve_intermediates)
+            .with_stale_runtime(stale_runtime);
+
+        #[cfg(feature = "dnssec")]
+        let client_cache = client_cache.with_nsec_cache(
+            (options.validate && options.aggressive_nsec)
+                .then(|| crate::nsec_cache::NsecCache::new(options.cache_size)),
+        );
+        #[cfg(not(feature = "dnssec"))]
+        if options.aggressive_nsec {
+            tracing::warn!("aggressive_nsec option is only available with 'dnssec' feature");
+        }
+
         Self {
             config,
-            client_cache: CachingClient::with_cache(lru, either, options.preserve_intermediates),
+            client_cache,
             options,
             hosts,
         }
@@ -203,11 +259,49 @@ impl<P: ConnectionProvider> Resolver<P> {
         Ok(Self::new_with_conn(config, options, conn_provider))
     }
 
+    /// Returns a resolver for a changed configuration, e.g. after `/etc/resolv.conf` was rewritten
+    ///
+    /// If neither the name servers, including those of domains, nor the options changed, the new
+    ///  resolver shares the connections and the cache of this one. If only options which do not
+    ///  change the answers or the cache changed, e.g. `ndots`, `timeout` or `rotate`, it shares
+    ///  the cache but makes new connections. Otherwise it starts with a new cache: the cached
+    ///  records were answered by the previous name servers, or for other queries. Either way,
+    ///  lookups already started by this resolver finish with its configuration.
+    #[cfg(unix)]
+    #[cfg(feature = "system-config")]
+    pub(crate) fn reconfigure(
+        &self,
+        config: ResolverConfig,
+        options: ResolverOpts,
+        conn_provider: P,
+    ) -> Self {
+        if config.name_servers() != self.config.name_servers()
+            || config.domain_name_servers() != self.config.domain_name_servers()
+            || !same_answers(&options, &self.options)
+        {
+            return Self::new_with_conn(config, options, conn_provider);
+        }
+
+        if options == self.options {
+            return Self {
+                config,
+                options,
+                client_cache: self.client_cache.clone(),
+                hosts: self.hosts.clone(),
+            };
+        }
+
+        let mut resolver = Self::new_with_conn(config, options, conn_provider);
+        resolver.client_cache = resolver.client_cache.with_caches_of(&self.client_cache);
+        resolver
+    }
+
     /// Per request options based on the ResolverOpts
     pub(crate) fn request_options(&self) -> DnsRequestOptions {
         let mut request_opts = DnsRequestOptions::default();
         request_opts.recursion_desired = self.options.recursion_desired;
         request_opts.use_edns = self.options.edns0;
+        request_opts.authentic_data = self.options.authentic_data;
 
         request_opts
     }
@@ -238,6 +332,45 @@ impl<P: ConnectionProvider> Resolver<P> {
             .await
     }
 
+    /// Generic lookup for any RecordType, made on behalf of a client
+    ///
+    /// If [`ResolverOpts::edns_client_subnet`] is enabled, a prefix of the client subnet is sent
+    /// to the name servers, and the records are cached for the scope they answer with, see
+    /// [`Lookup::client_subnet_scope`]. Otherwise, or if the client subnet may not be sent, this is
+    /// the same as [`Self::lookup`].
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name of the record to lookup, if name is not a valid domain name, an error will be returned
+    /// * `record_type` - type of record to lookup, all RecordData responses will be filtered to this type
+    /// * `client_subnet` - the subnet of the client, e.g. from its request or its address
+    pub async fn lookup_with_client_subnet<N: IntoName>(
+        &self,
+        name: N,
+        record_type: RecordType,
+        client_subnet: ClientSubnet,
+    ) -> Result<Lookup, ResolveError> {
+        let name = match name.into_name() {
+            Ok(name) => name,
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut options = self.request_options();
+        options.client_subnet = self
+            .options
+            .edns_client_subnet
+            .and_then(|config| config.client_subnet(client_subnet));
+
+        let lookup = self.inner_lookup(name, record_type, options).await?;
+        if options.client_subnet.is_none() {
+            return Ok(lookup);
+        }
+
+        // records cached without a scope are valid for all clients
+        let scope = lookup.client_subnet_scope().unwrap_or(0);
+        Ok(lookup.with_client_subnet_scope(Some(scope)))
+    }
+
     fn push_name(name: Name, names: &mut Vec<Name>) {
         if !names.contains(&name) {
             names.push(name);
@@ -316,6 +449,20 @@ impl<P: ConnectionProvider> Resolver<P> {
     where
         L: From<Lookup> + Send + Sync + 'static,
     {
+        // as glibc does, answer AAAA lookups with no records rather than sending them
+        if record_type == RecordType::AAAA && self.options.no_aaaa {
+            return Err(ProtoError::nx_error(
+                Box::new(Query::query(name, record_type)),
+                None,
+                None,
+                None,
+                ResponseCode::NoError,
+                false,
+                None,
+            )
+            .into());
+        }
+
         let names = self.build_names(name);
         LookupFuture::lookup_with_hosts(
             names,
@@ -370,18 +517,172 @@ impl<P: ConnectionProvider> Resolver<P> {
             }
         };
 
+        self.lookup_ip_with_strategy(
+            name,
+            self.options.ip_strategy,
+            finally_ip_addr.map(Record::into_data),
+        )
+        .await
+    }
+
+    /// Looks up the addresses of `name` with the hosts and search names of [`Self::lookup_ip`], and
+    ///  orders them by the sortlist
+    ///
+    /// Only IPv4 addresses are looked up with [`ResolverOpts::no_aaaa`], whatever the strategy.
+    async fn lookup_ip_with_strategy(
+        &self,
+        name: Name,
+        ip_strategy: LookupIpStrategy,
+        finally_ip_addr: Option<RData>,
+    ) -> Result<LookupIp, ResolveError> {
+        let ip_strategy = if self.options.no_aaaa {
+            LookupIpStrategy::Ipv4Only
+        } else {
+            ip_strategy
+        };
         let names = self.build_names(name);
         let hosts = self.hosts.as_ref().cloned();
 
         LookupIpFuture::lookup(
             names,
-            self.options.ip_strategy,
+            ip_strategy,
             self.client_cache.clone(),
             self.request_options(),
             hosts,
-            finally_ip_addr.map(Record::into_data),
+            finally_ip_addr,
         )
         .await
+        .map(|lookup| lookup.sort_by_networks(&self.options.sortlist))
+    }
+
+    /// Looks up the SRV records of a service, returning the addresses to try to connect to it, in order
+    ///
+    /// The targets of the SRV records are ordered as described in RFC 2782, see
+    ///  [`SrvLookup::ordered`](lookup::SrvLookup::ordered). The addresses of each target are those
+    ///  returned along with the SRV records, or otherwise looked up with [`Self::lookup_ip`], both
+    ///  according to the configured `ip_strategy`. Targets are only looked up once the addresses of
+    ///  the previous ones have been consumed.
+    ///
+    /// A failure to look up the SRV records ends the stream with that error. A failure to look up
+    ///  the addresses of a target is yielded in place of its addresses, and the stream continues
+    ///  with the next target.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - a string which parses to a domain name, e.g. `_sip._udp.example.com.`
+    pub fn srv_lookup_addrs<N: IntoName>(
+        &self,
+        query: N,
+    ) -> impl Stream<Item = Result<(SocketAddr, SRV), ResolveError>> + '_ {
+        let name = query.into_name();
+        stream::once(async move { self.srv_lookup(name?).await })
+            .map_ok(move |lookup| {
+                let strategy = self.options.ip_strategy;
+                stream::iter(lookup.ordered())
+                    .then(move |srv| {
+                        let ips = lookup.target_ips(srv.target(), strategy);
+                        async move {
+                            if !ips.is_empty() {
+                                return Ok((srv, ips));
+                            }
+
+                            debug!("looking up addresses of SRV target: {}", srv.target());
+                            let lookup = self.lookup_ip(srv.target().clone()).await?;
+                            Ok::<_, ResolveError>((srv, lookup.iter().collect()))
+                        }
+                    })
+                    .map_ok(|(srv, ips)| {
+                        stream::iter(ips.into_iter().map(move |ip| {
+                            Ok::<_, ResolveError>((SocketAddr::new(ip, srv.port()), srv.clone()))
+                        }))
+                    })
+                    .try_flatten()
+            })
+            .try_flatten()
+    }
+
+    /// Looks up the endpoints of the HTTPS service at `host` and `port`, as described in RFC 9460
+    ///
+    /// AliasMode HTTPS records are followed, and the endpoints of the ServiceMode records are
+    ///  returned in order of priority, with their protocols, port and ECH configuration. The
+    ///  addresses of each endpoint are looked up with [`Self::lookup_ip`], and the `ipv4hint` and
+    ///  `ipv6hint` of the record are used for the address families for which none are found.
+    ///
+    /// If the service has no usable HTTPS records, the endpoint of the origin itself is returned,
+    ///  with the addresses of `host`. No endpoints are returned if the service states that it is
+    ///  not available, or if every record offers ECH but none could be resolved, as clients must
+    ///  not fall back to connecting without ECH then.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - the host name of the service, or an IP address which is returned as is
+    /// * `port` - the port of the service, for ports other than 443 the records are looked up at
+    ///   `_<port>._https.<host>`
+    pub async fn lookup_service_endpoints<N: IntoName>(
+        &self,
+        host: N,
+        port: u16,
+    ) -> Result<Vec<ServiceEndpoint>, ResolveError> {
+        if let Some(ip) = host.to_ip() {
+            let name = host.into_name().unwrap_or_default();
+            return Ok(vec![ServiceEndpoint::origin(name, port, vec![ip])]);
+        }
+
+        lookup_service::lookup_service_endpoints(self, host.into_name()?, port).await
+    }
+
+    /// Connects over TCP to `host` and `port`, racing its IPv6 and IPv4 addresses as described in
+    /// RFC 8305, Happy Eyeballs version 2
+    ///
+    /// The AAAA and A records are looked up in parallel, and connecting starts as soon as the
+    ///  IPv6 addresses are known, or shortly after the IPv4 ones. The addresses are tried
+    ///  alternating between the families, starting the next attempt alongside the previous one if
+    ///  it takes longer than 250ms, or right away if it fails. The first connection established is
+    ///  returned with its address, and the other attempts are dropped. `Ipv4Only` and `Ipv6Only`
+    ///  of the configured `ip_strategy` restrict the addresses to their family, and each attempt
+    ///  times out after the configured `timeout`. The addresses of each family are those of
+    ///  [`Self::lookup_ip`], including the hosts, ordered by the configured `sortlist`.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - the runtime with which to connect
+    /// * `host` - the name of the host, or an IP address to connect to directly
+    /// * `port` - the port to connect to
+    pub async fn connect_tcp<R: RuntimeProvider>(
+        &self,
+        provider: &R,
+        host: impl IntoName,
+        port: u16,
+    ) -> Result<(R::Tcp, SocketAddr), ResolveError> {
+        let timeout = self.options.timeout;
+        let connect = |addr| provider.connect_tcp(addr, None, Some(timeout));
+
+        if let Some(ip) = host.to_ip() {
+            let addr = SocketAddr::new(ip, port);
+            return Ok((connect(addr).await?, addr));
+        }
+
+        // each family is looked up as by `lookup_ip`, so the hosts and the sortlist apply
+        let name = host.into_name()?;
+        let lookup = |ip_strategy| -> IpsFuture<'_> {
+            let name = name.clone();
+            Box::pin(async move {
+                let lookup = self
+                    .lookup_ip_with_strategy(name, ip_strategy, None)
+                    .await?;
+                Ok(lookup.iter().collect())
+            })
+        };
+        let ipv6 = || lookup(LookupIpStrategy::Ipv6Only);
+        let ipv4 = || lookup(LookupIpStrategy::Ipv4Only);
+
+        let (ipv6, ipv4) = match self.options.ip_strategy {
+            LookupIpStrategy::Ipv4Only => (None, Some(ipv4())),
+            LookupIpStrategy::Ipv6Only => (Some(ipv6()), None),
+            _ => (Some(ipv6()), Some(ipv4())),
+        };
+
+        HappyEyeballs::<_, _, R::Timer>::new(port, connect, ipv6, ipv4).await
     }
 
     /// Customizes the static hosts used in this resolver.
@@ -412,6 +713,29 @@ impl<P: ConnectionProvider> fmt::Debug for Resolver<P> {
     }
 }
 
+/// Returns whether the records cached with the options `a` are the answers to the queries made
+///  with the options `b`, in a cache configured the same way
+#[cfg(unix)]
+#[cfg(feature = "system-config")]
+fn same_answers(a: &ResolverOpts, b: &ResolverOpts) -> bool {
+    // the options of the queries, and of the servers they are sent to
+    a.edns0 == b.edns0
+        && a.validate == b.validate
+        && a.aggressive_nsec == b.aggressive_nsec
+        && a.recursion_desired == b.recursion_desired
+        && a.authentic_data == b.authentic_data
+        && a.edns_client_subnet == b.edns_client_subnet
+        && a.discover_designated_resolvers == b.discover_designated_resolvers
+        // the options of the cache
+        && a.cache_size == b.cache_size
+        && a.positive_min_ttl == b.positive_min_ttl
+        && a.negative_min_ttl == b.negative_min_ttl
+        && a.positive_max_ttl == b.positive_max_ttl
+        && a.negative_max_ttl == b.negative_max_ttl
+        && a.serve_stale == b.serve_stale
+        && a.preserve_intermediates == b.preserve_intermediates
+}
+
 /// Unit tests compatible with different runtime.
 #[cfg(any(test, feature = "testing"))]
 #[allow(dead_code, unreachable_pub)]
@@ -1210,4 +1534,176 @@ mod tests {
             assert_eq!(resolver.build_names(name.clone()).len(), 2);
         }
     }
+
+    #[test]
+    #[cfg(unix)]
+    #[cfg(feature = "system-config")]
+    fn test_reconfigure() {
+        use std::time::{Duration, Instant};
+
+        use crate::config::NameServerConfigGroup;
+        use crate::proto::rr::rdata::A;
+
+        let config = ResolverConfig::default();
+        let resolver = Resolver::<TokioConnectionProvider>::new(
+            config.clone(),
+            ResolverOpts::default(),
+            TokioConnectionProvider::default(),
+        );
+
+        let query = Query::query(Name::from_ascii("www.example.com.").unwrap(), RecordType::A);
+        let record =
+            Record::from_rdata(query.name().clone(), 86400, RData::A(A::new(127, 0, 0, 1)));
+        resolver.client_cache.lru().insert_records(
+            query.clone(),
+            [record].into_iter(),
+            Instant::now(),
+        );
+        let cached = |resolver: &Resolver<TokioConnectionProvider>| {
+            resolver
+                .client_cache
+                .lru()
+                .get(&query, Instant::now())
+                .is_some()
+        };
+
+        // the same name servers keep the cache
+        let mut search_config = config.clone();
+        search_config.add_search(Name::from_ascii("example.com.").unwrap());
+        let reconfigured = resolver.reconfigure(
+            search_config.clone(),
+            ResolverOpts::default(),
+            TokioConnectionProvider::default(),
+        );
+        assert_eq!(reconfigured.config(), &search_config);
+        assert!(cached(&reconfigured));
+
+        // options which do not change the answers keep the cache
+        let options = ResolverOpts {
+            ndots: 2,
+            timeout: Duration::from_secs(1),
+            attempts: 1,
+            rotate: true,
+            ..ResolverOpts::default()
+        };
+        let reconfigured = resolver.reconfigure(
+            config.clone(),
+            options.clone(),
+            TokioConnectionProvider::default(),
+        );
+        assert_eq!(reconfigured.options(), &options);
+        assert!(cached(&reconfigured));
+
+        // other name servers or options of the queries start with an empty cache
+        let reconfigured = resolver.reconfigure(
+            ResolverConfig::cloudflare(),
+            ResolverOpts::default(),
+            TokioConnectionProvider::default(),
+        );
+        assert!(!cached(&reconfigured));
+        let mut domain_config = config.clone();
+        domain_config.add_domain_name_servers(
+            Name::from_ascii("example.com.").unwrap(),
+            NameServerConfigGroup::cloudflare(),
+            None,
+        );
+        let reconfigured = resolver.reconfigure(
+            domain_config.clone(),
+            ResolverOpts::default(),
+            TokioConnectionProvider::default(),
+        );
+        assert_eq!(reconfigured.config(), &domain_config);
+        assert!(!cached(&reconfigured));
+        let reconfigured = resolver.reconfigure(
+            config,
+            ResolverOpts {
+                edns0: true,
+                ..ResolverOpts::default()
+            },
+            TokioConnectionProvider::default(),
+        );
+        assert!(!cached(&reconfigured));
+        assert!(cached(&resolver));
+    }
+
+    #[test]
+    fn test_no_aaaa() {
+        let io_loop = Runtime::new().unwrap();
+
+        let mut hosts = Hosts::new();
+        hosts
+            .read_hosts_conf("127.0.0.1 host.example.\n::1 host.example.\n".as_bytes())
+            .unwrap();
+
+        let mut resolver = Resolver::new(
+            ResolverConfig::new(),
+            ResolverOpts {
+                ip_strategy: LookupIpStrategy::Ipv6thenIpv4,
+                no_aaaa: true,
+                ..ResolverOpts::default()
+            },
+            TokioConnectionProvider::default(),
+        );
+        resolver.set_hosts(Some(hosts));
+
+        // only the IPv4 address is looked up, whatever the strategy
+        let lookup = io_loop
+            .block_on(resolver.lookup_ip("host.example."))
+            .unwrap();
+        assert_eq!(
+            lookup.iter().collect::<Vec<_>>(),
+            [IpAddr::from([127, 0, 0, 1])]
+        );
+
+        // AAAA lookups return no records without querying, there is no name server to query
+        let error = io_loop
+            .block_on(resolver.ipv6_lookup("www.example.com."))
+            .unwrap_err();
+        assert!(error.is_no_records_found());
+        let error = io_loop
+            .block_on(resolver.lookup("www.example.com.", RecordType::AAAA))
+            .unwrap_err();
+        assert!(error.is_no_records_found());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_connect_tcp_sortlist() {
+        use std::net::TcpListener;
+
+        use crate::config::SortlistNetwork;
+        use crate::proto::runtime::TokioRuntimeProvider;
+
+        let io_loop = Runtime::new().unwrap();
+
+        // the same port on two loopback addresses, listed in the hosts against the sortlist order
+        let first = TcpListener::bind("127.0.0.2:0").unwrap();
+        let port = first.local_addr().unwrap().port();
+        let _second = TcpListener::bind(("127.0.0.1", port)).unwrap();
+
+        let mut hosts = Hosts::new();
+        hosts
+            .read_hosts_conf("127.0.0.2 host.example.\n127.0.0.1 host.example.\n".as_bytes())
+            .unwrap();
+
+        let mut resolver = Resolver::new(
+            ResolverConfig::new(),
+            ResolverOpts {
+                ip_strategy: LookupIpStrategy::Ipv4Only,
+                sortlist: vec![SortlistNetwork {
+                    addr: IpAddr::from([127, 0, 0, 1]),
+                    mask: IpAddr::from([255, 255, 255, 255]),
+                }],
+                ..ResolverOpts::default()
+            },
+            TokioConnectionProvider::default(),
+        );
+        resolver.set_hosts(Some(hosts));
+
+        // the first address of the sortlist is tried first
+        let (_, addr) = io_loop
+            .block_on(resolver.connect_tcp(&TokioRuntimeProvider::new(), "host.example.", port))
+            .unwrap();
+        assert_eq!(addr, SocketAddr::from(([127, 0, 0, 1], port)));
+    }
 }
@@ -18,7 +18,10 @@ mod unix;
 
 #[cfg(unix)]
 #[cfg(feature = "system-config")]
-pub use self::unix::{parse_resolv_conf, read_system_conf};
+pub(crate) use self::unix::{into_resolver_config, parse};
+#[cfg(unix)]
+#[cfg(feature = "system-config")]
+pub use self::unix::{parse_link_conf, parse_resolv_conf, read_link_conf, read_system_conf};
 
 #[cfg(windows)]
 #[cfg(feature = "system-config")]
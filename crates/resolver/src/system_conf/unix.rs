@@ -14,14 +14,17 @@
 use std::fs::File;
 use std::io;
 use std::io::Read;
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
 use std::path::Path;
 use std::str::FromStr;
 use std::time::Duration;
 
-use resolv_conf;
+use resolv_conf::{self, Network};
 
-use crate::config::{NameServerConfig, ResolverConfig, ResolverOpts};
+use crate::config::{
+    LookupIpStrategy, NameServerConfig, NameServerConfigGroup, ResolverConfig, ResolverOpts,
+    ServerOrderingStrategy, SortlistNetwork,
+};
 use crate::proto::rr::Name;
 use crate::proto::xfer::Protocol;
 use crate::ResolveError;
@@ -32,7 +35,7 @@ pub fn read_system_conf() -> Result<(ResolverConfig, ResolverOpts), ResolveError
     read_resolv_conf("/etc/resolv.conf")
 }
 
-fn read_resolv_conf<P: AsRef<Path>>(
+pub(crate) fn read_resolv_conf<P: AsRef<Path>>(
     path: P,
 ) -> Result<(ResolverConfig, ResolverOpts), ResolveError> {
     let mut data = String::new();
@@ -44,17 +47,30 @@ fn read_resolv_conf<P: AsRef<Path>>(
 pub fn parse_resolv_conf<T: AsRef<[u8]>>(
     data: T,
 ) -> Result<(ResolverConfig, ResolverOpts), ResolveError> {
-    let parsed_conf = resolv_conf::Config::parse(&data).map_err(|e| {
+    into_resolver_config(parse(data)?)
+}
+
+/// Parses a `resolv.conf`, without converting it to the configuration of the resolver
+pub(crate) fn parse<T: AsRef<[u8]>>(data: T) -> Result<resolv_conf::Config, ResolveError> {
+    Ok(resolv_conf::Config::parse(&data).map_err(|e| {
         io::Error::new(
             io::ErrorKind::Other,
             format!("Error parsing resolv.conf: {e}"),
         )
-    })?;
-    into_resolver_config(parsed_conf)
+    })?)
 }
 
+/// Converts a parsed `resolv.conf` to the configuration of the resolver
+///
+/// Besides the name servers and search domains, the options are mapped to their glibc behaviour:
+///  `use-vc` only uses TCP, `rotate` orders the name servers round-robin, `no-aaaa` suppresses all
+///  AAAA queries, see [`ResolverOpts::no_aaaa`], and `inet6` looks up IPv6 addresses first. `single-request` and
+///  `single-request-reopen` need nothing, as IPv4 and IPv6 addresses are looked up one after
+///  another with these strategies. `trust-ad` only sets the AD bit in queries, see
+///  [`ResolverOpts::authentic_data`]. `no-reload` is honoured by
+///  [`SystemConfWatcher`](crate::watcher::SystemConfWatcher).
 // TODO: use a custom parsing error type maybe?
-fn into_resolver_config(
+pub(crate) fn into_resolver_config(
     parsed_config: resolv_conf::Config,
 ) -> Result<(ResolverConfig, ResolverOpts), ResolveError> {
     let domain = if let Some(domain) = parsed_config.get_system_domain() {
@@ -70,16 +86,20 @@ fn into_resolver_config(
     // nameservers
     let mut nameservers = Vec::<NameServerConfig>::with_capacity(parsed_config.nameservers.len());
     for ip in &parsed_config.nameservers {
-        nameservers.push(NameServerConfig {
-            socket_addr: SocketAddr::new(ip.into(), DEFAULT_PORT),
-            protocol: Protocol::Udp,
-            tls_dns_name: None,
-            http_endpoint: None,
-            trust_negative_responses: false,
-            #[cfg(feature = "dns-over-rustls")]
-            tls_config: None,
-            bind_addr: None,
-        });
+        if !parsed_config.use_vc {
+            nameservers.push(NameServerConfig {
+                socket_addr: SocketAddr::new(ip.into(), DEFAULT_PORT),
+                protocol: Protocol::Udp,
+                tls_dns_name: None,
+                http_endpoint: None,
+                trust_negative_responses: false,
+                #[cfg(feature = "dns-over-rustls")]
+                tls_config: None,
+                #[cfg(feature = "dns-over-odoh")]
+                odoh_config: None,
+                bind_addr: None,
+            });
+        }
         nameservers.push(NameServerConfig {
             socket_addr: SocketAddr::new(ip.into(), DEFAULT_PORT),
             protocol: Protocol::Tcp,
@@ -88,6 +108,8 @@ fn into_resolver_config(
             trust_negative_responses: false,
             #[cfg(feature = "dns-over-rustls")]
             tls_config: None,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh_config: None,
             bind_addr: None,
         });
     }
@@ -116,22 +138,214 @@ fn into_resolver_config(
 
     let config = ResolverConfig::from_parts(domain, search, nameservers);
 
+    let ip_strategy = if parsed_config.inet6 {
+        LookupIpStrategy::Ipv6thenIpv4
+    } else {
+        LookupIpStrategy::default()
+    };
+
+    let server_ordering_strategy = if parsed_config.rotate {
+        ServerOrderingStrategy::RoundRobin
+    } else {
+        ServerOrderingStrategy::default()
+    };
+
+    let sortlist = parsed_config
+        .sortlist
+        .iter()
+        .map(|network| match *network {
+            Network::V4(addr, mask) => SortlistNetwork {
+                addr: addr.into(),
+                mask: mask.into(),
+            },
+            Network::V6(addr, mask) => SortlistNetwork {
+                addr: addr.into(),
+                mask: mask.into(),
+            },
+        })
+        .collect();
+
     let options = ResolverOpts {
         ndots: parsed_config.ndots as usize,
         timeout: Duration::from_secs(u64::from(parsed_config.timeout)),
         attempts: parsed_config.attempts as usize,
+        edns0: parsed_config.edns0,
+        authentic_data: parsed_config.trust_ad,
+        ip_strategy,
+        server_ordering_strategy,
+        sortlist,
+        no_aaaa: parsed_config.no_aaaa,
         ..ResolverOpts::default()
     };
 
     Ok((config, options))
 }
 
+/// Reads the per-link DNS settings of systemd-resolved or systemd-networkd from `path`, e.g.
+/// `/run/systemd/resolve/netif/<ifindex>`, see [`parse_link_conf`]
+///
+/// The interface index of the link is taken from the file name, if it is a number.
+pub fn read_link_conf<P: AsRef<Path>>(
+    path: P,
+    config: &mut ResolverConfig,
+) -> Result<(), ResolveError> {
+    let path = path.as_ref();
+    let ifindex = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| u32::from_str(name).ok());
+
+    let mut data = String::new();
+    let mut file = File::open(path)?;
+    file.read_to_string(&mut data)?;
+    parse_link_conf(&data, ifindex, config)
+}
+
+/// Adds the per-link DNS settings of systemd-resolved or systemd-networkd to `config`
+///
+/// The settings are `KEY=value` lines. The name servers of the link, listed in `SERVERS=` or
+/// `DNS=`, are used for the names within each of its domains. Domains in `DOMAINS=` prefixed with
+/// `~`, and those in `ROUTE_DOMAINS=`, are only used for routing, the others are added to the
+/// search domains as well. The `~.` domain, which makes the link a default route, is ignored.
+///
+/// Link-local IPv6 name servers are only reachable through the interface of the link: `ifindex`,
+/// the index of that interface, is their scope unless they have a numeric `%scope` of their own.
+pub fn parse_link_conf<T: AsRef<str>>(
+    data: T,
+    ifindex: Option<u32>,
+    config: &mut ResolverConfig,
+) -> Result<(), ResolveError> {
+    let mut servers = Vec::new();
+    let mut domains = Vec::new();
+    let mut search = Vec::new();
+
+    for line in data.as_ref().lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        match key {
+            "SERVERS" | "DNS" => {
+                for server in value.split_whitespace() {
+                    servers.push(parse_link_server(server, ifindex).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::Other,
+                            format!("Error parsing link name server: {server}"),
+                        )
+                    })?);
+                }
+            }
+            "DOMAINS" | "ROUTE_DOMAINS" => {
+                for domain in value.split_whitespace() {
+                    let (route_only, domain) = match domain.strip_prefix('~') {
+                        Some(domain) => (true, domain),
+                        None => (key == "ROUTE_DOMAINS", domain),
+                    };
+
+                    if domain == "." {
+                        continue;
+                    }
+
+                    let mut name = Name::from_str_relaxed(domain).map_err(|e| {
+                        io::Error::new(
+                            io::ErrorKind::Other,
+                            format!("Error parsing link domain: {e}"),
+                        )
+                    })?;
+                    name.set_fqdn(true);
+
+                    if !route_only {
+                        search.push(name.clone());
+                    }
+                    domains.push(name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if servers.is_empty() {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "no nameservers found in link config",
+        ))?;
+    }
+
+    let mut name_servers = NameServerConfigGroup::with_capacity(servers.len() * 2);
+    for socket_addr in servers {
+        for protocol in [Protocol::Udp, Protocol::Tcp] {
+            name_servers.push(NameServerConfig::new(socket_addr, protocol));
+        }
+    }
+
+    for domain in domains {
+        config.add_domain_name_servers(domain, name_servers.clone(), None);
+    }
+    for domain in search {
+        config.add_search(domain);
+    }
+
+    Ok(())
+}
+
+/// Parses a name server of a link, e.g. `10.0.0.1`, `10.0.0.1:5353`, `fe80::1%2` or
+/// `[fd00::1]:53#dns.example`
+fn parse_link_server(server: &str, ifindex: Option<u32>) -> Option<SocketAddr> {
+    // the server name is only used for DNS-over-TLS by systemd-resolved
+    let server = server.split('#').next()?;
+    let (addr, port) = match server.strip_prefix('[') {
+        Some(server) => match server.split_once(']')? {
+            (addr, "") => (addr, DEFAULT_PORT),
+            (addr, port) => (addr, u16::from_str(port.strip_prefix(':')?).ok()?),
+        },
+        None => match server.split_once(':') {
+            // a single colon separates the port of an IPv4 address
+            Some((addr, port)) if !port.contains(':') => (addr, u16::from_str(port).ok()?),
+            _ => (server, DEFAULT_PORT),
+        },
+    };
+
+    let (ip, scope) = match addr.split_once('%') {
+        Some((ip, scope)) => (ip, Some(scope)),
+        None => (addr, None),
+    };
+
+    match IpAddr::from_str(ip).ok()? {
+        IpAddr::V4(ip) if scope.is_none() => Some(SocketAddr::new(ip.into(), port)),
+        IpAddr::V4(_) => None,
+        IpAddr::V6(ip) => {
+            // interfaces given by name are the interface of the link
+            let scope_id = match scope {
+                Some(scope) => u32::from_str(scope).ok().or(ifindex),
+                None if is_link_local(&ip) => ifindex,
+                None => None,
+            };
+
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                ip,
+                port,
+                0,
+                scope_id.unwrap_or(0),
+            )))
+        }
+    }
+}
+
+/// Whether `ip` is a link-local unicast address, in `fe80::/10`
+fn is_link_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xffc0 == 0xfe80
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::proto::rr::Name;
     use std::env;
-    use std::net::*;
     use std::str::FromStr;
 
     fn empty_config(name_servers: Vec<NameServerConfig>) -> ResolverConfig {
@@ -149,6 +363,8 @@ mod tests {
                 trust_negative_responses: false,
                 #[cfg(feature = "dns-over-rustls")]
                 tls_config: None,
+                #[cfg(feature = "dns-over-odoh")]
+                odoh_config: None,
                 bind_addr: None,
             },
             NameServerConfig {
@@ -159,6 +375,8 @@ mod tests {
                 trust_negative_responses: false,
                 #[cfg(feature = "dns-over-rustls")]
                 tls_config: None,
+                #[cfg(feature = "dns-over-odoh")]
+                odoh_config: None,
                 bind_addr: None,
             },
         ]
@@ -225,6 +443,54 @@ mod tests {
         is_default_opts(parsed.1);
     }
 
+    #[test]
+    fn test_options() {
+        let (config, opts) = parse_resolv_conf(
+            "nameserver 127.0.0.1\n\
+             options edns0 trust-ad use-vc rotate inet6 single-request no-reload\n\
+             sortlist 130.155.160.0/255.255.240.0 130.155.0.0\n",
+        )
+        .expect("failed");
+
+        let protocols = config
+            .name_servers()
+            .iter()
+            .map(|ns| ns.protocol)
+            .collect::<Vec<_>>();
+        assert_eq!(protocols, [Protocol::Tcp]);
+        assert!(opts.edns0);
+        assert!(opts.authentic_data);
+        assert_eq!(
+            opts.server_ordering_strategy,
+            ServerOrderingStrategy::RoundRobin
+        );
+        assert_eq!(opts.ip_strategy, LookupIpStrategy::Ipv6thenIpv4);
+        assert_eq!(
+            opts.sortlist,
+            [
+                SortlistNetwork {
+                    addr: "130.155.160.0".parse().unwrap(),
+                    mask: "255.255.240.0".parse().unwrap(),
+                },
+                SortlistNetwork {
+                    addr: "130.155.0.0".parse().unwrap(),
+                    mask: "255.255.0.0".parse().unwrap(),
+                },
+            ]
+        );
+
+        let (_, opts) =
+            parse_resolv_conf("nameserver 127.0.0.1\noptions inet6 no-aaaa\n").expect("failed");
+        assert!(opts.no_aaaa);
+
+        let (_, opts) = parse_resolv_conf("nameserver 127.0.0.1\n").expect("failed");
+        assert!(!opts.edns0);
+        assert!(!opts.authentic_data);
+        assert_eq!(opts.ip_strategy, LookupIpStrategy::default());
+        assert!(opts.sortlist.is_empty());
+        assert!(!opts.no_aaaa);
+    }
+
     #[test]
     fn test_read_resolv_conf() {
         read_resolv_conf(format!("{}/resolv.conf-simple", tests_dir())).expect("simple failed");
@@ -232,6 +498,66 @@ mod tests {
         read_resolv_conf(format!("{}/resolv.conf-linux", tests_dir())).expect("linux failed");
     }
 
+    #[test]
+    fn test_parse_link_conf() {
+        let mut cfg = empty_config(nameserver_config("127.0.0.1").to_vec());
+        parse_link_conf(
+            "# This is private data. Do not parse.\n\
+             LLMNR=yes\n\
+             SERVERS=10.8.0.1 10.8.0.2:5353 fe80::1%2 fe80::2 [fe80::3%eth0]:5353 \
+                     [fd00::1]:53#dns.corp.example\n\
+             DOMAINS=~corp.example ~10.in-addr.arpa vpn.example ~.\n",
+            Some(3),
+            &mut cfg,
+        )
+        .expect("failed");
+
+        let domains = cfg
+            .domain_name_servers()
+            .iter()
+            .map(|domain| domain.domain.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            domains,
+            ["corp.example.", "10.in-addr.arpa.", "vpn.example."]
+        );
+        assert_eq!(cfg.search(), [Name::from_str("vpn.example.").unwrap()]);
+
+        let addrs = cfg.domain_name_servers()[0]
+            .name_servers
+            .iter()
+            .filter(|ns| ns.protocol == Protocol::Udp)
+            .map(|ns| ns.socket_addr.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            addrs,
+            [
+                "10.8.0.1:53",
+                "10.8.0.2:5353",
+                "[fe80::1%2]:53",
+                "[fe80::2%3]:53",
+                "[fe80::3%3]:5353",
+                "[fd00::1]:53"
+            ]
+        );
+
+        // the default name servers are unchanged
+        assert_eq!(cfg.name_servers(), nameserver_config("127.0.0.1"));
+    }
+
+    #[test]
+    fn test_parse_link_route_domains() {
+        let mut cfg = empty_config(vec![]);
+        parse_link_conf("DNS=10.8.0.1\nROUTE_DOMAINS=corp.example\n", None, &mut cfg)
+            .expect("failed");
+        assert_eq!(cfg.domain_name_servers().len(), 1);
+        assert!(cfg.search().is_empty());
+
+        assert!(parse_link_conf("DOMAINS=corp.example\n", None, &mut cfg).is_err());
+        assert!(parse_link_conf("DNS=not-an-ip\n", None, &mut cfg).is_err());
+        assert!(parse_link_conf("DNS=10.8.0.1%2\n", None, &mut cfg).is_err());
+    }
+
     /// Validate that all options set in `into_resolver_config()` are at default values
     fn is_default_opts(opts: ResolverOpts) {
         assert_eq!(opts.ndots, 1);
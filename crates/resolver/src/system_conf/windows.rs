@@ -37,6 +37,8 @@ fn get_name_servers() -> Result<Vec<NameServerConfig>, ResolveError> {
             trust_negative_responses: false,
             #[cfg(feature = "dns-over-rustls")]
             tls_config: None,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh_config: None,
             bind_addr: None,
         });
         name_servers.push(NameServerConfig {
@@ -47,6 +49,8 @@ fn get_name_servers() -> Result<Vec<NameServerConfig>, ResolveError> {
             trust_negative_responses: false,
             #[cfg(feature = "dns-over-rustls")]
             tls_config: None,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh_config: None,
             bind_addr: None,
         });
     }
@@ -0,0 +1,466 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Reloading of the system configuration when `/etc/resolv.conf` or `/etc/hosts` change
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "tokio-runtime")]
+//! # #[tokio::main]
+//! # async fn main() {
+//! use std::time::Duration;
+//!
+//! use hickory_resolver::name_server::TokioConnectionProvider;
+//! use hickory_resolver::watcher::SystemConfWatcher;
+//!
+//! let watcher = SystemConfWatcher::new(TokioConnectionProvider::default()).unwrap();
+//! tokio::spawn(watcher.clone().watch(Duration::from_secs(5)));
+//!
+//! // each lookup uses the configuration read last
+//! let response = watcher.resolver().lookup_ip("www.example.com.").await.unwrap();
+//! # }
+//! # #[cfg(not(feature = "tokio-runtime"))]
+//! # fn main() {}
+//! ```
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc as std_mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fs, io, thread};
+
+use futures_channel::mpsc;
+use futures_util::StreamExt;
+use parking_lot::RwLock;
+use tracing::{debug, warn};
+
+use crate::config::{ResolveHosts, ResolverOpts};
+use crate::error::ResolveError;
+use crate::hosts::{self, Hosts};
+use crate::name_server::ConnectionProvider;
+use crate::proto::runtime::{RuntimeProvider, Time};
+use crate::system_conf;
+use crate::Resolver;
+
+/// A [`Resolver`] which is rebuilt when the system configuration files change
+///
+/// The files are polled for changes of their contents, by [`Self::reload`] or periodically by
+///  [`Self::watch`]. A change replaces the resolver at once: lookups started
+///  before finish with the previous configuration, and later ones use the new one. The cache is
+///  kept unless the name servers or the options of `resolv.conf` changed, since its records were
+///  answered by the previous name servers.
+///
+/// As with glibc, changes of `resolv.conf` are ignored if it has `options no-reload`.
+///
+/// Clones share the current resolver.
+#[derive(Clone)]
+pub struct SystemConfWatcher<P: ConnectionProvider> {
+    inner: Arc<Inner<P>>,
+}
+
+struct Inner<P: ConnectionProvider> {
+    resolv_conf_path: PathBuf,
+    hosts_path: PathBuf,
+    conn_provider: P,
+    state: RwLock<State<P>>,
+}
+
+struct State<P: ConnectionProvider> {
+    resolver: Resolver<P>,
+    resolv_conf: Option<FileStamp>,
+    hosts: Option<FileStamp>,
+    no_reload: bool,
+}
+
+impl<P: ConnectionProvider> SystemConfWatcher<P> {
+    /// Reads `/etc/resolv.conf` and `/etc/hosts`, and watches them for changes
+    pub fn new(conn_provider: P) -> Result<Self, ResolveError> {
+        Self::with_paths("/etc/resolv.conf", hosts::hosts_path(), conn_provider)
+    }
+
+    /// Reads a `resolv.conf` and a hosts file at other paths, and watches them for changes
+    ///
+    /// A missing hosts file is treated as an empty one.
+    pub fn with_paths(
+        resolv_conf_path: impl Into<PathBuf>,
+        hosts_path: impl Into<PathBuf>,
+        conn_provider: P,
+    ) -> Result<Self, ResolveError> {
+        let resolv_conf_path = resolv_conf_path.into();
+        let hosts_path = hosts_path.into();
+
+        let contents = Contents::read(&resolv_conf_path, &hosts_path);
+        let resolv_conf = contents.resolv_conf_stamp();
+        let hosts = contents.hosts_stamp();
+        let parsed = system_conf::parse(contents.resolv_conf?)?;
+        let no_reload = parsed.no_reload;
+        let (config, options) = system_conf::into_resolver_config(parsed)?;
+        let mut resolver = Resolver::new_with_conn(config, options, conn_provider.clone());
+        resolver.set_hosts(parse_hosts(contents.hosts, resolver.options()));
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                resolv_conf_path,
+                hosts_path,
+                conn_provider,
+                state: RwLock::new(State {
+                    resolver,
+                    resolv_conf,
+                    hosts,
+                    no_reload,
+                }),
+            }),
+        })
+    }
+
+    /// Returns the resolver for the current configuration
+    pub fn resolver(&self) -> Resolver<P> {
+        self.inner.state.read().resolver.clone()
+    }
+
+    /// Rebuilds the resolver if the files changed since they were last read
+    ///
+    /// Returns whether the resolver was replaced. If `resolv.conf` can't be read, e.g. because it
+    ///  is being rewritten, the current resolver is kept and the change is picked up by a later
+    ///  call.
+    ///
+    /// This reads the files with blocking calls, [`Self::watch`] reads them on another thread.
+    pub fn reload(&self) -> Result<bool, ResolveError> {
+        let inner = &*self.inner;
+        self.update(Contents::read(&inner.resolv_conf_path, &inner.hosts_path))
+    }
+
+    /// Rebuilds the resolver from the files read, if they changed
+    fn update(&self, contents: Contents) -> Result<bool, ResolveError> {
+        let inner = &*self.inner;
+        let resolv_conf = contents.resolv_conf_stamp();
+        let hosts = contents.hosts_stamp();
+
+        // held across the rebuild, so that concurrent reloads don't race each other
+        let mut state = inner.state.upgradable_read();
+        let resolv_conf_changed = !state.no_reload && resolv_conf != state.resolv_conf;
+        if !resolv_conf_changed && hosts == state.hosts {
+            return Ok(false);
+        }
+
+        let mut no_reload = state.no_reload;
+        let mut resolver = if resolv_conf_changed {
+            debug!("reloading {}", inner.resolv_conf_path.display());
+            let parsed = system_conf::parse(contents.resolv_conf?)?;
+            no_reload = parsed.no_reload;
+            let (config, options) = system_conf::into_resolver_config(parsed)?;
+            state
+                .resolver
+                .reconfigure(config, options, inner.conn_provider.clone())
+        } else {
+            state.resolver.clone()
+        };
+
+        debug!("reloading {}", inner.hosts_path.display());
+        resolver.set_hosts(parse_hosts(contents.hosts, resolver.options()));
+
+        state.with_upgraded(|state| {
+            *state = State {
+                resolver,
+                resolv_conf,
+                hosts,
+                no_reload,
+            }
+        });
+        Ok(true)
+    }
+
+    /// Polls the files for changes every `interval`, never returns
+    ///
+    /// This is meant to be spawned as a background task, failures to reload are logged. The files
+    ///  are read by a thread of their own, which lives as long as the task, so that the runtime
+    ///  isn't blocked by the file system.
+    pub async fn watch(self, interval: Duration) {
+        let (requests, mut contents) = spawn_reader(
+            self.inner.resolv_conf_path.clone(),
+            self.inner.hosts_path.clone(),
+        );
+
+        loop {
+            <P::RuntimeProvider as RuntimeProvider>::Timer::delay_for(interval).await;
+
+            if requests.send(()).is_err() {
+                warn!("failed to read the system configuration");
+                return;
+            }
+            let Some(contents) = contents.next().await else {
+                warn!("failed to read the system configuration");
+                return;
+            };
+
+            if let Err(e) = self.update(contents) {
+                warn!("failed to reload the system configuration: {e}");
+            }
+        }
+    }
+}
+
+/// Spawns the thread reading the files of [`SystemConfWatcher::watch`]
+///
+/// The files are read once for each request, and the thread ends once the requests are dropped.
+fn spawn_reader(
+    resolv_conf_path: PathBuf,
+    hosts_path: PathBuf,
+) -> (std_mpsc::Sender<()>, mpsc::UnboundedReceiver<Contents>) {
+    let (requests_tx, requests_rx) = std_mpsc::channel();
+    let (contents_tx, contents_rx) = mpsc::unbounded();
+    thread::spawn(move || {
+        while requests_rx.recv().is_ok() {
+            let contents = Contents::read(&resolv_conf_path, &hosts_path);
+            if contents_tx.unbounded_send(contents).is_err() {
+                break;
+            }
+        }
+    });
+
+    (requests_tx, contents_rx)
+}
+
+/// Parses the hosts file for a resolver, if its options use one
+///
+/// A hosts file which can't be read is treated as an empty one.
+fn parse_hosts(contents: io::Result<Vec<u8>>, options: &ResolverOpts) -> Option<Hosts> {
+    match options.use_hosts_file {
+        ResolveHosts::Always | ResolveHosts::Auto => {
+            let mut hosts = Hosts::default();
+            if let Ok(contents) = contents {
+                if let Err(e) = hosts.read_hosts_conf(&contents[..]) {
+                    warn!("failed to parse the hosts file: {e}");
+                }
+            }
+            Some(hosts)
+        }
+        ResolveHosts::Never => None,
+    }
+}
+
+/// The contents of the watched files
+struct Contents {
+    resolv_conf: io::Result<Vec<u8>>,
+    hosts: io::Result<Vec<u8>>,
+}
+
+impl Contents {
+    fn read(resolv_conf_path: &Path, hosts_path: &Path) -> Self {
+        Self {
+            resolv_conf: fs::read(resolv_conf_path),
+            hosts: fs::read(hosts_path),
+        }
+    }
+
+    fn resolv_conf_stamp(&self) -> Option<FileStamp> {
+        self.resolv_conf.as_deref().ok().map(FileStamp::new)
+    }
+
+    fn hosts_stamp(&self) -> Option<FileStamp> {
+        self.hosts.as_deref().ok().map(FileStamp::new)
+    }
+}
+
+/// A hash of the contents of a file
+///
+/// Unlike the modification time and size, this also catches a rewrite to the same size within
+///  the resolution of the modification time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FileStamp(u64);
+
+impl FileStamp {
+    fn new(contents: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+}
+
+#[cfg(all(test, feature = "tokio-runtime"))]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::name_server::TokioConnectionProvider;
+    use crate::Name;
+
+    struct Files {
+        dir: TempDir,
+    }
+
+    impl Files {
+        fn new() -> Self {
+            Self {
+                dir: tempfile::tempdir().unwrap(),
+            }
+        }
+
+        fn resolv_conf(&self) -> PathBuf {
+            self.dir.path().join("resolv.conf")
+        }
+
+        fn hosts(&self) -> PathBuf {
+            self.dir.path().join("hosts")
+        }
+
+        fn watcher(&self) -> SystemConfWatcher<TokioConnectionProvider> {
+            SystemConfWatcher::with_paths(
+                self.resolv_conf(),
+                self.hosts(),
+                TokioConnectionProvider::default(),
+            )
+            .unwrap()
+        }
+    }
+
+    async fn lookup_host(resolver: &Resolver<TokioConnectionProvider>) -> IpAddr {
+        let lookup = resolver.ipv4_lookup("host.example.test").await.unwrap();
+        IpAddr::V4(lookup.iter().next().unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn test_reload_resolv_conf() {
+        let files = Files::new();
+        fs::write(files.resolv_conf(), "nameserver 127.0.0.1\nsearch a.test\n").unwrap();
+        let watcher = files.watcher();
+        assert!(!watcher.reload().unwrap());
+
+        let old = watcher.resolver();
+        assert_eq!(old.config().search(), [Name::from_ascii("a.test").unwrap()]);
+
+        fs::write(
+            files.resolv_conf(),
+            "nameserver 127.0.0.1\nsearch b.example.test\n",
+        )
+        .unwrap();
+        assert!(watcher.reload().unwrap());
+        assert!(!watcher.reload().unwrap());
+
+        let new = watcher.resolver();
+        assert_eq!(
+            new.config().search(),
+            [Name::from_ascii("b.example.test").unwrap()]
+        );
+        // resolvers in use keep their configuration
+        assert_eq!(old.config().search(), [Name::from_ascii("a.test").unwrap()]);
+
+        // a file being rewritten keeps the current configuration
+        fs::remove_file(files.resolv_conf()).unwrap();
+        assert!(watcher.reload().is_err());
+        assert_eq!(watcher.resolver().config(), new.config());
+    }
+
+    #[tokio::test]
+    async fn test_reload_hosts() {
+        let files = Files::new();
+        fs::write(files.resolv_conf(), "nameserver 127.0.0.1\n").unwrap();
+        fs::write(files.hosts(), "127.0.0.2 host.example.test\n").unwrap();
+        let watcher = files.watcher();
+
+        let old = watcher.resolver();
+        assert_eq!(
+            lookup_host(&old).await,
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))
+        );
+
+        fs::write(files.hosts(), "127.0.0.22 host.example.test\n").unwrap();
+        assert!(watcher.reload().unwrap());
+        assert_eq!(
+            lookup_host(&watcher.resolver()).await,
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 22))
+        );
+        assert_eq!(
+            lookup_host(&old).await,
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reload_same_size() {
+        let files = Files::new();
+        fs::write(files.resolv_conf(), "nameserver 127.0.0.1\n").unwrap();
+        fs::write(files.hosts(), "127.0.0.2 host.example.test\n").unwrap();
+        let watcher = files.watcher();
+
+        // rewritten at once, the modification time may well be the same
+        fs::write(files.hosts(), "127.0.0.3 host.example.test\n").unwrap();
+        assert!(watcher.reload().unwrap());
+        assert_eq!(
+            lookup_host(&watcher.resolver()).await,
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 3))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_reload() {
+        let files = Files::new();
+        fs::write(
+            files.resolv_conf(),
+            "nameserver 127.0.0.1\noptions no-reload\n",
+        )
+        .unwrap();
+        fs::write(files.hosts(), "127.0.0.2 host.example.test\n").unwrap();
+        let watcher = files.watcher();
+
+        fs::write(files.resolv_conf(), "nameserver 127.0.0.1\nsearch a.test\n").unwrap();
+        assert!(!watcher.reload().unwrap());
+        assert!(watcher.resolver().config().search().is_empty());
+
+        // the hosts file is still reloaded
+        fs::write(files.hosts(), "127.0.0.22 host.example.test\n").unwrap();
+        assert!(watcher.reload().unwrap());
+        assert_eq!(
+            lookup_host(&watcher.resolver()).await,
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 22))
+        );
+        assert!(watcher.resolver().config().search().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let files = Files::new();
+        fs::write(files.resolv_conf(), "nameserver 127.0.0.1\n").unwrap();
+        let watcher = files.watcher();
+        tokio::spawn(watcher.clone().watch(Duration::from_millis(10)));
+
+        fs::write(files.resolv_conf(), "nameserver 127.0.0.1\nsearch a.test\n").unwrap();
+        // the files are read on another thread, so the clock can't be paused
+        for _ in 0..100 {
+            if !watcher.resolver().config().search().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            watcher.resolver().config().search(),
+            [Name::from_ascii("a.test").unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reader() {
+        let files = Files::new();
+        fs::write(files.resolv_conf(), "nameserver 127.0.0.1\n").unwrap();
+
+        // one thread reads the files for every request
+        let (requests, mut contents) = spawn_reader(files.resolv_conf(), files.hosts());
+        for _ in 0..2 {
+            requests.send(()).unwrap();
+            let read = contents.next().await.unwrap();
+            assert_eq!(read.resolv_conf.unwrap(), b"nameserver 127.0.0.1\n");
+            assert!(read.hosts.is_err());
+        }
+
+        // and ends with the requests
+        drop(requests);
+        assert!(contents.next().await.is_none());
+    }
+}
@@ -1,4 +1,7 @@
-use std::net::IpAddr;
+use std::{
+    net::IpAddr,
+    sync::{Arc, RwLock},
+};
 
 use ipnet::{IpNet, Ipv4Net, Ipv6Net};
 use prefix_trie::{Prefix, PrefixSet};
@@ -71,6 +74,39 @@ impl AccessControl {
     }
 }
 
+/// Handle to change the networks allowed to access a running [`ServerFuture`](crate::ServerFuture)
+#[derive(Clone, Default)]
+pub struct AccessControlHandle {
+    access: Arc<RwLock<AccessControl>>,
+}
+
+impl AccessControlHandle {
+    pub(crate) fn new(denied_networks: &[IpNet], allowed_networks: &[IpNet]) -> Self {
+        let handle = Self::default();
+        handle.set_networks(denied_networks, allowed_networks);
+        handle
+    }
+
+    /// Replaces the denied and allowed networks
+    ///
+    /// A more specific allowed network overrides a denied network. If only allowed networks are
+    ///  given, all other addresses are denied. The new networks apply to all requests received
+    ///  afterwards, including those on existing connections.
+    pub fn set_networks(&self, denied_networks: &[IpNet], allowed_networks: &[IpNet]) {
+        let mut access = AccessControl::default();
+        access.insert_deny(denied_networks);
+        access.insert_allow(allowed_networks);
+
+        *self.access.write().expect("lock poisoned") = access;
+    }
+
+    /// Evaluate the IP address against the allowed networks
+    #[must_use]
+    pub(crate) fn allow(&self, ip: IpAddr) -> bool {
+        self.access.read().expect("lock poisoned").allow(ip)
+    }
+}
+
 #[derive(Default)]
 struct InnerAccessControl<I: Prefix> {
     allow: PrefixSet<I>,
@@ -116,6 +152,18 @@ impl<I: Prefix> InnerAccessControl<I> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_handle_set_networks() {
+        let access = AccessControlHandle::new(&[], &["192.168.1.0/24".parse().unwrap()]);
+        assert!(!access.allow("192.168.2.1".parse().unwrap()));
+
+        access
+            .clone()
+            .set_networks(&["192.168.1.0/24".parse().unwrap()], &[]);
+        assert!(access.allow("192.168.2.1".parse().unwrap()));
+        assert!(!access.allow("192.168.1.1".parse().unwrap()));
+    }
+
     #[test]
     fn test_none() {
         let access = AccessControl::default();
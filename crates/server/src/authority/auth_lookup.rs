@@ -48,6 +48,9 @@ pub enum AuthLookup {
         /// The last SOA record of an AXFR (matches the first)
         end_soa: LookupRecords,
     },
+    /// An ixfr is a sequence of differences, each a deleted and an added set of records led by
+    ///  the SOA of its version, all enclosed by the current SOA
+    IXFR(Vec<Record>),
 }
 
 impl AuthLookup {
@@ -152,6 +155,7 @@ impl<'a> IntoIterator for &'a AuthLookup {
                 records,
                 end_soa,
             } => AuthLookupIter::AXFR(start_soa.into_iter().chain(records).chain(end_soa)),
+            AuthLookup::IXFR(records) => AuthLookupIter::IXFR(records.iter()),
         }
     }
 }
@@ -167,6 +171,8 @@ pub enum AuthLookupIter<'r> {
     Records(LookupRecordsIter<'r>),
     /// An iteration over an AXFR
     AXFR(Chain<Chain<LookupRecordsIter<'r>, LookupRecordsIter<'r>>, LookupRecordsIter<'r>>),
+    /// An iteration over an IXFR
+    IXFR(Iter<'r, Record>),
 }
 
 impl<'r> Iterator for AuthLookupIter<'r> {
@@ -177,6 +183,7 @@ impl<'r> Iterator for AuthLookupIter<'r> {
             AuthLookupIter::Empty => None,
             AuthLookupIter::Records(i) => i.next(),
             AuthLookupIter::AXFR(i) => i.next(),
+            AuthLookupIter::IXFR(i) => i.next(),
         }
     }
 }
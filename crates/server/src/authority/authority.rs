@@ -15,7 +15,10 @@ use hickory_proto::ProtoError;
 
 use crate::{
     authority::{LookupError, LookupObject, MessageRequest, UpdateResult, ZoneType},
-    proto::rr::{LowerName, RecordSet, RecordType, RrsetRecords},
+    proto::{
+        op::{Message, ResponseCode},
+        rr::{LowerName, RecordSet, RecordType, RrsetRecords},
+    },
     server::RequestInfo,
 };
 #[cfg(feature = "dnssec")]
@@ -117,7 +120,29 @@ pub trait Authority: Send + Sync {
     }
 
     /// Perform a dynamic update of a zone
-    async fn update(&self, update: &MessageRequest) -> UpdateResult<bool>;
+    ///
+    /// The `request_info` carries the name of the TSIG key the `Catalog` authenticated the update
+    ///  with, if it was signed with one.
+    async fn update(
+        &self,
+        request_info: RequestInfo<'_>,
+        update: &MessageRequest,
+    ) -> UpdateResult<bool>;
+
+    /// Process a NOTIFY for this zone, see [RFC 1996](https://tools.ietf.org/html/rfc1996)
+    ///
+    /// Only secondary zones accept NOTIFY, by default it is not implemented.
+    async fn notify(&self, _request_info: RequestInfo<'_>) -> Result<(), ResponseCode> {
+        Err(ResponseCode::NotImp)
+    }
+
+    /// Forwards a dynamic update to the primaries of the zone, returning the response of the
+    ///  primary to relay to the requestor, see [RFC 2136](https://tools.ietf.org/html/rfc2136#section-6)
+    ///
+    /// Only secondary zones forward updates, by default it is not implemented.
+    async fn forward_update(&self, _update: &MessageRequest) -> Result<Message, ResponseCode> {
+        Err(ResponseCode::NotImp)
+    }
 
     /// Get the origin of this zone, i.e. example.com is the origin for www.example.com
     fn origin(&self) -> &LowerName;
@@ -15,7 +15,10 @@ use crate::{
     authority::{
         Authority, LookupControlFlow, LookupOptions, MessageRequest, UpdateResult, ZoneType,
     },
-    proto::rr::{LowerName, Record, RecordType},
+    proto::{
+        op::{Message, ResponseCode},
+        rr::{LowerName, Record, RecordType},
+    },
     server::RequestInfo,
 };
 
@@ -32,7 +35,17 @@ pub trait AuthorityObject: Send + Sync {
     fn can_validate_dnssec(&self) -> bool;
 
     /// Perform a dynamic update of a zone
-    async fn update(&self, update: &MessageRequest) -> UpdateResult<bool>;
+    async fn update(
+        &self,
+        request_info: RequestInfo<'_>,
+        update: &MessageRequest,
+    ) -> UpdateResult<bool>;
+
+    /// Process a NOTIFY for this zone
+    async fn notify(&self, request_info: RequestInfo<'_>) -> Result<(), ResponseCode>;
+
+    /// Forwards a dynamic update to the primaries of the zone, returning the response to relay
+    async fn forward_update(&self, update: &MessageRequest) -> Result<Message, ResponseCode>;
 
     /// Get the origin of this zone, i.e. example.com is the origin for www.example.com
     fn origin(&self) -> &LowerName;
@@ -183,8 +196,22 @@ where
     }
 
     /// Perform a dynamic update of a zone
-    async fn update(&self, update: &MessageRequest) -> UpdateResult<bool> {
-        Authority::update(self, update).await
+    async fn update(
+        &self,
+        request_info: RequestInfo<'_>,
+        update: &MessageRequest,
+    ) -> UpdateResult<bool> {
+        Authority::update(self, request_info, update).await
+    }
+
+    /// Process a NOTIFY for this zone
+    async fn notify(&self, request_info: RequestInfo<'_>) -> Result<(), ResponseCode> {
+        Authority::notify(self, request_info).await
+    }
+
+    /// Forwards a dynamic update to the primaries of the zone, returning the response to relay
+    async fn forward_update(&self, update: &MessageRequest) -> Result<Message, ResponseCode> {
+        Authority::forward_update(self, update).await
     }
 
     /// Get the origin of this zone, i.e. example.com is the origin for www.example.com
@@ -340,6 +367,12 @@ pub trait LookupObject: Send {
     fn dnssec_summary(&self) -> DnssecSummary {
         DnssecSummary::Insecure
     }
+
+    /// The scope prefix length of the client subnet the records are valid for, if they were looked
+    ///  up with the EDNS Client Subnet of the request
+    fn client_subnet_scope(&self) -> Option<u8> {
+        None
+    }
 }
 
 /// A lookup that returns no records
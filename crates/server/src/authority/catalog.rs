@@ -8,37 +8,55 @@
 // TODO, I've implemented this as a separate entity from the cache, but I wonder if the cache
 //  should be the only "front-end" for lookups, where if that misses, then we go to the catalog
 //  then, if requested, do a recursive lookup... i.e. the catalog would only point to files.
-use std::{borrow::Borrow, collections::HashMap, io, sync::Arc};
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    io,
+    sync::{Arc, RwLock},
+};
 
 use cfg_if::cfg_if;
 use tracing::{debug, error, info, trace, warn};
 
-#[cfg(feature = "dnssec")]
 use crate::{
-    authority::Nsec3QueryInfo,
-    dnssec::NxProofKind,
+    authority::{
+        authority_object::DnssecSummary,
+        cookie::{CookieError, ServerCookies},
+        AuthLookup, AuthorityObject, EmptyLookup, LookupControlFlow, LookupError, LookupObject,
+        LookupOptions, LookupRecords, MessageResponse, MessageResponseBuilder, ZoneType,
+    },
     proto::{
-        dnssec::SupportedAlgorithms,
-        rr::rdata::opt::{EdnsCode, EdnsOption},
+        op::{Edns, Header, LowerQuery, Message, MessageType, OpCode, ResponseCode},
+        rr::{
+            rdata::opt::{ClientSubnet, EdnsOption},
+            LowerName, Record, RecordSet, RecordType,
+        },
     },
+    server::{Request, RequestHandler, RequestInfo, ResponseHandler, ResponseInfo},
 };
+#[cfg(feature = "dnssec")]
 use crate::{
     authority::{
-        authority_object::DnssecSummary, AuthLookup, AuthorityObject, EmptyLookup,
-        LookupControlFlow, LookupError, LookupObject, LookupOptions, LookupRecords,
-        MessageResponse, MessageResponseBuilder, ZoneType,
+        tsig::{self, ResponseTsig, TsigResponseHandler},
+        Nsec3QueryInfo,
     },
+    dnssec::NxProofKind,
     proto::{
-        op::{Edns, Header, LowerQuery, MessageType, OpCode, ResponseCode},
-        rr::{LowerName, Record, RecordSet, RecordType},
+        dnssec::{tsig::TSigner, SupportedAlgorithms},
+        rr::rdata::opt::EdnsCode,
     },
-    server::{Request, RequestHandler, RequestInfo, ResponseHandler, ResponseInfo},
 };
 
 /// Set of authorities, zones, available to this server.
-#[derive(Default)]
+///
+/// Cloning the catalog is cheap, the authorities and the DNS Cookies state are shared by the
+///  clones.
+#[derive(Clone, Default)]
 pub struct Catalog {
     authorities: HashMap<LowerName, Vec<Arc<dyn AuthorityObject>>>,
+    cookies: Option<Arc<ServerCookies>>,
+    #[cfg(feature = "dnssec")]
+    tsig_keys: HashMap<LowerName, TSigner>,
 }
 
 #[allow(unused_mut, unused_variables)]
@@ -61,6 +79,36 @@ async fn send_response<'a, R: ResponseHandler>(
     response_handle.send_response(response).await
 }
 
+/// Relays the response of another server to the request, e.g. of the primary to a forwarded update
+///
+/// The response keeps the header, EDNS and TSIG of the original, so that its signature still
+///  verifies.
+async fn relay_response<R: ResponseHandler>(
+    request: &Request,
+    response: Message,
+    response_handle: R,
+) -> io::Result<ResponseInfo> {
+    #[allow(unused_mut)]
+    let mut relayed = MessageResponseBuilder::new(Some(request.raw_query())).build(
+        *response.header(),
+        response.answers(),
+        response.name_servers(),
+        [],
+        response.additionals(),
+    );
+
+    #[cfg(feature = "dnssec")]
+    if let Some(tsig) = response
+        .signature()
+        .last()
+        .filter(|record| record.record_type() == RecordType::TSIG)
+    {
+        relayed.set_tsig(ResponseTsig::Relayed(tsig.clone()));
+    }
+
+    send_response(response.extensions().clone(), relayed, response_handle).await
+}
+
 #[async_trait::async_trait]
 impl RequestHandler for Catalog {
     /// Determines what needs to happen given the type of request, i.e. Query or Update.
@@ -70,13 +118,40 @@ impl RequestHandler for Catalog {
     /// * `request` - the requested action to perform.
     /// * `response_handle` - sink for the response message to be sent
     async fn handle_request<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: R,
+    ) -> ResponseInfo {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+
+        let response_info = self.handle_request_inner(request, response_handle).await;
+
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_request(
+            request.protocol(),
+            request.query().query_type(),
+            response_info.response_code(),
+            self.find(request.query().name())
+                .and_then(|authorities| authorities.first())
+                .map(|authority| authority.origin()),
+            start.elapsed(),
+        );
+
+        response_info
+    }
+}
+
+impl Catalog {
+    /// Handles the request, see [`RequestHandler::handle_request`]
+    async fn handle_request_inner<R: ResponseHandler>(
         &self,
         request: &Request,
         mut response_handle: R,
     ) -> ResponseInfo {
         trace!("request: {:?}", request);
 
-        let response_edns: Option<Edns>;
+        let mut response_edns: Option<Edns>;
 
         // check if it's edns
         if let Some(req_edns) = request.edns() {
@@ -122,19 +197,103 @@ impl RequestHandler for Catalog {
             response_edns = None;
         }
 
+        // DNS Cookies, RFC 7873
+        if let Some(cookies) = &self.cookies {
+            match cookies.check(request.src().ip(), request.protocol(), request.edns()) {
+                Ok(cookie) => {
+                    if let (Some(resp_edns), Some(cookie)) = (&mut response_edns, cookie) {
+                        resp_edns.options_mut().insert(cookie);
+                    }
+                }
+                Err(error) => {
+                    let response = MessageResponseBuilder::new(Some(request.raw_query()));
+                    let mut response_header = Header::response_from_request(request.header());
+
+                    match error {
+                        CookieError::BadCookie(cookie) => {
+                            response_header.set_response_code(ResponseCode::BADCOOKIE);
+                            if let Some(resp_edns) = &mut response_edns {
+                                resp_edns.set_rcode_high(ResponseCode::BADCOOKIE.high());
+                                resp_edns.options_mut().insert(cookie);
+                            }
+                        }
+                        // without a cookie, the client can only prove its address over TCP
+                        CookieError::Missing => {
+                            response_header.set_truncated(true);
+                        }
+                    }
+
+                    let result = send_response(
+                        response_edns,
+                        response.build_no_records(response_header),
+                        response_handle,
+                    )
+                    .await;
+
+                    return match result {
+                        Err(e) => {
+                            error!("request error: {}", e);
+                            ResponseInfo::serve_failed()
+                        }
+                        Ok(info) => info,
+                    };
+                }
+            }
+        }
+
+        #[allow(unused_mut)]
+        let mut request_info = request.request_info();
+
+        // requests signed with TSIG must be authenticated, and the response is signed with the same
+        //  key. Updates forwarded to a primary are authenticated by the primary, whose signed
+        //  response is relayed, RFC 2136 section 6.
+        #[cfg(feature = "dnssec")]
+        let authenticated = match self.forwards_update(request) {
+            true => Ok(None),
+            false => tsig::authenticate(&self.tsig_keys, request),
+        };
+        #[cfg(feature = "dnssec")]
+        let mut response_handle = match authenticated {
+            Ok(authenticated) => {
+                let (tsig_key, tsig) = authenticated.unzip();
+                request_info.tsig_key = tsig_key;
+                TsigResponseHandler::new(response_handle, tsig)
+            }
+            Err(tsig) => {
+                let response = MessageResponseBuilder::new(Some(request.raw_query()));
+                let mut response = response.error_msg(request.header(), ResponseCode::NotAuth);
+                response.set_tsig(*tsig);
+
+                return match send_response(response_edns, response, response_handle).await {
+                    Err(e) => {
+                        error!("request error: {}", e);
+                        ResponseInfo::serve_failed()
+                    }
+                    Ok(info) => info,
+                };
+            }
+        };
+
         let result = match request.message_type() {
             // TODO think about threading query lookups for multiple lookups, this could be a huge improvement
             //  especially for recursive lookups
             MessageType::Query => match request.op_code() {
                 OpCode::Query => {
                     debug!("query received: {}", request.id());
-                    let info = self.lookup(request, response_edns, response_handle).await;
+                    let info = self
+                        .lookup_with_info(request_info, request, response_edns, response_handle)
+                        .await;
 
                     Ok(info)
                 }
                 OpCode::Update => {
                     debug!("update received: {}", request.id());
-                    self.update(request, response_edns, response_handle).await
+                    self.update(request_info, request, response_edns, response_handle)
+                        .await
+                }
+                OpCode::Notify => {
+                    debug!("notify received: {}", request.id());
+                    self.notify(request, response_edns, response_handle).await
                 }
                 c => {
                     warn!("unimplemented op_code: {:?}", c);
@@ -163,16 +322,39 @@ impl RequestHandler for Catalog {
             Ok(info) => info,
         }
     }
-}
 
-impl Catalog {
     /// Constructs a new Catalog
     pub fn new() -> Self {
         Self {
             authorities: HashMap::new(),
+            cookies: None,
+            #[cfg(feature = "dnssec")]
+            tsig_keys: HashMap::new(),
         }
     }
 
+    /// Enables DNS Cookies, [RFC 7873](https://tools.ietf.org/html/rfc7873), for responses to
+    ///  requests using EDNS
+    pub fn set_cookies(&mut self, cookies: Option<ServerCookies>) {
+        self.cookies = cookies.map(Arc::new);
+    }
+
+    /// Adds a key for TSIG authentication of requests
+    ///
+    /// Requests signed with an unknown key, or which fail authentication, are answered with
+    ///  NOTAUTH. Responses to authenticated requests are signed with the same key.
+    #[cfg(feature = "dnssec")]
+    pub fn add_tsig_key(&mut self, signer: TSigner) {
+        self.tsig_keys
+            .insert(LowerName::from(signer.signer_name()), signer);
+    }
+
+    /// Removes the TSIG key with the name
+    #[cfg(feature = "dnssec")]
+    pub fn remove_tsig_key(&mut self, name: &LowerName) -> Option<TSigner> {
+        self.tsig_keys.remove(name)
+    }
+
     /// Insert or update a zone authority
     ///
     /// # Arguments
@@ -183,6 +365,21 @@ impl Catalog {
         self.authorities.insert(name, authorities);
     }
 
+    /// Sets the serial metric of each zone from the authorities in the catalog
+    ///
+    /// This is to be called once the zones are loaded or replaced. The serials of updates, and of
+    ///  the transfers of secondary zones, are recorded as they happen. The metrics facade can't
+    ///  remove a series, so to drop those of zones removed from the catalog, the recorder should
+    ///  drop idle gauges and this be called more often than their idle timeout.
+    #[cfg(feature = "metrics")]
+    pub async fn record_zone_serials(&self) {
+        for authorities in self.authorities.values() {
+            if let Some(authority) = authorities.first() {
+                crate::metrics::record_authority_serial(&**authority).await;
+            }
+        }
+    }
+
     /// Remove a zone from the catalog
     pub fn remove(&mut self, name: &LowerName) -> Option<Vec<Arc<dyn AuthorityObject>>> {
         self.authorities.remove(name)
@@ -235,16 +432,16 @@ impl Catalog {
     ///
     /// # Arguments
     ///
+    /// * `request_info` - the information of the request, with the TSIG key it was authenticated with
     /// * `request` - an update message
     /// * `response_handle` - sink for the response message to be sent
     pub async fn update<R: ResponseHandler>(
         &self,
+        request_info: RequestInfo<'_>,
         update: &Request,
         response_edns: Option<Edns>,
         response_handle: R,
     ) -> io::Result<ResponseInfo> {
-        let request_info = update.request_info();
-
         let verify_request = move || -> Result<RequestInfo<'_>, ResponseCode> {
             // 2.3 - Zone Section
             //
@@ -276,15 +473,25 @@ impl Catalog {
             for authority in authorities {
                 #[allow(deprecated)]
                 let response_code = match authority.zone_type() {
-                    ZoneType::Secondary | ZoneType::Slave => {
-                        error!("secondary forwarding for update not yet implemented");
-                        ResponseCode::NotImp
-                    }
                     ZoneType::Primary | ZoneType::Master => {
-                        let update_result = authority.update(update).await;
+                        let update_result = authority.update(verify_request.clone(), update).await;
                         match update_result {
                             // successful update
-                            Ok(..) => ResponseCode::NoError,
+                            Ok(..) => {
+                                #[cfg(feature = "metrics")]
+                                crate::metrics::record_authority_serial(&**authority).await;
+                                ResponseCode::NoError
+                            }
+                            Err(response_code) => response_code,
+                        }
+                    }
+                    // secondaries forward the update to the primary, and relay its response, see
+                    //  section 6
+                    ZoneType::Secondary | ZoneType::Slave => {
+                        match authority.forward_update(update).await {
+                            Ok(response) => {
+                                return relay_response(update, response, response_handle).await
+                            }
                             Err(response_code) => response_code,
                         }
                     }
@@ -310,6 +517,104 @@ impl Catalog {
         Ok(ResponseInfo::serve_failed())
     }
 
+    /// Process a NOTIFY of a change to a zone.
+    ///
+    /// [RFC 1996](https://tools.ietf.org/html/rfc1996), NOTIFY, August 1996
+    ///
+    /// ```text
+    /// 3.7. A NOTIFY request has QDCOUNT>0, ANCOUNT>=0, AUCOUNT>=0,
+    ///    ADCOUNT>=0.  If ANCOUNT>0, then the answer section represents an
+    ///    unsecure hint at the new RRset for this <QNAME,QCLASS,QTYPE>.  A
+    ///    slave receiving such a hint is free to treat equivalence of this
+    ///    answer section with its local data as a "no further work needs to
+    ///    be done" indication.  If ANCOUNT=0, or ANCOUNT>0 and the answer
+    ///    section differs from the slave's local data, then the slave should
+    ///    query its known masters to retrieve the new data.
+    ///
+    /// 3.10. If a slave receives a NOTIFY request from a host that is not a
+    ///    known master for the zone containing the QNAME, it should ignore the
+    ///    request and produce an error message in its operations log.
+    /// ```
+    ///
+    /// Only secondary zones accept a NOTIFY, which is passed to the zone's authority to schedule a
+    ///  refresh from its primaries. The response is sent without waiting for the refresh.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - a notify message
+    /// * `response_handle` - sink for the response message to be sent
+    pub async fn notify<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_edns: Option<Edns>,
+        response_handle: R,
+    ) -> io::Result<ResponseInfo> {
+        let request_info = request.request_info();
+        let query = request_info.query;
+
+        let response_code = if query.query_type() != RecordType::SOA {
+            warn!(
+                "unsupported notify for type: {} from {}",
+                query.query_type(),
+                request_info.src
+            );
+            ResponseCode::NotImp
+        } else {
+            // NOTIFY is only for the origin of the zone
+            let authority = self
+                .find(query.name())
+                .and_then(|authorities| authorities.first())
+                .filter(|authority| authority.origin() == query.name());
+
+            #[allow(deprecated)]
+            match authority {
+                Some(authority)
+                    if matches!(authority.zone_type(), ZoneType::Secondary | ZoneType::Slave) =>
+                {
+                    match authority.notify(request_info.clone()).await {
+                        Ok(()) => ResponseCode::NoError,
+                        Err(response_code) => response_code,
+                    }
+                }
+                _ => {
+                    warn!(
+                        "notify for {} from {}, which is not a secondary zone",
+                        query.name(),
+                        request_info.src
+                    );
+                    ResponseCode::NotAuth
+                }
+            }
+        };
+
+        let response = MessageResponseBuilder::new(Some(request.raw_query()));
+        let mut response_header = Header::response_from_request(request.header());
+        response_header.set_authoritative(response_code == ResponseCode::NoError);
+        response_header.set_response_code(response_code);
+
+        send_response(
+            response_edns,
+            response.build_no_records(response_header),
+            response_handle,
+        )
+        .await
+    }
+
+    /// Whether the request is an update of a secondary zone, which is forwarded to its primaries
+    #[cfg(feature = "dnssec")]
+    fn forwards_update(&self, request: &Request) -> bool {
+        #[allow(deprecated)]
+        let is_secondary = |authority: &Arc<dyn AuthorityObject>| {
+            matches!(authority.zone_type(), ZoneType::Secondary | ZoneType::Slave)
+        };
+
+        request.op_code() == OpCode::Update
+            && self
+                .find(request.query().name())
+                .and_then(|authorities| authorities.first())
+                .is_some_and(is_secondary)
+    }
+
     /// Checks whether the `Catalog` contains DNS records for `name`
     ///
     /// Use this when you know the exact `LowerName` that was used when
@@ -335,7 +640,22 @@ impl Catalog {
         response_edns: Option<Edns>,
         response_handle: R,
     ) -> ResponseInfo {
-        let request_info = request.request_info();
+        self.lookup_with_info(
+            request.request_info(),
+            request,
+            response_edns,
+            response_handle,
+        )
+        .await
+    }
+
+    async fn lookup_with_info<R: ResponseHandler>(
+        &self,
+        request_info: RequestInfo<'_>,
+        request: &Request,
+        response_edns: Option<Edns>,
+        response_handle: R,
+    ) -> ResponseInfo {
         let authorities = self.find(request_info.query.name());
 
         let Some(authorities) = authorities else {
@@ -389,11 +709,57 @@ impl Catalog {
     }
 }
 
+/// A [`Catalog`] shared with a running server, which can be changed without restarting it
+///
+/// Each request is handled by the catalog current when it was received. Changes are applied to a
+///  copy of the catalog, which then replaces it atomically, so requests in progress, e.g. zone
+///  transfers, do not delay them and do not observe partial changes.
+#[derive(Clone, Default)]
+pub struct SharedCatalog {
+    current: Arc<RwLock<Arc<Catalog>>>,
+}
+
+impl SharedCatalog {
+    /// Shares the catalog
+    pub fn new(catalog: Catalog) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(catalog))),
+        }
+    }
+
+    /// Returns the current catalog
+    pub fn load(&self) -> Arc<Catalog> {
+        self.current.read().expect("lock poisoned").clone()
+    }
+
+    /// Changes the catalog, e.g. with [`Catalog::upsert`] and [`Catalog::remove`]
+    ///
+    /// The changes are visible to the requests received after `update` returns.
+    pub fn update<T>(&self, update: impl FnOnce(&mut Catalog) -> T) -> T {
+        let mut current = self.current.write().expect("lock poisoned");
+        let mut catalog = Catalog::clone(&current);
+        let result = update(&mut catalog);
+        *current = Arc::new(catalog);
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl RequestHandler for SharedCatalog {
+    async fn handle_request<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: R,
+    ) -> ResponseInfo {
+        self.load().handle_request(request, response_handle).await
+    }
+}
+
 async fn lookup<'a, R: ResponseHandler + Unpin>(
     request_info: RequestInfo<'_>,
     authorities: &[Arc<dyn AuthorityObject>],
     request: &Request,
-    response_edns: Option<Edns>,
+    mut response_edns: Option<Edns>,
     response_handle: R,
 ) -> Result<ResponseInfo, LookupError> {
     let edns = request.edns();
@@ -463,6 +829,21 @@ async fn lookup<'a, R: ResponseHandler + Unpin>(
         )
         .await;
 
+        // echo the client subnet with the scope the answers are valid for, RFC 7871 section 7.2.1
+        if let (Some(resp_edns), Some(client_subnet), Some(scope)) = (
+            &mut response_edns,
+            request_info.client_subnet,
+            sections.answers.client_subnet_scope(),
+        ) {
+            resp_edns
+                .options_mut()
+                .insert(EdnsOption::Subnet(ClientSubnet::new(
+                    client_subnet.addr(),
+                    client_subnet.source_prefix(),
+                    scope,
+                )));
+        }
+
         let message_response = MessageResponseBuilder::new(Some(request.raw_query())).build(
             response_header,
             sections.answers.iter(),
@@ -573,10 +954,12 @@ async fn build_authoritative_response(
             response_header.set_authoritative(true);
             Some(records)
         }
-        // This request was refused
-        // TODO: there are probably other error cases that should just drop through (FormErr, ServFail)
-        Err(LookupError::ResponseCode(ResponseCode::Refused)) => {
-            response_header.set_response_code(ResponseCode::Refused);
+        // This request was refused, or the zone is unable to answer (e.g. an expired secondary)
+        // TODO: there are probably other error cases that should just drop through (FormErr)
+        Err(LookupError::ResponseCode(
+            response_code @ (ResponseCode::Refused | ResponseCode::ServFail),
+        )) => {
+            response_header.set_response_code(response_code);
             return LookupSections {
                 answers: Box::<AuthLookup>::default(),
                 ns: Box::<AuthLookup>::default(),
@@ -584,6 +967,18 @@ async fn build_authoritative_response(
                 additionals: Box::<AuthLookup>::default(),
             };
         }
+        // The name is delegated to another zone: the referral is not an authoritative answer, the
+        // NS records go into the authority section, and their glue into the additional section
+        Err(LookupError::Referral { name_servers, glue }) => {
+            response_header.set_response_code(ResponseCode::NoError);
+            response_header.set_authoritative(false);
+            return LookupSections {
+                answers: Box::<AuthLookup>::default(),
+                ns: Box::new(name_servers),
+                soa: Box::<AuthLookup>::default(),
+                additionals: Box::new(glue),
+            };
+        }
         Err(e) => {
             if e.is_nx_domain() {
                 response_header.set_response_code(ResponseCode::NXDomain);
@@ -0,0 +1,409 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Server side of DNS Cookies
+//!
+//! [RFC 7873](https://www.rfc-editor.org/rfc/rfc7873), Domain Name System (DNS) Cookies, May 2016
+//! [RFC 9018](https://www.rfc-editor.org/rfc/rfc9018), Interoperable Domain Name System (DNS) Server Cookies, April 2021
+
+use std::{
+    fmt,
+    hash::Hasher,
+    net::IpAddr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lru_cache::LruCache;
+use siphasher::sip::SipHasher24;
+use tracing::debug;
+
+use crate::proto::{
+    op::Edns,
+    rr::rdata::opt::{Cookie, EdnsCode, EdnsOption},
+    xfer::Protocol,
+};
+
+/// Version of the server cookie format, RFC 9018 section 4.1
+const VERSION: u8 = 1;
+
+/// Length of the server cookie, RFC 9018 section 4
+const SERVER_COOKIE_LEN: usize = 16;
+
+/// Server cookies older than this are not accepted, RFC 9018 section 4.3
+const MAX_AGE: i64 = 3600;
+
+/// Server cookies younger than this are echoed instead of generating a new one, RFC 9018
+///  section 4.3
+const REUSE_AGE: i64 = 1800;
+
+/// Server cookies from further in the future than this are not accepted, RFC 9018 section 4.3
+const MAX_CLOCK_SKEW: i64 = 300;
+
+/// Number of sources tracked, beyond which the least recently seen source is dropped
+const MAX_TRACKED_SOURCES: usize = 65_536;
+
+/// Generates and validates the server cookies of DNS Cookies
+///
+/// Server cookies are generated as described in RFC 9018, so servers sharing the secret, e.g. in
+///  an anycast deployment, accept each other's cookies.
+///
+/// Optionally, UDP clients sending more than a threshold of requests per second are required to
+///  present a valid server cookie. Clients which send a client cookie are answered with BADCOOKIE
+///  and a new server cookie, clients without any cookie receive a truncated response, forcing the
+///  request to be retried over TCP. Spoofed sources can not learn the server cookie, which limits
+///  the responses that can be reflected towards them.
+pub struct ServerCookies {
+    secret: [u8; 16],
+    require_threshold: Option<u32>,
+    request_counts: Mutex<LruCache<IpAddr, (u64, u32)>>,
+}
+
+impl ServerCookies {
+    /// Creates server cookies with the secret, which should be shared by all servers of an anycast
+    ///  deployment
+    pub fn new(secret: [u8; 16]) -> Self {
+        Self {
+            secret,
+            require_threshold: None,
+            request_counts: Mutex::new(LruCache::new(MAX_TRACKED_SOURCES)),
+        }
+    }
+
+    /// Creates server cookies with a random secret
+    pub fn with_random_secret() -> Self {
+        Self::new(rand::random())
+    }
+
+    /// Requires a valid server cookie from UDP clients sending more than `threshold` requests per
+    ///  second, `0` requires it for all UDP requests.
+    pub fn set_require_threshold(&mut self, threshold: Option<u32>) {
+        self.require_threshold = threshold;
+    }
+
+    /// Checks the cookie of a request, returning the cookie option for the response
+    ///
+    /// ```text
+    /// 5.2.3.  Only a Client Cookie
+    ///
+    ///    Based on server policy, including rate limiting, the server chooses
+    ///    one of the following:
+    ///
+    ///    (1)  Silently discard the request.
+    ///
+    ///    (2)  Send a BADCOOKIE error response.
+    ///
+    ///    (3)  Process the request and provide a normal response.  The RCODE
+    ///         is NOERROR, unless some non-cookie error occurs in processing
+    ///         the request.
+    ///
+    ///    If the server responds, choosing (2) or (3) above, it SHALL generate
+    ///    its own COOKIE option containing both the Client Cookie copied from
+    ///    the request and a Server Cookie it has generated, and it will add
+    ///    this COOKIE option to the response's OPT record.
+    /// ```
+    ///
+    /// A request with an invalid server cookie is handled as if it only had a client cookie,
+    ///  section 5.2.4. A valid server cookie is echoed until it is half an hour old, RFC 9018
+    ///  section 4.3.
+    pub(crate) fn check(
+        &self,
+        src: IpAddr,
+        protocol: Protocol,
+        edns: Option<&Edns>,
+    ) -> Result<Option<EdnsOption>, CookieError> {
+        let now = now();
+        let required = protocol == Protocol::Udp && self.over_threshold(src, now);
+
+        let Some(EdnsOption::Cookie(cookie)) = edns.and_then(|edns| edns.option(EdnsCode::Cookie))
+        else {
+            if required {
+                debug!("requiring cookie from {src}, no cookie in request");
+                return Err(CookieError::Missing);
+            }
+            return Ok(None);
+        };
+
+        let age = self.valid_age(cookie, src, now);
+        let valid = age.is_some();
+        if age.is_some_and(|age| age <= REUSE_AGE) {
+            return Ok(Some(EdnsOption::Cookie(cookie.clone())));
+        }
+
+        let response = Cookie::new(
+            *cookie.client(),
+            Some(
+                self.server_cookie(cookie.client(), src, now as u32)
+                    .to_vec(),
+            ),
+        )
+        .map(EdnsOption::Cookie)
+        .expect("server cookie has a valid length");
+
+        if required && !valid {
+            debug!("requiring cookie from {src}, no valid server cookie in request");
+            return Err(CookieError::BadCookie(response));
+        }
+
+        Ok(Some(response))
+    }
+
+    /// Counts the request from the source, returning true if its rate is above the threshold
+    fn over_threshold(&self, src: IpAddr, now: u64) -> bool {
+        let Some(threshold) = self.require_threshold else {
+            return false;
+        };
+
+        let mut request_counts = self.request_counts.lock().expect("lock poisoned");
+        if !request_counts.contains_key(&src) {
+            // evicts the least recently seen source once the table is full
+            request_counts.insert(src, (now, 0));
+        }
+
+        let (second, count) = request_counts.get_mut(&src).expect("source was inserted");
+        if *second != now {
+            *second = now;
+            *count = 0;
+        }
+
+        *count = count.saturating_add(1);
+        *count > threshold
+    }
+
+    /// Verifies the server cookie was generated by this server for the client, and is recent,
+    ///  returning its age in seconds
+    ///
+    /// ```text
+    /// 4.3.  The Hash Field ...
+    ///
+    ///    The Server Cookie is not considered valid if the Timestamp
+    ///    is more than 1 hour in the past or more than 5 minutes in the future.
+    /// ```
+    fn valid_age(&self, cookie: &Cookie, src: IpAddr, now: u64) -> Option<i64> {
+        let server = cookie.server()?;
+        if server.len() != SERVER_COOKIE_LEN || server[0] != VERSION {
+            return None;
+        }
+
+        let timestamp = u32::from_be_bytes([server[4], server[5], server[6], server[7]]);
+        // timestamps use serial number arithmetic, RFC 9018 section 4.3
+        let age = i64::from((now as u32).wrapping_sub(timestamp) as i32);
+        if !(-MAX_CLOCK_SKEW..=MAX_AGE).contains(&age) {
+            return None;
+        }
+
+        (server == self.server_cookie(cookie.client(), src, timestamp)).then_some(age)
+    }
+
+    /// Generates the server cookie for the client
+    ///
+    /// ```text
+    /// 4.  Server Cookie Construction
+    ///
+    ///    The Server Cookie is calculated as follows:
+    ///
+    ///     Server Cookie = Version | Reserved | Timestamp | Hash
+    ///
+    ///     Hash = SipHash-2-4(
+    ///         Client Cookie | Version | Reserved | Timestamp | Client-IP,
+    ///         Server Secret )
+    /// ```
+    fn server_cookie(
+        &self,
+        client: &[u8; Cookie::CLIENT_COOKIE_LEN],
+        src: IpAddr,
+        timestamp: u32,
+    ) -> [u8; SERVER_COOKIE_LEN] {
+        let mut cookie = [0; SERVER_COOKIE_LEN];
+        cookie[0] = VERSION;
+        cookie[4..8].copy_from_slice(&timestamp.to_be_bytes());
+
+        let mut hasher = SipHasher24::new_with_key(&self.secret);
+        hasher.write(client);
+        hasher.write(&cookie[..8]);
+        match src {
+            IpAddr::V4(ip) => hasher.write(&ip.octets()),
+            IpAddr::V6(ip) => hasher.write(&ip.octets()),
+        }
+
+        cookie[8..].copy_from_slice(&hasher.finish().to_le_bytes());
+        cookie
+    }
+}
+
+impl fmt::Debug for ServerCookies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerCookies")
+            .field("require_threshold", &self.require_threshold)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A valid server cookie is required from the client, but is not present
+#[derive(Debug)]
+pub(crate) enum CookieError {
+    /// The request has a client cookie, the response is BADCOOKIE with this cookie option
+    BadCookie(EdnsOption),
+    /// The request has no cookie, the response is truncated to force a retry over TCP
+    Missing,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn edns(cookie: Cookie) -> Edns {
+        let mut edns = Edns::new();
+        edns.options_mut().insert(EdnsOption::Cookie(cookie));
+        edns
+    }
+
+    fn server_cookie(option: EdnsOption) -> Cookie {
+        match option {
+            EdnsOption::Cookie(cookie) => cookie,
+            _ => panic!("not a cookie: {option:?}"),
+        }
+    }
+
+    /// RFC 9018, Appendix A.1
+    #[test]
+    fn test_rfc9018_server_cookie() {
+        let cookies = ServerCookies::new([
+            0xe5, 0xe9, 0x73, 0xe5, 0xa6, 0xb2, 0xa4, 0x3f, 0x48, 0xe7, 0xdc, 0x84, 0x9e, 0x37,
+            0xbf, 0xcf,
+        ]);
+
+        let cookie = cookies.server_cookie(
+            &[0x24, 0x64, 0xc4, 0xab, 0xcf, 0x10, 0xc9, 0x57],
+            IpAddr::V4(Ipv4Addr::new(198, 51, 100, 100)),
+            1_559_731_985,
+        );
+
+        assert_eq!(
+            cookie,
+            [
+                0x01, 0x00, 0x00, 0x00, 0x5c, 0xf7, 0x9f, 0x11, 0x1f, 0x81, 0x30, 0xc3, 0xee, 0xe2,
+                0x94, 0x80
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_cookie_roundtrip() {
+        let cookies = ServerCookies::with_random_secret();
+        let src = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let client = Cookie::new([1; 8], None).unwrap();
+
+        let response = cookies
+            .check(src, Protocol::Udp, Some(&edns(client.clone())))
+            .unwrap()
+            .unwrap();
+        let cookie = server_cookie(response);
+        assert_eq!(cookie.client(), client.client());
+        assert!(cookies.valid_age(&cookie, src, now()).is_some());
+
+        // the server cookie is bound to the client address
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        assert!(cookies.valid_age(&cookie, other, now()).is_none());
+
+        // and expires after an hour
+        assert!(cookies.valid_age(&cookie, src, now() + 3601).is_none());
+    }
+
+    #[test]
+    fn test_reuse_server_cookie() {
+        let cookies = ServerCookies::with_random_secret();
+        let src = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let client = [1; 8];
+        let cookie = |age: u64| {
+            let server = cookies.server_cookie(&client, src, (now() - age) as u32);
+            Cookie::new(client, Some(server.to_vec())).unwrap()
+        };
+
+        // a recent server cookie is echoed
+        let recent = cookie(60);
+        let response = cookies
+            .check(src, Protocol::Udp, Some(&edns(recent.clone())))
+            .unwrap()
+            .unwrap();
+        assert_eq!(server_cookie(response), recent);
+
+        // an older one is replaced
+        let old = cookie(2000);
+        let response = cookies
+            .check(src, Protocol::Udp, Some(&edns(old.clone())))
+            .unwrap()
+            .unwrap();
+        let response = server_cookie(response);
+        assert_ne!(response, old);
+        assert!(cookies.valid_age(&response, src, now()).unwrap() <= 1);
+    }
+
+    #[test]
+    fn test_require_cookie() {
+        let mut cookies = ServerCookies::with_random_secret();
+        cookies.set_require_threshold(Some(0));
+        let src = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let client = Cookie::new([1; 8], None).unwrap();
+
+        assert!(matches!(
+            cookies.check(src, Protocol::Udp, None),
+            Err(CookieError::Missing)
+        ));
+        assert!(cookies.check(src, Protocol::Tcp, None).unwrap().is_none());
+
+        let Err(CookieError::BadCookie(response)) =
+            cookies.check(src, Protocol::Udp, Some(&edns(client)))
+        else {
+            panic!("expected BADCOOKIE");
+        };
+
+        // the server cookie from the BADCOOKIE response is accepted
+        let cookie = server_cookie(response);
+        assert!(cookies
+            .check(src, Protocol::Udp, Some(&edns(cookie)))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_require_threshold() {
+        let mut cookies = ServerCookies::with_random_secret();
+        cookies.set_require_threshold(Some(2));
+        let src = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+        assert!(!cookies.over_threshold(src, 100));
+        assert!(!cookies.over_threshold(src, 100));
+        assert!(cookies.over_threshold(src, 100));
+
+        // the count starts over every second
+        assert!(!cookies.over_threshold(src, 101));
+    }
+
+    #[test]
+    fn test_tracked_sources_are_bounded() {
+        let mut cookies = ServerCookies::with_random_secret();
+        cookies.set_require_threshold(Some(1));
+
+        for i in 0..=MAX_TRACKED_SOURCES as u32 {
+            cookies.over_threshold(IpAddr::V4(Ipv4Addr::from(i)), 100);
+        }
+
+        let mut request_counts = cookies.request_counts.lock().unwrap();
+        assert_eq!(request_counts.len(), MAX_TRACKED_SOURCES);
+        // the first source was dropped for the last one
+        assert!(!request_counts.contains_key(&IpAddr::V4(Ipv4Addr::from(0))));
+    }
+}
@@ -10,6 +10,7 @@ use std::{io, sync::Arc};
 use enum_as_inner::EnumAsInner;
 use thiserror::Error;
 
+use crate::authority::LookupRecords;
 use crate::proto::op::ResponseCode;
 use crate::proto::rr::{rdata::SOA, Record};
 use crate::proto::{ProtoError, ProtoErrorKind};
@@ -44,6 +45,15 @@ pub enum LookupError {
     /// An underlying IO error occurred
     #[error("io error: {0}")]
     Io(io::Error),
+    /// The name is at or below a zone cut, and is answered with a referral to the name servers of
+    ///  the delegated zone
+    #[error("referral to the name servers of a delegated zone")]
+    Referral {
+        /// The NS records at the zone cut, with the DS records or the proof of their absence
+        name_servers: LookupRecords,
+        /// The address records of the name servers, if they are in the zone
+        glue: LookupRecords,
+    },
 }
 
 impl LookupError {
@@ -78,6 +88,7 @@ impl LookupError {
     /// Returns the SOA record, if the error contains one
     pub fn into_soa(self) -> Option<Box<Record<SOA>>> {
         match self {
+            Self::ProtoError(e) => e.into_soa(),
             #[cfg(feature = "resolver")]
             Self::ResolveError(e) => e.into_soa(),
             #[cfg(feature = "recursor")]
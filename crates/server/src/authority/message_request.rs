@@ -12,7 +12,7 @@ use crate::proto::{
         message::{self, EmitAndCount},
         Edns, Header, LowerQuery, Message, MessageType, OpCode, ResponseCode,
     },
-    rr::Record,
+    rr::{Record, RecordType},
     serialize::binary::{BinDecodable, BinDecoder, BinEncodable, BinEncoder},
     ProtoError, ProtoErrorKind,
 };
@@ -27,6 +27,7 @@ pub struct MessageRequest {
     additionals: Vec<Record>,
     sig0: Vec<Record>,
     edns: Option<Edns>,
+    raw_message: Option<Vec<u8>>,
 }
 
 impl MessageRequest {
@@ -180,12 +181,22 @@ impl MessageRequest {
     pub(crate) fn raw_query(&self) -> &WireQuery {
         &self.query
     }
+
+    /// The message as received, if it is an update or is signed with TSIG
+    ///
+    /// The MAC of a TSIG covers the message exactly as it was sent, so it is kept to verify the
+    ///  signature rather than re-encoding the request as is done for SIG(0). Updates are kept so
+    ///  that a secondary can forward them to the primary unchanged.
+    pub(crate) fn raw_message(&self) -> Option<&[u8]> {
+        self.raw_message.as_deref()
+    }
 }
 
 impl<'q> BinDecodable<'q> for MessageRequest {
     // TODO: generify this with Message?
     /// Reads a MessageRequest from the decoder
     fn read(decoder: &mut BinDecoder<'q>) -> Result<Self, ProtoError> {
+        let start = decoder.index();
         let mut header = Header::read(decoder)?;
 
         let mut try_parse_rest = move || {
@@ -207,6 +218,15 @@ impl<'q> BinDecodable<'q> for MessageRequest {
                 header.merge_response_code(high_response_code);
             }
 
+            let is_tsig = sig0
+                .last()
+                .is_some_and(|record| record.record_type() == RecordType::TSIG);
+            let raw_message = if is_tsig || header.op_code() == OpCode::Update {
+                Some(decoder.slice_from(start)?.to_vec())
+            } else {
+                None
+            };
+
             Ok(Self {
                 header,
                 query,
@@ -215,6 +235,7 @@ impl<'q> BinDecodable<'q> for MessageRequest {
                 additionals,
                 sig0,
                 edns,
+                raw_message,
             })
         };
 
@@ -309,6 +330,11 @@ pub(crate) struct WireQuery {
 }
 
 impl WireQuery {
+    /// The query, as decoded
+    pub(crate) fn query(&self) -> &LowerQuery {
+        &self.query
+    }
+
     pub(crate) fn as_emit_and_count(&self) -> QueriesEmitAndCount<'_> {
         QueriesEmitAndCount {
             length: 1,
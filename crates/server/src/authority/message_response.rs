@@ -5,6 +5,8 @@
 // https://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+use std::{iter, vec};
+
 use crate::{
     authority::{
         message_request::{MessageRequest, QueriesEmitAndCount},
@@ -13,7 +15,7 @@ use crate::{
     proto::{
         op::{
             message::{self, EmitAndCount},
-            Edns, Header, ResponseCode,
+            Edns, Header, LowerQuery, ResponseCode,
         },
         rr::Record,
         serialize::binary::BinEncoder,
@@ -23,6 +25,8 @@ use crate::{
 };
 
 use super::message_request::WireQuery;
+#[cfg(feature = "dnssec")]
+use super::tsig::ResponseTsig;
 
 /// A EncodableMessage with borrowed data for Responses in the Server
 #[derive(Debug)]
@@ -41,6 +45,8 @@ where
     additionals: Additionals,
     sig0: Vec<Record>,
     edns: Option<Edns>,
+    #[cfg(feature = "dnssec")]
+    tsig: Option<ResponseTsig>,
 }
 
 enum EmptyOrQueries<'q> {
@@ -73,7 +79,7 @@ impl EmitAndCount for EmptyOrQueries<'_> {
     }
 }
 
-impl<'a, A, N, S, D> MessageResponse<'_, 'a, A, N, S, D>
+impl<'q, 'a, A, N, S, D> MessageResponse<'q, 'a, A, N, S, D>
 where
     A: Iterator<Item = &'a Record> + Send + 'a,
     N: Iterator<Item = &'a Record> + Send + 'a,
@@ -101,25 +107,171 @@ where
         &self.edns
     }
 
+    /// Sets the TSIG to add to the Response, signing it when the request was authenticated
+    #[cfg(feature = "dnssec")]
+    pub(crate) fn set_tsig(&mut self, tsig: ResponseTsig) -> &mut Self {
+        self.tsig = Some(tsig);
+        self
+    }
+
+    /// Returns the query of the request this responds to, if any
+    pub(crate) fn query(&self) -> Option<&LowerQuery> {
+        self.query.map(WireQuery::query)
+    }
+
+    /// Collects the answers and the name servers, so that they can be inspected before the
+    ///  response is emitted
+    pub(crate) fn collect_records(
+        self,
+    ) -> MessageResponse<
+        'q,
+        'a,
+        vec::IntoIter<&'a Record>,
+        vec::IntoIter<&'a Record>,
+        iter::Empty<&'a Record>,
+        D,
+    > {
+        MessageResponse {
+            header: self.header,
+            query: self.query,
+            answers: self.answers.collect::<Vec<_>>().into_iter(),
+            // soa records are part of the nameserver section
+            name_servers: self
+                .name_servers
+                .chain(self.soa)
+                .collect::<Vec<_>>()
+                .into_iter(),
+            soa: iter::empty(),
+            additionals: self.additionals,
+            sig0: self.sig0,
+            edns: self.edns,
+            #[cfg(feature = "dnssec")]
+            tsig: self.tsig,
+        }
+    }
+
+    /// Drops all records and sets the truncated flag, so that the client retries over TCP
+    pub(crate) fn truncate(
+        self,
+    ) -> MessageResponse<
+        'q,
+        'a,
+        iter::Empty<&'a Record>,
+        iter::Empty<&'a Record>,
+        iter::Empty<&'a Record>,
+        iter::Empty<&'a Record>,
+    > {
+        let mut header = self.header;
+        header.set_truncated(true);
+
+        MessageResponse {
+            header,
+            query: self.query,
+            answers: iter::empty(),
+            name_servers: iter::empty(),
+            soa: iter::empty(),
+            additionals: iter::empty(),
+            sig0: Vec::new(),
+            edns: self.edns,
+            #[cfg(feature = "dnssec")]
+            tsig: self.tsig,
+        }
+    }
+
     /// Consumes self, and emits to the encoder.
     pub fn destructive_emit(
+        self,
+        encoder: &mut BinEncoder<'_>,
+    ) -> Result<ResponseInfo, ProtoError> {
+        self.emit_inspecting(encoder, |_| (), |_| ())
+    }
+
+    /// Consumes self, and emits to the encoder, also returning the freshness lifetime of the
+    ///  response for HTTP caches
+    ///
+    /// This is the `max-age` computed by [`max_age`](crate::proto::http::response::max_age), from
+    ///  the records as they are emitted rather than from a decoded message.
+    #[cfg(any(feature = "dns-over-https-rustls", feature = "dns-over-h3"))]
+    pub(crate) fn destructive_emit_with_max_age(
+        self,
+        encoder: &mut BinEncoder<'_>,
+    ) -> Result<(ResponseInfo, u32), ProtoError> {
+        let mut min_ttl = None;
+        let mut soa_ttl = None;
+        let info = self.emit_inspecting(
+            encoder,
+            |answer| {
+                min_ttl = Some(min_ttl.map_or(answer.ttl(), |ttl: u32| ttl.min(answer.ttl())));
+            },
+            |name_server| {
+                if soa_ttl.is_none() {
+                    soa_ttl = name_server
+                        .data()
+                        .as_soa()
+                        .map(|soa| name_server.ttl().min(soa.minimum()));
+                }
+            },
+        )?;
+
+        let max_age = match info.response_code() {
+            ResponseCode::NoError | ResponseCode::NXDomain => min_ttl.or(soa_ttl).unwrap_or(0),
+            _ => 0,
+        };
+        Ok((info, max_age))
+    }
+
+    /// Emits to the encoder, passing the answers and the name servers to the closures as they are
+    ///  emitted
+    fn emit_inspecting(
         mut self,
         encoder: &mut BinEncoder<'_>,
+        mut answer: impl FnMut(&Record),
+        mut name_server: impl FnMut(&Record),
     ) -> Result<ResponseInfo, ProtoError> {
+        #[cfg(feature = "dnssec")]
+        let start = encoder.offset();
+
+        let mut answers = self.answers.inspect(|record| answer(record));
         // soa records are part of the nameserver section
-        let mut name_servers = self.name_servers.chain(self.soa);
+        let mut name_servers = self
+            .name_servers
+            .chain(self.soa)
+            .inspect(|record| name_server(record));
 
-        message::emit_message_parts(
+        let header = message::emit_message_parts(
             &self.header,
             &mut EmptyOrQueries::from(self.query),
-            &mut self.answers,
+            &mut answers,
             &mut name_servers,
             &mut self.additionals,
             self.edns.as_ref(),
             &self.sig0,
             encoder,
-        )
-        .map(Into::into)
+        )?;
+
+        // the TSIG is computed over the rest of the message, so it is always emitted last
+        #[cfg(feature = "dnssec")]
+        if let Some(tsig) = &self.tsig {
+            return tsig.emit(header, start, encoder).map(Into::into);
+        }
+
+        Ok(header.into())
+    }
+}
+
+impl<'a, S, D> MessageResponse<'_, 'a, vec::IntoIter<&'a Record>, vec::IntoIter<&'a Record>, S, D>
+where
+    S: Iterator<Item = &'a Record> + Send + 'a,
+    D: Iterator<Item = &'a Record> + Send + 'a,
+{
+    /// Returns the answers, see [`Self::collect_records`]
+    pub(crate) fn answers(&self) -> &[&'a Record] {
+        self.answers.as_slice()
+    }
+
+    /// Returns the name servers, including the SOA, see [`Self::collect_records`]
+    pub(crate) fn name_servers(&self) -> &[&'a Record] {
+        self.name_servers.as_slice()
     }
 }
 
@@ -191,6 +343,8 @@ impl<'q> MessageResponseBuilder<'q> {
             additionals: additionals.into_iter(),
             sig0: self.sig0.unwrap_or_default(),
             edns: self.edns,
+            #[cfg(feature = "dnssec")]
+            tsig: None,
         }
     }
 
@@ -215,6 +369,8 @@ impl<'q> MessageResponseBuilder<'q> {
             additionals: Box::new(None.into_iter()),
             sig0: self.sig0.unwrap_or_default(),
             edns: self.edns,
+            #[cfg(feature = "dnssec")]
+            tsig: None,
         }
     }
 
@@ -249,6 +405,8 @@ impl<'q> MessageResponseBuilder<'q> {
             additionals: Box::new(None.into_iter()),
             sig0: self.sig0.unwrap_or_default(),
             edns: self.edns,
+            #[cfg(feature = "dnssec")]
+            tsig: None,
         }
     }
 }
@@ -289,6 +447,8 @@ mod tests {
                 additionals: iter::once(&answer),
                 sig0: vec![],
                 edns: None,
+                #[cfg(feature = "dnssec")]
+                tsig: None,
             };
 
             message
@@ -327,6 +487,8 @@ mod tests {
                 additionals: iter::repeat(&answer),
                 sig0: vec![],
                 edns: None,
+                #[cfg(feature = "dnssec")]
+                tsig: None,
             };
 
             message
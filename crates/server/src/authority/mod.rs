@@ -17,9 +17,12 @@ mod auth_lookup;
 mod authority;
 pub(crate) mod authority_object;
 mod catalog;
+mod cookie;
 mod error;
 pub(crate) mod message_request;
 mod message_response;
+#[cfg(feature = "dnssec")]
+pub(crate) mod tsig;
 mod zone_type;
 
 pub use self::auth_lookup::{
@@ -27,7 +30,8 @@ pub use self::auth_lookup::{
 };
 pub use self::authority::{Authority, LookupControlFlow, LookupOptions};
 pub use self::authority_object::{AuthorityObject, DnssecSummary, EmptyLookup, LookupObject};
-pub use self::catalog::Catalog;
+pub use self::catalog::{Catalog, SharedCatalog};
+pub use self::cookie::ServerCookies;
 pub use self::error::LookupError;
 pub use self::message_request::{MessageRequest, Queries, UpdateRequest};
 pub use self::message_response::{MessageResponse, MessageResponseBuilder};
@@ -0,0 +1,379 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! TSIG authentication of requests, and signing of the responses to them
+//!
+//! [RFC 8945](https://www.rfc-editor.org/rfc/rfc8945), Secret Key Transaction Authentication for DNS (TSIG), November 2020
+
+use std::{collections::HashMap, fmt, io};
+
+use time::OffsetDateTime;
+use tracing::{debug, warn};
+
+use crate::{
+    authority::{MessageRequest, MessageResponse},
+    proto::{
+        dnssec::{
+            rdata::{
+                tsig::{make_tsig_record, TsigAlgorithm, TSIG},
+                DNSSECRData,
+            },
+            tsig::TSigner,
+        },
+        op::{Header, ResponseCode},
+        rr::{LowerName, Name, RData, Record},
+        serialize::binary::{BinEncodable, BinEncoder},
+        ProtoError,
+    },
+    server::{ResponseHandler, ResponseInfo},
+};
+
+/// The TSIG record to add to a response, see RFC 8945 section 5.3
+#[derive(Clone)]
+pub(crate) enum ResponseTsig {
+    /// The response is signed with the key that authenticated the request
+    Signed {
+        signer: TSigner,
+        request_mac: Vec<u8>,
+        error: Option<ResponseCode>,
+    },
+    /// The request could not be authenticated, the TSIG only carries the error
+    Unsigned {
+        key_name: Name,
+        algorithm: TsigAlgorithm,
+        error: ResponseCode,
+    },
+    /// The TSIG of a response relayed from another server, e.g. the primary of a forwarded update
+    Relayed(Record),
+}
+
+impl fmt::Debug for ResponseTsig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Signed { signer, error, .. } => f
+                .debug_struct("Signed")
+                .field("key_name", signer.signer_name())
+                .field("error", error)
+                .finish(),
+            Self::Unsigned {
+                key_name, error, ..
+            } => f
+                .debug_struct("Unsigned")
+                .field("key_name", key_name)
+                .field("error", error)
+                .finish(),
+            Self::Relayed(record) => f.debug_tuple("Relayed").field(record.name()).finish(),
+        }
+    }
+}
+
+impl ResponseTsig {
+    /// Appends the TSIG record to a response, which was emitted to the encoder starting at `start`
+    ///
+    /// ```text
+    /// 5.3.  Generation on Response
+    ///
+    ///    When a server has generated a response to a signed request, it signs
+    ///    the response using the same algorithm and key.  The server MUST NOT
+    ///    generate a signed response to a request if either the KEY is invalid
+    ///    (e.g., key name or algorithm name are unknown) or the MAC fails
+    ///    validation; see Section 5.3.2 for details of responding in these
+    ///    cases.
+    /// ```
+    pub(crate) fn emit(
+        &self,
+        mut header: Header,
+        start: usize,
+        encoder: &mut BinEncoder<'_>,
+    ) -> Result<Header, ProtoError> {
+        let now = now();
+
+        let record = match self {
+            Self::Signed {
+                signer,
+                request_mac,
+                error,
+            } => {
+                // the server's time is reported to clients outside of the time window, 5.2.3
+                let other = match error {
+                    Some(ResponseCode::BADTIME) => {
+                        let mut other = ((now >> 32) as u16).to_be_bytes().to_vec();
+                        other.extend((now as u32).to_be_bytes());
+                        other
+                    }
+                    _ => Vec::new(),
+                };
+
+                let pre_tsig = TSIG::new(
+                    signer.algorithm().clone(),
+                    now,
+                    signer.fudge(),
+                    Vec::new(),
+                    header.id(),
+                    error.map_or(0, u16::from),
+                    other,
+                );
+
+                // the MAC covers the MAC of the request, the response and the TSIG variables, 4.3.1
+                let mut tbs = Vec::with_capacity(encoder.offset() - start + 128);
+                {
+                    let mut tbs_encoder = BinEncoder::new(&mut tbs);
+                    tbs_encoder.emit_u16(request_mac.len() as u16)?;
+                    tbs_encoder.emit_vec(request_mac)?;
+                    tbs_encoder.emit_vec(encoder.slice_of(start, encoder.offset()))?;
+                    pre_tsig.emit_tsig_for_mac(&mut tbs_encoder, signer.signer_name())?;
+                }
+
+                let mac = signer.sign(&tbs)?;
+                make_tsig_record(signer.signer_name().clone(), pre_tsig.set_mac(mac))
+            }
+            Self::Unsigned {
+                key_name,
+                algorithm,
+                error,
+            } => make_tsig_record(
+                key_name.clone(),
+                TSIG::new(
+                    algorithm.clone(),
+                    now,
+                    0,
+                    Vec::new(),
+                    header.id(),
+                    u16::from(*error),
+                    Vec::new(),
+                ),
+            ),
+            Self::Relayed(record) => record.clone(),
+        };
+
+        encoder.with_canonical_names(|encoder| record.emit(encoder))?;
+
+        // the TSIG is not included in the additional count covered by the MAC
+        header.set_additional_count(header.additional_count() + 1);
+        let end = encoder.offset();
+        encoder.set_offset(start);
+        header.emit(encoder)?;
+        encoder.set_offset(end);
+
+        Ok(header)
+    }
+}
+
+/// Authenticates a request with the TSIG it is signed with, see RFC 8945 section 5.2
+///
+/// Returns `Ok(None)` if the request is not signed with TSIG, or the name of the key along with
+///  the TSIG to sign the response. If the request fails authentication, the error is the TSIG
+///  for the error response.
+pub(crate) fn authenticate<'r>(
+    keys: &HashMap<LowerName, TSigner>,
+    request: &'r MessageRequest,
+) -> Result<Option<(&'r Name, ResponseTsig)>, Box<ResponseTsig>> {
+    let Some((record, tsig)) = request
+        .sig0()
+        .last()
+        .and_then(|record| match record.data() {
+            RData::DNSSEC(DNSSECRData::TSIG(tsig)) => Some((record, tsig)),
+            _ => None,
+        })
+    else {
+        return Ok(None);
+    };
+
+    let key_name = record.name();
+    let signer = match keys.get(&LowerName::from(key_name)) {
+        Some(signer) if signer.algorithm() == tsig.algorithm() => signer,
+        _ => {
+            warn!(
+                "request: {} signed with unknown TSIG key: {key_name} {}",
+                request.id(),
+                tsig.algorithm()
+            );
+            return Err(Box::new(ResponseTsig::Unsigned {
+                key_name: key_name.clone(),
+                algorithm: tsig.algorithm().clone(),
+                error: ResponseCode::BADKEY,
+            }));
+        }
+    };
+
+    match verify(signer, request) {
+        Ok(request_mac) => Ok(Some((
+            key_name,
+            ResponseTsig::Signed {
+                signer: signer.clone(),
+                request_mac,
+                error: None,
+            },
+        ))),
+        Err(ResponseCode::BADTIME) => Err(Box::new(ResponseTsig::Signed {
+            signer: signer.clone(),
+            request_mac: tsig.mac().to_vec(),
+            error: Some(ResponseCode::BADTIME),
+        })),
+        Err(error) => Err(Box::new(ResponseTsig::Unsigned {
+            key_name: key_name.clone(),
+            algorithm: tsig.algorithm().clone(),
+            error,
+        })),
+    }
+}
+
+/// Verifies the TSIG of a request with the key, returning the MAC of the request
+///
+/// The error is the TSIG error for the failure, `BADSIG` or `BADTIME`.
+pub(crate) fn verify(signer: &TSigner, request: &MessageRequest) -> Result<Vec<u8>, ResponseCode> {
+    let message = request.raw_message().ok_or(ResponseCode::BADSIG)?;
+
+    let (mac, valid_time, _) = signer
+        .verify_message_byte(None, message, true)
+        .map_err(|e| {
+            warn!(
+                "request: {} failed TSIG verification with key {}: {e}",
+                request.id(),
+                signer.signer_name()
+            );
+            ResponseCode::BADSIG
+        })?;
+
+    let now = now();
+    if !valid_time.contains(&now) {
+        warn!(
+            "request: {} TSIG time outside of {valid_time:?}, now: {now}",
+            request.id()
+        );
+        return Err(ResponseCode::BADTIME);
+    }
+
+    debug!(
+        "request: {} authenticated with TSIG key: {}",
+        request.id(),
+        signer.signer_name()
+    );
+    Ok(mac)
+}
+
+fn now() -> u64 {
+    OffsetDateTime::now_utc().unix_timestamp() as u64
+}
+
+/// Adds the TSIG to the response of a request, signing it if the request was authenticated
+#[derive(Clone)]
+pub(crate) struct TsigResponseHandler<R: ResponseHandler> {
+    handler: R,
+    tsig: Option<ResponseTsig>,
+}
+
+impl<R: ResponseHandler> TsigResponseHandler<R> {
+    pub(crate) fn new(handler: R, tsig: Option<ResponseTsig>) -> Self {
+        Self { handler, tsig }
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: ResponseHandler> ResponseHandler for TsigResponseHandler<R> {
+    async fn send_response<'a>(
+        &mut self,
+        mut response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        if let Some(tsig) = &self.tsig {
+            response.set_tsig(tsig.clone());
+        }
+
+        self.handler.send_response(response).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{
+        op::{Message, Query},
+        rr::RecordType,
+        serialize::binary::BinDecodable,
+    };
+
+    fn signer(name: &str, key: &[u8]) -> TSigner {
+        TSigner::new(
+            key.to_vec(),
+            TsigAlgorithm::HmacSha256,
+            Name::from_ascii(name).unwrap(),
+            300,
+        )
+        .unwrap()
+    }
+
+    fn keys() -> HashMap<LowerName, TSigner> {
+        let signer = signer("key.", b"some secret key");
+        HashMap::from([(LowerName::from(signer.signer_name()), signer)])
+    }
+
+    fn request(signer: Option<&TSigner>) -> MessageRequest {
+        let mut message = Message::new();
+        message.add_query(Query::query(
+            Name::from_ascii("example.com.").unwrap(),
+            RecordType::A,
+        ));
+        if let Some(signer) = signer {
+            message.finalize(signer, now() as u32).unwrap();
+        }
+
+        MessageRequest::from_bytes(&message.to_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let keys = keys();
+        let request = request(Some(&signer("key.", b"some secret key")));
+
+        let (key_name, tsig) = authenticate(&keys, &request)
+            .unwrap()
+            .expect("request is signed");
+        assert_eq!(key_name, &Name::from_ascii("key.").unwrap());
+        assert!(matches!(tsig, ResponseTsig::Signed { error: None, .. }));
+    }
+
+    #[test]
+    fn test_authenticate_unsigned() {
+        assert!(authenticate(&keys(), &request(None)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_authenticate_unknown_key() {
+        let request = request(Some(&signer("other-key.", b"some secret key")));
+
+        let error = authenticate(&keys(), &request).unwrap_err();
+        assert!(matches!(
+            *error,
+            ResponseTsig::Unsigned {
+                error: ResponseCode::BADKEY,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_authenticate_bad_mac() {
+        // the key has the name of a known key, but a different secret
+        let request = request(Some(&signer("key.", b"another secret key")));
+
+        let error = authenticate(&keys(), &request).unwrap_err();
+        assert!(matches!(
+            *error,
+            ResponseTsig::Unsigned {
+                error: ResponseCode::BADSIG,
+                ..
+            }
+        ));
+    }
+}
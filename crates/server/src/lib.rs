@@ -58,6 +58,8 @@ mod access;
 pub mod authority;
 mod error;
 pub use error::{ConfigError, ConfigErrorKind, PersistenceError, PersistenceErrorKind};
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod server;
 pub mod store;
 
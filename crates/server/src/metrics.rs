@@ -0,0 +1,104 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Metrics of the server, recorded with the [`metrics`] facade
+//!
+//! Nothing is recorded until the application installs a recorder, e.g. a Prometheus exporter.
+//!  The caches of the forwarder and recursor stores are covered by the metrics of
+//!  `hickory_resolver::metrics` and `hickory_recursor::metrics`.
+
+use std::time::Duration;
+
+use metrics::{
+    counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Unit,
+};
+
+use crate::{
+    authority::AuthorityObject,
+    proto::{
+        op::ResponseCode,
+        rr::{LowerName, RData, RecordType},
+        xfer::Protocol,
+    },
+};
+
+/// Requests handled by the catalog, labeled with the `protocol`, `query_type`, `response_code`
+///  and `zone`
+pub const REQUESTS_TOTAL: &str = "hickory_requests_total";
+
+/// Time to handle a request and send the response, labeled with the `protocol`
+pub const RESPONSE_DURATION_SECONDS: &str = "hickory_response_duration_seconds";
+
+/// Queries matching a blocklist
+pub const BLOCKLIST_HITS_TOTAL: &str = "hickory_blocklist_hits_total";
+
+/// Serial of the SOA of each zone, labeled with the `zone`
+pub const ZONE_SERIAL: &str = "hickory_zone_serial";
+
+/// Describes the metrics of the server, and of the resolver and recursor it uses, to the
+///  installed recorder
+pub fn describe() {
+    describe_counter!(REQUESTS_TOTAL, "Number of requests handled by the catalog");
+    describe_histogram!(
+        RESPONSE_DURATION_SECONDS,
+        Unit::Seconds,
+        "Time to handle a request and send the response"
+    );
+    describe_counter!(
+        BLOCKLIST_HITS_TOTAL,
+        "Number of queries matching a blocklist"
+    );
+    describe_gauge!(ZONE_SERIAL, "Serial of the SOA of the zone");
+
+    #[cfg(feature = "resolver")]
+    crate::resolver::metrics::describe();
+    #[cfg(feature = "recursor")]
+    crate::recursor::metrics::describe();
+}
+
+pub(crate) fn record_request(
+    protocol: Protocol,
+    query_type: RecordType,
+    response_code: ResponseCode,
+    zone: Option<&LowerName>,
+    duration: Duration,
+) {
+    let protocol = protocol.to_string();
+    counter!(
+        REQUESTS_TOTAL,
+        "protocol" => protocol.clone(),
+        "query_type" => query_type.to_string(),
+        "response_code" => response_code.to_str(),
+        "zone" => zone.map(ToString::to_string).unwrap_or_default(),
+    )
+    .increment(1);
+    histogram!(RESPONSE_DURATION_SECONDS, "protocol" => protocol).record(duration);
+}
+
+#[cfg(feature = "blocklist")]
+pub(crate) fn record_blocklist_hit() {
+    counter!(BLOCKLIST_HITS_TOTAL).increment(1);
+}
+
+pub(crate) fn record_zone_serial(zone: &LowerName, serial: u32) {
+    gauge!(ZONE_SERIAL, "zone" => zone.to_string()).set(serial);
+}
+
+/// Sets the serial of the zone from the SOA of the authority serving it
+pub(crate) async fn record_authority_serial(authority: &dyn AuthorityObject) {
+    let Some(Ok(soa)) = authority.soa().await.map_result() else {
+        return;
+    };
+
+    let serial = soa.iter().find_map(|record| match record.data() {
+        RData::SOA(soa) => Some(soa.serial()),
+        _ => None,
+    });
+    if let Some(serial) = serial {
+        record_zone_serial(authority.origin(), serial);
+    }
+}
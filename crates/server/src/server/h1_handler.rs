@@ -0,0 +1,213 @@
+// Copyright 2015-2021 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{convert::Infallible, io, net::SocketAddr, sync::Arc};
+
+use bytes::Bytes;
+use futures_util::lock::Mutex;
+use hickory_proto::{
+    http::{request, Version},
+    rr::Record,
+};
+use http::{Method, Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::{body::Incoming, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::oneshot,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+use crate::{
+    access::AccessControlHandle,
+    authority::MessageResponse,
+    proto::xfer::Protocol,
+    server::{
+        request_handler::RequestHandler, response_handler::ResponseHandler, server_future,
+        ResponseInfo,
+    },
+};
+
+/// The largest DNS message that will be accepted in a POST body
+const MAX_MESSAGE_LEN: usize = u16::MAX as usize;
+
+pub(crate) async fn h1_handler<T, I>(
+    access: AccessControlHandle,
+    handler: Arc<T>,
+    io: I,
+    src_addr: SocketAddr,
+    dns_hostname: Option<Arc<str>>,
+    http_endpoint: Arc<str>,
+    shutdown: CancellationToken,
+) where
+    T: RequestHandler,
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = service_fn(move |request| {
+        let access = access.clone();
+        let handler = handler.clone();
+        let dns_hostname = dns_hostname.clone();
+        let http_endpoint = http_endpoint.clone();
+
+        async move {
+            Ok::<_, Infallible>(
+                respond(
+                    request,
+                    src_addr,
+                    access,
+                    handler,
+                    dns_hostname,
+                    http_endpoint,
+                )
+                .await,
+            )
+        }
+    });
+
+    // requests on a connection are handled one at a time, HTTP/1.1 has no multiplexing
+    let connection = http1::Builder::new().serve_connection(TokioIo::new(io), service);
+    tokio::pin!(connection);
+
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = shutdown.cancelled() => {
+            // A graceful shutdown was initiated, finish the in-flight request
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        },
+    };
+
+    if let Err(err) = result {
+        debug!("http/1.1 connection error from {src_addr}: {err}");
+    }
+}
+
+async fn respond<T>(
+    request: Request<Incoming>,
+    src_addr: SocketAddr,
+    access: AccessControlHandle,
+    handler: Arc<T>,
+    dns_hostname: Option<Arc<str>>,
+    http_endpoint: Arc<str>,
+) -> Response<Full<Bytes>>
+where
+    T: RequestHandler,
+{
+    debug!("Received request: {:#?}", request);
+
+    if !access.allow(src_addr.ip()) {
+        debug!("request:Refused src:https://{src_addr}");
+        return status(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(err) = request::verify(
+        Version::Http1,
+        dns_hostname.as_deref(),
+        &http_endpoint,
+        &request,
+    ) {
+        warn!("bad request from {src_addr}: {err}");
+        return status(StatusCode::BAD_REQUEST);
+    }
+
+    let bytes = match *request.method() {
+        Method::GET => match request::message_from_query(request.uri()) {
+            Ok(bytes) => Bytes::from(bytes),
+            Err(err) => {
+                warn!("bad GET request from {src_addr}: {err}");
+                return status(StatusCode::BAD_REQUEST);
+            }
+        },
+        Method::POST => match Limited::new(request.into_body(), MAX_MESSAGE_LEN)
+            .collect()
+            .await
+        {
+            Ok(body) => body.to_bytes(),
+            Err(err) => {
+                warn!("bad POST request from {src_addr}: {err}");
+                return status(StatusCode::BAD_REQUEST);
+            }
+        },
+        _ => return status(StatusCode::METHOD_NOT_ALLOWED),
+    };
+
+    let (tx, rx) = oneshot::channel();
+    let responder = Http1ResponseHandle(Arc::new(Mutex::new(Some(tx))));
+    server_future::handle_request(
+        &bytes,
+        src_addr,
+        Protocol::Https,
+        access,
+        handler,
+        responder,
+    )
+    .await;
+
+    match rx.await {
+        Ok(response) => response,
+        // the request was dropped without a response, e.g. it could not be parsed
+        Err(_) => status(StatusCode::BAD_REQUEST),
+    }
+}
+
+fn status(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::default());
+    *response.status_mut() = status;
+    response
+}
+
+/// The sender for the single HTTP response to a request, taken when the response is sent
+type ResponseSender = Arc<Mutex<Option<oneshot::Sender<Response<Full<Bytes>>>>>>;
+
+#[derive(Clone)]
+struct Http1ResponseHandle(ResponseSender);
+
+#[async_trait::async_trait]
+impl ResponseHandler for Http1ResponseHandle {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        use crate::proto::http::response;
+        use crate::proto::serialize::binary::BinEncoder;
+
+        let mut bytes = Vec::with_capacity(512);
+        // mut block
+        let (info, max_age) = {
+            let mut encoder = BinEncoder::new(&mut bytes);
+            response.destructive_emit_with_max_age(&mut encoder)?
+        };
+        let response = response::new(Version::Http1, bytes.len(), max_age)?
+            .map(|()| Full::new(Bytes::from(bytes)));
+
+        debug!("sending response: {:#?}", response);
+        let Some(sender) = self.0.lock().await.take() else {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "response already sent",
+            ));
+        };
+
+        if sender.send(response).is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "http/1.1 connection closed",
+            ));
+        }
+
+        Ok(info)
+    }
+}
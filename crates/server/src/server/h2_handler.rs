@@ -15,8 +15,10 @@ use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, warn};
 
+#[cfg(feature = "dns-over-odoh")]
+use crate::server::odoh_handler::{is_odoh_request, odoh_request};
 use crate::{
-    access::AccessControl,
+    access::AccessControlHandle,
     authority::MessageResponse,
     proto::h2::h2_server,
     proto::xfer::Protocol,
@@ -26,21 +28,25 @@ use crate::{
     },
 };
 
+/// The settings of an HTTPS listener, shared by all its connections
+pub(crate) struct HttpsListener<T> {
+    pub(crate) access: AccessControlHandle,
+    pub(crate) handler: Arc<T>,
+    pub(crate) dns_hostname: Option<Arc<str>>,
+    pub(crate) http_endpoint: Arc<str>,
+    #[cfg(feature = "dns-over-odoh")]
+    pub(crate) odoh: Option<Arc<super::ObliviousDoh>>,
+}
+
 pub(crate) async fn h2_handler<T, I>(
-    access: Arc<AccessControl>,
-    handler: Arc<T>,
+    listener: Arc<HttpsListener<T>>,
     io: I,
     src_addr: SocketAddr,
-    dns_hostname: Option<Arc<str>>,
-    http_endpoint: Arc<str>,
     shutdown: CancellationToken,
 ) where
     T: RequestHandler,
     I: AsyncRead + AsyncWrite + Unpin,
 {
-    let dns_hostname = dns_hostname.clone();
-    let http_endpoint = http_endpoint.clone();
-
     // Start the HTTP/2.0 connection handshake
     let mut h2 = match server::handshake(io).await {
         Ok(h2) => h2,
@@ -71,10 +77,23 @@ pub(crate) async fn h2_handler<T, I>(
         };
 
         debug!("Received request: {:#?}", request);
-        let dns_hostname = dns_hostname.clone();
-        let http_endpoint = http_endpoint.clone();
-        let handler = handler.clone();
-        let access = access.clone();
+
+        #[cfg(feature = "dns-over-odoh")]
+        if let Some(odoh) = listener.odoh.as_ref().filter(|_| is_odoh_request(&request)) {
+            tokio::spawn(odoh_request(
+                listener.clone(),
+                odoh.clone(),
+                request,
+                respond,
+                src_addr,
+            ));
+            continue;
+        }
+
+        let dns_hostname = listener.dns_hostname.clone();
+        let http_endpoint = listener.http_endpoint.clone();
+        let handler = listener.handler.clone();
+        let access = listener.access.clone();
         let responder = HttpsResponseHandle(Arc::new(Mutex::new(respond)));
 
         tokio::spawn(async move {
@@ -91,7 +110,7 @@ pub(crate) async fn h2_handler<T, I>(
 async fn handle_request<T>(
     bytes: BytesMut,
     src_addr: SocketAddr,
-    access: Arc<AccessControl>,
+    access: AccessControlHandle,
     handler: Arc<T>,
     responder: HttpsResponseHandle,
 ) where
@@ -130,12 +149,12 @@ impl ResponseHandler for HttpsResponseHandle {
 
         let mut bytes = Vec::with_capacity(512);
         // mut block
-        let info = {
+        let (info, max_age) = {
             let mut encoder = BinEncoder::new(&mut bytes);
-            response.destructive_emit(&mut encoder)?
+            response.destructive_emit_with_max_age(&mut encoder)?
         };
         let bytes = Bytes::from(bytes);
-        let response = response::new(Version::Http2, bytes.len())?;
+        let response = response::new(Version::Http2, bytes.len(), max_age)?;
 
         debug!("sending response: {:#?}", response);
         let mut stream = self
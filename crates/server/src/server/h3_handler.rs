@@ -11,11 +11,12 @@ use bytes::{Buf, Bytes};
 use futures_util::lock::Mutex;
 use h3::server::RequestStream;
 use h3_quinn::BidiStream;
+use http::{Method, Response, StatusCode};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, warn};
 
 use crate::{
-    access::AccessControl,
+    access::AccessControlHandle,
     authority::MessageResponse,
     server::{
         request_handler::RequestHandler, response_handler::ResponseHandler, server_future,
@@ -24,14 +25,14 @@ use crate::{
 };
 use hickory_proto::{
     h3::{h3_server::H3Connection, H3Error},
-    http::Version,
+    http::{request::message_from_query, Version},
     rr::Record,
     xfer::Protocol,
     ProtoError,
 };
 
 pub(crate) async fn h3_handler<T>(
-    access: Arc<AccessControl>,
+    access: AccessControlHandle,
     handler: Arc<T>,
     mut connection: H3Connection,
     src_addr: SocketAddr,
@@ -46,7 +47,7 @@ where
 
     // Accept all inbound requests sent over the connection.
     loop {
-        let (_, mut stream) = tokio::select! {
+        let (request, mut stream) = tokio::select! {
             result = connection.accept() => match result {
                 Some(Ok(next_request)) => next_request,
                 Some(Err(err)) => {
@@ -63,13 +64,27 @@ where
             },
         };
 
-        let request = match stream
-            .recv_data()
-            .await
-            .map_err(|e| ProtoError::from(format!("h3 stream receive data failed: {e}")))?
-        {
-            Some(mut request) => request.copy_to_bytes(request.remaining()),
-            None => continue,
+        // GET carries the message in the query, POST in the body
+        let request = if request.method() == Method::GET {
+            match message_from_query(request.uri()) {
+                Ok(request) => Bytes::from(request),
+                Err(err) => {
+                    warn!("bad GET request from {src_addr}: {err}");
+                    if let Err(err) = bad_request(&mut stream).await {
+                        debug!("failed to reject bad GET request from {src_addr}: {err}");
+                    }
+                    continue;
+                }
+            }
+        } else {
+            match stream
+                .recv_data()
+                .await
+                .map_err(|e| ProtoError::from(format!("h3 stream receive data failed: {e}")))?
+            {
+                Some(mut request) => request.copy_to_bytes(request.remaining()),
+                None => continue,
+            }
         };
 
         debug!(
@@ -97,10 +112,19 @@ where
     Ok(())
 }
 
+/// Rejects a request that does not carry a valid DNS message
+async fn bad_request(stream: &mut RequestStream<BidiStream<Bytes>, Bytes>) -> Result<(), H3Error> {
+    let mut response = Response::new(());
+    *response.status_mut() = StatusCode::BAD_REQUEST;
+    stream.send_response(response).await?;
+    stream.finish().await?;
+    Ok(())
+}
+
 async fn handle_request<T>(
     bytes: Bytes,
     src_addr: SocketAddr,
-    access: Arc<AccessControl>,
+    access: AccessControlHandle,
     handler: Arc<T>,
     responder: H3ResponseHandle,
 ) where
@@ -130,12 +154,12 @@ impl ResponseHandler for H3ResponseHandle {
 
         let mut bytes = Vec::with_capacity(512);
         // mut block
-        let info = {
+        let (info, max_age) = {
             let mut encoder = BinEncoder::new(&mut bytes);
-            response.destructive_emit(&mut encoder)?
+            response.destructive_emit_with_max_age(&mut encoder)?
         };
         let bytes = Bytes::from(bytes);
-        let response = response::new(Version::Http3, bytes.len())?;
+        let response = response::new(Version::Http3, bytes.len(), max_age)?;
 
         debug!("sending response: {:#?}", response);
         let mut stream = self.0.lock().await;
@@ -7,18 +7,27 @@
 
 //! `Server` component for hosting a domain name servers operations.
 
+#[cfg(feature = "dns-over-https-rustls")]
+mod h1_handler;
 #[cfg(feature = "dns-over-https-rustls")]
 mod h2_handler;
 #[cfg(feature = "dns-over-h3")]
 mod h3_handler;
+#[cfg(feature = "dns-over-odoh")]
+mod odoh_handler;
 #[cfg(feature = "dns-over-quic")]
 mod quic_handler;
 mod request_handler;
 mod response_handler;
+mod rrl;
 mod server_future;
 mod timeout_stream;
 
+#[cfg(feature = "dns-over-odoh")]
+pub use self::odoh_handler::{ObliviousDoh, OdohProxy};
 pub use self::request_handler::{Request, RequestHandler, RequestInfo, ResponseInfo};
 pub use self::response_handler::{ResponseHandle, ResponseHandler};
+pub use self::rrl::ResponseRateLimitConfig;
 pub use self::server_future::ServerFuture;
 pub use self::timeout_stream::TimeoutStream;
+pub use crate::access::AccessControlHandle;
@@ -0,0 +1,456 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{collections::HashMap, io, net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::{lock::Mutex, FutureExt};
+use h2::{client::SendRequest, server::SendResponse, RecvStream};
+use hickory_proto::{
+    h2::{h2_server, HttpsError},
+    http::{request, response, Version},
+    odoh::{self, OdohKeyPair, OdohResponseKey, MIME_APPLICATION_ODOH, ODOH_CONFIGS_PATH},
+    rr::Record,
+    serialize::binary::BinEncoder,
+};
+use http::{
+    header::{CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE},
+    uri::Authority,
+    HeaderValue, Method, Request, Response, StatusCode,
+};
+use rustls::{pki_types::ServerName, ClientConfig};
+use tokio::{net::TcpStream, time::timeout};
+use tokio_rustls::TlsConnector;
+use tracing::{debug, warn};
+
+use crate::{
+    authority::MessageResponse,
+    proto::xfer::{is_global, Protocol},
+    server::{
+        h2_handler::HttpsListener, request_handler::RequestHandler,
+        response_handler::ResponseHandler, server_future, ResponseInfo,
+    },
+};
+
+/// Media type of the `ObliviousDoHConfigs` served to clients
+const MIME_APPLICATION_OCTET_STREAM: &str = "application/octet-stream";
+
+/// Time allowed to connect to a target and receive its response
+const PROXY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The largest response accepted from a target
+const MAX_TARGET_RESPONSE_LEN: usize = u16::MAX as usize;
+
+/// The Oblivious DoH roles of an HTTPS listener, RFC 9230
+///
+/// Both roles share the endpoint of DNS-over-HTTPS queries, typically `/dns-query`: queries which
+///  name a target in the `targethost` and `targetpath` parameters are forwarded to it, the others
+///  are decrypted and answered.
+#[derive(Clone, Debug, Default)]
+pub struct ObliviousDoh {
+    /// Decrypts and answers queries with this key pair, and publishes its configuration at
+    ///  `/.well-known/odohconfigs`, as a target
+    pub target: Option<OdohKeyPair>,
+    /// Forwards queries to the targets they name, as a proxy
+    pub proxy: Option<OdohProxy>,
+}
+
+/// The proxy role of an HTTPS listener
+///
+/// Queries are only forwarded to the targets of an explicit allowlist, and only from the clients
+///  allowed by the access control of the server.
+#[derive(Clone, Debug)]
+pub struct OdohProxy {
+    /// TLS configuration of the connections to targets, `h2` is negotiated if no ALPN is set
+    pub client_config: Arc<ClientConfig>,
+    /// The targets queries may be forwarded to, by the `targethost` of the request, with the
+    ///  address to connect to
+    pub targets: HashMap<String, SocketAddr>,
+    /// Whether targets may have loopback, private or link-local addresses, `false` by default
+    pub allow_private_targets: bool,
+    /// The connections to the targets, reused by the queries forwarded to the same target
+    connections: Arc<std::sync::Mutex<HashMap<String, SendRequest<Bytes>>>>,
+}
+
+impl OdohProxy {
+    /// Creates a proxy forwarding to the `targets`, see [`Self::targets`]
+    pub fn new(client_config: Arc<ClientConfig>, targets: HashMap<String, SocketAddr>) -> Self {
+        Self {
+            client_config,
+            targets,
+            allow_private_targets: false,
+            connections: Arc::default(),
+        }
+    }
+
+    /// Returns the address of an allowed target
+    fn target_address(&self, target_host: &str) -> Result<SocketAddr, HttpsError> {
+        let address = *self
+            .targets
+            .get(target_host)
+            .ok_or_else(|| format!("target is not allowed: {target_host}"))?;
+
+        if !self.allow_private_targets && !is_global(address.ip()) {
+            return Err(format!("target {target_host} has a private address: {address}").into());
+        }
+
+        Ok(address)
+    }
+
+    /// Returns a connection to the target, reusing the previous one if it is still open
+    async fn connection(
+        &self,
+        target_host: &str,
+        address: SocketAddr,
+    ) -> Result<SendRequest<Bytes>, HttpsError> {
+        let cached = self
+            .connections
+            .lock()
+            .expect("poisoned lock")
+            .get(target_host)
+            .cloned();
+        if let Some(h2) = cached {
+            match h2.ready().await {
+                Ok(h2) => return Ok(h2),
+                Err(err) => debug!("connection to target {target_host} closed: {err}"),
+            }
+        }
+
+        let h2 = self.connect(target_host, address).await?;
+        self.connections
+            .lock()
+            .expect("poisoned lock")
+            .insert(target_host.to_owned(), h2.clone());
+
+        Ok(h2.ready().await?)
+    }
+
+    async fn connect(
+        &self,
+        target_host: &str,
+        address: SocketAddr,
+    ) -> Result<SendRequest<Bytes>, HttpsError> {
+        let host = Authority::from_str(target_host)
+            .map_err(|e| HttpsError::from(format!("invalid target host: {e}")))?
+            .host()
+            .to_owned();
+        let server_name = ServerName::try_from(host)
+            .map_err(|e| HttpsError::from(format!("invalid target host: {e}")))?;
+
+        let mut client_config = self.client_config.clone();
+        if client_config.alpn_protocols.is_empty() {
+            let mut config = (*client_config).clone();
+            config.alpn_protocols = vec![b"h2".to_vec()];
+            client_config = Arc::new(config);
+        }
+
+        let tcp = TcpStream::connect(address)
+            .await
+            .map_err(|e| HttpsError::from(format!("could not connect to {address}: {e}")))?;
+        let tls = TlsConnector::from(client_config)
+            .connect(server_name, tcp)
+            .await
+            .map_err(|e| HttpsError::from(format!("TLS error with {address}: {e}")))?;
+
+        let (h2, connection) = h2::client::handshake(tls).await?;
+        tokio::spawn(connection.map(|result| {
+            if let Err(err) = result {
+                debug!("h2 connection to target failed: {err}");
+            }
+        }));
+
+        Ok(h2)
+    }
+}
+
+/// Returns true if the request is for one of the ODoH roles rather than a DoH query
+pub(crate) fn is_odoh_request<T>(request: &Request<T>) -> bool {
+    match *request.method() {
+        Method::GET => request.uri().path() == ODOH_CONFIGS_PATH,
+        Method::POST => request
+            .headers()
+            .get(CONTENT_TYPE)
+            .is_some_and(|ctype| ctype == MIME_APPLICATION_ODOH),
+        _ => false,
+    }
+}
+
+pub(crate) async fn odoh_request<T: RequestHandler>(
+    listener: Arc<HttpsListener<T>>,
+    odoh: Arc<ObliviousDoh>,
+    request: Request<RecvStream>,
+    respond: SendResponse<Bytes>,
+    src_addr: SocketAddr,
+) {
+    let respond = Arc::new(Mutex::new(respond));
+    let target = odoh::target_from_query(request.uri());
+
+    let result = match (request.method().clone(), target) {
+        (Method::GET, None) => send_configs(&odoh, &request, respond.clone()).await,
+        (Method::POST, None) => match target_query(&odoh, &listener, request, src_addr).await {
+            Ok((query, response_key)) => {
+                let responder = OdohResponseHandle {
+                    respond: respond.clone(),
+                    response_key: Arc::new(response_key),
+                };
+                server_future::handle_request(
+                    &query,
+                    src_addr,
+                    Protocol::Odoh,
+                    listener.access.clone(),
+                    listener.handler.clone(),
+                    responder,
+                )
+                .await;
+                Ok(())
+            }
+            Err(err) => Err(err),
+        },
+        (Method::POST, Some((target_host, target_path))) => match &odoh.proxy {
+            Some(_) if !listener.access.allow(src_addr.ip()) => {
+                debug!("refusing to forward ODoH request from {src_addr}");
+                send_status(respond.clone(), StatusCode::FORBIDDEN).await
+            }
+            Some(proxy) => {
+                match proxy_request(proxy, &listener, request, &target_host, &target_path).await {
+                    Ok((status, content_type, body)) => {
+                        send(respond.clone(), status, content_type, body).await
+                    }
+                    Err(err) => {
+                        warn!("error forwarding ODoH request from {src_addr}: {err}");
+                        send_status(respond.clone(), StatusCode::BAD_GATEWAY).await
+                    }
+                }
+            }
+            None => Err("not an ODoH proxy".into()),
+        },
+        _ => Err("unsupported ODoH request".into()),
+    };
+
+    if let Err(err) = result {
+        warn!("error while handling ODoH request from {src_addr}: {err}");
+        if let Err(err) = send_status(respond, StatusCode::BAD_REQUEST).await {
+            debug!("error sending ODoH error status to {src_addr}: {err}");
+        }
+    }
+}
+
+/// Serves the `ObliviousDoHConfigs` of the target
+async fn send_configs(
+    odoh: &ObliviousDoh,
+    request: &Request<RecvStream>,
+    respond: Arc<Mutex<SendResponse<Bytes>>>,
+) -> Result<(), HttpsError> {
+    let Some(key_pair) = &odoh.target else {
+        return Err("not an ODoH target".into());
+    };
+    if request.uri().path() != ODOH_CONFIGS_PATH {
+        return Err(format!("bad path: {}", request.uri().path()).into());
+    }
+
+    let configs = Bytes::from(key_pair.config().to_configs());
+    let response =
+        response::new_odoh(Version::Http2, MIME_APPLICATION_OCTET_STREAM, configs.len())?;
+    send_response(respond, response, configs).await
+}
+
+/// Decrypts a query sent to the target
+async fn target_query<T>(
+    odoh: &ObliviousDoh,
+    listener: &HttpsListener<T>,
+    request: Request<RecvStream>,
+    src_addr: SocketAddr,
+) -> Result<(Vec<u8>, OdohResponseKey), HttpsError> {
+    let Some(key_pair) = &odoh.target else {
+        return Err("not an ODoH target".into());
+    };
+
+    let message = h2_server::odoh_message_from(
+        listener.dns_hostname.clone(),
+        listener.http_endpoint.clone(),
+        request,
+    )
+    .await?;
+    debug!("received ODoH query from {src_addr}");
+
+    Ok(key_pair.decrypt_query(&message)?)
+}
+
+/// Forwards a query to the target, returning the status, content type and body of its response
+async fn proxy_request<T>(
+    proxy: &OdohProxy,
+    listener: &HttpsListener<T>,
+    request: Request<RecvStream>,
+    target_host: &str,
+    target_path: &str,
+) -> Result<(StatusCode, Option<HeaderValue>, Bytes), HttpsError> {
+    let address = proxy.target_address(target_host)?;
+    let message = h2_server::odoh_message_from(
+        listener.dns_hostname.clone(),
+        listener.http_endpoint.clone(),
+        request,
+    )
+    .await?;
+    let request = request::new_odoh(Version::Http2, target_host, target_path, message.len())?;
+
+    debug!("forwarding ODoH request to {target_host} at {address}");
+    timeout(
+        PROXY_TIMEOUT,
+        forward(proxy, target_host, address, request, message.freeze()),
+    )
+    .await
+    .map_err(|_| HttpsError::from(format!("timed out forwarding to {target_host}")))?
+}
+
+async fn forward(
+    proxy: &OdohProxy,
+    target_host: &str,
+    address: SocketAddr,
+    request: Request<()>,
+    body: Bytes,
+) -> Result<(StatusCode, Option<HeaderValue>, Bytes), HttpsError> {
+    let mut h2 = proxy.connection(target_host, address).await?;
+    let (response, mut send_stream) = h2.send_request(request, false)?;
+    send_stream.send_data(body, true)?;
+
+    let (parts, mut response_body) = response.await?.into_parts();
+    let mut bytes = BytesMut::new();
+    while let Some(data) = response_body.data().await {
+        let data = data?;
+        if bytes.len() + data.len() > MAX_TARGET_RESPONSE_LEN {
+            return Err("target response too large".into());
+        }
+
+        bytes.extend_from_slice(&data);
+        response_body.flow_control().release_capacity(data.len())?;
+    }
+
+    Ok((
+        parts.status,
+        parts.headers.get(CONTENT_TYPE).cloned(),
+        bytes.freeze(),
+    ))
+}
+
+async fn send(
+    respond: Arc<Mutex<SendResponse<Bytes>>>,
+    status: StatusCode,
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+) -> Result<(), HttpsError> {
+    let mut response = Response::builder()
+        .status(status)
+        .version(http::Version::HTTP_2)
+        .header(CONTENT_LENGTH, body.len())
+        .header(CACHE_CONTROL, "no-cache, no-store");
+    if let Some(content_type) = content_type {
+        response = response.header(CONTENT_TYPE, content_type);
+    }
+
+    let response = response
+        .body(())
+        .map_err(|e| HttpsError::from(format!("invalid response: {e}")))?;
+    send_response(respond, response, body).await
+}
+
+async fn send_status(
+    respond: Arc<Mutex<SendResponse<Bytes>>>,
+    status: StatusCode,
+) -> Result<(), HttpsError> {
+    send(respond, status, None, Bytes::new()).await
+}
+
+async fn send_response(
+    respond: Arc<Mutex<SendResponse<Bytes>>>,
+    response: Response<()>,
+    body: Bytes,
+) -> Result<(), HttpsError> {
+    debug!("sending response: {:#?}", response);
+    let mut respond = respond.lock().await;
+    let mut stream = respond.send_response(response, body.is_empty())?;
+    if !body.is_empty() {
+        stream.send_data(body, true)?;
+    }
+
+    Ok(())
+}
+
+/// Encrypts the responses of the target
+#[derive(Clone)]
+struct OdohResponseHandle {
+    respond: Arc<Mutex<SendResponse<Bytes>>>,
+    response_key: Arc<OdohResponseKey>,
+}
+
+#[async_trait::async_trait]
+impl ResponseHandler for OdohResponseHandle {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        let mut bytes = Vec::with_capacity(512);
+        // mut block
+        let info = {
+            let mut encoder = BinEncoder::new(&mut bytes);
+            response.destructive_emit(&mut encoder)?
+        };
+
+        let message = self
+            .response_key
+            .encrypt_response(&bytes)
+            .map_err(HttpsError::from)?;
+        let message = Bytes::from(message);
+        let response = response::new_odoh(Version::Http2, MIME_APPLICATION_ODOH, message.len())?;
+
+        send_response(self.respond.clone(), response, message).await?;
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::RootCertStore;
+
+    use super::*;
+
+    #[test]
+    fn test_target_address() {
+        let client_config =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(RootCertStore::empty())
+                .with_no_client_auth();
+
+        let public = SocketAddr::from(([192, 0, 2, 1], 443));
+        let private = SocketAddr::from(([10, 0, 0, 1], 443));
+        let mut proxy = OdohProxy::new(
+            Arc::new(client_config),
+            HashMap::from([
+                ("public.example.com".to_owned(), public),
+                ("private.example.com".to_owned(), private),
+            ]),
+        );
+
+        assert_eq!(proxy.target_address("public.example.com").unwrap(), public);
+        assert!(proxy.target_address("private.example.com").is_err());
+        assert!(proxy.target_address("other.example.com").is_err());
+
+        proxy.allow_private_targets = true;
+        assert_eq!(
+            proxy.target_address("private.example.com").unwrap(),
+            private
+        );
+    }
+}
@@ -13,7 +13,7 @@ use tokio_util::sync::CancellationToken;
 use tracing::{debug, warn};
 
 use crate::{
-    access::AccessControl,
+    access::AccessControlHandle,
     authority::MessageResponse,
     proto::{
         quic::QuicStreams,
@@ -29,7 +29,7 @@ use crate::{
 };
 
 pub(crate) async fn quic_handler<T>(
-    access: Arc<AccessControl>,
+    access: AccessControlHandle,
     handler: Arc<T>,
     mut quic_streams: QuicStreams,
     src_addr: SocketAddr,
@@ -90,7 +90,7 @@ where
 async fn handle_request<T>(
     bytes: BytesMut,
     src_addr: SocketAddr,
-    access: Arc<AccessControl>,
+    access: AccessControlHandle,
     handler: Arc<T>,
     responder: QuicResponseHandle,
 ) where
@@ -13,6 +13,13 @@ use crate::{
     authority::MessageRequest,
     proto::{
         op::{Header, LowerQuery, ResponseCode},
+        rr::{
+            rdata::{
+                opt::{ClientSubnet, EdnsCode, EdnsOption},
+                SOA,
+            },
+            Name, RecordType,
+        },
         xfer::Protocol,
     },
     server::ResponseHandler,
@@ -48,9 +55,35 @@ impl Request {
             protocol: self.protocol,
             header: self.message.header(),
             query: self.message.query(),
+            ixfr_serial: self.ixfr_serial(),
+            tsig_key: None,
+            client_subnet: self.client_subnet(),
         }
     }
 
+    /// The EDNS Client Subnet option of the request, if any
+    fn client_subnet(&self) -> Option<ClientSubnet> {
+        match self.message.edns()?.option(EdnsCode::Subnet)? {
+            EdnsOption::Subnet(client_subnet) => Some(*client_subnet),
+            _ => None,
+        }
+    }
+
+    /// For IXFR requests, the serial of the zone version the client already holds
+    ///
+    /// [RFC 1995](https://tools.ietf.org/html/rfc1995#section-3), IXFR, August 1996, carries the
+    ///  client's SOA in the authority section of the query.
+    fn ixfr_serial(&self) -> Option<u32> {
+        if self.message.query().query_type() != RecordType::IXFR {
+            return None;
+        }
+
+        self.message
+            .name_servers()
+            .iter()
+            .find_map(|record| record.data().as_soa().map(SOA::serial))
+    }
+
     /// The IP address from which the request originated.
     pub fn src(&self) -> SocketAddr {
         self.src
@@ -83,6 +116,13 @@ pub struct RequestInfo<'a> {
     pub header: &'a Header,
     /// The query from the request
     pub query: &'a LowerQuery,
+    /// The serial of the client's version of the zone, only present for IXFR requests
+    pub ixfr_serial: Option<u32>,
+    /// The name of the TSIG key the request was authenticated with, set by the `Catalog` once the
+    ///  signature has been verified
+    pub tsig_key: Option<&'a Name>,
+    /// The EDNS Client Subnet option from the request, if any
+    pub client_subnet: Option<ClientSubnet>,
 }
 
 impl<'a> RequestInfo<'a> {
@@ -105,6 +145,9 @@ impl<'a> RequestInfo<'a> {
             protocol,
             header,
             query,
+            ixfr_serial: None,
+            tsig_key: None,
+            client_subnet: None,
         }
     }
 }
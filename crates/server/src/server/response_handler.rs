@@ -5,7 +5,7 @@
 // https://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use std::{io, net::SocketAddr};
+use std::{io, net::SocketAddr, sync::Arc};
 
 use hickory_proto::rr::Record;
 use tracing::{debug, trace};
@@ -17,7 +17,10 @@ use crate::{
         xfer::{Protocol, SerialMessage},
         BufDnsStreamHandle, DnsStreamHandle,
     },
-    server::ResponseInfo,
+    server::{
+        rrl::{RateLimitAction, ResponseParts, ResponseRateLimiter},
+        ResponseInfo,
+    },
 };
 
 /// A handler for send a response to a client
@@ -49,6 +52,7 @@ pub struct ResponseHandle {
     dst: SocketAddr,
     stream_handle: BufDnsStreamHandle,
     protocol: Protocol,
+    rate_limiter: Option<Arc<ResponseRateLimiter>>,
 }
 
 impl ResponseHandle {
@@ -58,7 +62,57 @@ impl ResponseHandle {
             dst,
             stream_handle,
             protocol,
+            rate_limiter: None,
+        }
+    }
+
+    /// Limits the rate of the responses sent over UDP
+    pub(crate) fn with_rate_limiter(
+        mut self,
+        rate_limiter: Option<Arc<ResponseRateLimiter>>,
+    ) -> Self {
+        if self.protocol == Protocol::Udp {
+            self.rate_limiter = rate_limiter;
         }
+        self
+    }
+
+    /// Serializes the response and sends it to the destination
+    fn encode_and_send<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> io::Result<ResponseInfo> {
+        let mut buffer = Vec::with_capacity(512);
+        let encode_result = {
+            let mut encoder = BinEncoder::new(&mut buffer);
+
+            // Set an appropriate maximum on the encoder.
+            let max_size = self.max_size_for_response(&response);
+            trace!(
+                "setting response max size: {max_size} for protocol: {:?}",
+                self.protocol
+            );
+            encoder.set_max_size(max_size);
+
+            response.destructive_emit(&mut encoder)
+        };
+
+        let info = encode_result.map_err(|e| {
+            io::Error::new(io::ErrorKind::Other, format!("error encoding message: {e}"))
+        })?;
+
+        self.stream_handle
+            .send(SerialMessage::new(buffer, self.dst))
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "unknown"))?;
+
+        Ok(info)
     }
 
     /// Selects an appropriate maximum serialized size for the given response.
@@ -109,29 +163,26 @@ impl ResponseHandler for ResponseHandle {
             response.header().id(),
             response.header().response_code(),
         );
-        let mut buffer = Vec::with_capacity(512);
-        let encode_result = {
-            let mut encoder = BinEncoder::new(&mut buffer);
-
-            // Set an appropriate maximum on the encoder.
-            let max_size = self.max_size_for_response(&response);
-            trace!(
-                "setting response max size: {max_size} for protocol: {:?}",
-                self.protocol
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let response = response.collect_records();
+            let action = rate_limiter.check(
+                self.dst.ip(),
+                &ResponseParts {
+                    response_code: response.header().response_code(),
+                    query: response.query(),
+                    answers: response.answers(),
+                    name_servers: response.name_servers(),
+                },
             );
-            encoder.set_max_size(max_size);
-
-            response.destructive_emit(&mut encoder)
-        };
 
-        let info = encode_result.map_err(|e| {
-            io::Error::new(io::ErrorKind::Other, format!("error encoding message: {e}"))
-        })?;
-
-        self.stream_handle
-            .send(SerialMessage::new(buffer, self.dst))
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "unknown"))?;
+            return match action {
+                RateLimitAction::Send => self.encode_and_send(response),
+                RateLimitAction::Drop => Ok((*response.header()).into()),
+                // the client is expected to retry over TCP
+                RateLimitAction::Slip => self.encode_and_send(response.truncate()),
+            };
+        }
 
-        Ok(info)
+        self.encode_and_send(response)
     }
 }
@@ -0,0 +1,514 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Response Rate Limiting (RRL) of UDP responses
+//!
+//! Limits how fast identical responses are sent to a network, in the manner of the `rate-limit`
+//!  option of BIND, so that the server is of little use as a reflector in amplification attacks.
+//!  Responses over TCP are not limited, as the source address of a TCP connection can not be
+//!  spoofed.
+
+use std::{net::IpAddr, sync::Mutex, time::Instant};
+
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use lru_cache::LruCache;
+use serde::Deserialize;
+use tracing::{debug, info};
+
+#[cfg(feature = "dnssec")]
+use crate::proto::{dnssec::rdata::DNSSECRData, rr::RData};
+use crate::proto::{
+    op::{LowerQuery, ResponseCode},
+    rr::{LowerName, Record, RecordType},
+};
+
+/// Configuration of Response Rate Limiting
+///
+/// Responses are counted in buckets per network of the client, kind of response and the name it
+///  is about. Each bucket is credited with its rate every second, and may go into debt up to the
+///  rate times the window, so a client has to slow down for a while before its responses are sent
+///  again.
+#[derive(Clone, Copy, Deserialize, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseRateLimitConfig {
+    /// Responses per second with records for a name and type, `0` for no limit
+    ///
+    /// Answers synthesized from a wildcard are counted towards the wildcard rather than the name
+    ///  queried, which is only known from the RRSIG records of signed zones. Without the `dnssec`
+    ///  feature, or in unsigned zones, they are counted per name queried, so that queries of
+    ///  random names below a wildcard are not limited together.
+    pub responses_per_second: u32,
+    /// Responses per second without records for a name, including referrals, defaults to
+    ///  `responses_per_second`
+    #[serde(default)]
+    pub nodata_per_second: Option<u32>,
+    /// NXDOMAIN responses per second for a zone, defaults to `responses_per_second`
+    #[serde(default)]
+    pub nxdomains_per_second: Option<u32>,
+    /// Error responses per second, regardless of the name, defaults to `responses_per_second`
+    #[serde(default)]
+    pub errors_per_second: Option<u32>,
+    /// Number of seconds over which the rate is averaged
+    #[serde(default = "default_window")]
+    pub window: u32,
+    /// Every `slip`-th limited response is sent truncated instead of being dropped, so that
+    ///  legitimate clients retry over TCP. `0` drops all limited responses.
+    #[serde(default = "default_slip")]
+    pub slip: u32,
+    /// Length of the prefix grouping IPv4 clients
+    #[serde(default = "default_ipv4_prefix_length")]
+    pub ipv4_prefix_length: u8,
+    /// Length of the prefix grouping IPv6 clients
+    #[serde(default = "default_ipv6_prefix_length")]
+    pub ipv6_prefix_length: u8,
+    /// Only log the responses which would be limited, and send them anyway
+    #[serde(default)]
+    pub log_only: bool,
+    /// Maximum number of buckets, once reached the least recently used bucket is dropped for a
+    ///  new one
+    #[serde(default = "default_max_table_size")]
+    pub max_table_size: usize,
+}
+
+impl ResponseRateLimitConfig {
+    /// Limits responses to `responses_per_second`, with the defaults of BIND for the other
+    ///  settings
+    pub fn new(responses_per_second: u32) -> Self {
+        Self {
+            responses_per_second,
+            nodata_per_second: None,
+            nxdomains_per_second: None,
+            errors_per_second: None,
+            window: default_window(),
+            slip: default_slip(),
+            ipv4_prefix_length: default_ipv4_prefix_length(),
+            ipv6_prefix_length: default_ipv6_prefix_length(),
+            log_only: false,
+            max_table_size: default_max_table_size(),
+        }
+    }
+
+    fn rate(&self, kind: ResponseKind) -> u32 {
+        match kind {
+            ResponseKind::Answer => Some(self.responses_per_second),
+            ResponseKind::NoData => self.nodata_per_second,
+            ResponseKind::NxDomain => self.nxdomains_per_second,
+            ResponseKind::Error => self.errors_per_second,
+        }
+        .unwrap_or(self.responses_per_second)
+    }
+}
+
+fn default_window() -> u32 {
+    15
+}
+
+fn default_slip() -> u32 {
+    2
+}
+
+fn default_ipv4_prefix_length() -> u8 {
+    24
+}
+
+fn default_ipv6_prefix_length() -> u8 {
+    56
+}
+
+fn default_max_table_size() -> usize {
+    20_000
+}
+
+/// What to do with a response
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum RateLimitAction {
+    /// The response is within the limit
+    Send,
+    /// The response is over the limit, and is dropped
+    Drop,
+    /// The response is over the limit, and a truncated response is sent instead
+    Slip,
+}
+
+/// The parts of a response which decide its bucket, taken from it before it is emitted
+pub(crate) struct ResponseParts<'r> {
+    pub(crate) response_code: ResponseCode,
+    pub(crate) query: Option<&'r LowerQuery>,
+    pub(crate) answers: &'r [&'r Record],
+    /// The authority section, including the SOA
+    pub(crate) name_servers: &'r [&'r Record],
+}
+
+/// Counts the UDP responses to each bucket, see [`ResponseRateLimitConfig`]
+pub(crate) struct ResponseRateLimiter {
+    config: ResponseRateLimitConfig,
+    start: Instant,
+    buckets: Mutex<LruCache<BucketKey, Bucket>>,
+}
+
+impl ResponseRateLimiter {
+    pub(crate) fn new(config: ResponseRateLimitConfig) -> Self {
+        Self {
+            config,
+            start: Instant::now(),
+            buckets: Mutex::new(LruCache::new(config.max_table_size.max(1))),
+        }
+    }
+
+    /// Counts the response to `dst`, returning what to do with it
+    pub(crate) fn check(&self, dst: IpAddr, response: &ResponseParts<'_>) -> RateLimitAction {
+        self.check_at(dst, response, self.start.elapsed().as_secs())
+    }
+
+    fn check_at(&self, dst: IpAddr, response: &ResponseParts<'_>, now: u64) -> RateLimitAction {
+        let key = BucketKey::new(&self.config, dst, response);
+        let rate = self.config.rate(key.kind);
+        if rate == 0 {
+            return RateLimitAction::Send;
+        }
+
+        let mut buckets = self.buckets.lock().expect("lock poisoned");
+        if !buckets.contains_key(&key) {
+            // evicts the least recently used bucket once the table is full
+            buckets.insert(
+                key.clone(),
+                Bucket {
+                    balance: i64::from(rate),
+                    updated: now,
+                    limited: 0,
+                },
+            );
+        }
+        let bucket = buckets.get_mut(&key).expect("bucket was inserted");
+
+        let rate = i64::from(rate);
+        let elapsed = i64::try_from(now.saturating_sub(bucket.updated)).unwrap_or(i64::MAX);
+        bucket.balance = bucket
+            .balance
+            .saturating_add(elapsed.saturating_mul(rate))
+            .min(rate);
+        bucket.updated = now;
+
+        bucket.balance = (bucket.balance - 1).max(-rate * i64::from(self.config.window.max(1)));
+        if bucket.balance >= 0 {
+            bucket.limited = 0;
+            return RateLimitAction::Send;
+        }
+
+        bucket.limited = bucket.limited.wrapping_add(1);
+        let action = if self.config.slip > 0 && bucket.limited % self.config.slip == 0 {
+            RateLimitAction::Slip
+        } else {
+            RateLimitAction::Drop
+        };
+
+        let log_only = if self.config.log_only {
+            " (log only)"
+        } else {
+            ""
+        };
+        if bucket.limited == 1 {
+            info!(
+                "rate limiting{log_only} {kind:?} responses to: {network} for: {name}",
+                kind = key.kind,
+                network = key.network,
+                name = key.name.as_ref().map_or(String::new(), ToString::to_string),
+            );
+        } else {
+            debug!(
+                "rate limit{log_only} {action:?} {kind:?} response to: {dst}",
+                kind = key.kind,
+            );
+        }
+
+        if self.config.log_only {
+            RateLimitAction::Send
+        } else {
+            action
+        }
+    }
+}
+
+/// The kind of response, which are counted separately
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum ResponseKind {
+    Answer,
+    NoData,
+    NxDomain,
+    Error,
+}
+
+/// Identical responses to a network are counted in the same bucket
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct BucketKey {
+    network: IpNet,
+    kind: ResponseKind,
+    name: Option<LowerName>,
+    query_type: Option<RecordType>,
+}
+
+impl BucketKey {
+    /// Responses with records are identical for the same name and type, where answers synthesized
+    ///  from a wildcard count towards the wildcard. Responses without records are identical for
+    ///  the same name, or the delegation for referrals, and NXDOMAIN responses for the same zone.
+    ///  All errors to a network are counted together.
+    fn new(config: &ResponseRateLimitConfig, dst: IpAddr, response: &ResponseParts<'_>) -> Self {
+        let network = match dst {
+            IpAddr::V4(ip) => IpNet::V4(
+                Ipv4Net::new(ip, config.ipv4_prefix_length.min(32))
+                    .expect("prefix length is valid")
+                    .trunc(),
+            ),
+            IpAddr::V6(ip) => IpNet::V6(
+                Ipv6Net::new(ip, config.ipv6_prefix_length.min(128))
+                    .expect("prefix length is valid")
+                    .trunc(),
+            ),
+        };
+
+        let query = response.query;
+        let query_name = query.map(|query| query.name().clone());
+        let soa_name = || {
+            response
+                .name_servers
+                .iter()
+                .find(|record| record.record_type() == RecordType::SOA)
+                .map(|record| LowerName::from(record.name()))
+        };
+
+        let (kind, name, query_type) = match response.response_code {
+            ResponseCode::NoError if !response.answers.is_empty() => (
+                ResponseKind::Answer,
+                wildcard_name(response).or(query_name),
+                query.map(|query| query.query_type()),
+            ),
+            ResponseCode::NoError => {
+                let delegation = response
+                    .name_servers
+                    .iter()
+                    .find(|record| record.record_type() == RecordType::NS)
+                    .map(|record| LowerName::from(record.name()));
+
+                match soa_name() {
+                    Some(_) => (ResponseKind::NoData, query_name, None),
+                    // a referral, which is identical for all names below the delegation
+                    None => (ResponseKind::NoData, delegation.or(query_name), None),
+                }
+            }
+            ResponseCode::NXDomain => (ResponseKind::NxDomain, soa_name().or(query_name), None),
+            _ => (ResponseKind::Error, None, None),
+        };
+
+        Self {
+            network,
+            kind,
+            name,
+            query_type,
+        }
+    }
+}
+
+/// The wildcard an answer was synthesized from, which is only known from its signature
+///
+/// The labels of an RRSIG of a synthesized record are fewer than the labels of its owner name,
+///  see RFC 4035 section 5.3.4.
+#[cfg(feature = "dnssec")]
+fn wildcard_name(response: &ResponseParts<'_>) -> Option<LowerName> {
+    response.answers.iter().find_map(|record| {
+        let RData::DNSSEC(DNSSECRData::RRSIG(rrsig)) = record.data() else {
+            return None;
+        };
+
+        let labels = usize::from(rrsig.num_labels());
+        if labels >= usize::from(record.name().num_labels()) {
+            return None;
+        }
+
+        // trim to one more label than the signer covers, and replace it with `*`
+        let wildcard = record.name().trim_to(labels + 1).into_wildcard();
+        Some(LowerName::from(wildcard))
+    })
+}
+
+/// Without RRSIG records, wildcard answers look like any other answer, see
+///  [`ResponseRateLimitConfig::responses_per_second`]
+#[cfg(not(feature = "dnssec"))]
+fn wildcard_name(_response: &ResponseParts<'_>) -> Option<LowerName> {
+    None
+}
+
+struct Bucket {
+    balance: i64,
+    updated: u64,
+    limited: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, str::FromStr};
+
+    use super::*;
+    use crate::proto::{
+        op::Query,
+        rr::{
+            rdata::{A, SOA},
+            Name, RData, Record,
+        },
+    };
+
+    struct Response {
+        response_code: ResponseCode,
+        query: LowerQuery,
+        answers: Vec<Record>,
+        name_servers: Vec<Record>,
+    }
+
+    impl Response {
+        fn check(&self, limiter: &ResponseRateLimiter, dst: IpAddr, now: u64) -> RateLimitAction {
+            let answers = self.answers.iter().collect::<Vec<_>>();
+            let name_servers = self.name_servers.iter().collect::<Vec<_>>();
+            let parts = ResponseParts {
+                response_code: self.response_code,
+                query: Some(&self.query),
+                answers: &answers,
+                name_servers: &name_servers,
+            };
+            limiter.check_at(dst, &parts, now)
+        }
+    }
+
+    fn response(name: &str, response_code: ResponseCode) -> Response {
+        let name = Name::from_str(name).unwrap();
+        let mut answers = Vec::new();
+        if response_code == ResponseCode::NoError {
+            answers.push(Record::from_rdata(
+                name.clone(),
+                300,
+                RData::A(A::new(192, 0, 2, 1)),
+            ));
+        }
+        Response {
+            response_code,
+            query: LowerQuery::query(Query::query(name, RecordType::A)),
+            answers,
+            name_servers: Vec::new(),
+        }
+    }
+
+    fn client(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(198, 51, 100, last))
+    }
+
+    #[test]
+    fn test_limit_identical_responses() {
+        let mut config = ResponseRateLimitConfig::new(2);
+        config.slip = 0;
+        let limiter = ResponseRateLimiter::new(config);
+        let www = response("www.example.com.", ResponseCode::NoError);
+
+        assert_eq!(www.check(&limiter, client(1), 0), RateLimitAction::Send);
+        // clients in the same network share the bucket
+        assert_eq!(www.check(&limiter, client(2), 0), RateLimitAction::Send);
+        assert_eq!(www.check(&limiter, client(3), 0), RateLimitAction::Drop);
+
+        // other names and networks are counted separately
+        let ftp = response("ftp.example.com.", ResponseCode::NoError);
+        assert_eq!(ftp.check(&limiter, client(1), 0), RateLimitAction::Send);
+        let other = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
+        assert_eq!(www.check(&limiter, other, 0), RateLimitAction::Send);
+
+        // the debt of the bucket has to be paid off before responses are sent again
+        assert_eq!(www.check(&limiter, client(1), 1), RateLimitAction::Send);
+        assert_eq!(www.check(&limiter, client(1), 1), RateLimitAction::Drop);
+        assert_eq!(www.check(&limiter, client(1), 1), RateLimitAction::Drop);
+        assert_eq!(www.check(&limiter, client(1), 3), RateLimitAction::Send);
+    }
+
+    #[test]
+    fn test_slip() {
+        let limiter = ResponseRateLimiter::new(ResponseRateLimitConfig::new(1));
+        let www = response("www.example.com.", ResponseCode::NoError);
+
+        assert_eq!(www.check(&limiter, client(1), 0), RateLimitAction::Send);
+        assert_eq!(www.check(&limiter, client(1), 0), RateLimitAction::Drop);
+        assert_eq!(www.check(&limiter, client(1), 0), RateLimitAction::Slip);
+        assert_eq!(www.check(&limiter, client(1), 0), RateLimitAction::Drop);
+        assert_eq!(www.check(&limiter, client(1), 0), RateLimitAction::Slip);
+    }
+
+    #[test]
+    fn test_nxdomain_and_errors() {
+        let mut config = ResponseRateLimitConfig::new(10);
+        config.nxdomains_per_second = Some(1);
+        config.errors_per_second = Some(1);
+        config.slip = 0;
+        let limiter = ResponseRateLimiter::new(config);
+
+        // NXDOMAIN responses for the same zone are identical
+        let mut nxdomain = response("a.example.com.", ResponseCode::NXDomain);
+        let soa = Record::from_rdata(
+            Name::from_str("example.com.").unwrap(),
+            300,
+            RData::SOA(SOA::new(
+                Name::from_str("ns.example.com.").unwrap(),
+                Name::from_str("hostmaster.example.com.").unwrap(),
+                1,
+                3600,
+                600,
+                86400,
+                300,
+            )),
+        );
+        nxdomain.name_servers.push(soa.clone());
+        let mut other = response("b.example.com.", ResponseCode::NXDomain);
+        other.name_servers.push(soa);
+
+        assert_eq!(
+            nxdomain.check(&limiter, client(1), 0),
+            RateLimitAction::Send
+        );
+        assert_eq!(other.check(&limiter, client(1), 0), RateLimitAction::Drop);
+
+        let refused = response("a.example.net.", ResponseCode::Refused);
+        let other = response("b.example.net.", ResponseCode::Refused);
+        assert_eq!(refused.check(&limiter, client(1), 0), RateLimitAction::Send);
+        assert_eq!(other.check(&limiter, client(1), 0), RateLimitAction::Drop);
+    }
+
+    #[test]
+    fn test_full_table() {
+        let mut config = ResponseRateLimitConfig::new(1);
+        config.slip = 0;
+        config.max_table_size = 2;
+        let limiter = ResponseRateLimiter::new(config);
+        let www = response("www.example.com.", ResponseCode::NoError);
+
+        // fill the table with other names
+        for name in ["a.example.com.", "b.example.com."] {
+            let other = response(name, ResponseCode::NoError);
+            assert_eq!(other.check(&limiter, client(1), 0), RateLimitAction::Send);
+        }
+
+        // a new source is still tracked, and limited
+        let source = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
+        assert_eq!(www.check(&limiter, source, 0), RateLimitAction::Send);
+        assert_eq!(www.check(&limiter, source, 0), RateLimitAction::Drop);
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_log_only() {
+        let mut config = ResponseRateLimitConfig::new(1);
+        config.log_only = true;
+        let limiter = ResponseRateLimiter::new(config);
+        let www = response("www.example.com.", ResponseCode::NoError);
+
+        for _ in 0..5 {
+            assert_eq!(www.check(&limiter, client(1), 0), RateLimitAction::Send);
+        }
+    }
+}
@@ -28,7 +28,7 @@ use tracing::{debug, info, warn};
 #[cfg(all(feature = "dns-over-openssl", not(feature = "dns-over-rustls")))]
 use crate::proto::openssl::tls_server::*;
 use crate::{
-    access::AccessControl,
+    access::AccessControlHandle,
     authority::{MessageRequest, MessageResponseBuilder},
     proto::{
         op::{Header, LowerQuery, Query, ResponseCode},
@@ -39,7 +39,10 @@ use crate::{
         xfer::{Protocol, SerialMessage},
         BufDnsStreamHandle, ProtoError,
     },
-    server::{Request, RequestHandler, ResponseHandle, ResponseHandler, TimeoutStream},
+    server::{
+        rrl::{ResponseRateLimitConfig, ResponseRateLimiter},
+        Request, RequestHandler, ResponseHandle, ResponseHandler, TimeoutStream,
+    },
 };
 
 // TODO, would be nice to have a Slab for buffers here...
@@ -48,7 +51,10 @@ pub struct ServerFuture<T: RequestHandler> {
     handler: Arc<T>,
     join_set: JoinSet<Result<(), ProtoError>>,
     shutdown_token: CancellationToken,
-    access: Arc<AccessControl>,
+    access: AccessControlHandle,
+    rate_limiter: Option<Arc<ResponseRateLimiter>>,
+    #[cfg(feature = "dns-over-odoh")]
+    odoh: Option<Arc<super::ObliviousDoh>>,
 }
 
 impl<T: RequestHandler> ServerFuture<T> {
@@ -59,18 +65,37 @@ impl<T: RequestHandler> ServerFuture<T> {
 
     /// Creates a new ServerFuture with the specified Handler and Access
     pub fn with_access(handler: T, denied_networks: &[IpNet], allowed_networks: &[IpNet]) -> Self {
-        let mut access = AccessControl::default();
-        access.insert_deny(denied_networks);
-        access.insert_allow(allowed_networks);
-
         Self {
             handler: Arc::new(handler),
             join_set: JoinSet::new(),
             shutdown_token: CancellationToken::new(),
-            access: Arc::new(access),
+            access: AccessControlHandle::new(denied_networks, allowed_networks),
+            rate_limiter: None,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh: None,
         }
     }
 
+    /// Returns a handle to change the denied and allowed networks while the server is running
+    pub fn access_control(&self) -> AccessControlHandle {
+        self.access.clone()
+    }
+
+    /// Limits the rate of identical responses sent over UDP, see [`ResponseRateLimitConfig`]
+    ///
+    /// This only applies to the UDP sockets registered afterwards.
+    pub fn set_response_rate_limit(&mut self, config: Option<ResponseRateLimitConfig>) {
+        self.rate_limiter = config.map(|config| Arc::new(ResponseRateLimiter::new(config)));
+    }
+
+    /// Enables the Oblivious DoH target and proxy roles, see [`ObliviousDoh`](super::ObliviousDoh)
+    ///
+    /// This only applies to the HTTPS listeners registered afterwards.
+    #[cfg(feature = "dns-over-odoh")]
+    pub fn set_odoh(&mut self, odoh: Option<super::ObliviousDoh>) {
+        self.odoh = odoh.map(Arc::new);
+    }
+
     /// Register a UDP socket. Should be bound before calling this function.
     pub fn register_socket(&mut self, socket: net::UdpSocket) {
         debug!("registering udp: {:?}", socket);
@@ -82,6 +107,7 @@ impl<T: RequestHandler> ServerFuture<T> {
         let shutdown = self.shutdown_token.clone();
         let handler = self.handler.clone();
         let access = self.access.clone();
+        let rate_limiter = self.rate_limiter.clone();
 
         // this spawns a ForEach future which handles all the requests into a Handler.
         self.join_set.spawn({
@@ -122,11 +148,19 @@ impl<T: RequestHandler> ServerFuture<T> {
 
                     let handler = handler.clone();
                     let access = access.clone();
+                    let rate_limiter = rate_limiter.clone();
                     let stream_handle = stream_handle.with_remote_addr(src_addr);
 
                     inner_join_set.spawn(async move {
-                        handle_raw_request(message, Protocol::Udp, access, handler, stream_handle)
-                            .await;
+                        handle_raw_request(
+                            message,
+                            Protocol::Udp,
+                            access,
+                            rate_limiter,
+                            handler,
+                            stream_handle,
+                        )
+                        .await;
                     });
 
                     reap_tasks(&mut inner_join_set);
@@ -227,6 +261,7 @@ impl<T: RequestHandler> ServerFuture<T> {
                             message,
                             Protocol::Tcp,
                             access.clone(),
+                            None,
                             handler.clone(),
                             stream_handle.clone(),
                         )
@@ -386,6 +421,7 @@ impl<T: RequestHandler> ServerFuture<T> {
                             message,
                             Protocol::Tls,
                             access.clone(),
+                            None,
                             handler.clone(),
                             stream_handle.clone(),
                         )
@@ -539,6 +575,7 @@ impl<T: RequestHandler> ServerFuture<T> {
                             message,
                             Protocol::Tls,
                             access.clone(),
+                            None,
                             handler.clone(),
                             stream_handle.clone(),
                         )
@@ -618,13 +655,16 @@ impl<T: RequestHandler> ServerFuture<T> {
         use tokio_rustls::TlsAcceptor;
 
         use crate::proto::rustls::tls_server;
-        use crate::server::h2_handler::h2_handler;
-
-        let dns_hostname: Option<Arc<str>> = dns_hostname.map(|n| n.into());
-        let http_endpoint: Arc<str> = Arc::from(http_endpoint);
-
-        let handler = self.handler.clone();
-        let access = self.access.clone();
+        use crate::server::h2_handler::{h2_handler, HttpsListener};
+
+        let https_listener = Arc::new(HttpsListener {
+            access: self.access.clone(),
+            handler: self.handler.clone(),
+            dns_hostname: dns_hostname.map(|n| n.into()),
+            http_endpoint: Arc::from(http_endpoint),
+            #[cfg(feature = "dns-over-odoh")]
+            odoh: self.odoh.clone(),
+        });
         debug!("registered https: {listener:?}");
 
         let tls_acceptor = tls_server::new_acceptor(certificate_and_key.0, certificate_and_key.1)
@@ -665,11 +705,8 @@ impl<T: RequestHandler> ServerFuture<T> {
                     continue;
                 }
 
-                let handler = handler.clone();
-                let access = access.clone();
+                let https_listener = https_listener.clone();
                 let tls_acceptor = tls_acceptor.clone();
-                let dns_hostname = dns_hostname.clone();
-                let http_endpoint = http_endpoint.clone();
 
                 inner_join_set.spawn(async move {
                     debug!("starting HTTPS request from: {src_addr}");
@@ -692,14 +729,146 @@ impl<T: RequestHandler> ServerFuture<T> {
                     };
                     debug!("accepted HTTPS request from: {src_addr}");
 
-                    h2_handler(
+                    h2_handler(https_listener, tls_stream, src_addr, shutdown.clone()).await;
+                });
+
+                reap_tasks(&mut inner_join_set);
+            }
+
+            if shutdown.is_cancelled() {
+                Ok(())
+            } else {
+                Err(ProtoError::from("unexpected close of socket"))
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Register a TcpListener for HTTP/1.1 to the Server for supporting DoH (dns-over-https) to
+    /// clients and load balancers that can't speak HTTP/2. The TcpListener should already be bound to
+    /// either an IPv6 or an IPv4 address.
+    ///
+    /// Without a certificate the listener speaks plain HTTP, which should only be exposed to a load
+    ///  balancer that terminates TLS.
+    ///
+    /// # Arguments
+    /// * `listener` - a bound TCP (needs to be on a different port from standard TCP connections) socket
+    /// * `handshake_timeout` - timeout duration of the TLS handshake
+    /// * `certificate_and_key` - certificate and key used to announce to clients, if TLS is terminated here
+    /// * `dns_hostname` - the name requests must be addressed to, if any
+    /// * `http_endpoint` - the path requests must be sent to, typically `/dns-query`
+    #[cfg(feature = "dns-over-https-rustls")]
+    pub fn register_http1_listener(
+        &mut self,
+        listener: net::TcpListener,
+        handshake_timeout: Duration,
+        certificate_and_key: Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>,
+        dns_hostname: Option<String>,
+        http_endpoint: String,
+    ) -> io::Result<()> {
+        use tokio_rustls::TlsAcceptor;
+
+        use crate::proto::rustls::tls_server;
+        use crate::server::h1_handler::h1_handler;
+
+        let dns_hostname: Option<Arc<str>> = dns_hostname.map(|n| n.into());
+        let http_endpoint: Arc<str> = Arc::from(http_endpoint);
+
+        let handler = self.handler.clone();
+        let access = self.access.clone();
+        debug!("registered http/1.1: {listener:?}");
+
+        let tls_acceptor = certificate_and_key
+            .map(|(cert, key)| {
+                let mut tls_config = tls_server::new_acceptor(cert, key).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("error creating TLS acceptor: {e}"),
+                    )
+                })?;
+                tls_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+                Ok::<_, io::Error>(TlsAcceptor::from(Arc::new(tls_config)))
+            })
+            .transpose()?;
+
+        // for each incoming request...
+        let shutdown = self.shutdown_token.clone();
+        self.join_set.spawn(async move {
+            let mut inner_join_set = JoinSet::new();
+            loop {
+                let shutdown = shutdown.clone();
+                let (tcp_stream, src_addr) = tokio::select! {
+                    tcp_stream = listener.accept() => match tcp_stream {
+                        Ok((t, s)) => (t, s),
+                        Err(e) => {
+                            debug!("error receiving HTTP/1.1 tcp_stream error: {}", e);
+                            if is_unrecoverable_socket_error(&e) {
+                                break;
+                            }
+                            continue;
+                        },
+                    },
+                    _ = shutdown.cancelled() => {
+                        // A graceful shutdown was initiated. Break out of the loop.
+                        break;
+                    },
+                };
+
+                // verify that the src address is safe for responses
+                if let Err(e) = sanitize_src_address(src_addr) {
+                    warn!("address can not be responded to {src_addr}: {e}");
+                    continue;
+                }
+
+                let handler = handler.clone();
+                let access = access.clone();
+                let tls_acceptor = tls_acceptor.clone();
+                let dns_hostname = dns_hostname.clone();
+                let http_endpoint = http_endpoint.clone();
+
+                inner_join_set.spawn(async move {
+                    debug!("starting HTTP/1.1 request from: {src_addr}");
+
+                    let Some(tls_acceptor) = tls_acceptor else {
+                        h1_handler(
+                            access,
+                            handler,
+                            tcp_stream,
+                            src_addr,
+                            dns_hostname,
+                            http_endpoint,
+                            shutdown,
+                        )
+                        .await;
+                        return;
+                    };
+
+                    let Ok(tls_stream) =
+                        timeout(handshake_timeout, tls_acceptor.accept(tcp_stream)).await
+                    else {
+                        warn!("http/1.1 timeout expired during handshake");
+                        return;
+                    };
+
+                    let tls_stream = match tls_stream {
+                        Ok(tls_stream) => tls_stream,
+                        Err(e) => {
+                            debug!("http/1.1 handshake src: {src_addr} error: {e}");
+                            return;
+                        }
+                    };
+                    debug!("accepted HTTP/1.1 request from: {src_addr}");
+
+                    h1_handler(
                         access,
                         handler,
                         tls_stream,
                         src_addr,
                         dns_hostname,
                         http_endpoint,
-                        shutdown.clone(),
+                        shutdown,
                     )
                     .await;
                 });
@@ -967,12 +1136,14 @@ fn reap_tasks(join_set: &mut JoinSet<()>) {
 pub(crate) async fn handle_raw_request<T: RequestHandler>(
     message: SerialMessage,
     protocol: Protocol,
-    access: Arc<AccessControl>,
+    access: AccessControlHandle,
+    rate_limiter: Option<Arc<ResponseRateLimiter>>,
     request_handler: Arc<T>,
     response_handler: BufDnsStreamHandle,
 ) {
     let src_addr = message.addr();
-    let response_handler = ResponseHandle::new(message.addr(), response_handler, protocol);
+    let response_handler = ResponseHandle::new(message.addr(), response_handler, protocol)
+        .with_rate_limiter(rate_limiter);
 
     handle_request(
         message.bytes(),
@@ -1049,7 +1220,7 @@ pub(crate) async fn handle_request<R: ResponseHandler, T: RequestHandler>(
     message_bytes: &[u8],
     src_addr: SocketAddr,
     protocol: Protocol,
-    access: Arc<AccessControl>,
+    access: AccessControlHandle,
     request_handler: Arc<T>,
     response_handler: R,
 ) {
@@ -1310,6 +1481,8 @@ mod tests {
         rustls_addr: SocketAddr,
         #[cfg(feature = "dns-over-https-rustls")]
         https_rustls_addr: SocketAddr,
+        #[cfg(feature = "dns-over-https-rustls")]
+        http1_addr: SocketAddr,
         #[cfg(feature = "dns-over-quic")]
         quic_addr: SocketAddr,
         #[cfg(feature = "dns-over-h3")]
@@ -1326,6 +1499,8 @@ mod tests {
             let rustls = TcpListener::bind("127.0.0.1:0").await.unwrap();
             #[cfg(feature = "dns-over-https-rustls")]
             let https_rustls = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            #[cfg(feature = "dns-over-https-rustls")]
+            let http1 = TcpListener::bind("127.0.0.1:0").await.unwrap();
             #[cfg(feature = "dns-over-quic")]
             let quic = UdpSocket::bind("127.0.0.1:0").await.unwrap();
             #[cfg(feature = "dns-over-h3")]
@@ -1340,6 +1515,8 @@ mod tests {
                 rustls_addr: rustls.local_addr().unwrap(),
                 #[cfg(feature = "dns-over-https-rustls")]
                 https_rustls_addr: https_rustls.local_addr().unwrap(),
+                #[cfg(feature = "dns-over-https-rustls")]
+                http1_addr: http1.local_addr().unwrap(),
                 #[cfg(feature = "dns-over-quic")]
                 quic_addr: quic.local_addr().unwrap(),
                 #[cfg(feature = "dns-over-h3")]
@@ -1387,6 +1564,15 @@ mod tests {
                         "/dns-query".into(),
                     )
                     .unwrap();
+                server
+                    .register_http1_listener(
+                        TcpListener::bind(self.http1_addr).await.unwrap(),
+                        Duration::from_secs(1),
+                        None,
+                        None,
+                        "/dns-query".into(),
+                    )
+                    .unwrap();
             }
 
             #[cfg(feature = "dns-over-quic")]
@@ -1425,6 +1611,8 @@ mod tests {
             TcpListener::bind(self.rustls_addr).await.unwrap();
             #[cfg(feature = "dns-over-https-rustls")]
             TcpListener::bind(self.https_rustls_addr).await.unwrap();
+            #[cfg(feature = "dns-over-https-rustls")]
+            TcpListener::bind(self.http1_addr).await.unwrap();
             #[cfg(feature = "dns-over-quic")]
             UdpSocket::bind(self.quic_addr).await.unwrap();
             #[cfg(feature = "dns-over-h3")]
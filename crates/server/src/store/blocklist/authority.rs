@@ -273,6 +273,8 @@ impl BlocklistAuthority {
             .any(|entry| self.blocklist.contains_key(entry))
         {
             info!("block list matched query {name}");
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_blocklist_hit();
             return true;
         }
 
@@ -326,7 +328,11 @@ impl Authority for BlocklistAuthority {
         false
     }
 
-    async fn update(&self, _update: &MessageRequest) -> UpdateResult<bool> {
+    async fn update(
+        &self,
+        _request_info: RequestInfo<'_>,
+        _update: &MessageRequest,
+    ) -> UpdateResult<bool> {
         Err(ResponseCode::NotImp)
     }
 
@@ -0,0 +1,309 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+#![cfg(any(feature = "resolver", feature = "recursor"))]
+
+//! Snapshots of resolver caches, written to a file so the cache survives restarts
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use tracing::{debug, info, warn};
+
+#[cfg(feature = "recursor")]
+use crate::recursor::Recursor;
+use crate::resolver::{name_server::ConnectionProvider, Resolver};
+
+/// Default interval between snapshots of the cache, in seconds
+pub(crate) fn cache_save_interval_default() -> u64 {
+    300
+}
+
+/// A cache that can be written to and restored from a snapshot
+pub(crate) trait CacheSnapshot: Send + Sync + 'static {
+    fn save_cache(&self, writer: &mut dyn Write) -> io::Result<usize>;
+
+    fn load_cache(&self, reader: &mut dyn Read) -> io::Result<usize>;
+}
+
+impl<P: ConnectionProvider> CacheSnapshot for Resolver<P> {
+    fn save_cache(&self, writer: &mut dyn Write) -> io::Result<usize> {
+        Self::save_cache(self, writer)
+    }
+
+    fn load_cache(&self, reader: &mut dyn Read) -> io::Result<usize> {
+        Self::load_cache(self, reader)
+    }
+}
+
+#[cfg(feature = "recursor")]
+impl CacheSnapshot for Recursor {
+    fn save_cache(&self, writer: &mut dyn Write) -> io::Result<usize> {
+        Self::save_cache(self, writer)
+    }
+
+    fn load_cache(&self, reader: &mut dyn Read) -> io::Result<usize> {
+        Self::load_cache(self, reader)
+    }
+}
+
+/// The live cache files, so that a new cache file takes over the file of the one it replaces
+static OWNERS: Mutex<Vec<(PathBuf, Weak<dyn HandOver>)>> = Mutex::new(Vec::new());
+
+/// Restores a cache from a file, and periodically writes it back
+///
+/// The cache is written once more when this is dropped, e.g. when the server shuts down. Only one
+///  cache file writes to a path at a time: when an authority is replaced on a reload, the cache
+///  file of the new authority takes the file over from the old one, see [`CacheFile::new`].
+pub(crate) struct CacheFile<C: CacheSnapshot> {
+    shared: Arc<Shared<C>>,
+}
+
+impl<C: CacheSnapshot> CacheFile<C> {
+    /// Restores the cache from `path` if it exists, then writes it every `interval` seconds
+    ///
+    /// If another cache file still writes to `path`, it saves its cache one last time and stops
+    ///  writing, before the cache is restored from it. Restoring reads the file synchronously, as
+    ///  zone files are read, while the periodic writes run on the blocking thread pool.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub(crate) fn new(path: PathBuf, interval: u64, cache: C) -> Self {
+        let shared = Arc::new(Shared {
+            path,
+            cache,
+            owned: Mutex::new(true),
+        });
+
+        let previous = {
+            let mut owners = OWNERS.lock().expect("cache file owners poisoned");
+            owners.retain(|(_, owner)| owner.strong_count() > 0);
+
+            let owner = Arc::downgrade(&shared) as Weak<dyn HandOver>;
+            match owners.iter_mut().find(|(path, _)| path == &shared.path) {
+                Some((_, previous)) => std::mem::replace(previous, owner).upgrade(),
+                None => {
+                    owners.push((shared.path.clone(), owner));
+                    None
+                }
+            }
+        };
+
+        if let Some(previous) = previous {
+            debug!("taking over cache file {}", shared.path.display());
+            previous.hand_over();
+        }
+
+        shared.load();
+
+        tokio::spawn(save_loop(
+            Arc::downgrade(&shared),
+            Duration::from_secs(interval.max(1)),
+        ));
+
+        Self { shared }
+    }
+}
+
+impl<C: CacheSnapshot> Drop for CacheFile<C> {
+    fn drop(&mut self) {
+        self.shared.save();
+    }
+}
+
+/// Periodically writes the cache, until the [`CacheFile`] is dropped or has handed its file over
+async fn save_loop<C: CacheSnapshot>(shared: Weak<Shared<C>>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+
+        if !shared.is_owned() {
+            return;
+        }
+
+        if let Err(e) = tokio::task::spawn_blocking(move || shared.save()).await {
+            warn!("cache save task failed: {e}");
+            return;
+        }
+    }
+}
+
+/// Stops a cache file from writing to its path, for another one to take it over
+trait HandOver: Send + Sync {
+    /// Saves the cache one last time, then never writes to the file again
+    fn hand_over(&self);
+}
+
+struct Shared<C> {
+    path: PathBuf,
+    cache: C,
+    /// Whether the file is still written by this cache, locked for the duration of each write
+    owned: Mutex<bool>,
+}
+
+impl<C: CacheSnapshot> Shared<C> {
+    fn load(&self) {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("no cache file at {}", self.path.display());
+                return;
+            }
+            Err(e) => {
+                warn!("failed to open cache file {}: {e}", self.path.display());
+                return;
+            }
+        };
+
+        match self.cache.load_cache(&mut BufReader::new(file)) {
+            Ok(count) => info!(
+                "restored {count} cached queries from {}",
+                self.path.display()
+            ),
+            Err(e) => warn!("failed to restore cache from {}: {e}", self.path.display()),
+        }
+    }
+
+    fn is_owned(&self) -> bool {
+        *self.owned.lock().expect("cache file lock poisoned")
+    }
+
+    /// Writes the cache, unless the file was handed over to another cache file
+    fn save(&self) {
+        let owned = self.owned.lock().expect("cache file lock poisoned");
+        if *owned {
+            self.write();
+        }
+    }
+
+    fn write(&self) {
+        match self.try_write() {
+            Ok(count) => debug!("saved {count} cached queries to {}", self.path.display()),
+            Err(e) => warn!("failed to save cache to {}: {e}", self.path.display()),
+        }
+    }
+
+    /// Writes to a temporary file first, so a crash never leaves a truncated cache file behind
+    fn try_write(&self) -> io::Result<usize> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        let count = self.cache.save_cache(&mut writer)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        Ok(count)
+    }
+}
+
+impl<C: CacheSnapshot> HandOver for Shared<C> {
+    fn hand_over(&self) {
+        let mut owned = self.owned.lock().expect("cache file lock poisoned");
+        if *owned {
+            self.write();
+            *owned = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, str::FromStr, time::Instant};
+
+    use super::*;
+    use crate::{
+        proto::{
+            op::Query,
+            rr::{rdata::A, Name, RData, Record, RecordType},
+        },
+        resolver::{
+            config::{ResolverConfig, ResolverOpts},
+            dns_lru::{DnsLru, TtlConfig},
+            name_server::TokioConnectionProvider,
+        },
+    };
+
+    fn resolver() -> Resolver<TokioConnectionProvider> {
+        Resolver::new(
+            ResolverConfig::new(),
+            ResolverOpts::default(),
+            TokioConnectionProvider::default(),
+        )
+    }
+
+    /// Writes a snapshot with a single cached query to `path`
+    fn write_snapshot(path: &Path) -> Query {
+        let name = Name::from_str("www.example.com.").unwrap();
+        let query = Query::query(name.clone(), RecordType::A);
+        let lru = DnsLru::new(8, TtlConfig::default());
+        lru.insert_records(
+            query.clone(),
+            [Record::from_rdata(
+                name,
+                300,
+                RData::A(A::new(192, 0, 2, 1)),
+            )]
+            .into_iter(),
+            Instant::now(),
+        );
+
+        lru.save(File::create(path).unwrap()).unwrap();
+        query
+    }
+
+    fn assert_snapshot(path: &Path, query: &Query) {
+        let restored = DnsLru::new(8, TtlConfig::default());
+        assert_eq!(restored.load(File::open(path).unwrap()).unwrap(), 1);
+        assert!(restored.get(query, Instant::now()).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cache_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.bin");
+
+        // restore the snapshot of a populated cache into the resolver
+        let query = write_snapshot(&path);
+        drop(CacheFile::new(path.clone(), 300, resolver()));
+
+        // the resolver cache was written back when the cache file was dropped
+        assert_snapshot(&path, &query);
+    }
+
+    #[tokio::test]
+    async fn test_cache_file_hand_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.bin");
+
+        let query = write_snapshot(&path);
+        let old = CacheFile::new(path.clone(), 300, resolver());
+
+        // the old cache file saves its cache for the new one to restore
+        fs::remove_file(&path).unwrap();
+        let new = CacheFile::new(path.clone(), 300, resolver());
+        assert_snapshot(&path, &query);
+
+        // the old cache file no longer writes once it handed the file over
+        fs::remove_file(&path).unwrap();
+        drop(old);
+        assert!(!path.exists());
+
+        drop(new);
+        assert_snapshot(&path, &query);
+    }
+}
@@ -0,0 +1,446 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+#![cfg(any(feature = "resolver", feature = "recursor"))]
+
+//! DNS64, synthesis of AAAA records from A records for IPv6-only clients behind NAT64
+//!
+//! [RFC 6147](https://tools.ietf.org/html/rfc6147), DNS64, April 2011
+
+use std::{
+    future::Future,
+    net::{Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+};
+
+use ipnet::{Ipv4Net, Ipv6Net};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::{
+    authority::{LookupError, LookupOptions},
+    proto::{
+        op::{Header, Query},
+        rr::{rdata::AAAA, LowerName, Name, RData, Record, RecordType},
+    },
+    resolver::lookup::Lookup,
+};
+
+/// TTL of synthesized records if the AAAA response has no SOA, [RFC 6147, section 5.1.7](https://tools.ietf.org/html/rfc6147#section-5.1.7)
+const MAX_SYNTHESIZED_TTL: u32 = 600;
+
+/// Configuration for DNS64
+#[derive(Clone, Deserialize, Eq, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Dns64Config {
+    /// NAT64 prefixes the IPv4 addresses are mapped into, defaults to the well-known prefix
+    ///  `64:ff9b::/96`. The prefix lengths must be one of 32, 40, 48, 56, 64 or 96, see
+    ///  [RFC 6052](https://tools.ietf.org/html/rfc6052#section-2.2).
+    #[serde(default = "prefixes_default")]
+    pub prefixes: Vec<Ipv6Net>,
+
+    /// Networks of A records that are not mapped to AAAA records
+    #[serde(default)]
+    pub exclude: Vec<Ipv4Net>,
+}
+
+impl Default for Dns64Config {
+    fn default() -> Self {
+        Self {
+            prefixes: prefixes_default(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+fn prefixes_default() -> Vec<Ipv6Net> {
+    vec![Ipv6Net::new(Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0), 96).unwrap()]
+}
+
+/// Returns true if the client validates the responses itself, having set both the DO and CD bits
+///
+/// Records must not be synthesized for these clients, they are answered like by any other
+///  recursive resolver, [RFC 6147, section 5.5](https://tools.ietf.org/html/rfc6147#section-5.5).
+pub(crate) fn client_validates(header: &Header, lookup_options: LookupOptions) -> bool {
+    header.checking_disabled() && lookup_options.dnssec_ok()
+}
+
+/// Synthesizes AAAA records, and answers PTR queries for the synthesized addresses
+pub(crate) struct Dns64 {
+    prefixes: Vec<Ipv6Net>,
+    exclude: Vec<Ipv4Net>,
+}
+
+impl Dns64 {
+    pub(crate) fn new(config: &Dns64Config) -> Result<Self, String> {
+        if config.prefixes.is_empty() {
+            return Err("at least one dns64 prefix is required".to_string());
+        }
+
+        if let Some(prefix) = config
+            .prefixes
+            .iter()
+            .find(|prefix| ![32, 40, 48, 56, 64, 96].contains(&prefix.prefix_len()))
+        {
+            return Err(format!(
+                "invalid dns64 prefix {prefix}, the length must be one of 32, 40, 48, 56, 64 or 96"
+            ));
+        }
+
+        Ok(Self {
+            prefixes: config.prefixes.iter().map(Ipv6Net::trunc).collect(),
+            exclude: config.exclude.clone(),
+        })
+    }
+
+    /// Looks up the records with `lookup`, synthesizing AAAA records if there are none
+    ///
+    /// PTR queries for addresses in the NAT64 prefixes are answered with the PTR records of the
+    ///  IPv4 address.
+    pub(crate) async fn lookup<F, Fut, E>(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup: F,
+    ) -> Result<Lookup, E>
+    where
+        F: Fn(Name, RecordType) -> Fut,
+        Fut: Future<Output = Result<Lookup, E>>,
+        E: Clone + Into<LookupError>,
+    {
+        let name = Name::from(name);
+        match rtype {
+            RecordType::AAAA => self.synthesize(name, lookup).await,
+            RecordType::PTR => match self.ipv4_ptr_name(&name) {
+                Some(ipv4_name) => self.map_ptr(name, ipv4_name, lookup).await,
+                None => lookup(name, rtype).await,
+            },
+            _ => lookup(name, rtype).await,
+        }
+    }
+
+    /// [RFC 6147, section 5.1](https://tools.ietf.org/html/rfc6147#section-5.1)
+    async fn synthesize<F, Fut, E>(&self, name: Name, lookup: F) -> Result<Lookup, E>
+    where
+        F: Fn(Name, RecordType) -> Fut,
+        Fut: Future<Output = Result<Lookup, E>>,
+        E: Clone + Into<LookupError>,
+    {
+        let aaaa = lookup(name.clone(), RecordType::AAAA).await;
+
+        // the TTL of the synthesized records is limited by the negative TTL of the AAAA response
+        let max_ttl = match &aaaa {
+            Ok(lookup) if lookup.record_iter().any(is_aaaa) => return aaaa,
+            Ok(_) => MAX_SYNTHESIZED_TTL,
+            Err(error) => {
+                let error: LookupError = error.clone().into();
+                if error.is_nx_domain() {
+                    return aaaa;
+                }
+
+                error.into_soa().map_or(MAX_SYNTHESIZED_TTL, |soa| {
+                    soa.ttl().min(soa.data().minimum())
+                })
+            }
+        };
+
+        let Ok(a) = lookup(name.clone(), RecordType::A).await else {
+            return aaaa;
+        };
+
+        let mut synthesized = false;
+        let records = a
+            .record_iter()
+            .flat_map(|record| match record.data() {
+                RData::A(a) if !self.is_excluded(a.0) => {
+                    synthesized = true;
+                    let ttl = record.ttl().min(max_ttl);
+                    self.prefixes
+                        .iter()
+                        .map(|prefix| {
+                            let address = embed(prefix, a.0);
+                            Record::from_rdata(
+                                record.name().clone(),
+                                ttl,
+                                RData::AAAA(AAAA(address)),
+                            )
+                        })
+                        .collect()
+                }
+                RData::A(_) => vec![],
+                // the synthesized records are not signed, RFC 6147 section 5.5
+                _ if record.record_type() == RecordType::RRSIG => vec![],
+                _ => vec![record.clone()],
+            })
+            .collect::<Vec<_>>();
+
+        if !synthesized {
+            return aaaa;
+        }
+
+        debug!("dns64 synthesized AAAA records for {name}");
+        Ok(Lookup::new_with_deadline(
+            Query::query(name, RecordType::AAAA),
+            Arc::from(records),
+            a.valid_until(),
+        ))
+    }
+
+    /// [RFC 6147, section 5.3.1](https://tools.ietf.org/html/rfc6147#section-5.3.1)
+    async fn map_ptr<F, Fut, E>(&self, name: Name, ipv4_name: Name, lookup: F) -> Result<Lookup, E>
+    where
+        F: Fn(Name, RecordType) -> Fut,
+        Fut: Future<Output = Result<Lookup, E>>,
+    {
+        debug!("dns64 mapping PTR query for {name} to {ipv4_name}");
+        let ptr = lookup(ipv4_name.clone(), RecordType::PTR).await?;
+
+        let records = ptr
+            .record_iter()
+            .map(|record| {
+                let mut record = record.clone();
+                if record.name() == &ipv4_name {
+                    record.set_name(name.clone());
+                }
+                record
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Lookup::new_with_deadline(
+            Query::query(name, RecordType::PTR),
+            Arc::from(records),
+            ptr.valid_until(),
+        ))
+    }
+
+    /// Returns the in-addr.arpa name for ip6.arpa names of addresses in the NAT64 prefixes
+    fn ipv4_ptr_name(&self, name: &Name) -> Option<Name> {
+        let address = match name.parse_arpa_name().ok()? {
+            ipnet::IpNet::V6(net) if net.prefix_len() == 128 => net.addr(),
+            _ => return None,
+        };
+
+        self.prefixes
+            .iter()
+            .find(|prefix| prefix.contains(&address))
+            .map(|prefix| Name::from(extract(prefix, address)))
+    }
+
+    fn is_excluded(&self, address: Ipv4Addr) -> bool {
+        self.exclude.iter().any(|net| net.contains(&address))
+    }
+}
+
+fn is_aaaa(record: &Record) -> bool {
+    record.record_type() == RecordType::AAAA
+}
+
+/// Embeds the IPv4 address in the NAT64 prefix, skipping bits 64 to 71
+///
+/// [RFC 6052, section 2.2](https://tools.ietf.org/html/rfc6052#section-2.2)
+fn embed(prefix: &Ipv6Net, address: Ipv4Addr) -> Ipv6Addr {
+    let mut octets = prefix.network().octets();
+    for (index, octet) in address_indexes(prefix).zip(address.octets()) {
+        octets[index] = octet;
+    }
+
+    Ipv6Addr::from(octets)
+}
+
+/// Extracts the IPv4 address embedded in the NAT64 prefix, the inverse of [`embed`]
+fn extract(prefix: &Ipv6Net, address: Ipv6Addr) -> Ipv4Addr {
+    let octets = address.octets();
+    let mut ipv4 = [0; 4];
+    for (octet, index) in ipv4.iter_mut().zip(address_indexes(prefix)) {
+        *octet = octets[index];
+    }
+
+    Ipv4Addr::from(ipv4)
+}
+
+fn address_indexes(prefix: &Ipv6Net) -> impl Iterator<Item = usize> {
+    let start = usize::from(prefix.prefix_len() / 8);
+    (start..16).filter(|index| *index != 8).take(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use futures_executor::block_on;
+
+    use super::*;
+    use crate::proto::{
+        op::ResponseCode,
+        rr::rdata::{A, PTR, SOA},
+        ProtoError,
+    };
+
+    #[test]
+    fn test_embed_extract() {
+        let address = Ipv4Addr::new(192, 0, 2, 33);
+        // RFC 6052, section 2.4
+        for (prefix, expected) in [
+            ("2001:db8::/32", "2001:db8:c000:221::"),
+            ("2001:db8:100::/40", "2001:db8:1c0:2:21::"),
+            ("2001:db8:122::/48", "2001:db8:122:c000:2:2100::"),
+            ("2001:db8:122:300::/56", "2001:db8:122:3c0:0:221::"),
+            ("2001:db8:122:344::/64", "2001:db8:122:344:c0:2:2100:0"),
+            ("2001:db8:122:344::/96", "2001:db8:122:344::192.0.2.33"),
+        ] {
+            let prefix = Ipv6Net::from_str(prefix).unwrap();
+            let embedded = embed(&prefix, address);
+            assert_eq!(embedded, Ipv6Addr::from_str(expected).unwrap(), "{prefix}");
+            assert_eq!(extract(&prefix, embedded), address, "{prefix}");
+        }
+    }
+
+    #[cfg(feature = "dnssec")]
+    #[test]
+    fn test_client_validates() {
+        use crate::proto::dnssec::SupportedAlgorithms;
+
+        let dnssec_ok = LookupOptions::for_dnssec(true, SupportedAlgorithms::default());
+        let mut header = Header::new();
+        assert!(!client_validates(&header, dnssec_ok));
+        assert!(!client_validates(&header, LookupOptions::default()));
+
+        header.set_checking_disabled(true);
+        assert!(client_validates(&header, dnssec_ok));
+        assert!(!client_validates(&header, LookupOptions::default()));
+    }
+
+    #[test]
+    fn test_invalid_prefix() {
+        let config = Dns64Config {
+            prefixes: vec![Ipv6Net::from_str("64:ff9b::/80").unwrap()],
+            exclude: vec![],
+        };
+        assert!(Dns64::new(&config).is_err());
+    }
+
+    fn name() -> Name {
+        Name::from_str("www.example.com.").unwrap()
+    }
+
+    fn lookup_a(rtype: RecordType, a: &[(Ipv4Addr, u32)]) -> Result<Lookup, ProtoError> {
+        match rtype {
+            RecordType::A => Ok(Lookup::new_with_max_ttl(
+                Query::query(name(), rtype),
+                a.iter()
+                    .map(|(ip, ttl)| Record::from_rdata(name(), *ttl, RData::A(A(*ip))))
+                    .collect(),
+            )),
+            _ => {
+                let soa = Record::from_rdata(
+                    Name::from_str("example.com.").unwrap(),
+                    3600,
+                    SOA::new(name(), name(), 1, 1, 1, 1, 300),
+                );
+                Err(ProtoError::nx_error(
+                    Box::new(Query::query(name(), rtype)),
+                    Some(Box::new(soa)),
+                    None,
+                    None,
+                    ResponseCode::NoError,
+                    false,
+                    None,
+                ))
+            }
+        }
+    }
+
+    #[test]
+    fn test_synthesize() {
+        let dns64 = Dns64::new(&Dns64Config {
+            exclude: vec![Ipv4Net::from_str("10.0.0.0/8").unwrap()],
+            ..Dns64Config::default()
+        })
+        .unwrap();
+
+        let a = [
+            (Ipv4Addr::new(192, 0, 2, 1), 3600),
+            (Ipv4Addr::new(10, 0, 0, 1), 3600),
+            (Ipv4Addr::new(192, 0, 2, 2), 60),
+        ];
+        let lookup = block_on(dns64.lookup(&name().into(), RecordType::AAAA, |_, rtype| {
+            let result = lookup_a(rtype, &a);
+            async move { result }
+        }))
+        .unwrap();
+
+        let records = lookup
+            .record_iter()
+            .map(|r| (r.data().clone(), r.ttl()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                // the TTL is limited by the SOA minimum of the AAAA response
+                (
+                    RData::AAAA(AAAA::from_str("64:ff9b::192.0.2.1").unwrap()),
+                    300
+                ),
+                (
+                    RData::AAAA(AAAA::from_str("64:ff9b::192.0.2.2").unwrap()),
+                    60
+                ),
+            ]
+        );
+
+        // nothing to synthesize from, the AAAA response is answered
+        let a = [(Ipv4Addr::new(10, 0, 0, 1), 3600)];
+        let error = block_on(dns64.lookup(&name().into(), RecordType::AAAA, |_, rtype| {
+            let result = lookup_a(rtype, &a);
+            async move { result }
+        }))
+        .unwrap_err();
+        assert!(error.is_no_records_found());
+    }
+
+    #[test]
+    fn test_existing_aaaa() {
+        let dns64 = Dns64::new(&Dns64Config::default()).unwrap();
+        let aaaa = RData::AAAA(AAAA::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+
+        let lookup = block_on(
+            dns64.lookup(&name().into(), RecordType::AAAA, |name, rtype| {
+                assert_eq!(rtype, RecordType::AAAA, "no A lookup expected");
+                let result: Result<_, ProtoError> =
+                    Ok(Lookup::from_rdata(Query::query(name, rtype), aaaa.clone()));
+                async move { result }
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(lookup.iter().collect::<Vec<_>>(), vec![&aaaa]);
+    }
+
+    #[test]
+    fn test_map_ptr() {
+        let dns64 = Dns64::new(&Dns64Config::default()).unwrap();
+        let ptr_name = Name::from(Ipv6Addr::from_str("64:ff9b::192.0.2.1").unwrap());
+        let target = RData::PTR(PTR(name()));
+
+        let lookup =
+            block_on(
+                dns64.lookup(&ptr_name.clone().into(), RecordType::PTR, |name, rtype| {
+                    assert_eq!(name, Name::from_str("1.2.0.192.in-addr.arpa.").unwrap());
+                    let result: Result<_, ProtoError> = Ok(Lookup::from_rdata(
+                        Query::query(name, rtype),
+                        target.clone(),
+                    ));
+                    async move { result }
+                }),
+            )
+            .unwrap();
+
+        let record = lookup.record_iter().next().unwrap();
+        assert_eq!(record.name(), &ptr_name);
+        assert_eq!(record.data(), &target);
+    }
+}
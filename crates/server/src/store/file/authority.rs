@@ -146,7 +146,11 @@ impl Authority for FileAuthority {
     }
 
     /// Perform a dynamic update of a zone
-    async fn update(&self, _update: &MessageRequest) -> UpdateResult<bool> {
+    async fn update(
+        &self,
+        _request_info: RequestInfo<'_>,
+        _update: &MessageRequest,
+    ) -> UpdateResult<bool> {
         use crate::proto::op::ResponseCode;
         Err(ResponseCode::NotImp)
     }
@@ -5,7 +5,7 @@
 // https://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use std::io;
+use std::{io, net::IpAddr};
 
 use hickory_resolver::{
     config::ResolveHosts,
@@ -22,11 +22,15 @@ use crate::{
     },
     proto::{
         op::ResponseCode,
-        rr::{LowerName, Name, Record, RecordType},
+        rr::{rdata::opt::ClientSubnet, LowerName, Name, Record, RecordType},
     },
     resolver::{config::ResolverConfig, lookup::Lookup as ResolverLookup, Resolver},
     server::RequestInfo,
-    store::forwarder::ForwardConfig,
+    store::{
+        cache_file::CacheFile,
+        dns64::{self, Dns64},
+        forwarder::ForwardConfig,
+    },
 };
 
 /// An authority that will forward resolutions to upstream resolvers.
@@ -35,6 +39,9 @@ use crate::{
 pub struct ForwardAuthority<P: ConnectionProvider = TokioConnectionProvider> {
     origin: LowerName,
     resolver: Resolver<P>,
+    dns64: Option<Dns64>,
+    /// Writes the cache back to the file when dropped
+    _cache_file: Option<CacheFile<Resolver<P>>>,
 }
 
 impl<P: ConnectionProvider> ForwardAuthority<P> {
@@ -48,6 +55,8 @@ impl<P: ConnectionProvider> ForwardAuthority<P> {
         Ok(Self {
             origin: Name::root().into(),
             resolver,
+            dns64: None,
+            _cache_file: None,
         })
     }
 
@@ -88,9 +97,14 @@ impl<P: ConnectionProvider> ForwardAuthority<P> {
             options.use_hosts_file = ResolveHosts::Never;
         }
 
+        let dns64 = config.dns64.as_ref().map(Dns64::new).transpose()?;
+        let cache_file = config.cache_file.clone();
+        let cache_save_interval = config.cache_save_interval;
         let config = ResolverConfig::from_parts(None, vec![], name_servers);
 
         let resolver = Resolver::new(config, options, runtime);
+        let cache_file =
+            cache_file.map(|path| CacheFile::new(path, cache_save_interval, resolver.clone()));
 
         info!("forward resolver configured: {}: ", origin);
 
@@ -98,6 +112,8 @@ impl<P: ConnectionProvider> ForwardAuthority<P> {
         Ok(Self {
             origin: origin.into(),
             resolver,
+            dns64,
+            _cache_file: cache_file,
         })
     }
 }
@@ -118,6 +134,47 @@ impl ForwardAuthority<TokioConnectionProvider> {
     }
 }
 
+impl<P: ConnectionProvider> ForwardAuthority<P> {
+    async fn forward(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        client_subnet: Option<ClientSubnet>,
+        dns64: Option<&Dns64>,
+    ) -> LookupControlFlow<ForwardLookup> {
+        // TODO: make this an error?
+        debug_assert!(self.origin.zone_of(name));
+
+        debug!("forwarding lookup: {} {}", name, rtype);
+
+        let lookup = |mut name: Name, rtype| async move {
+            // Ignore FQDN when we forward DNS queries. Without this we can't look
+            // up addresses from system hosts file.
+            name.set_fqdn(false);
+
+            match client_subnet {
+                Some(client_subnet) => {
+                    self.resolver
+                        .lookup_with_client_subnet(name, rtype, client_subnet)
+                        .await
+                }
+                None => self.resolver.lookup(name, rtype).await,
+            }
+        };
+
+        let result = match dns64 {
+            Some(dns64) => dns64.lookup(name, rtype, lookup).await,
+            None => lookup(name.into(), rtype).await,
+        };
+
+        use LookupControlFlow::*;
+        match result {
+            Ok(lookup) => Continue(Ok(ForwardLookup(lookup))),
+            Err(e) => Continue(Err(LookupError::from(e))),
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl<P: ConnectionProvider> Authority for ForwardAuthority<P> {
     type Lookup = ForwardLookup;
@@ -132,7 +189,11 @@ impl<P: ConnectionProvider> Authority for ForwardAuthority<P> {
         false
     }
 
-    async fn update(&self, _update: &MessageRequest) -> UpdateResult<bool> {
+    async fn update(
+        &self,
+        _request_info: RequestInfo<'_>,
+        _update: &MessageRequest,
+    ) -> UpdateResult<bool> {
         Err(ResponseCode::NotImp)
     }
 
@@ -152,32 +213,37 @@ impl<P: ConnectionProvider> Authority for ForwardAuthority<P> {
         rtype: RecordType,
         _lookup_options: LookupOptions,
     ) -> LookupControlFlow<Self::Lookup> {
-        // TODO: make this an error?
-        debug_assert!(self.origin.zone_of(name));
-
-        debug!("forwarding lookup: {} {}", name, rtype);
-
-        // Ignore FQDN when we forward DNS queries. Without this we can't look
-        // up addresses from system hosts file.
-        let mut name: Name = name.clone().into();
-        name.set_fqdn(false);
-
-        use LookupControlFlow::*;
-        match self.resolver.lookup(name, rtype).await {
-            Ok(lookup) => Continue(Ok(ForwardLookup(lookup))),
-            Err(e) => Continue(Err(LookupError::from(e))),
-        }
+        self.forward(name, rtype, None, self.dns64.as_ref()).await
     }
 
+    /// Forwards the query with the client subnet of the request, if it has one, or of the client
+    ///  address otherwise. The subnet is only sent if enabled in the resolver options.
+    ///
+    /// DNS64 records are not synthesized for clients that validate the responses themselves.
     async fn search(
         &self,
         request_info: RequestInfo<'_>,
         lookup_options: LookupOptions,
     ) -> LookupControlFlow<Self::Lookup> {
-        self.lookup(
+        let client_subnet = request_info.client_subnet.unwrap_or_else(|| {
+            let address = request_info.src.ip();
+            let source_prefix = match address {
+                IpAddr::V4(_) => 32,
+                IpAddr::V6(_) => 128,
+            };
+            ClientSubnet::new(address, source_prefix, 0)
+        });
+
+        let dns64 = self
+            .dns64
+            .as_ref()
+            .filter(|_| !dns64::client_validates(request_info.header, lookup_options));
+
+        self.forward(
             request_info.query.name(),
             request_info.query.query_type(),
-            lookup_options,
+            Some(client_subnet),
+            dns64,
         )
         .await
     }
@@ -228,4 +294,8 @@ impl LookupObject for ForwardLookup {
     fn take_additionals(&mut self) -> Option<Box<dyn LookupObject>> {
         None
     }
+
+    fn client_subnet_scope(&self) -> Option<u8> {
+        self.0.client_subnet_scope()
+    }
 }
@@ -5,9 +5,12 @@
 // https://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+use std::path::PathBuf;
+
 use serde::Deserialize;
 
 use crate::resolver::config::{NameServerConfigGroup, ResolverOpts};
+use crate::store::{cache_file::cache_save_interval_default, dns64::Dns64Config};
 
 /// Configuration for file based zones
 #[derive(Clone, Deserialize, Debug)]
@@ -17,4 +20,13 @@ pub struct ForwardConfig {
     pub name_servers: NameServerConfigGroup,
     /// Resolver options
     pub options: Option<ResolverOpts>,
+    /// Synthesize AAAA records for IPv6-only clients behind NAT64, RFC 6147; disabled by default
+    #[serde(default)]
+    pub dns64: Option<Dns64Config>,
+    /// File the cache is restored from on startup, and written to periodically and on shutdown
+    #[serde(default)]
+    pub cache_file: Option<PathBuf>,
+    /// Seconds between writes of the cache to the `cache_file`, defaults to 300
+    #[serde(default = "cache_save_interval_default")]
+    pub cache_save_interval: u64,
 }
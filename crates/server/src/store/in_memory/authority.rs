@@ -57,6 +57,7 @@ pub struct InMemoryAuthority {
     class: DNSClass,
     zone_type: ZoneType,
     allow_axfr: bool,
+    axfr_keys: Vec<Name>,
     inner: RwLock<InnerInMemory>,
     #[cfg(feature = "dnssec")]
     nx_proof_kind: Option<NxProofKind>,
@@ -138,6 +139,7 @@ impl InMemoryAuthority {
             class: DNSClass::IN,
             zone_type,
             allow_axfr,
+            axfr_keys: Vec::new(),
             inner: RwLock::new(InnerInMemory::default()),
 
             #[cfg(feature = "dnssec")]
@@ -156,6 +158,21 @@ impl InMemoryAuthority {
         self.allow_axfr = allow_axfr;
     }
 
+    /// Allow zone transfers to requests authenticated with one of these TSIG keys
+    ///
+    /// This is in addition to `allow_axfr`, which allows transfers to everyone.
+    pub fn set_axfr_keys(&mut self, axfr_keys: Vec<Name>) {
+        self.axfr_keys = axfr_keys;
+    }
+
+    /// Returns true if the zone may be transferred in response to the request
+    pub fn is_axfr_allowed_for(&self, request_info: &RequestInfo<'_>) -> bool {
+        self.allow_axfr
+            || request_info
+                .tsig_key
+                .is_some_and(|key| self.axfr_keys.contains(key))
+    }
+
     /// Clears all records (including SOA, etc)
     pub fn clear(&mut self) {
         self.inner.get_mut().records.clear()
@@ -173,6 +190,28 @@ impl InMemoryAuthority {
         records.clone()
     }
 
+    /// Get the record sets at `names`, of all types
+    ///
+    /// Unlike [`Self::records`], this only looks up the names rather than copying all records.
+    pub async fn record_sets_at<'n>(
+        &self,
+        names: impl IntoIterator<Item = &'n LowerName>,
+    ) -> Vec<Arc<RecordSet>> {
+        let inner = self.inner.read().await;
+        names
+            .into_iter()
+            .flat_map(|name| {
+                // this range covers all the records for any of the RecordTypes at a given label.
+                let start_range_key = RrKey::new(name.clone(), RecordType::Unknown(u16::MIN));
+                let end_range_key = RrKey::new(name.clone(), RecordType::Unknown(u16::MAX));
+                inner
+                    .records
+                    .range(start_range_key..=end_range_key)
+                    .map(|(_, rr_set)| rr_set.clone())
+            })
+            .collect()
+    }
+
     /// Get a mutable reference to the records
     pub async fn records_mut(
         &self,
@@ -377,6 +416,147 @@ impl InnerInMemory {
         soa.serial()
     }
 
+    /// Returns the zone cut at or above the name, if the name is delegated to another zone
+    ///
+    /// This is the name closest to the origin, other than the origin itself, with NS records,
+    /// [RFC 1034, section 4.3.2](https://tools.ietf.org/html/rfc1034#section-4.3.2).
+    fn zone_cut(&self, name: &LowerName, origin: &LowerName) -> Option<LowerName> {
+        if !origin.zone_of(name) {
+            return None;
+        }
+
+        let mut ancestors = Vec::new();
+        let mut ancestor = name.clone();
+        while ancestor.num_labels() > origin.num_labels() {
+            let base_name = ancestor.base_name();
+            ancestors.push(ancestor);
+            ancestor = base_name;
+        }
+
+        ancestors.into_iter().rev().find(|ancestor| {
+            self.records
+                .contains_key(&RrKey::new(ancestor.clone(), RecordType::NS))
+        })
+    }
+
+    /// Returns true if the records are authoritative data of the zone
+    ///
+    /// At a zone cut only the DS and NSEC records belong to the zone, the NS records and everything
+    /// below the cut belong to the delegated zone, [RFC 4035, section 2.2](https://tools.ietf.org/html/rfc4035#section-2.2).
+    #[cfg(feature = "dnssec")]
+    fn is_authoritative(
+        &self,
+        name: &LowerName,
+        record_type: RecordType,
+        origin: &LowerName,
+    ) -> bool {
+        match self.zone_cut(name, origin) {
+            Some(cut) => cut == *name && matches!(record_type, RecordType::DS | RecordType::NSEC),
+            None => true,
+        }
+    }
+
+    /// Returns true if the records are covered by the NSEC or NSEC3 chain of the zone, i.e. they
+    /// are authoritative data, or the NS records of a zone cut
+    #[cfg(feature = "dnssec")]
+    fn is_in_nsec_chain(&self, key: &RrKey, origin: &LowerName) -> bool {
+        match self.zone_cut(&key.name, origin) {
+            Some(cut) => {
+                cut == key.name
+                    && matches!(
+                        key.record_type,
+                        RecordType::NS | RecordType::DS | RecordType::NSEC
+                    )
+            }
+            None => true,
+        }
+    }
+
+    /// Returns the referral to the name servers of the zone delegated at the cut
+    fn referral(
+        &self,
+        cut: &LowerName,
+        origin: &LowerName,
+        lookup_options: LookupOptions,
+        #[cfg(feature = "dnssec")] nx_proof_kind: Option<&NxProofKind>,
+    ) -> LookupError {
+        let Some(ns) = self.records.get(&RrKey::new(cut.clone(), RecordType::NS)) else {
+            return LookupError::from(ResponseCode::ServFail);
+        };
+
+        // glue is only known for the name servers inside of this zone
+        let glue = ns
+            .records_without_rrsigs()
+            .filter_map(|record| record.data().as_ns())
+            .map(|ns| LowerName::from(&ns.0))
+            .filter(|target| origin.zone_of(target))
+            .flat_map(|target| {
+                [RecordType::A, RecordType::AAAA]
+                    .into_iter()
+                    .filter_map(move |record_type| {
+                        self.records.get(&RrKey::new(target.clone(), record_type))
+                    })
+            })
+            .cloned()
+            .collect();
+
+        #[allow(unused_mut)]
+        let mut name_servers = vec![ns.clone()];
+
+        // the DS records, or the proof that there are none, are signed by this zone
+        #[cfg(feature = "dnssec")]
+        if lookup_options.dnssec_ok() {
+            let ds = self
+                .records
+                .get(&RrKey::new(cut.clone(), RecordType::DS))
+                .cloned()
+                .or_else(|| self.no_ds_proof(cut, origin, nx_proof_kind?));
+
+            name_servers.extend(ds);
+        }
+
+        LookupError::Referral {
+            name_servers: LookupRecords::many(lookup_options, name_servers),
+            glue: LookupRecords::many(lookup_options, glue),
+        }
+    }
+
+    /// Returns the NSEC or NSEC3 record proving that there are no DS records at the zone cut, and
+    /// the delegation is insecure, [RFC 4035, section 3.1.4](https://tools.ietf.org/html/rfc4035#section-3.1.4)
+    #[cfg(feature = "dnssec")]
+    fn no_ds_proof(
+        &self,
+        cut: &LowerName,
+        origin: &LowerName,
+        nx_proof_kind: &NxProofKind,
+    ) -> Option<Arc<RecordSet>> {
+        let name = match nx_proof_kind {
+            NxProofKind::Nsec => RrKey::new(cut.clone(), RecordType::NSEC),
+            NxProofKind::Nsec3 {
+                algorithm,
+                salt,
+                iterations,
+            } => {
+                let info = Nsec3QueryInfo {
+                    qname: cut,
+                    qtype: RecordType::DS,
+                    has_wildcard_match: false,
+                    algorithm: *algorithm,
+                    salt,
+                    iterations: *iterations,
+                };
+
+                let hashed_name = info
+                    .get_hashed_owner_name(cut, &Name::from(origin))
+                    .map_err(|e| warn!("failed to hash zone cut {cut}: {e}"))
+                    .ok()?;
+                RrKey::new(hashed_name, RecordType::NSEC3)
+            }
+        };
+
+        self.records.get(&name).cloned()
+    }
+
     fn inner_lookup(
         &self,
         name: &LowerName,
@@ -467,6 +647,7 @@ impl InnerInMemory {
     /// * original_query_type - original type in the request query
     /// * next_name - the name from the CNAME, ANAME, MX, etc. record that is being searched
     /// * search_type - the root search type, ANAME, CNAME, MX, i.e. the beginning of the chain
+    /// * origin - the origin of the zone, aliases are not followed into delegated zones
     /// * lookup_options - Query-related lookup options (e.g., DNSSEC DO bit, supported hash
     ///                    algorithms, etc.)
     fn additional_search(
@@ -474,7 +655,8 @@ impl InnerInMemory {
         original_name: &LowerName,
         original_query_type: RecordType,
         next_name: LowerName,
-        _search_type: RecordType,
+        search_type: RecordType,
+        origin: &LowerName,
         lookup_options: LookupOptions,
     ) -> Option<Vec<Arc<RecordSet>>> {
         let mut additionals: Vec<Arc<RecordSet>> = vec![];
@@ -507,6 +689,13 @@ impl InnerInMemory {
                     break;
                 }
 
+                // The records of a delegated zone are not authoritative answers
+                if matches!(search_type, RecordType::CNAME | RecordType::ANAME)
+                    && self.zone_cut(&search, origin).is_some()
+                {
+                    break;
+                }
+
                 let additional = self.inner_lookup(&search, *query_type, lookup_options);
                 names.insert(search);
 
@@ -705,7 +894,11 @@ impl InnerInMemory {
 
         {
             let mut nsec_info: Option<(&Name, Vec<RecordType>)> = None;
-            for key in self.records.keys() {
+            for key in self
+                .records
+                .keys()
+                .filter(|key| self.is_in_nsec_chain(key, origin))
+            {
                 match &mut nsec_info {
                     None => nsec_info = Some((&key.name, vec![key.record_type])),
                     Some((name, vec)) if LowerName::new(name) == key.name => {
@@ -777,16 +970,23 @@ impl InnerInMemory {
         // domain name.
         let mut record_types = HashMap::new();
 
-        for key in self.records.keys() {
+        for key in self
+            .records
+            .keys()
+            .filter(|key| self.is_in_nsec_chain(key, origin))
+        {
+            // The NS records of a zone cut are not signed, RFC 5155 section 7.1
+            let signed = key.record_type != RecordType::NS || key.name == *origin;
+
             // Store the type of the current record under its domain name
             match record_types.entry(key.name.clone()) {
                 Entry::Occupied(mut entry) => {
                     let (rtypes, exists): &mut (HashSet<RecordType>, bool) = entry.get_mut();
                     rtypes.insert(key.record_type);
-                    *exists = true;
+                    *exists |= signed;
                 }
                 Entry::Vacant(entry) => {
-                    entry.insert((HashSet::from([key.record_type]), true));
+                    entry.insert((HashSet::from([key.record_type]), signed));
                 }
             }
 
@@ -942,6 +1142,15 @@ impl InnerInMemory {
         debug!("signing zone: {}", origin);
 
         let minimum_ttl = self.minimum_ttl(origin);
+
+        // the NS records at zone cuts, and the glue below them, are not signed
+        let delegated = self
+            .records
+            .keys()
+            .filter(|key| !self.is_authoritative(&key.name, key.record_type, origin))
+            .cloned()
+            .collect::<HashSet<_>>();
+
         let secure_keys = &self.secure_keys;
         let records = &mut self.records;
 
@@ -954,7 +1163,10 @@ impl InnerInMemory {
         }
 
         // sign all record_sets, as of 0.12.1 this includes DNSKEY
-        for rr_set_orig in records.values_mut() {
+        for (_, rr_set_orig) in records
+            .iter_mut()
+            .filter(|(key, _)| !delegated.contains(key))
+        {
             // because the rrset is an Arc, it must be cloned before mutated
             let rr_set = Arc::make_mut(rr_set_orig);
             Self::sign_rrset(rr_set, secure_keys, minimum_ttl, dns_class)?;
@@ -1145,7 +1357,11 @@ impl Authority for InMemoryAuthority {
     ///
     /// true if any of additions, updates or deletes were made to the zone, false otherwise. Err is
     ///  returned in the case of bad data, etc.
-    async fn update(&self, _update: &MessageRequest) -> UpdateResult<bool> {
+    async fn update(
+        &self,
+        _request_info: RequestInfo<'_>,
+        _update: &MessageRequest,
+    ) -> UpdateResult<bool> {
         Err(ResponseCode::NotImp)
     }
 
@@ -1177,6 +1393,22 @@ impl Authority for InMemoryAuthority {
     ) -> LookupControlFlow<Self::Lookup> {
         let inner = self.inner.read().await;
 
+        // names at or below a zone cut are answered with a referral, except for the DS records at
+        // the cut, which are part of this zone
+        if query_type != RecordType::AXFR {
+            if let Some(cut) = inner.zone_cut(name, self.origin()) {
+                if !(query_type == RecordType::DS && cut == *name) {
+                    return LookupControlFlow::Continue(Err(inner.referral(
+                        &cut,
+                        self.origin(),
+                        lookup_options,
+                        #[cfg(feature = "dnssec")]
+                        self.nx_proof_kind.as_ref(),
+                    )));
+                }
+            }
+        }
+
         // Collect the records from each rr_set
         let (result, additionals): (LookupControlFlow<LookupRecords, _>, Option<LookupRecords>) =
             match query_type {
@@ -1207,6 +1439,7 @@ impl Authority for InMemoryAuthority {
                                     query_type,
                                     search_name,
                                     search_type,
+                                    self.origin(),
                                     lookup_options,
                                 )
                                 .map(|adds| (adds, search_type))
@@ -1351,9 +1584,8 @@ impl Authority for InMemoryAuthority {
 
         // if this is an AXFR zone transfer, verify that this is either the Secondary or Primary
         //  for AXFR the first and last record must be the SOA
-        if RecordType::AXFR == record_type {
-            // TODO: support more advanced AXFR options
-            if !self.is_axfr_allowed() {
+        if let RecordType::AXFR | RecordType::IXFR = record_type {
+            if !self.is_axfr_allowed_for(&request_info) {
                 return LookupControlFlow::Continue(Err(LookupError::from(ResponseCode::Refused)));
             }
 
@@ -1375,7 +1607,8 @@ impl Authority for InMemoryAuthority {
                 self.lookup(self.origin(), record_type, lookup_options)
                     .await
             }
-            RecordType::AXFR => {
+            // without a history of changes, IXFR is answered with the full zone, RFC 1995 section 4
+            RecordType::AXFR | RecordType::IXFR => {
                 // TODO: shouldn't these SOA's be secure? at least the first, perhaps not the last?
                 use LookupControlFlow::Continue;
                 let start_soa = if let Continue(Ok(res)) = self.soa_secure(lookup_options).await {
@@ -1389,8 +1622,9 @@ impl Authority for InMemoryAuthority {
                     LookupRecords::Empty
                 };
 
-                let records = if let Continue(Ok(res)) =
-                    self.lookup(lookup_name, record_type, lookup_options).await
+                let records = if let Continue(Ok(res)) = self
+                    .lookup(lookup_name, RecordType::AXFR, lookup_options)
+                    .await
                 {
                     res.unwrap_records()
                 } else {
@@ -8,9 +8,14 @@
 //! All persistent store implementations
 
 pub mod blocklist;
+mod cache_file;
+pub mod dns64;
 pub mod file;
 pub mod forwarder;
 pub mod in_memory;
+#[cfg(feature = "sqlite")]
+mod notify;
 pub mod recursor;
+pub mod secondary;
 #[cfg(feature = "sqlite")]
 pub mod sqlite;
@@ -0,0 +1,107 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Sending NOTIFY to secondaries when a zone changes, see RFC 1996
+
+use std::{net::SocketAddr, time::Duration};
+
+use futures_util::StreamExt;
+use tracing::{debug, info, warn};
+
+use crate::proto::{
+    op::{Message, MessageType, OpCode, Query, ResponseCode},
+    rr::{Name, Record, RecordType},
+    runtime::{TokioRuntimeProvider, TokioTime},
+    udp::UdpClientStream,
+    xfer::{DnsExchange, DnsHandle, DnsRequest, DnsRequestOptions},
+    ProtoError,
+};
+
+/// Number of times a NOTIFY is sent before giving up on a target
+const NOTIFY_ATTEMPTS: usize = 5;
+
+/// Time to wait for the response to a NOTIFY
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before the first retransmission of a NOTIFY, doubled for each later one
+const NOTIFY_RETRY: Duration = Duration::from_secs(2);
+
+/// Sends a NOTIFY for the zone to each of the targets, in the background.
+///
+/// [RFC 1996](https://tools.ietf.org/html/rfc1996), NOTIFY, August 1996
+///
+/// ```text
+/// 3.6. If a NOTIFY request is received by a slave who does not
+///    implement the NOTIFY opcode, it will respond with a NOTIMP
+///    (unimplemented feature error) message.  A master server who receives
+///    such a NOTIMP should consider the NOTIFY transaction complete for
+///    that slave.
+/// ```
+///
+/// NOTIFY is retried until the target responds, for up to `NOTIFY_ATTEMPTS` attempts. As section
+/// 3.6 suggests, the interval between retransmissions grows: it is `NOTIFY_RETRY` before the
+/// first one, and doubles for each later one.
+pub(crate) fn spawn_notify(origin: &Name, soa: &Record, targets: &[SocketAddr]) {
+    for target in targets.iter().copied() {
+        let origin = origin.clone();
+        let soa = soa.clone();
+
+        tokio::spawn(async move {
+            let mut retry = NOTIFY_RETRY;
+            for attempt in 1..=NOTIFY_ATTEMPTS {
+                if attempt > 1 {
+                    tokio::time::sleep(retry).await;
+                    retry *= 2;
+                }
+
+                match notify(target, &origin, &soa).await {
+                    Ok(response_code) => {
+                        info!("notified {target} of {origin} change: {response_code}");
+                        return;
+                    }
+                    Err(e) => {
+                        debug!("NOTIFY of {origin} to {target} failed, attempt {attempt}: {e}")
+                    }
+                }
+            }
+
+            warn!("no response from {target} to NOTIFY of {origin}, giving up");
+        });
+    }
+}
+
+async fn notify(
+    target: SocketAddr,
+    origin: &Name,
+    soa: &Record,
+) -> Result<ResponseCode, ProtoError> {
+    let stream = UdpClientStream::builder(target, TokioRuntimeProvider::new())
+        .with_timeout(Some(NOTIFY_TIMEOUT))
+        .build();
+    let (exchange, background) = DnsExchange::connect::<_, _, TokioTime>(stream).await?;
+    tokio::spawn(background);
+
+    let mut message = Message::new();
+    message
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Notify)
+        .set_authoritative(true)
+        .add_query(Query::query(origin.clone(), RecordType::SOA))
+        // the current SOA is a hint to the secondary, section 3.7
+        .add_answer(soa.clone());
+
+    let mut options = DnsRequestOptions::default();
+    options.recursion_desired = false;
+
+    let response = exchange
+        .send(DnsRequest::new(message, options))
+        .next()
+        .await
+        .ok_or_else(|| ProtoError::from("no response to NOTIFY"))??;
+
+    Ok(response.response_code())
+}
@@ -25,7 +25,11 @@ use crate::{
         lookup::Lookup,
     },
     server::RequestInfo,
-    store::recursor::RecursiveConfig,
+    store::{
+        cache_file::CacheFile,
+        dns64::{self, Dns64},
+        recursor::RecursiveConfig,
+    },
 };
 #[cfg(feature = "dnssec")]
 use crate::{
@@ -40,6 +44,9 @@ use crate::{
 pub struct RecursiveAuthority {
     origin: LowerName,
     recursor: Recursor,
+    dns64: Option<Dns64>,
+    /// Writes the cache back to the file when dropped
+    _cache_file: Option<CacheFile<Recursor>>,
 }
 
 impl RecursiveAuthority {
@@ -68,6 +75,8 @@ impl RecursiveAuthority {
                 trust_negative_responses: false,
                 #[cfg(feature = "dns-over-rustls")]
                 tls_config: None,
+                #[cfg(feature = "dns-over-odoh")]
+                odoh_config: None,
                 bind_addr: None, // TODO: need to support bind addresses
             });
 
@@ -79,10 +88,14 @@ impl RecursiveAuthority {
                 trust_negative_responses: false,
                 #[cfg(feature = "dns-over-rustls")]
                 tls_config: None,
+                #[cfg(feature = "dns-over-odoh")]
+                odoh_config: None,
                 bind_addr: None,
             });
         }
 
+        let dns64 = config.dns64.as_ref().map(Dns64::new).transpose()?;
+
         let mut builder = Recursor::builder();
         if let Some(ns_cache_size) = config.ns_cache_size {
             builder = builder.ns_cache_size(ns_cache_size);
@@ -93,6 +106,7 @@ impl RecursiveAuthority {
 
         let recursor = builder
             .dnssec_policy(config.dnssec_policy.load()?)
+            .aggressive_nsec(config.aggressive_nsec)
             .nameserver_filter(config.allow_server.iter(), config.deny_server.iter())
             .recursion_limit(match config.recursion_limit {
                 0 => None,
@@ -103,15 +117,53 @@ impl RecursiveAuthority {
                 limit => Some(limit),
             })
             .avoid_local_udp_ports(config.avoid_local_udp_ports.clone())
-            .ttl_config(config.cache_policy.clone())
+            .ttl_config({
+                let mut ttl_config = config.cache_policy.clone();
+                ttl_config.with_serve_stale(config.serve_stale);
+                ttl_config
+            })
             .build(roots)
             .map_err(|e| format!("failed to initialize recursor: {e}"))?;
 
+        let cache_file = config
+            .cache_file
+            .clone()
+            .map(|path| CacheFile::new(path, config.cache_save_interval, recursor.clone()));
+
         Ok(Self {
             origin: origin.into(),
             recursor,
+            dns64,
+            _cache_file: cache_file,
         })
     }
+
+    async fn resolve(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+        dns64: Option<&Dns64>,
+    ) -> LookupControlFlow<RecursiveLookup> {
+        debug!("recursive lookup: {} {}", name, rtype);
+
+        let lookup = |name, rtype| {
+            let query = Query::query(name, rtype);
+            self.recursor
+                .resolve(query, Instant::now(), lookup_options.dnssec_ok())
+        };
+
+        let result = match dns64 {
+            Some(dns64) => dns64.lookup(name, rtype, lookup).await,
+            None => lookup(name.into(), rtype).await,
+        };
+
+        use LookupControlFlow::*;
+        match result {
+            Ok(lookup) => Continue(Ok(RecursiveLookup(lookup))),
+            Err(error) => Continue(Err(LookupError::from(error))),
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -132,7 +184,11 @@ impl Authority for RecursiveAuthority {
         self.recursor.is_validating()
     }
 
-    async fn update(&self, _update: &MessageRequest) -> UpdateResult<bool> {
+    async fn update(
+        &self,
+        _request_info: RequestInfo<'_>,
+        _update: &MessageRequest,
+    ) -> UpdateResult<bool> {
         Err(ResponseCode::NotImp)
     }
 
@@ -152,32 +208,26 @@ impl Authority for RecursiveAuthority {
         rtype: RecordType,
         lookup_options: LookupOptions,
     ) -> LookupControlFlow<Self::Lookup> {
-        debug!("recursive lookup: {} {}", name, rtype);
-
-        let query = Query::query(name.into(), rtype);
-        let now = Instant::now();
-
-        let result = self
-            .recursor
-            .resolve(query, now, lookup_options.dnssec_ok())
-            .await;
-
-        use LookupControlFlow::*;
-        match result {
-            Ok(lookup) => Continue(Ok(RecursiveLookup(lookup))),
-            Err(error) => Continue(Err(LookupError::from(error))),
-        }
+        self.resolve(name, rtype, lookup_options, self.dns64.as_ref())
+            .await
     }
 
+    /// DNS64 records are not synthesized for clients that validate the responses themselves.
     async fn search(
         &self,
         request_info: RequestInfo<'_>,
         lookup_options: LookupOptions,
     ) -> LookupControlFlow<Self::Lookup> {
-        self.lookup(
+        let dns64 = self
+            .dns64
+            .as_ref()
+            .filter(|_| !dns64::client_validates(request_info.header, lookup_options));
+
+        self.resolve(
             request_info.query.name(),
             request_info.query.query_type(),
             lookup_options,
+            dns64,
         )
         .await
     }
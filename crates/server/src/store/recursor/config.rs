@@ -30,7 +30,8 @@ use crate::proto::{
     serialize::txt::Parser,
 };
 use crate::recursor::DnssecPolicy;
-use crate::resolver::dns_lru::TtlConfig;
+use crate::resolver::dns_lru::{ServeStaleConfig, TtlConfig};
+use crate::store::{cache_file::cache_save_interval_default, dns64::Dns64Config};
 
 /// Configuration for file based zones
 #[derive(Clone, Deserialize, Eq, PartialEq, Debug)]
@@ -57,6 +58,11 @@ pub struct RecursiveConfig {
     #[serde(default)]
     pub dnssec_policy: DnssecPolicyConfig,
 
+    /// Answer queries from validated NSEC and NSEC3 records in the cache, RFC 8198; only used when
+    /// validating, disabled by default
+    #[serde(default)]
+    pub aggressive_nsec: bool,
+
     /// Networks that will be queried during resolution
     #[serde(default)]
     pub allow_server: Vec<IpNet>,
@@ -72,6 +78,23 @@ pub struct RecursiveConfig {
     /// Caching policy, setting minimum and maximum TTLs
     #[serde(default)]
     pub cache_policy: TtlConfig,
+
+    /// Serve expired records from the cache if the name servers fail, RFC 8767; disabled by default
+    #[serde(default)]
+    pub serve_stale: Option<ServeStaleConfig>,
+
+    /// Synthesize AAAA records for IPv6-only clients behind NAT64, RFC 6147; disabled by default
+    #[serde(default)]
+    pub dns64: Option<Dns64Config>,
+
+    /// File the record cache is restored from on startup, and written to periodically and on
+    /// shutdown
+    #[serde(default)]
+    pub cache_file: Option<PathBuf>,
+
+    /// Seconds between writes of the record cache to the `cache_file`, defaults to 300
+    #[serde(default = "cache_save_interval_default")]
+    pub cache_save_interval: u64,
 }
 
 impl RecursiveConfig {
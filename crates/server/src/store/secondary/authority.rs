@@ -0,0 +1,786 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Secondary authority, replicated from a primary with AXFR
+
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use futures_util::StreamExt;
+use ipnet::IpNet;
+use tokio::sync::{watch, Notify};
+use tracing::{debug, error, info, warn};
+
+#[cfg(feature = "dnssec")]
+use crate::{
+    authority::Nsec3QueryInfo,
+    dnssec::NxProofKind,
+    proto::{
+        dnssec::{rdata::DNSSECRData, tsig::TSigner},
+        rr::RData,
+    },
+};
+use crate::{
+    authority::{
+        Authority, LookupControlFlow, LookupError, LookupOptions, MessageRequest, UpdateResult,
+        ZoneType,
+    },
+    proto::{
+        op::{update_message, Message, MessageFinalizer, Query, ResponseCode},
+        rr::{rdata::SOA, LowerName, Name, Record, RecordSet, RecordType, RrKey, SerialNumber},
+        runtime::{TokioRuntimeProvider, TokioTime},
+        tcp::TcpClientStream,
+        xfer::{
+            DnsExchange, DnsHandle, DnsMultiplexer, DnsRequest, DnsRequestOptions, DnsStreamHandle,
+            SerialMessage,
+        },
+        ProtoError, ProtoErrorKind,
+    },
+    server::RequestInfo,
+    store::{in_memory::InMemoryAuthority, secondary::SecondaryConfig},
+};
+
+/// Delay before retrying a transfer when no SOA has been received from a primary yet
+const DEFAULT_RETRY: Duration = Duration::from_secs(60);
+
+/// Lower bound of the SOA timers, so that a primary can't make the secondary query it continuously
+const MIN_TIMER: Duration = Duration::from_secs(30);
+
+/// SecondaryAuthority serves a zone that is transferred from one or more primary servers.
+///
+/// The zone is pulled with AXFR in the background once the authority is created, and is then kept
+/// up to date following the refresh, retry and expire timers of the primary's SOA record, see
+/// [RFC 1034, section 4.3.5](https://tools.ietf.org/html/rfc1034#section-4.3.5). Until the first
+/// transfer succeeds, and after the zone has expired, all queries are answered with SERVFAIL.
+///
+/// A NOTIFY from a primary, [RFC 1996](https://tools.ietf.org/html/rfc1996), triggers an immediate
+/// refresh. The requests to the primaries are signed with the transfer TSIG key, if one is set.
+/// Dynamic updates are forwarded to the primaries, see
+/// [RFC 2136, section 6](https://tools.ietf.org/html/rfc2136#section-6).
+pub struct SecondaryAuthority {
+    shared: Arc<Shared>,
+    refresh_now: Arc<Notify>,
+    allow_notify: Vec<IpNet>,
+}
+
+impl SecondaryAuthority {
+    /// Creates the authority, and starts transferring the zone from the configured primaries.
+    ///
+    /// The zone is transferred in the background, so that unreachable primaries don't hold up the
+    /// loading of other zones. It answers SERVFAIL until the transfer succeeds, a failed transfer
+    /// is retried. This must be called from within a Tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - The zone `Name` being transferred.
+    /// * `zone_type` - The type of zone, this should be `ZoneType::Secondary`.
+    /// * `allow_axfr` - Whether AXFR is allowed from this server.
+    /// * `config` - The primaries and transfer options for this zone.
+    /// * `nx_proof_kind` - The kind of non-existence proof to be used by the server.
+    /// * `axfr_keys` - Names of the TSIG keys allowed to transfer the zone from this server.
+    /// * `transfer_key` - The TSIG key signing the SOA queries and transfers sent to the primaries.
+    pub async fn try_from_config(
+        origin: Name,
+        zone_type: ZoneType,
+        allow_axfr: bool,
+        config: &SecondaryConfig,
+        #[cfg(feature = "dnssec")] nx_proof_kind: Option<NxProofKind>,
+        #[cfg(feature = "dnssec")] axfr_keys: Vec<Name>,
+        #[cfg(feature = "dnssec")] transfer_key: Option<TSigner>,
+    ) -> Result<Self, String> {
+        if config.primaries.is_empty() {
+            return Err(format!(
+                "no primaries configured for secondary zone {origin}"
+            ));
+        }
+
+        info!("loading secondary zone: {origin}");
+
+        #[allow(unused_mut)]
+        let mut authority = InMemoryAuthority::empty(
+            origin,
+            zone_type,
+            allow_axfr,
+            #[cfg(feature = "dnssec")]
+            nx_proof_kind,
+        );
+        #[cfg(feature = "dnssec")]
+        authority.set_axfr_keys(axfr_keys);
+
+        let shared = Arc::new(Shared {
+            authority,
+            primaries: config.primaries.clone(),
+            timeout: config.transfer_timeout(),
+            #[cfg(feature = "dnssec")]
+            transfer_key,
+            state: Mutex::new(ZoneState::Pending),
+            refreshed: watch::channel(false).0,
+        });
+
+        let refresh_now = Arc::new(Notify::new());
+        tokio::spawn(refresh_loop(
+            Arc::downgrade(&shared),
+            refresh_now.clone(),
+            Duration::ZERO,
+        ));
+
+        Ok(Self {
+            shared,
+            refresh_now,
+            allow_notify: config.allow_notify.clone(),
+        })
+    }
+
+    /// Returns true if the zone has been transferred and has not expired
+    pub fn is_serving(&self) -> bool {
+        self.shared.is_serving()
+    }
+
+    /// Waits for the first transfer of the zone to succeed or fail, returns true if the zone is
+    ///  served
+    pub async fn wait_for_transfer(&self) -> bool {
+        let mut refreshed = self.shared.refreshed.subscribe();
+        // the sender is kept by the authority, the channel is not closed while it is borrowed here
+        while !*refreshed.borrow() {
+            if refreshed.changed().await.is_err() {
+                break;
+            }
+        }
+
+        self.is_serving()
+    }
+
+    /// The primary servers this zone is transferred from
+    pub fn primaries(&self) -> &[SocketAddr] {
+        &self.shared.primaries
+    }
+
+    /// NOTIFY is accepted from the primaries and the configured networks
+    fn is_notify_allowed(&self, src: IpAddr) -> bool {
+        self.shared
+            .primaries
+            .iter()
+            .any(|primary| primary.ip() == src)
+            || self.allow_notify.iter().any(|net| net.contains(&src))
+    }
+}
+
+/// Periodically refreshes the zone, or when notified, until the authority is dropped
+async fn refresh_loop(shared: Weak<Shared>, refresh_now: Arc<Notify>, mut delay: Duration) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => (),
+            _ = refresh_now.notified() => (),
+        }
+
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+
+        delay = match shared.refresh().await {
+            Ok(timers) => {
+                #[cfg(feature = "metrics")]
+                crate::metrics::record_zone_serial(
+                    shared.authority.origin(),
+                    shared.authority.serial().await,
+                );
+                timers.refresh
+            }
+            Err(e) => {
+                let origin = shared.authority.origin();
+                warn!("refresh of secondary zone {origin} failed: {e}");
+                if !shared.is_serving() {
+                    error!("secondary zone {origin} is expired or not loaded, answering SERVFAIL");
+                }
+                shared.retry_delay()
+            }
+        };
+        shared.refreshed.send_replace(true);
+    }
+}
+
+struct Shared {
+    authority: InMemoryAuthority,
+    primaries: Vec<SocketAddr>,
+    timeout: Duration,
+    #[cfg(feature = "dnssec")]
+    transfer_key: Option<TSigner>,
+    state: Mutex<ZoneState>,
+    /// Set once the first refresh, successful or not, is done
+    refreshed: watch::Sender<bool>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ZoneState {
+    /// No transfer has succeeded yet
+    Pending,
+    /// The zone was transferred, or confirmed to be current, at `refreshed`
+    Loaded {
+        refreshed: Instant,
+        timers: SoaTimers,
+    },
+}
+
+/// The refresh, retry and expire intervals of a zone, from its SOA record
+#[derive(Clone, Copy, Debug)]
+struct SoaTimers {
+    refresh: Duration,
+    retry: Duration,
+    expire: Duration,
+}
+
+impl SoaTimers {
+    fn from_soa(soa: &SOA) -> Self {
+        let secs = |value: i32| Duration::from_secs(value.max(0) as u64).max(MIN_TIMER);
+        Self {
+            refresh: secs(soa.refresh()),
+            retry: secs(soa.retry()),
+            expire: secs(soa.expire()),
+        }
+    }
+}
+
+impl Shared {
+    fn state(&self) -> ZoneState {
+        *self
+            .state
+            .lock()
+            .expect("secondary zone state lock poisoned")
+    }
+
+    fn set_loaded(&self, timers: SoaTimers) {
+        *self
+            .state
+            .lock()
+            .expect("secondary zone state lock poisoned") = ZoneState::Loaded {
+            refreshed: Instant::now(),
+            timers,
+        };
+    }
+
+    fn is_serving(&self) -> bool {
+        match self.state() {
+            ZoneState::Pending => false,
+            ZoneState::Loaded { refreshed, timers } => refreshed.elapsed() < timers.expire,
+        }
+    }
+
+    fn retry_delay(&self) -> Duration {
+        match self.state() {
+            ZoneState::Pending => DEFAULT_RETRY,
+            ZoneState::Loaded { timers, .. } => timers.retry,
+        }
+    }
+
+    /// Signs the requests to the primaries with the transfer key
+    fn signer(&self) -> Option<Arc<dyn MessageFinalizer>> {
+        #[cfg(feature = "dnssec")]
+        if let Some(key) = &self.transfer_key {
+            return Some(Arc::new(key.clone()));
+        }
+
+        None
+    }
+
+    /// Checks each primary in turn, transferring the zone if it has changed
+    async fn refresh(&self) -> Result<SoaTimers, String> {
+        let current_serial = match self.state() {
+            ZoneState::Pending => None,
+            ZoneState::Loaded { .. } => Some(self.authority.serial().await),
+        };
+
+        let mut errors = Vec::with_capacity(self.primaries.len());
+        for primary in &self.primaries {
+            match self.refresh_from(*primary, current_serial).await {
+                Ok(timers) => return Ok(timers),
+                Err(e) => {
+                    debug!("refresh from {primary} failed: {e}");
+                    errors.push(format!("{primary}: {e}"));
+                }
+            }
+        }
+
+        Err(errors.join(", "))
+    }
+
+    async fn refresh_from(
+        &self,
+        primary: SocketAddr,
+        current_serial: Option<u32>,
+    ) -> Result<SoaTimers, ProtoError> {
+        let origin = Name::from(self.authority.origin());
+        let exchange = connect(primary, self.timeout, self.signer()).await?;
+
+        if let Some(current_serial) = current_serial {
+            let soa = query_soa(&exchange, &origin).await?;
+            if SerialNumber::from(soa.serial()) <= SerialNumber::from(current_serial) {
+                debug!(
+                    "secondary zone {origin} is current at serial {current_serial} with {primary}"
+                );
+                let timers = SoaTimers::from_soa(&soa);
+                self.set_loaded(timers);
+                return Ok(timers);
+            }
+
+            info!(
+                "secondary zone {origin} has serial {} on {primary}, local serial is {current_serial}",
+                soa.serial()
+            );
+        }
+
+        let (soa, records) = axfr(&exchange, &origin).await?;
+        let record_count = records.len();
+        *self.authority.records_mut().await = records;
+
+        let timers = SoaTimers::from_soa(&soa);
+        self.set_loaded(timers);
+        info!(
+            "secondary zone {origin} transferred from {primary}: serial {} with {record_count} rrsets",
+            soa.serial()
+        );
+
+        Ok(timers)
+    }
+}
+
+/// Opens a TCP connection to the primary, the requests are signed by `signer`
+async fn connect(
+    primary: SocketAddr,
+    timeout: Duration,
+    signer: Option<Arc<dyn MessageFinalizer>>,
+) -> Result<DnsExchange, ProtoError> {
+    let (stream, sender) =
+        TcpClientStream::new(primary, None, Some(timeout), TokioRuntimeProvider::new());
+    let multiplexer = DnsMultiplexer::with_timeout(stream, sender, timeout, signer);
+    let (exchange, background) = DnsExchange::connect::<_, _, TokioTime>(multiplexer).await?;
+    tokio::spawn(background);
+    Ok(exchange)
+}
+
+/// Sends the update to the primary, returning the response of the primary
+///
+/// Each update is sent on a new TCP connection, so the ID of the requestor is kept rather than
+///  allocating a new one as RFC 2136 section 6 suggests. Changing the ID would invalidate a SIG(0)
+///  or TSIG signature, which covers the header.
+async fn send_update(
+    primary: SocketAddr,
+    update: &[u8],
+    id: u16,
+    timeout: Duration,
+) -> Result<Message, ProtoError> {
+    let (stream, mut sender) =
+        TcpClientStream::new(primary, None, Some(timeout), TokioRuntimeProvider::new());
+    let mut stream = stream.await?;
+    sender.send(SerialMessage::new(update.to_vec(), primary))?;
+
+    let response = tokio::time::timeout(timeout, stream.next())
+        .await
+        .map_err(|_| ProtoError::from(ProtoErrorKind::Timeout))?
+        .ok_or_else(|| ProtoError::from("connection closed before response to update"))??;
+
+    let response = Message::from_vec(response.bytes())?;
+    if response.id() != id {
+        return Err(ProtoError::from("response to update has a different id"));
+    }
+
+    Ok(response)
+}
+
+/// Queries the primary for the SOA of the zone
+async fn query_soa(exchange: &DnsExchange, origin: &Name) -> Result<SOA, ProtoError> {
+    let mut options = DnsRequestOptions::default();
+    options.recursion_desired = false;
+
+    let response = exchange
+        .lookup(Query::query(origin.clone(), RecordType::SOA), options)
+        .next()
+        .await
+        .ok_or_else(|| ProtoError::from("no response to SOA query"))??;
+
+    if !response.authoritative() {
+        return Err(ProtoError::from(
+            "primary is not authoritative for the zone",
+        ));
+    }
+
+    response
+        .answers()
+        .iter()
+        .filter(|record| record.name() == origin)
+        .find_map(|record| record.data().as_soa())
+        .cloned()
+        .ok_or_else(|| ProtoError::from("no SOA in response"))
+}
+
+/// Performs an AXFR of the zone, returning the SOA and all records of the zone
+async fn axfr(
+    exchange: &DnsExchange,
+    origin: &Name,
+) -> Result<(SOA, BTreeMap<RrKey, Arc<RecordSet>>), ProtoError> {
+    let mut options = DnsRequestOptions::default();
+    options.recursion_desired = false;
+    let message = update_message::zone_transfer(origin.clone(), None);
+    let mut responses = exchange.send(DnsRequest::new(message, options));
+
+    let mut transfer = Transfer::new(origin);
+    loop {
+        let response = match responses.next().await {
+            Some(response) => response?,
+            None => {
+                return Err(ProtoError::from(
+                    "zone transfer ended before the closing SOA",
+                ))
+            }
+        };
+
+        if response.response_code() != ResponseCode::NoError {
+            return Err(ProtoError::from(format!(
+                "zone transfer failed: {}",
+                response.response_code()
+            )));
+        }
+
+        if transfer.add(response.answers())? {
+            break;
+        }
+    }
+
+    let soa = transfer.soa.expect("zone transfer started with an SOA");
+    Ok((soa.clone(), records_to_map(soa.serial(), transfer.records)))
+}
+
+/// The records of a zone transfer, received up to the closing SOA
+struct Transfer<'a> {
+    origin: &'a Name,
+    /// The SOA the transfer started with
+    soa: Option<SOA>,
+    records: Vec<Record>,
+}
+
+impl<'a> Transfer<'a> {
+    fn new(origin: &'a Name) -> Self {
+        Self {
+            origin,
+            soa: None,
+            records: Vec::new(),
+        }
+    }
+
+    /// Adds the answers of a response, returns true once the transfer is closed by the SOA
+    ///
+    /// The transfer starts and ends with the SOA of the zone, all records must be in the zone.
+    fn add(&mut self, answers: &[Record]) -> Result<bool, ProtoError> {
+        for record in answers {
+            if !self.origin.zone_of(record.name()) {
+                return Err(ProtoError::from(format!(
+                    "zone transfer has a record outside of the zone: {}",
+                    record.name()
+                )));
+            }
+
+            let apex_soa = record
+                .data()
+                .as_soa()
+                .filter(|_| record.name() == self.origin);
+            match (&self.soa, apex_soa) {
+                (None, Some(soa)) => {
+                    self.soa = Some(soa.clone());
+                    self.records.push(record.clone());
+                }
+                (None, None) => {
+                    return Err(ProtoError::from(
+                        "zone transfer did not start with the SOA of the zone",
+                    ));
+                }
+                (Some(start), Some(end)) if start.serial() == end.serial() => return Ok(true),
+                (Some(_), _) => self.records.push(record.clone()),
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// Groups the transferred records into record sets, attaching RRSIGs to the records they cover
+fn records_to_map(serial: u32, records: Vec<Record>) -> BTreeMap<RrKey, Arc<RecordSet>> {
+    let mut rrsets = BTreeMap::<RrKey, RecordSet>::new();
+    #[cfg(feature = "dnssec")]
+    let mut rrsigs = Vec::new();
+
+    for record in records {
+        #[cfg(feature = "dnssec")]
+        if let RData::DNSSEC(DNSSECRData::RRSIG(rrsig)) = record.data() {
+            rrsigs.push((rrsig.type_covered(), record));
+            continue;
+        }
+
+        let rr_key = RrKey::new(record.name().into(), record.record_type());
+        rrsets
+            .entry(rr_key)
+            .or_insert_with(|| RecordSet::new(record.name().clone(), record.record_type(), serial))
+            .insert(record, serial);
+    }
+
+    #[cfg(feature = "dnssec")]
+    for (type_covered, rrsig) in rrsigs {
+        let rr_key = RrKey::new(rrsig.name().into(), type_covered);
+        match rrsets.get_mut(&rr_key) {
+            Some(rrset) => rrset.insert_rrsig(rrsig),
+            None => warn!("dropping RRSIG for missing rrset: {rr_key:?}"),
+        }
+    }
+
+    rrsets
+        .into_iter()
+        .map(|(key, rrset)| (key, Arc::new(rrset)))
+        .collect()
+}
+
+fn serve_failed<T>() -> LookupControlFlow<T> {
+    LookupControlFlow::Continue(Err(LookupError::from(ResponseCode::ServFail)))
+}
+
+#[async_trait::async_trait]
+impl Authority for SecondaryAuthority {
+    type Lookup = <InMemoryAuthority as Authority>::Lookup;
+
+    /// What type is this zone
+    fn zone_type(&self) -> ZoneType {
+        self.shared.authority.zone_type()
+    }
+
+    /// Return true if AXFR is allowed
+    fn is_axfr_allowed(&self) -> bool {
+        self.shared.authority.is_axfr_allowed()
+    }
+
+    /// Forwards the update to the primaries, returning the response code of the first to respond
+    async fn update(
+        &self,
+        _request_info: RequestInfo<'_>,
+        update: &MessageRequest,
+    ) -> UpdateResult<bool> {
+        match self.forward_update(update).await?.response_code() {
+            ResponseCode::NoError => Ok(true),
+            response_code => Err(response_code),
+        }
+    }
+
+    /// Forwards the update to the primaries, returning the response of the first to respond
+    ///
+    /// [RFC 2136](https://tools.ietf.org/html/rfc2136#section-6), DNS Update, April 1997
+    ///
+    /// ```text
+    /// 6.1. The set of forward servers will be same as the set of servers this
+    ///    zone slave would use as the source of AXFR or IXFR data.  So, while
+    ///    the original requestor might have used the zone's NS RRset to locate
+    ///    its update server, a forwarder always forwards toward its designated
+    ///    zone master servers.
+    /// ```
+    ///
+    /// The update is sent as it was received, so any SIG(0) or TSIG signature of the requestor is
+    ///  verified by the primary, and the response of the primary is relayed with its signature.
+    async fn forward_update(&self, update: &MessageRequest) -> Result<Message, ResponseCode> {
+        let message = update.raw_message().ok_or(ResponseCode::FormErr)?;
+
+        for primary in self.primaries() {
+            match send_update(*primary, message, update.id(), self.shared.timeout).await {
+                Ok(response) => {
+                    info!(
+                        "update of {} forwarded to {primary}: {}",
+                        self.origin(),
+                        response.response_code()
+                    );
+                    return Ok(response);
+                }
+                Err(e) => warn!(
+                    "failed to forward update of {} to {primary}: {e}",
+                    self.origin()
+                ),
+            }
+        }
+
+        Err(ResponseCode::ServFail)
+    }
+
+    /// Refreshes the zone from the primaries, if the NOTIFY is from an allowed source.
+    ///
+    /// The refresh happens in the background, the zone is only transferred if the serial of the
+    ///  primary is newer.
+    async fn notify(&self, request_info: RequestInfo<'_>) -> Result<(), ResponseCode> {
+        let src = request_info.src.ip();
+        if !self.is_notify_allowed(src) {
+            warn!(
+                "refusing NOTIFY for secondary zone {} from {src}, not a primary or allowed network",
+                self.origin()
+            );
+            return Err(ResponseCode::Refused);
+        }
+
+        info!("NOTIFY for secondary zone {} from {src}", self.origin());
+        self.refresh_now.notify_one();
+        Ok(())
+    }
+
+    /// Get the origin of this zone, i.e. example.com is the origin for www.example.com
+    fn origin(&self) -> &LowerName {
+        self.shared.authority.origin()
+    }
+
+    /// Looks up all Resource Records matching the given `Name` and `RecordType`, answering
+    /// SERVFAIL if the zone is not loaded or has expired.
+    async fn lookup(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        lookup_options: LookupOptions,
+    ) -> LookupControlFlow<Self::Lookup> {
+        if !self.shared.is_serving() {
+            return serve_failed();
+        }
+
+        self.shared
+            .authority
+            .lookup(name, rtype, lookup_options)
+            .await
+    }
+
+    async fn search(
+        &self,
+        request_info: RequestInfo<'_>,
+        lookup_options: LookupOptions,
+    ) -> LookupControlFlow<Self::Lookup> {
+        if !self.shared.is_serving() {
+            return serve_failed();
+        }
+
+        self.shared
+            .authority
+            .search(request_info, lookup_options)
+            .await
+    }
+
+    async fn get_nsec_records(
+        &self,
+        name: &LowerName,
+        lookup_options: LookupOptions,
+    ) -> LookupControlFlow<Self::Lookup> {
+        if !self.shared.is_serving() {
+            return serve_failed();
+        }
+
+        self.shared
+            .authority
+            .get_nsec_records(name, lookup_options)
+            .await
+    }
+
+    #[cfg(feature = "dnssec")]
+    async fn get_nsec3_records(
+        &self,
+        info: Nsec3QueryInfo<'_>,
+        lookup_options: LookupOptions,
+    ) -> LookupControlFlow<Self::Lookup> {
+        if !self.shared.is_serving() {
+            return serve_failed();
+        }
+
+        self.shared
+            .authority
+            .get_nsec3_records(info, lookup_options)
+            .await
+    }
+
+    #[cfg(feature = "dnssec")]
+    fn nx_proof_kind(&self) -> Option<&NxProofKind> {
+        self.shared.authority.nx_proof_kind()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::proto::rr::{rdata::A, RData};
+
+    fn soa(name: &str, serial: u32) -> Record {
+        Record::from_rdata(
+            Name::from_str(name).unwrap(),
+            3600,
+            RData::SOA(SOA::new(
+                Name::from_str("ns.example.com.").unwrap(),
+                Name::from_str("hostmaster.example.com.").unwrap(),
+                serial,
+                0,
+                0,
+                0,
+                0,
+            )),
+        )
+    }
+
+    fn a(name: &str) -> Record {
+        Record::from_rdata(
+            Name::from_str(name).unwrap(),
+            3600,
+            RData::A(A::new(192, 0, 2, 1)),
+        )
+    }
+
+    #[test]
+    fn test_timers_minimum() {
+        let timers = SoaTimers::from_soa(soa("example.com.", 1).data().as_soa().unwrap());
+        assert_eq!(timers.refresh, MIN_TIMER);
+        assert_eq!(timers.retry, MIN_TIMER);
+        assert_eq!(timers.expire, MIN_TIMER);
+    }
+
+    #[test]
+    fn test_transfer() {
+        let origin = Name::from_str("example.com.").unwrap();
+        let mut transfer = Transfer::new(&origin);
+        assert!(!transfer
+            .add(&[soa("example.com.", 1), a("www.example.com.")])
+            .unwrap());
+        assert!(transfer
+            .add(&[a("example.com."), soa("example.com.", 1)])
+            .unwrap());
+        assert_eq!(transfer.records.len(), 3);
+    }
+
+    #[test]
+    fn test_transfer_outside_of_zone() {
+        let origin = Name::from_str("example.com.").unwrap();
+        let mut transfer = Transfer::new(&origin);
+        assert!(transfer
+            .add(&[soa("example.com.", 1), a("www.example.net.")])
+            .is_err());
+
+        let mut transfer = Transfer::new(&origin);
+        assert!(transfer.add(&[soa("example.net.", 1)]).is_err());
+    }
+
+    #[test]
+    fn test_transfer_closed_at_apex() {
+        let origin = Name::from_str("example.com.").unwrap();
+        let mut transfer = Transfer::new(&origin);
+        assert!(transfer.add(&[a("www.example.com.")]).is_err());
+
+        // an SOA below the apex does not close the transfer
+        let mut transfer = Transfer::new(&origin);
+        assert!(!transfer
+            .add(&[soa("example.com.", 1), soa("sub.example.com.", 1)])
+            .unwrap());
+        assert!(transfer.add(&[soa("example.com.", 1)]).unwrap());
+        assert_eq!(transfer.records.len(), 2);
+    }
+}
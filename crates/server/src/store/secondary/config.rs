@@ -0,0 +1,48 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{net::SocketAddr, time::Duration};
+
+use ipnet::IpNet;
+use serde::Deserialize;
+
+use crate::proto::{rr::Name, ProtoError};
+
+/// Default timeout for a single SOA query or zone transfer, in seconds
+const DEFAULT_TRANSFER_TIMEOUT: u64 = 60;
+
+/// Configuration for secondary zones
+#[derive(Clone, Deserialize, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SecondaryConfig {
+    /// Primary servers the zone is transferred from, tried in order
+    pub primaries: Vec<SocketAddr>,
+    /// Timeout, in seconds, for a single SOA query or zone transfer. Defaults to 60 seconds.
+    pub transfer_timeout: Option<u64>,
+    /// Networks allowed to send NOTIFY for the zone, in addition to the primaries
+    #[serde(default)]
+    pub allow_notify: Vec<IpNet>,
+    /// Name of the TSIG key, from the server's `tsig_keys`, signing the SOA queries and zone
+    ///  transfers sent to the primaries
+    #[serde(default)]
+    pub transfer_key: Option<String>,
+}
+
+impl SecondaryConfig {
+    /// Timeout for a single SOA query or zone transfer
+    pub fn transfer_timeout(&self) -> Duration {
+        Duration::from_secs(self.transfer_timeout.unwrap_or(DEFAULT_TRANSFER_TIMEOUT))
+    }
+
+    /// The name of the TSIG key signing the requests to the primaries
+    pub fn transfer_key(&self) -> Result<Option<Name>, ProtoError> {
+        self.transfer_key
+            .as_deref()
+            .map(|key| Name::parse(key, Some(&Name::root())))
+            .transpose()
+    }
+}
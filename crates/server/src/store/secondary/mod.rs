@@ -0,0 +1,14 @@
+// Copyright 2015-2024 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// https://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// https://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Secondary zones, transferred from a primary server
+
+mod authority;
+mod config;
+
+pub use self::authority::SecondaryAuthority;
+pub use self::config::SecondaryConfig;
@@ -8,30 +8,41 @@
 //! Sqlite database-backed authority
 
 use std::{
+    collections::HashSet,
+    iter,
+    net::SocketAddr,
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        Arc,
+    },
 };
 
 use futures_util::lock::Mutex;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 #[cfg(feature = "dnssec")]
 use LookupControlFlow::Continue;
 
 use crate::{
     authority::{
-        Authority, LookupControlFlow, LookupOptions, MessageRequest, UpdateResult, ZoneType,
+        AuthLookup, Authority, LookupControlFlow, LookupOptions, MessageRequest, UpdateResult,
+        ZoneType,
     },
     error::{PersistenceError, PersistenceErrorKind},
     proto::{
         op::ResponseCode,
-        rr::{DNSClass, LowerName, Name, RData, Record, RecordSet, RecordType, RrKey},
+        rr::{
+            rdata::SOA, DNSClass, LowerName, Name, RData, Record, RecordSet, RecordType, RrKey,
+            SerialNumber,
+        },
     },
     server::RequestInfo,
     store::{
         in_memory::InMemoryAuthority,
-        sqlite::{Journal, SqliteConfig},
+        notify::spawn_notify,
+        sqlite::{persistence::Difference, Journal, SqliteConfig},
     },
 };
 #[cfg(feature = "dnssec")]
@@ -40,6 +51,7 @@ use crate::{
     dnssec::NxProofKind,
     proto::dnssec::{
         rdata::{key::KEY, DNSSECRData},
+        tsig::TSigner,
         DnsSecResult, SigSigner, Verifier,
     },
 };
@@ -52,8 +64,15 @@ use crate::{
 pub struct SqliteAuthority {
     in_memory: InMemoryAuthority,
     journal: Mutex<Option<Journal>>,
+    /// The row of the journal up to which the records are in the zone, see `take_over`
+    journal_row: AtomicI64,
+    /// Set once the zone is taken over by another authority, updates are no longer accepted
+    retired: AtomicBool,
     allow_update: bool,
     is_dnssec_enabled: bool,
+    also_notify: Vec<SocketAddr>,
+    #[cfg(feature = "dnssec")]
+    update_tsig_keys: Vec<TSigner>,
 }
 
 impl SqliteAuthority {
@@ -73,8 +92,13 @@ impl SqliteAuthority {
         Self {
             in_memory,
             journal: Mutex::new(None),
+            journal_row: AtomicI64::new(0),
+            retired: AtomicBool::new(false),
             allow_update,
             is_dnssec_enabled,
+            also_notify: Vec::new(),
+            #[cfg(feature = "dnssec")]
+            update_tsig_keys: Vec::new(),
         }
     }
 
@@ -112,6 +136,7 @@ impl SqliteAuthority {
                 nx_proof_kind,
             );
             let mut authority = Self::new(in_memory, config.allow_update, enable_dnssec);
+            authority.set_also_notify(config.also_notify.clone());
 
             authority
                 .recover_with_journal(&journal)
@@ -142,6 +167,7 @@ impl SqliteAuthority {
             .unwrap();
 
             let mut authority = Self::new(in_memory, config.allow_update, enable_dnssec);
+            authority.set_also_notify(config.also_notify.clone());
 
             // if dynamic update is enabled, enable the journal
             info!("creating new journal: {:?}", journal_path);
@@ -216,16 +242,61 @@ impl SqliteAuthority {
             }
 
             // TODO: COMMIT THE TRANSACTION!!!
+            self.journal_row
+                .store(journal.last_row_id()?, Ordering::Release);
         }
 
         Ok(())
     }
 
     /// Associate a backing Journal with this Authority for Updatable zones
+    ///
+    /// The records in the journal are expected to be in the zone already, as after
+    ///  `recover_with_journal`.
     pub async fn set_journal(&mut self, journal: Journal) {
+        match journal.last_row_id() {
+            Ok(row_id) => self.journal_row.store(row_id, Ordering::Release),
+            Err(error) => warn!("could not read the last row of the journal: {error}"),
+        }
         *self.journal.lock().await = Some(journal);
     }
 
+    /// Takes over the zone from `previous`, an authority loaded earlier from the same journal
+    ///
+    /// The previous authority stops accepting updates, once the update it may be processing is
+    ///  done. The records it journaled since this authority was loaded are then applied to this
+    ///  one, so that no update is lost when this authority replaces it.
+    pub async fn take_over(&self, previous: &Self) -> Result<(), PersistenceError> {
+        let previous_journal = previous.journal.lock().await;
+        previous.retired.store(true, Ordering::Release);
+        drop(previous_journal);
+
+        // the records are applied without journaling them again
+        let journal = self.journal.lock().await.take();
+        let result = match &journal {
+            Some(journal) => self.recover_after(journal).await,
+            None => Ok(()),
+        };
+        *self.journal.lock().await = journal;
+
+        result
+    }
+
+    /// Applies the records journaled after `journal_row`
+    async fn recover_after(&self, journal: &Journal) -> Result<(), PersistenceError> {
+        let mut records = journal.iter_after(self.journal_row.load(Ordering::Acquire));
+        while let Some(record) = records.next() {
+            if record.record_type() == RecordType::AXFR {
+                self.in_memory.records_mut().await.clear();
+            } else if let Err(error) = self.update_records(&[record], false).await {
+                return Err(PersistenceErrorKind::Recovery(error.to_str()).into());
+            }
+            self.journal_row.store(records.row_id(), Ordering::Release);
+        }
+
+        Ok(())
+    }
+
     /// Returns the associated Journal
     #[cfg(any(test, feature = "testing"))]
     pub async fn journal(&self) -> impl Deref<Target = Option<Journal>> + '_ {
@@ -237,6 +308,17 @@ impl SqliteAuthority {
         self.allow_update = allow_update;
     }
 
+    /// Servers to send a NOTIFY to when an update changes the zone
+    pub fn set_also_notify(&mut self, also_notify: Vec<SocketAddr>) {
+        self.also_notify = also_notify;
+    }
+
+    /// TSIG keys which are allowed to update the zone, in addition to the SIG(0) KEYs in the zone
+    #[cfg(feature = "dnssec")]
+    pub fn set_update_tsig_keys(&mut self, update_tsig_keys: Vec<TSigner>) {
+        self.update_tsig_keys = update_tsig_keys;
+    }
+
     /// Get serial
     #[cfg(any(test, feature = "testing"))]
     pub async fn serial(&self) -> u32 {
@@ -467,11 +549,15 @@ impl SqliteAuthority {
     ///   requestor.
     /// ```
     ///
+    /// An update signed with TSIG is authorized if the `Catalog` authenticated it, as recorded in
+    ///  the `tsig_key` of the `request_info`, with one of the keys allowed to update the zone.
     #[cfg(feature = "dnssec")]
     #[allow(clippy::blocks_in_conditions)]
-    pub async fn authorize(&self, update_message: &MessageRequest) -> UpdateResult<()> {
-        use tracing::debug;
-
+    pub async fn authorize(
+        &self,
+        request_info: &RequestInfo<'_>,
+        update_message: &MessageRequest,
+    ) -> UpdateResult<()> {
         // 3.3.3 - Pseudocode for Permission Checking
         //
         //      if (security policy exists)
@@ -490,7 +576,25 @@ impl SqliteAuthority {
             return Err(ResponseCode::Refused);
         }
 
-        // verify sig0, currently the only authorization that is accepted.
+        // the TSIG was verified by the catalog, the key must be one allowed to update the zone
+        if let Some(key_name) = request_info.tsig_key {
+            if !self
+                .update_tsig_keys
+                .iter()
+                .any(|signer| signer.signer_name() == key_name)
+            {
+                warn!(
+                    "TSIG key {key_name} is not allowed to update: {}",
+                    self.origin()
+                );
+                return Err(ResponseCode::Refused);
+            }
+
+            info!("authorized update with TSIG key: {key_name}");
+            return Ok(());
+        }
+
+        // verify sig0
         let sig0s: &[Record] = update_message.sig0();
         debug!("authorizing with: {:?}", sig0s);
         if !sig0s.is_empty() {
@@ -670,14 +774,38 @@ impl SqliteAuthority {
         let mut updated = false;
         let serial: u32 = self.in_memory.serial().await;
 
+        // the journal is locked for the whole update, so that the update is complete when the zone
+        //  is taken over by another authority
+        let journal = self.journal.lock().await;
+        if self.retired.load(Ordering::Acquire) {
+            warn!("zone {} was reloaded, not applying update", self.origin());
+            return Err(ResponseCode::ServFail);
+        }
+
         // the persistence act as a write-ahead log. The WAL will also be used for recovery of a zone
         //  subsequent to a failure of the server.
-        if let Some(journal) = &*self.journal.lock().await {
+        let journaled = if let Some(journal) = &*journal {
             if let Err(error) = journal.insert_records(serial, records) {
                 error!("could not persist update records: {}", error);
                 return Err(ResponseCode::ServFail);
             }
-        }
+            true
+        } else {
+            false
+        };
+
+        // the records at the updated names before the update, the differences are journaled for
+        //  IXFR. Signatures are not journaled, so there is no history for DNSSEC zones.
+        let names = records
+            .iter()
+            .map(|rr| LowerName::from(rr.name()))
+            .collect::<HashSet<_>>();
+        let previous = match self.soa_record().await {
+            Some(soa) if journaled && auto_signing_and_increment && !self.is_dnssec_enabled => {
+                Some((soa, self.records_at(&names).await))
+            }
+            _ => None,
+        };
 
         // 3.4.2.7 - Pseudocode For Update Section Processing
         //
@@ -837,10 +965,133 @@ impl SqliteAuthority {
                 //  dnssec, then we need to do it here...
                 self.in_memory.increment_soa_serial().await;
             }
+
+            let soa = self.soa_record().await;
+            let difference = match (previous, &soa) {
+                (Some((previous_soa, previous)), Some(soa)) => {
+                    let current = self.records_at(&names).await;
+                    let deleted = previous.iter().filter(|r| !current.contains(r));
+                    let added = current.iter().filter(|r| !previous.contains(r));
+
+                    Some(Difference {
+                        from_serial: serial,
+                        to_serial: self.in_memory.serial().await,
+                        deleted: iter::once(previous_soa).chain(deleted.cloned()).collect(),
+                        added: iter::once(soa.clone()).chain(added.cloned()).collect(),
+                    })
+                }
+                _ => None,
+            };
+
+            // journal the new SOA, this closes the version of the zone for the update, and restores
+            //  the serial on recovery.
+            if let Some(journal) = &*journal {
+                let serial = self.in_memory.serial().await;
+                let journaled = soa
+                    .as_ref()
+                    .map_or(Ok(()), |soa| journal.insert_record(serial, soa))
+                    .and_then(|()| {
+                        difference
+                            .map_or(Ok(()), |difference| journal.insert_difference(&difference))
+                    });
+                if let Err(error) = journaled {
+                    error!("could not persist update SOA: {}", error);
+                    return Err(ResponseCode::ServFail);
+                }
+            }
+
+            if let (Some(soa), false) = (soa, self.also_notify.is_empty()) {
+                spawn_notify(soa.name(), &soa, &self.also_notify);
+            }
         }
 
         Ok(updated)
     }
+
+    /// The SOA record of the zone, without any signatures
+    async fn soa_record(&self) -> Option<Record> {
+        let soa_key = RrKey::new(self.origin().clone(), RecordType::SOA);
+        self.in_memory
+            .records()
+            .await
+            .get(&soa_key)
+            .and_then(|rr_set| rr_set.records_without_rrsigs().next().cloned())
+    }
+
+    /// The records at `names`, other than the SOA and signatures
+    async fn records_at(&self, names: &HashSet<LowerName>) -> Vec<Record> {
+        self.in_memory
+            .record_sets_at(names)
+            .await
+            .iter()
+            .filter(|rr_set| rr_set.record_type() != RecordType::SOA)
+            .flat_map(|rr_set| rr_set.records_without_rrsigs().cloned())
+            .collect()
+    }
+
+    /// Builds an incremental zone transfer from the version of the zone at `serial` to the current
+    ///  version.
+    ///
+    /// [RFC 1995](https://tools.ietf.org/html/rfc1995), IXFR, August 1996
+    ///
+    /// ```text
+    /// 4. Response Format
+    ///
+    ///    If incremental zone transfer is available, one or more difference
+    ///    sequences is returned.  The list of difference sequences is preceded
+    ///    and followed by a copy of the server's current version of the SOA.
+    ///
+    ///    Each difference sequence represents one update to the zone (one SOA
+    ///    serial change) consisting of deleted RRs and added RRs.  The first RR
+    ///    of the deleted RRs is the older SOA RR and the first RR of the added
+    ///    RRs is the newer SOA RR.
+    /// ```
+    ///
+    /// Each update journaled since `serial` is sent as its own difference sequence, only the
+    ///  journal entries from the update of `serial` onwards are read. Returns `None` if the journal
+    ///  does not have the history back to `serial`, in which case the full zone should be sent.
+    pub async fn ixfr(&self, serial: u32) -> Result<Option<Vec<Record>>, PersistenceError> {
+        let Some(current_soa) = self.soa_record().await else {
+            return Ok(None);
+        };
+        let current_serial = current_soa.data().as_soa().map_or(0, SOA::serial);
+
+        // the client is up to date, RFC 1995 section 2, reply with the current SOA only
+        if SerialNumber::from(serial) >= SerialNumber::from(current_serial) {
+            return Ok(Some(vec![current_soa]));
+        }
+
+        // signatures are not journaled, the history can not reproduce them
+        if self.is_dnssec_enabled {
+            return Ok(None);
+        }
+
+        let differences = match &*self.journal.lock().await {
+            Some(journal) => journal.select_differences(serial)?,
+            None => return Ok(None),
+        };
+
+        // the history must lead from `serial` to the current version without gaps
+        let mut version = serial;
+        for difference in &differences {
+            if difference.from_serial != version {
+                return Ok(None);
+            }
+            version = difference.to_serial;
+        }
+        if version != current_serial {
+            return Ok(None);
+        }
+
+        let mut records = vec![current_soa.clone()];
+        for difference in differences {
+            records.extend(difference.deleted);
+            records.extend(difference.added);
+        }
+        records.push(current_soa);
+
+        Ok(Some(records))
+    }
 }
 
 impl Deref for SqliteAuthority {
@@ -929,10 +1180,14 @@ impl Authority for SqliteAuthority {
     /// true if any of additions, updates or deletes were made to the zone, false otherwise. Err is
     ///  returned in the case of bad data, etc.
     #[cfg(feature = "dnssec")]
-    async fn update(&self, update: &MessageRequest) -> UpdateResult<bool> {
+    async fn update(
+        &self,
+        request_info: RequestInfo<'_>,
+        update: &MessageRequest,
+    ) -> UpdateResult<bool> {
         //let this = &mut self.in_memory.lock().await;
         // the spec says to authorize after prereqs, seems better to auth first.
-        self.authorize(update).await?;
+        self.authorize(&request_info, update).await?;
         self.verify_prerequisites(update.prerequisites()).await?;
         self.pre_scan(update.updates()).await?;
 
@@ -941,7 +1196,11 @@ impl Authority for SqliteAuthority {
 
     /// Always fail when DNSSEC is disabled.
     #[cfg(not(feature = "dnssec"))]
-    async fn update(&self, _update: &MessageRequest) -> UpdateResult<bool> {
+    async fn update(
+        &self,
+        _request_info: RequestInfo<'_>,
+        _update: &MessageRequest,
+    ) -> UpdateResult<bool> {
         Err(ResponseCode::NotImp)
     }
 
@@ -979,6 +1238,23 @@ impl Authority for SqliteAuthority {
         request_info: RequestInfo<'_>,
         lookup_options: LookupOptions,
     ) -> LookupControlFlow<Self::Lookup> {
+        // incremental transfers come from the journal, otherwise fall back to the full zone
+        if let (RecordType::IXFR, Some(serial)) =
+            (request_info.query.query_type(), request_info.ixfr_serial)
+        {
+            if self.in_memory.is_axfr_allowed_for(&request_info) {
+                match self.ixfr(serial).await {
+                    Ok(Some(records)) => {
+                        return LookupControlFlow::Continue(Ok(AuthLookup::IXFR(records)))
+                    }
+                    Ok(None) => {
+                        debug!("no journal history from serial {serial}, sending full zone")
+                    }
+                    Err(error) => warn!("failed to read journal history for IXFR: {error}"),
+                }
+            }
+        }
+
         self.in_memory.search(request_info, lookup_options).await
     }
 
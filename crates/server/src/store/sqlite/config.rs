@@ -5,6 +5,8 @@
 // https://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+use std::net::SocketAddr;
+
 use serde::Deserialize;
 
 /// Configuration for zone file for sqlite based zones
@@ -18,4 +20,10 @@ pub struct SqliteConfig {
     /// Are updates allowed to this zone
     #[serde(default)]
     pub allow_update: bool,
+    /// Servers sent a NOTIFY when an update changes the zone
+    ///
+    /// Secondaries are not notified otherwise, e.g. those named by NS records of the zone, each
+    ///  should be listed here.
+    #[serde(default)]
+    pub also_notify: Vec<SocketAddr>,
 }
@@ -21,7 +21,7 @@ use crate::proto::rr::Record;
 use crate::proto::serialize::binary::{BinDecodable, BinDecoder, BinEncodable, BinEncoder};
 
 /// The current Journal version of the application
-pub const CURRENT_VERSION: i64 = 1;
+pub const CURRENT_VERSION: i64 = 2;
 
 /// The Journal is the audit log of all changes to a zone after initial creation.
 pub struct Journal {
@@ -59,7 +59,28 @@ impl Journal {
 
     /// this returns an iterator from the beginning of time, to be used to recreate an authority
     pub fn iter(&self) -> JournalIter<'_> {
-        JournalIter::new(self)
+        JournalIter::new(self, 0)
+    }
+
+    /// this returns an iterator over the records inserted after the record at `row_id`
+    pub fn iter_after(&self, row_id: i64) -> JournalIter<'_> {
+        JournalIter::new(self, row_id)
+    }
+
+    /// Returns the row_id of the last record in the journal, or 0 if there are none
+    pub fn last_row_id(&self) -> Result<i64, PersistenceError> {
+        assert!(
+            self.version == CURRENT_VERSION,
+            "schema version mismatch, schema_up() resolves this"
+        );
+
+        let row_id = self.conn.lock().expect("conn poisoned").query_row(
+            "SELECT COALESCE(MAX(_rowid_), 0) FROM records",
+            [],
+            |row| row.get(0),
+        )?;
+
+        Ok(row_id)
     }
 
     /// Inserts a record, this is an append only operation.
@@ -127,6 +148,88 @@ impl Journal {
         Ok(())
     }
 
+    /// Inserts the differences of an update to the zone, used to answer IXFR requests.
+    ///
+    /// The first deleted record should be the SOA before the update, and the first added record
+    ///  the SOA after it, as in an IXFR difference sequence.
+    pub fn insert_difference(&self, difference: &Difference) -> Result<(), PersistenceError> {
+        assert!(
+            self.version == CURRENT_VERSION,
+            "schema version mismatch, schema_up() resolves this"
+        );
+
+        let deleted = emit_records(&difference.deleted)?;
+        let added = emit_records(&difference.added)?;
+        let timestamp = time::OffsetDateTime::now_utc();
+        let from_serial = i64::from(difference.from_serial);
+        let to_serial = i64::from(difference.to_serial);
+
+        let count = self.conn.lock().expect("conn poisoned").execute(
+            "INSERT INTO differences (from_serial, to_serial, timestamp, deleted, added)
+                VALUES ($1, $2, $3, $4, $5)",
+            [
+                &from_serial as &dyn ToSql,
+                &to_serial,
+                &timestamp,
+                &deleted,
+                &added,
+            ],
+        )?;
+        if count != 1 {
+            return Err(PersistenceErrorKind::WrongInsertCount {
+                got: count,
+                expect: 1,
+            }
+            .into());
+        };
+
+        Ok(())
+    }
+
+    /// Selects the differences of the zone since the last update from `from_serial`, in order.
+    ///
+    /// Only the entries from that update onwards are read, the result is empty if no update from
+    ///  `from_serial` was journaled.
+    pub fn select_differences(
+        &self,
+        from_serial: u32,
+    ) -> Result<Vec<Difference>, PersistenceError> {
+        assert!(
+            self.version == CURRENT_VERSION,
+            "schema version mismatch, schema_up() resolves this"
+        );
+
+        let conn = self.conn.lock().expect("conn poisoned");
+        let mut stmt = conn.prepare(
+            "SELECT from_serial, to_serial, deleted, added
+                FROM differences
+                WHERE _rowid_ >= (SELECT MAX(_rowid_) FROM differences WHERE from_serial = $1)
+                ORDER BY _rowid_",
+        )?;
+
+        let rows = stmt
+            .query_map([&i64::from(from_serial)], |row| {
+                Ok((
+                    row.get::<_, u32>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                    row.get::<_, Vec<u8>>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(from_serial, to_serial, deleted, added)| {
+                Ok(Difference {
+                    from_serial,
+                    to_serial,
+                    deleted: read_records(&deleted)?,
+                    added: read_records(&added)?,
+                })
+            })
+            .collect()
+    }
+
     /// Selects a record from the given row_id.
     ///
     /// This allows for the entire set of records to be iterated through, by starting at 0, and
@@ -239,6 +342,7 @@ impl Journal {
             match self.version + 1 {
                 0 => self.version = self.init_up()?,
                 1 => self.version = self.records_up()?,
+                2 => self.version = self.differences_up()?,
                 _ => panic!("incorrect version somewhere"), // valid panic, non-recoverable state
             }
 
@@ -295,6 +399,56 @@ impl Journal {
 
         Ok(1)
     }
+
+    /// adds the differences table, the records deleted and added by each update of the zone, from
+    ///  which incremental zone transfers are answered
+    fn differences_up(&self) -> Result<i64, PersistenceError> {
+        self.conn.lock().expect("conn poisoned").execute(
+            "CREATE TABLE differences (
+                from_serial    INTEGER NOT NULL,
+                to_serial      INTEGER NOT NULL,
+                timestamp      TEXT NOT NULL,
+                deleted        BLOB NOT NULL,
+                added          BLOB NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(2)
+    }
+}
+
+/// The records deleted and added by an update of a zone, from one serial to the next
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Difference {
+    /// The serial of the zone before the update
+    pub from_serial: u32,
+    /// The serial of the zone after the update
+    pub to_serial: u32,
+    /// The deleted records, starting with the SOA before the update
+    pub deleted: Vec<Record>,
+    /// The added records, starting with the SOA after the update
+    pub added: Vec<Record>,
+}
+
+fn emit_records(records: &[Record]) -> Result<Vec<u8>, PersistenceError> {
+    let mut bytes = Vec::with_capacity(512);
+    let mut encoder = BinEncoder::new(&mut bytes);
+    for record in records {
+        record.emit(&mut encoder)?;
+    }
+
+    Ok(bytes)
+}
+
+fn read_records(bytes: &[u8]) -> Result<Vec<Record>, PersistenceError> {
+    let mut decoder = BinDecoder::new(bytes);
+    let mut records = Vec::new();
+    while !decoder.is_empty() {
+        records.push(Record::read(&mut decoder)?);
+    }
+
+    Ok(records)
 }
 
 /// Returns an iterator over all items in a Journal
@@ -306,12 +460,17 @@ pub struct JournalIter<'j> {
 }
 
 impl<'j> JournalIter<'j> {
-    fn new(journal: &'j Journal) -> Self {
+    fn new(journal: &'j Journal, current_row_id: i64) -> Self {
         JournalIter {
-            current_row_id: 0,
+            current_row_id,
             journal,
         }
     }
+
+    /// The row_id of the last record returned
+    pub fn row_id(&self) -> i64 {
+        self.current_row_id
+    }
 }
 
 impl Iterator for JournalIter<'_> {
@@ -619,3 +619,246 @@ async fn test_multiple_cname_additionals() {
         &RData::A(A::new(93, 184, 215, 14))
     );
 }
+
+/// Delegates dept.test.com. to ns1.dept.test.com., with glue
+fn add_delegation(authority: &mut InMemoryAuthority) {
+    let cut = Name::from_str("dept.test.com.").unwrap();
+    let ns = Name::from_str("ns1.dept.test.com.").unwrap();
+
+    authority.upsert_mut(
+        Record::from_rdata(cut, 86400, RData::NS(NS(ns.clone())))
+            .set_dns_class(DNSClass::IN)
+            .clone(),
+        0,
+    );
+    authority.upsert_mut(
+        Record::from_rdata(ns, 86400, RData::A(A::new(192, 0, 2, 1)))
+            .set_dns_class(DNSClass::IN)
+            .clone(),
+        0,
+    );
+}
+
+async fn lookup_test(catalog: &Catalog, name: &str, query_type: RecordType) -> Message {
+    let mut question: Message = Message::new();
+
+    let mut query: Query = Query::new();
+    query.set_name(Name::from_str(name).unwrap());
+    query.set_query_type(query_type);
+
+    question.add_query(query);
+
+    // temp request
+    let question_bytes = question.to_bytes().unwrap();
+    let question_req = MessageRequest::from_bytes(&question_bytes).unwrap();
+    let question_req = Request::new(question_req, ([127, 0, 0, 1], 5553).into(), Protocol::Udp);
+
+    let response_handler = TestResponseHandler::new();
+    catalog
+        .lookup(&question_req, None, response_handler.clone())
+        .await;
+    response_handler.into_message().await
+}
+
+#[tokio::test]
+async fn test_delegation_referral() {
+    let mut test = create_test();
+    add_delegation(&mut test);
+    let origin = test.origin().clone();
+
+    let mut catalog = Catalog::new();
+    catalog.upsert(origin, vec![Arc::new(test)]);
+
+    // names below the zone cut, and the NS records at the cut, are referred to the child zone
+    for (name, query_type) in [
+        ("www.dept.test.com.", RecordType::A),
+        ("ns1.dept.test.com.", RecordType::A),
+        ("dept.test.com.", RecordType::NS),
+        ("dept.test.com.", RecordType::A),
+    ] {
+        let result = lookup_test(&catalog, name, query_type).await;
+
+        assert_eq!(result.response_code(), ResponseCode::NoError, "{name}");
+        assert!(!result.header().authoritative(), "{name}");
+        assert!(result.answers().is_empty(), "{name}");
+
+        let ns = result.name_servers();
+        assert_eq!(ns.len(), 1, "{name}");
+        assert_eq!(*ns[0].name(), Name::from_str("dept.test.com.").unwrap());
+        assert_eq!(
+            ns[0].data(),
+            &RData::NS(NS(Name::from_str("ns1.dept.test.com.").unwrap()))
+        );
+
+        let glue = result.additionals();
+        assert_eq!(glue.len(), 1, "{name}");
+        assert_eq!(glue[0].data(), &RData::A(A::new(192, 0, 2, 1)));
+    }
+
+    // names above the cut are still answered authoritatively
+    let result = lookup_test(&catalog, "www.test.com.", RecordType::A).await;
+    assert!(result.header().authoritative());
+    assert_eq!(
+        result.answers()[0].data(),
+        &RData::A(A::new(94, 184, 216, 34))
+    );
+}
+
+#[tokio::test]
+async fn test_delegation_ds_from_parent() {
+    let mut test = create_test();
+    add_delegation(&mut test);
+    let origin = test.origin().clone();
+
+    let mut catalog = Catalog::new();
+    catalog.upsert(origin, vec![Arc::new(test)]);
+
+    // the DS records are served from the parent side of the cut
+    let result = lookup_test(&catalog, "dept.test.com.", RecordType::DS).await;
+
+    assert_eq!(result.response_code(), ResponseCode::NoError);
+    assert!(result.header().authoritative());
+    assert!(result.answers().is_empty());
+    assert_eq!(result.name_servers().len(), 1);
+    assert_eq!(result.name_servers()[0].record_type(), RecordType::SOA);
+}
+
+#[cfg(feature = "dnssec-openssl")]
+#[tokio::test]
+async fn test_delegation_not_signed() {
+    use hickory_proto::dnssec::{
+        openssl::RsaSigningKey,
+        rdata::{DNSSECRData, DNSKEY},
+        Algorithm, SigSigner, SigningKey, SupportedAlgorithms,
+    };
+    use hickory_server::authority::{LookupError, LookupOptions};
+
+    let mut test = create_test();
+    add_delegation(&mut test);
+
+    let key = RsaSigningKey::generate(Algorithm::RSASHA256).unwrap();
+    let signer = SigSigner::dnssec(
+        DNSKEY::from_key(&key.to_public_key().unwrap(), Algorithm::RSASHA256),
+        Box::new(key),
+        test.origin().clone().into(),
+        time::Duration::weeks(1).try_into().unwrap(),
+    );
+    test.add_zone_signing_key_mut(signer).unwrap();
+    test.secure_zone_mut().unwrap();
+
+    let cut = LowerName::from_str("dept.test.com.").unwrap();
+    let glue = LowerName::from_str("ns1.dept.test.com.").unwrap();
+    let records = test.records().await;
+
+    // the delegation and the glue are not signed, nor part of the NSEC chain
+    assert!(records[&RrKey::new(cut.clone(), RecordType::NS)]
+        .rrsigs()
+        .is_empty());
+    assert!(records[&RrKey::new(glue.clone(), RecordType::A)]
+        .rrsigs()
+        .is_empty());
+    assert!(!records.contains_key(&RrKey::new(glue, RecordType::NSEC)));
+    assert!(
+        !records[&RrKey::new(LowerName::from_str("www.test.com.").unwrap(), RecordType::A)]
+            .rrsigs()
+            .is_empty()
+    );
+
+    // the NSEC at the cut proves that the delegation is insecure
+    let nsec = &records[&RrKey::new(cut.clone(), RecordType::NSEC)];
+    assert!(!nsec.rrsigs().is_empty());
+    let types = nsec
+        .records_without_rrsigs()
+        .next()
+        .and_then(|record| record.data().as_dnssec())
+        .and_then(DNSSECRData::as_nsec)
+        .unwrap()
+        .type_bit_maps();
+    assert!(types.contains(&RecordType::NS));
+    assert!(!types.contains(&RecordType::A));
+
+    let lookup_options = LookupOptions::for_dnssec(true, SupportedAlgorithms::default());
+    let result = test
+        .lookup(&cut, RecordType::A, lookup_options)
+        .await
+        .map_result()
+        .unwrap();
+    let Err(LookupError::Referral { name_servers, .. }) = result else {
+        panic!("expected a referral: {result:?}");
+    };
+    let types = name_servers
+        .iter()
+        .map(Record::record_type)
+        .collect::<Vec<_>>();
+    assert!(types.contains(&RecordType::NS));
+    assert!(types.contains(&RecordType::NSEC));
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_catalog_metrics() {
+    use hickory_server::{metrics::*, server::RequestHandler};
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+
+    metrics::with_local_recorder(&recorder, || {
+        futures::executor::block_on(async {
+            let example = create_example();
+            let mut catalog = Catalog::new();
+            catalog.upsert(example.origin().clone(), vec![Arc::new(example)]);
+            catalog.record_zone_serials().await;
+
+            let mut question = Message::new();
+            question.add_query(Query::query(
+                Name::from_str("www.example.com.").unwrap(),
+                RecordType::A,
+            ));
+            let question_bytes = question.to_bytes().unwrap();
+            let question_req = MessageRequest::from_bytes(&question_bytes).unwrap();
+            let question_req =
+                Request::new(question_req, ([127, 0, 0, 1], 5553).into(), Protocol::Udp);
+
+            catalog
+                .handle_request(&question_req, TestResponseHandler::new())
+                .await;
+        })
+    });
+
+    let metrics = snapshotter.snapshot().into_vec();
+    let find = |name: &str| {
+        metrics
+            .iter()
+            .find(|(key, ..)| key.key().name() == name)
+            .map(|(key, _, _, value)| {
+                let labels = key
+                    .key()
+                    .labels()
+                    .map(|label| (label.key().to_owned(), label.value().to_owned()))
+                    .collect::<Vec<_>>();
+                (labels, value)
+            })
+            .unwrap_or_else(|| panic!("no metric {name}"))
+    };
+
+    let (labels, value) = find(REQUESTS_TOTAL);
+    assert_eq!(value, &DebugValue::Counter(1));
+    for label in [
+        ("protocol", "udp"),
+        ("query_type", "A"),
+        ("response_code", "No Error"),
+        ("zone", "example.com."),
+    ] {
+        assert!(
+            labels.contains(&(label.0.to_owned(), label.1.to_owned())),
+            "{label:?} not in {labels:?}"
+        );
+    }
+
+    let (labels, value) = find(ZONE_SERIAL);
+    assert_eq!(labels, [("zone".to_owned(), "example.com.".to_owned())]);
+    assert_eq!(value, &DebugValue::Gauge(2015082403.0.into()));
+
+    find(RESPONSE_DURATION_SECONDS);
+}
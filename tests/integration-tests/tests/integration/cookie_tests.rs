@@ -0,0 +1,116 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use tokio::net::UdpSocket;
+
+use hickory_client::client::{Client, ClientHandle};
+use hickory_integration::example_authority::create_example;
+use hickory_proto::op::ResponseCode;
+use hickory_proto::rr::rdata::opt::{EdnsCode, EdnsOption};
+use hickory_proto::rr::{DNSClass, Name, RecordType};
+use hickory_proto::runtime::TokioRuntimeProvider;
+use hickory_proto::udp::UdpClientStream;
+use hickory_server::authority::{Catalog, ServerCookies};
+use hickory_server::ServerFuture;
+
+async fn spawn_server(require_threshold: Option<u32>) -> (SocketAddr, ServerFuture<Catalog>) {
+    let authority = create_example();
+    let mut catalog = Catalog::new();
+    catalog.upsert(
+        Name::from_str("example.com.").unwrap().into(),
+        vec![Arc::new(authority)],
+    );
+
+    let mut cookies = ServerCookies::with_random_secret();
+    cookies.set_require_threshold(require_threshold);
+    catalog.set_cookies(Some(cookies));
+
+    let udp_socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = udp_socket.local_addr().unwrap();
+    let mut server = ServerFuture::new(catalog);
+    server.register_socket(udp_socket);
+
+    (addr, server)
+}
+
+async fn connect(addr: SocketAddr, cookies: bool) -> Client {
+    let stream = UdpClientStream::builder(addr, TokioRuntimeProvider::new())
+        .with_cookies(cookies)
+        .build();
+    let (client, driver) = Client::connect(stream).await.expect("failed to connect");
+    tokio::spawn(driver);
+    client
+}
+
+#[tokio::test]
+async fn test_server_cookie_in_response() {
+    let (addr, mut server) = spawn_server(None).await;
+    let mut client = connect(addr, true).await;
+
+    let response = client
+        .query(
+            Name::from_str("www.example.com.").unwrap(),
+            DNSClass::IN,
+            RecordType::A,
+        )
+        .await
+        .expect("query failed");
+
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(!response.answers().is_empty());
+
+    let Some(EdnsOption::Cookie(cookie)) = response
+        .extensions()
+        .as_ref()
+        .and_then(|edns| edns.option(EdnsCode::Cookie))
+    else {
+        panic!("no cookie in response");
+    };
+    assert_eq!(cookie.server().map(<[u8]>::len), Some(16));
+
+    server.shutdown_gracefully().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_required_cookie_retried_after_badcookie() {
+    let (addr, mut server) = spawn_server(Some(0)).await;
+    let mut client = connect(addr, true).await;
+
+    // the first query only has a client cookie, the client retries with the server cookie
+    for _ in 0..2 {
+        let response = client
+            .query(
+                Name::from_str("www.example.com.").unwrap(),
+                DNSClass::IN,
+                RecordType::A,
+            )
+            .await
+            .expect("query failed");
+
+        assert_eq!(response.response_code(), ResponseCode::NoError);
+        assert!(!response.answers().is_empty());
+    }
+
+    server.shutdown_gracefully().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_required_cookie_missing_truncated() {
+    let (addr, mut server) = spawn_server(Some(0)).await;
+    let mut client = connect(addr, false).await;
+
+    let response = client
+        .query(
+            Name::from_str("www.example.com.").unwrap(),
+            DNSClass::IN,
+            RecordType::A,
+        )
+        .await
+        .expect("query failed");
+
+    assert!(response.truncated());
+    assert!(response.answers().is_empty());
+
+    server.shutdown_gracefully().await.unwrap();
+}
@@ -2,10 +2,13 @@ mod catalog_tests;
 mod chained_authority_tests;
 mod client_future_tests;
 mod client_tests;
+mod cookie_tests;
 mod dnssec_client_handle_tests;
 mod lookup_tests;
 mod name_server_pool_tests;
 mod retry_dns_handle_tests;
+mod secondary_authority_tests;
 mod server_future_tests;
 mod sqlite_authority_tests;
 mod truncation_tests;
+mod tsig_tests;
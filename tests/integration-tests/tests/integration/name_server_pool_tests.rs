@@ -86,6 +86,8 @@ fn mock_nameserver_on_send_nx<O: OnSend + Unpin>(
             trust_negative_responses,
             #[cfg(any(feature = "dns-over-rustls", feature = "dns-over-https-rustls"))]
             tls_config: None,
+            #[cfg(feature = "dns-over-odoh")]
+            odoh_config: None,
             bind_addr: None,
         },
         options,
@@ -0,0 +1,441 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+
+use hickory_integration::{example_authority::create_example, TestResponseHandler};
+use hickory_proto::op::{Message, MessageType, OpCode, Query, ResponseCode};
+use hickory_proto::rr::rdata::A;
+use hickory_proto::rr::{LowerName, Name, RData, Record, RecordType};
+use hickory_proto::serialize::binary::{BinDecodable, BinEncodable};
+use hickory_proto::xfer::Protocol;
+use hickory_server::authority::{
+    Authority, Catalog, LookupError, LookupOptions, MessageRequest, ZoneType,
+};
+use hickory_server::server::{Request, RequestHandler};
+use hickory_server::store::in_memory::InMemoryAuthority;
+use hickory_server::store::secondary::{SecondaryAuthority, SecondaryConfig};
+use hickory_server::ServerFuture;
+
+fn create_primary() -> InMemoryAuthority {
+    let mut authority = create_example();
+    authority.set_allow_axfr(true);
+    authority
+}
+
+async fn spawn_primary(
+    authority: Arc<dyn hickory_server::authority::AuthorityObject>,
+) -> (SocketAddr, ServerFuture<Catalog>) {
+    let mut catalog = Catalog::new();
+    catalog.upsert(authority.origin().clone(), vec![authority]);
+
+    let tcp_listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = tcp_listener.local_addr().unwrap();
+    let mut server = ServerFuture::new(catalog);
+    server.register_listener(tcp_listener, Duration::from_secs(5));
+
+    (addr, server)
+}
+
+async fn secondary(primaries: Vec<SocketAddr>) -> SecondaryAuthority {
+    let config = SecondaryConfig {
+        primaries,
+        transfer_timeout: Some(5),
+        allow_notify: vec![],
+        transfer_key: None,
+    };
+
+    SecondaryAuthority::try_from_config(
+        Name::from_str("example.com.").unwrap(),
+        ZoneType::Secondary,
+        false,
+        &config,
+        #[cfg(feature = "dnssec")]
+        None,
+        #[cfg(feature = "dnssec")]
+        vec![],
+        #[cfg(feature = "dnssec")]
+        None,
+    )
+    .await
+    .expect("failed to create secondary")
+}
+
+/// Adds new.example.com to the zone, and increments the serial
+async fn add_record_to_primary(primary: &InMemoryAuthority) {
+    let mut soa = primary.soa().await.unwrap().iter().next().unwrap().clone();
+    let serial = primary.serial().await;
+
+    primary
+        .upsert(
+            Record::from_rdata(
+                Name::from_str("new.example.com.").unwrap(),
+                86400,
+                RData::A(A::new(10, 0, 0, 1)),
+            ),
+            serial,
+        )
+        .await;
+
+    let mut rdata = soa.data().as_soa().unwrap().clone();
+    rdata.increment_serial();
+    soa.set_data(RData::SOA(rdata));
+    primary.upsert(soa, serial).await;
+}
+
+/// Waits for new.example.com to be transferred to the secondary
+async fn wait_for_new_record(secondary: &SecondaryAuthority) -> bool {
+    let name = LowerName::from_str("new.example.com.").unwrap();
+    for _ in 0..50 {
+        let lookup = secondary
+            .lookup(&name, RecordType::A, LookupOptions::default())
+            .await;
+        if let Some(Ok(lookup)) = lookup.map_result() {
+            if !lookup.is_empty() {
+                return true;
+            }
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    false
+}
+
+/// Waits for the zone to be transferred to the secondary
+async fn wait_for_serving(secondary: &SecondaryAuthority) -> bool {
+    for _ in 0..50 {
+        if secondary.is_serving() {
+            return true;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    false
+}
+
+fn notify_request(src: SocketAddr) -> Request {
+    let mut message = Message::new();
+    message
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Notify)
+        .add_query(Query::query(
+            Name::from_str("example.com.").unwrap(),
+            RecordType::SOA,
+        ));
+
+    let bytes = message.to_bytes().unwrap();
+    let message = MessageRequest::from_bytes(&bytes).unwrap();
+    Request::new(message, src, Protocol::Udp)
+}
+
+#[tokio::test]
+async fn test_secondary_transfers_zone() {
+    let (addr, mut server) = spawn_primary(Arc::new(create_primary())).await;
+    let secondary = secondary(vec![addr]).await;
+    assert!(wait_for_serving(&secondary).await);
+
+    let lookup = secondary
+        .lookup(
+            &LowerName::from_str("www.example.com.").unwrap(),
+            RecordType::A,
+            LookupOptions::default(),
+        )
+        .await
+        .expect("lookup failed");
+
+    match lookup
+        .into_iter()
+        .next()
+        .expect("A record not found in secondary")
+        .data()
+    {
+        RData::A(ip) => assert_eq!(A::new(93, 184, 215, 14), *ip),
+        _ => panic!("wrong rdata type returned"),
+    }
+
+    server.shutdown_gracefully().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_secondary_unavailable_primary_servfail() {
+    // bind and drop a listener to find a port which is not accepting connections
+    let addr = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let secondary = secondary(vec![addr]).await;
+    assert!(!secondary.is_serving());
+
+    let error = secondary
+        .lookup(
+            &LowerName::from_str("www.example.com.").unwrap(),
+            RecordType::A,
+            LookupOptions::default(),
+        )
+        .await
+        .unwrap_err();
+
+    assert!(matches!(
+        error,
+        LookupError::ResponseCode(ResponseCode::ServFail)
+    ));
+}
+
+#[tokio::test]
+async fn test_secondary_wait_for_transfer() {
+    let (addr, mut server) = spawn_primary(Arc::new(create_primary())).await;
+    assert!(secondary(vec![addr]).await.wait_for_transfer().await);
+    server.shutdown_gracefully().await.unwrap();
+
+    let addr = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    assert!(!secondary(vec![addr]).await.wait_for_transfer().await);
+}
+
+#[tokio::test]
+async fn test_secondary_does_not_wait_for_primary() {
+    // the primary accepts connections, but never answers the transfer
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let secondary = tokio::time::timeout(Duration::from_secs(1), secondary(vec![addr]))
+        .await
+        .expect("creating the secondary waited for the transfer");
+    assert!(!secondary.is_serving());
+
+    drop(listener);
+}
+
+#[tokio::test]
+async fn test_secondary_refreshes_on_notify() {
+    let primary = Arc::new(create_primary());
+    let (addr, mut server) = spawn_primary(primary.clone()).await;
+    let secondary = Arc::new(secondary(vec![addr]).await);
+    assert!(wait_for_serving(&secondary).await);
+
+    let mut catalog = Catalog::new();
+    catalog.upsert(secondary.origin().clone(), vec![secondary.clone()]);
+
+    add_record_to_primary(&primary).await;
+
+    let response_handler = TestResponseHandler::new();
+    catalog
+        .handle_request(
+            &notify_request(SocketAddr::new(addr.ip(), 5353)),
+            response_handler.clone(),
+        )
+        .await;
+    let response = response_handler.into_message().await;
+
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(response.op_code(), OpCode::Notify);
+    assert!(wait_for_new_record(&secondary).await);
+
+    server.shutdown_gracefully().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_secondary_refuses_notify_from_unknown_source() {
+    let (addr, mut server) = spawn_primary(Arc::new(create_primary())).await;
+    let secondary = secondary(vec![addr]).await;
+
+    let mut catalog = Catalog::new();
+    catalog.upsert(secondary.origin().clone(), vec![Arc::new(secondary)]);
+
+    let response_handler = TestResponseHandler::new();
+    catalog
+        .handle_request(
+            &notify_request(([192, 0, 2, 1], 53).into()),
+            response_handler.clone(),
+        )
+        .await;
+    let response = response_handler.into_message().await;
+
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+
+    server.shutdown_gracefully().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_primary_notify_not_secondary() {
+    let primary = create_primary();
+
+    let mut catalog = Catalog::new();
+    catalog.upsert(primary.origin().clone(), vec![Arc::new(primary)]);
+
+    let response_handler = TestResponseHandler::new();
+    catalog
+        .handle_request(
+            &notify_request(([127, 0, 0, 1], 53).into()),
+            response_handler.clone(),
+        )
+        .await;
+    let response = response_handler.into_message().await;
+
+    assert_eq!(response.response_code(), ResponseCode::NotAuth);
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_sqlite_update_notifies_secondary() {
+    use hickory_server::store::sqlite::SqliteAuthority;
+    use tokio::net::UdpSocket;
+
+    // the secondary's server must be bound before the primary is configured to notify it
+    let udp_socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let secondary_addr = udp_socket.local_addr().unwrap();
+
+    let mut primary = SqliteAuthority::new(create_primary(), true, false);
+    primary.set_also_notify(vec![secondary_addr]);
+    let primary = Arc::new(primary);
+    let (primary_addr, mut primary_server) = spawn_primary(primary.clone()).await;
+
+    let secondary = Arc::new(secondary(vec![primary_addr]).await);
+    let mut catalog = Catalog::new();
+    catalog.upsert(secondary.origin().clone(), vec![secondary.clone()]);
+    let mut secondary_server = ServerFuture::new(catalog);
+    secondary_server.register_socket(udp_socket);
+
+    let record = Record::from_rdata(
+        Name::from_str("new.example.com.").unwrap(),
+        86400,
+        RData::A(A::new(10, 0, 0, 1)),
+    );
+    assert!(primary.update_records(&[record], true).await.unwrap());
+
+    assert!(wait_for_new_record(&secondary).await);
+
+    primary_server.shutdown_gracefully().await.unwrap();
+    secondary_server.shutdown_gracefully().await.unwrap();
+}
+
+// updates are only implemented by the SqliteAuthority with dnssec
+#[cfg(all(
+    feature = "sqlite",
+    any(feature = "dnssec-ring", feature = "dnssec-openssl")
+))]
+#[tokio::test]
+async fn test_secondary_forwards_update_response_code() {
+    use hickory_proto::op::update_message;
+    use hickory_proto::rr::RecordSet;
+    use hickory_server::store::sqlite::SqliteAuthority;
+
+    // the primary does not allow updates
+    let primary = SqliteAuthority::new(create_primary(), false, false);
+    let (addr, mut server) = spawn_primary(Arc::new(primary)).await;
+    let secondary = secondary(vec![addr]).await;
+
+    let mut catalog = Catalog::new();
+    catalog.upsert(secondary.origin().clone(), vec![Arc::new(secondary)]);
+
+    let record = Record::from_rdata(
+        Name::from_str("new.example.com.").unwrap(),
+        86400,
+        RData::A(A::new(10, 0, 0, 1)),
+    );
+    let message = update_message::create(
+        RecordSet::from(record),
+        Name::from_str("example.com.").unwrap(),
+        false,
+    );
+    let bytes = message.to_bytes().unwrap();
+    let request = Request::new(
+        MessageRequest::from_bytes(&bytes).unwrap(),
+        ([127, 0, 0, 1], 5353).into(),
+        Protocol::Udp,
+    );
+
+    let response_handler = TestResponseHandler::new();
+    catalog
+        .handle_request(&request, response_handler.clone())
+        .await;
+    let response = response_handler.into_message().await;
+
+    assert_eq!(response.op_code(), OpCode::Update);
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+
+    server.shutdown_gracefully().await.unwrap();
+}
+
+#[cfg(all(
+    feature = "sqlite",
+    any(feature = "dnssec-ring", feature = "dnssec-openssl")
+))]
+#[tokio::test]
+async fn test_secondary_forwards_signed_update() {
+    use hickory_client::client::{Client, ClientHandle};
+    use hickory_proto::dnssec::{rdata::tsig::TsigAlgorithm, tsig::TSigner};
+    use hickory_proto::op::MessageFinalizer;
+    use hickory_proto::runtime::TokioRuntimeProvider;
+    use hickory_proto::tcp::TcpClientStream;
+    use hickory_proto::xfer::DnsMultiplexer;
+    use hickory_server::store::sqlite::SqliteAuthority;
+
+    let signer = TSigner::new(
+        b"some secret key".to_vec(),
+        TsigAlgorithm::HmacSha256,
+        Name::from_ascii("update-key.").unwrap(),
+        300,
+    )
+    .unwrap();
+
+    let mut primary = SqliteAuthority::new(create_primary(), true, false);
+    primary.set_update_tsig_keys(vec![signer.clone()]);
+    let primary = Arc::new(primary);
+    let mut primary_catalog = Catalog::new();
+    primary_catalog.upsert(primary.origin().clone(), vec![primary.clone()]);
+    primary_catalog.add_tsig_key(signer.clone());
+    let primary_listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let primary_addr = primary_listener.local_addr().unwrap();
+    let mut primary_server = ServerFuture::new(primary_catalog);
+    primary_server.register_listener(primary_listener, Duration::from_secs(5));
+
+    // the secondary does not hold the key, the signed response of the primary is relayed to the
+    //  client, which verifies it
+    let secondary = secondary(vec![primary_addr]).await;
+    let mut catalog = Catalog::new();
+    catalog.upsert(secondary.origin().clone(), vec![Arc::new(secondary)]);
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut secondary_server = ServerFuture::new(catalog);
+    secondary_server.register_listener(listener, Duration::from_secs(5));
+
+    let (stream, sender) = TcpClientStream::new(addr, None, None, TokioRuntimeProvider::new());
+    let signer = Arc::new(signer) as Arc<dyn MessageFinalizer>;
+    let multiplexer = DnsMultiplexer::new(stream, sender, Some(signer));
+    let (mut client, driver) = Client::connect(multiplexer).await.unwrap();
+    tokio::spawn(driver);
+
+    let record = Record::from_rdata(
+        Name::from_str("new.example.com.").unwrap(),
+        86400,
+        RData::A(A::new(10, 0, 0, 1)),
+    );
+    let response = client
+        .create(record, Name::from_str("example.com.").unwrap())
+        .await
+        .expect("create failed");
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+
+    let lookup = primary
+        .lookup(
+            &LowerName::from_str("new.example.com.").unwrap(),
+            RecordType::A,
+            LookupOptions::default(),
+        )
+        .await
+        .expect("lookup failed");
+    assert!(lookup.iter().next().is_some());
+
+    primary_server.shutdown_gracefully().await.unwrap();
+    secondary_server.shutdown_gracefully().await.unwrap();
+}
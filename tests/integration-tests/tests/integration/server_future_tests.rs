@@ -23,7 +23,8 @@ use hickory_proto::op::{Message, MessageType, OpCode, Query, ResponseCode};
 use hickory_proto::rr::rdata::A;
 use hickory_proto::rr::{DNSClass, Name, RData, RecordType};
 use hickory_proto::xfer::{DnsHandle, DnsMultiplexer};
-use hickory_server::authority::{Authority, Catalog};
+use hickory_server::authority::{Authority, Catalog, SharedCatalog};
+use hickory_server::server::ResponseRateLimitConfig;
 use hickory_server::ServerFuture;
 
 #[tokio::test]
@@ -66,6 +67,104 @@ async fn test_server_www_tcp() {
     server.await.unwrap();
 }
 
+#[tokio::test]
+async fn test_server_rate_limit_udp() {
+    let udp_socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let udp_addr = udp_socket.local_addr().unwrap();
+    let tcp_listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let tcp_addr = tcp_listener.local_addr().unwrap();
+
+    let mut config = ResponseRateLimitConfig::new(1);
+    config.slip = 1;
+    let mut server = ServerFuture::new(new_catalog());
+    server.set_response_rate_limit(Some(config));
+    server.register_socket(udp_socket);
+    server.register_listener(tcp_listener, Duration::from_secs(5));
+
+    let name = Name::from_str("www.example.com.").unwrap();
+    let mut client = lazy_udp_client(udp_addr).await;
+    let mut truncated = 0;
+    for _ in 0..5 {
+        let response = client
+            .query(name.clone(), DNSClass::IN, RecordType::A)
+            .await
+            .expect("error querying");
+
+        if response.truncated() {
+            assert!(response.answers().is_empty());
+            truncated += 1;
+        }
+    }
+    assert!(truncated >= 2, "only {truncated} responses were truncated");
+
+    // TCP is not limited
+    let mut client = lazy_tcp_client(tcp_addr).await;
+    for _ in 0..5 {
+        let response = client
+            .query(name.clone(), DNSClass::IN, RecordType::A)
+            .await
+            .expect("error querying");
+        assert!(!response.truncated());
+        assert!(!response.answers().is_empty());
+    }
+
+    server.shutdown_gracefully().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_server_shared_catalog_update() {
+    let udp_socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let udp_addr = udp_socket.local_addr().unwrap();
+
+    let catalog = SharedCatalog::new(Catalog::new());
+    let mut server = ServerFuture::new(catalog.clone());
+    server.register_socket(udp_socket);
+
+    let name = Name::from_str("www.example.com.").unwrap();
+    let stream = UdpClientStream::builder(udp_addr, TokioRuntimeProvider::default())
+        .with_timeout(Some(Duration::from_millis(500)))
+        .build();
+    let (mut client, driver) = Client::connect(stream).await.expect("failed to connect");
+    tokio::spawn(driver);
+
+    let response = client
+        .query(name.clone(), DNSClass::IN, RecordType::A)
+        .await
+        .expect("error querying");
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+
+    // zones are added to, and removed from, the running server
+    let example = create_example();
+    let origin = example.origin().clone();
+    catalog.update(|catalog| catalog.upsert(origin.clone(), vec![Arc::new(example)]));
+
+    let response = client
+        .query(name.clone(), DNSClass::IN, RecordType::A)
+        .await
+        .expect("error querying");
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(!response.answers().is_empty());
+
+    catalog.update(|catalog| catalog.remove(&origin));
+
+    let response = client
+        .query(name.clone(), DNSClass::IN, RecordType::A)
+        .await
+        .expect("error querying");
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+
+    // as are the allowed networks, requests from denied networks are dropped
+    server
+        .access_control()
+        .set_networks(&[], &["192.0.2.0/24".parse().unwrap()]);
+    assert!(client
+        .query(name.clone(), DNSClass::IN, RecordType::A)
+        .await
+        .is_err());
+
+    server.shutdown_gracefully().await.unwrap();
+}
+
 #[tokio::test]
 async fn test_server_unknown_type() {
     let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
@@ -250,6 +349,254 @@ async fn test_server_www_tls() {
     server.await.unwrap();
 }
 
+#[cfg(feature = "dns-over-https-rustls")]
+#[tokio::test]
+async fn test_server_www_http1() {
+    use hickory_proto::http::request;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+    let tcp_listener = TcpListener::bind(&addr).await.unwrap();
+    let ipaddr = tcp_listener.local_addr().unwrap();
+
+    let mut server = ServerFuture::new(new_catalog());
+    server
+        .register_http1_listener(
+            tcp_listener,
+            Duration::from_secs(30),
+            None,
+            Some("ns.example.com".to_string()),
+            "/dns-query".to_string(),
+        )
+        .unwrap();
+
+    let mut query = Message::new();
+    query
+        .add_query(Query::query(
+            Name::from_str("www.example.com.").unwrap(),
+            RecordType::A,
+        ))
+        .set_recursion_desired(true);
+    let query = query.to_vec().unwrap();
+
+    // the same request, as a GET and as a POST, on one connection
+    let get = request::new_get(
+        hickory_proto::http::Version::Http1,
+        "ns.example.com",
+        "/dns-query",
+        &query,
+    )
+    .unwrap();
+    let mut requests = format!(
+        "GET {} HTTP/1.1\r\nHost: ns.example.com\r\nAccept: application/dns-message\r\n\r\n",
+        get.uri().path_and_query().unwrap()
+    )
+    .into_bytes();
+    requests.extend_from_slice(
+        format!(
+            "POST /dns-query HTTP/1.1\r\nHost: ns.example.com\r\nAccept: application/dns-message\r\n\
+             Content-Type: application/dns-message\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            query.len()
+        )
+        .as_bytes(),
+    );
+    requests.extend_from_slice(&query);
+
+    let mut stream = tokio::net::TcpStream::connect(ipaddr).await.unwrap();
+    stream.write_all(&requests).await.unwrap();
+    let mut responses = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut responses))
+        .await
+        .expect("http/1.1 responses timed out")
+        .unwrap();
+
+    let mut responses = &responses[..];
+    for _ in 0..2 {
+        let header_end = responses
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .expect("no end of headers")
+            + 4;
+        let headers = std::str::from_utf8(&responses[..header_end])
+            .unwrap()
+            .to_ascii_lowercase();
+        assert!(headers.starts_with("http/1.1 200 ok"), "{headers}");
+        assert!(headers.contains("content-type: application/dns-message"));
+        assert!(
+            headers.contains("cache-control: max-age=86400"),
+            "{headers}"
+        );
+
+        let content_length = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length: "))
+            .unwrap()
+            .trim()
+            .parse::<usize>()
+            .unwrap();
+        let body = &responses[header_end..header_end + content_length];
+        responses = &responses[header_end + content_length..];
+
+        let response = Message::from_vec(body).unwrap();
+        assert_eq!(response.response_code(), ResponseCode::NoError);
+        assert_eq!(
+            *response.answers()[0].data(),
+            RData::A(A::new(93, 184, 215, 14))
+        );
+    }
+    assert!(responses.is_empty());
+
+    server.shutdown_gracefully().await.unwrap();
+}
+
+#[cfg(feature = "dns-over-odoh")]
+#[tokio::test]
+async fn test_server_www_odoh() {
+    use std::collections::HashMap;
+    use std::env;
+    use std::path::Path;
+
+    use hickory_proto::h2::HttpsClientStreamBuilder;
+    use hickory_proto::odoh::{self, OdohConfig, OdohKeyPair};
+    use hickory_proto::rustls::tls_server;
+    use hickory_server::server::{ObliviousDoh, OdohProxy};
+
+    let server_path = env::var("TDNS_WORKSPACE_ROOT").unwrap_or_else(|_| "../..".to_owned());
+    let ca =
+        tls_server::read_cert(Path::new(&format!("{server_path}/tests/test-data/ca.pem"))).unwrap();
+    let cert_key = || {
+        let cert = tls_server::read_cert(Path::new(&format!(
+            "{server_path}/tests/test-data/cert.pem"
+        )))
+        .unwrap();
+        let key = tls_server::read_key(Path::new(&format!(
+            "{server_path}/tests/test-data/cert.key"
+        )))
+        .unwrap();
+        (cert, key)
+    };
+
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(ca);
+    let client_config = Arc::new(
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(root_store)
+            .with_no_client_auth(),
+    );
+
+    // the target answers from the catalog
+    let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+    let target_listener = TcpListener::bind(&addr).await.unwrap();
+    let target_addr = target_listener.local_addr().unwrap();
+    let target_key = OdohKeyPair::generate();
+    let target_config = *target_key.config();
+    let mut target = ServerFuture::new(new_catalog());
+    target.set_odoh(Some(ObliviousDoh {
+        target: Some(target_key),
+        proxy: None,
+    }));
+    target
+        .register_https_listener(
+            target_listener,
+            Duration::from_secs(30),
+            cert_key(),
+            Some("ns.example.com".to_string()),
+            "/dns-query".to_string(),
+        )
+        .unwrap();
+
+    // the proxy has no zones, it only forwards to the target, which is on the loopback interface
+    let proxy_listener = TcpListener::bind(&addr).await.unwrap();
+    let proxy_addr = proxy_listener.local_addr().unwrap();
+    let mut proxy = ServerFuture::new(Catalog::new());
+    let mut odoh_proxy = OdohProxy::new(
+        client_config.clone(),
+        HashMap::from([("ns.example.com".to_string(), target_addr)]),
+    );
+    odoh_proxy.allow_private_targets = true;
+    proxy.set_odoh(Some(ObliviousDoh {
+        target: None,
+        proxy: Some(odoh_proxy),
+    }));
+    proxy
+        .register_https_listener(
+            proxy_listener,
+            Duration::from_secs(30),
+            cert_key(),
+            Some("ns.example.com".to_string()),
+            "/dns-query".to_string(),
+        )
+        .unwrap();
+
+    // queries are encrypted to the configuration of the target, known to the client
+    async fn odoh_client(
+        client_config: Arc<ClientConfig>,
+        proxy_addr: SocketAddr,
+        target_host: &str,
+        target_config: OdohConfig,
+    ) -> Client {
+        let mut builder = HttpsClientStreamBuilder::with_client_config(
+            client_config,
+            TokioRuntimeProvider::new(),
+        );
+        builder.odoh(target_config);
+        let (client, bg) = Client::connect(builder.build(
+            proxy_addr,
+            "ns.example.com".to_string(),
+            odoh::proxy_path("/dns-query", target_host, "/dns-query"),
+        ))
+        .await
+        .expect("failed to connect");
+        tokio::spawn(bg);
+        client
+    }
+
+    let mut client = odoh_client(
+        client_config.clone(),
+        proxy_addr,
+        "ns.example.com",
+        target_config,
+    )
+    .await;
+    client_thread_www(async { client.clone() }).await;
+    client_thread_www(async { client.clone() }).await;
+
+    // the proxy only forwards to the configured targets
+    let mut other = odoh_client(
+        client_config,
+        proxy_addr,
+        "other.example.com",
+        target_config,
+    )
+    .await;
+    other
+        .query(
+            Name::from_str("www.example.com").unwrap(),
+            DNSClass::IN,
+            RecordType::A,
+        )
+        .await
+        .expect_err("proxied to an unknown target");
+
+    // and only for the clients allowed by its access control
+    proxy
+        .access_control()
+        .set_networks(&["127.0.0.0/8".parse().unwrap()], &[]);
+    client
+        .query(
+            Name::from_str("www.example.com").unwrap(),
+            DNSClass::IN,
+            RecordType::A,
+        )
+        .await
+        .expect_err("proxied for a denied client");
+
+    proxy.shutdown_gracefully().await.unwrap();
+    target.shutdown_gracefully().await.unwrap();
+}
+
 async fn lazy_udp_client(addr: SocketAddr) -> Client {
     let conn = UdpClientStream::builder(addr, TokioRuntimeProvider::default()).build();
     let (client, driver) = Client::connect(conn).await.expect("failed to connect");
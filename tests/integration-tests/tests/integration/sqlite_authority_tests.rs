@@ -215,9 +215,15 @@ async fn test_authorize() {
 
     let bytes = message.to_bytes().unwrap();
     let message = MessageRequest::from_bytes(&bytes).unwrap();
+    let request_info = RequestInfo::new(
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 53)),
+        Protocol::Udp,
+        message.header(),
+        message.query(),
+    );
 
     assert_eq!(
-        authority.authorize(&message).await,
+        authority.authorize(&request_info, &message).await,
         Err(ResponseCode::Refused)
     );
 
@@ -965,6 +971,55 @@ async fn test_journal() {
     assert!(delete_rrset.was_empty());
 }
 
+#[tokio::test]
+async fn test_take_over() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal_path = dir.path().join("example.com.jrnl");
+
+    let mut previous = create_example();
+    previous
+        .set_journal(Journal::from_file(&journal_path).unwrap())
+        .await;
+    previous.persist_to_journal().await.unwrap();
+
+    // the zone is loaded again from the journal, while the previous authority is updated
+    let in_memory = InMemoryAuthority::empty(
+        previous.origin().clone().into(),
+        ZoneType::Primary,
+        false,
+        #[cfg(feature = "dnssec")]
+        Some(NxProofKind::Nsec),
+    );
+    let mut authority = SqliteAuthority::new(in_memory, true, false);
+    let journal = Journal::from_file(&journal_path).unwrap();
+    authority.recover_with_journal(&journal).await.unwrap();
+    authority.set_journal(journal).await;
+
+    let new_name = Name::from_str("new.example.com").unwrap();
+    let new_record = Record::from_rdata(new_name.clone(), 0, RData::A(A::new(10, 11, 12, 13)));
+    previous
+        .update_records(&[new_record.clone()], true)
+        .await
+        .unwrap();
+
+    authority.take_over(&previous).await.unwrap();
+    assert_eq!(authority.serial().await, previous.serial().await);
+    let new_rrset: Vec<Record> = authority
+        .lookup(&new_name.into(), RecordType::A, LookupOptions::default())
+        .await
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect();
+    assert_eq!(new_rrset, vec![new_record.clone()]);
+
+    // the previous authority no longer accepts updates
+    assert_eq!(
+        previous.update_records(&[new_record], true).await,
+        Err(ResponseCode::ServFail)
+    );
+}
+
 #[tokio::test]
 #[allow(clippy::blocks_in_conditions)]
 async fn test_recovery() {
@@ -1089,3 +1144,239 @@ async fn test_refused_axfr() {
     // just update this if the count goes up in the authority
     assert!(result.unwrap_err().is_refused());
 }
+
+#[tokio::test]
+async fn test_ixfr() {
+    let conn = Connection::open_in_memory().expect("could not create in memory DB");
+    let mut journal = Journal::new(conn).unwrap();
+    journal.schema_up().unwrap();
+
+    let mut authority = create_example();
+    authority.set_allow_axfr(true);
+    authority.set_journal(journal).await;
+    authority.persist_to_journal().await.unwrap();
+
+    let original_serial = authority.serial().await;
+
+    let new_record = Record::from_rdata(
+        Name::from_str("new.example.com.").unwrap(),
+        86400,
+        RData::A(A::new(10, 11, 12, 13)),
+    );
+    let delete_record = Record::from_rdata(
+        Name::from_str("www.example.com.").unwrap(),
+        0,
+        RData::A(A::new(93, 184, 215, 14)),
+    )
+    .set_dns_class(DNSClass::NONE)
+    .clone();
+    authority
+        .update_records(&[new_record.clone(), delete_record], true)
+        .await
+        .unwrap();
+
+    let current_serial = authority.serial().await;
+    assert_ne!(original_serial, current_serial);
+
+    let query = LowerQuery::from(Query::query(
+        Name::from_str("example.com.").unwrap(),
+        RecordType::IXFR,
+    ));
+    let mut request_info = RequestInfo::new(
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 53)),
+        Protocol::Tcp,
+        TEST_HEADER,
+        &query,
+    );
+    request_info.ixfr_serial = Some(original_serial);
+
+    let result = authority
+        .search(request_info.clone(), LookupOptions::default())
+        .await
+        .unwrap();
+    let records = result.iter().collect::<Vec<_>>();
+
+    let serials = records
+        .iter()
+        .filter_map(|r| r.data().as_soa().map(|soa| soa.serial()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        serials,
+        [
+            current_serial,
+            original_serial,
+            current_serial,
+            current_serial
+        ]
+    );
+    assert_eq!(records.len(), 6);
+    assert_eq!(
+        records[2].name(),
+        &Name::from_str("www.example.com.").unwrap()
+    );
+    assert_eq!(records[2].data(), &RData::A(A::new(93, 184, 215, 14)));
+    assert_eq!(*records[4], new_record);
+
+    // an up to date client only gets the current SOA
+    request_info.ixfr_serial = Some(current_serial);
+    let result = authority
+        .search(request_info, LookupOptions::default())
+        .await
+        .unwrap();
+    let serials = result
+        .iter()
+        .map(|r| r.data().as_soa().map(|soa| soa.serial()))
+        .collect::<Vec<_>>();
+    assert_eq!(serials, [Some(current_serial)]);
+
+    // the recovered zone is at the same serial
+    let in_memory = InMemoryAuthority::empty(
+        authority.origin().clone().into(),
+        ZoneType::Primary,
+        false,
+        #[cfg(feature = "dnssec")]
+        Some(NxProofKind::Nsec),
+    );
+    let mut recovered_authority = SqliteAuthority::new(in_memory, false, false);
+    recovered_authority
+        .recover_with_journal(authority.journal().await.as_ref().unwrap())
+        .await
+        .expect("recovery");
+    assert_eq!(recovered_authority.serial().await, current_serial);
+}
+
+#[tokio::test]
+async fn test_ixfr_multiple_updates() {
+    let conn = Connection::open_in_memory().expect("could not create in memory DB");
+    let mut journal = Journal::new(conn).unwrap();
+    journal.schema_up().unwrap();
+
+    let mut authority = create_example();
+    authority.set_allow_axfr(true);
+    authority.set_journal(journal).await;
+    authority.persist_to_journal().await.unwrap();
+
+    let original_serial = authority.serial().await;
+
+    let first_record = Record::from_rdata(
+        Name::from_str("first.example.com.").unwrap(),
+        86400,
+        RData::A(A::new(10, 0, 0, 1)),
+    );
+    authority
+        .update_records(std::slice::from_ref(&first_record), true)
+        .await
+        .unwrap();
+    let middle_serial = authority.serial().await;
+
+    let second_record = Record::from_rdata(
+        Name::from_str("second.example.com.").unwrap(),
+        86400,
+        RData::A(A::new(10, 0, 0, 2)),
+    );
+    authority
+        .update_records(std::slice::from_ref(&second_record), true)
+        .await
+        .unwrap();
+    let current_serial = authority.serial().await;
+
+    let query = LowerQuery::from(Query::query(
+        Name::from_str("example.com.").unwrap(),
+        RecordType::IXFR,
+    ));
+    let mut request_info = RequestInfo::new(
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 53)),
+        Protocol::Tcp,
+        TEST_HEADER,
+        &query,
+    );
+
+    // one difference sequence for each update
+    request_info.ixfr_serial = Some(original_serial);
+    let result = authority
+        .search(request_info.clone(), LookupOptions::default())
+        .await
+        .unwrap();
+    let records = result.iter().collect::<Vec<_>>();
+    let serials = records
+        .iter()
+        .filter_map(|r| r.data().as_soa().map(|soa| soa.serial()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        serials,
+        [
+            current_serial,
+            original_serial,
+            middle_serial,
+            middle_serial,
+            current_serial,
+            current_serial
+        ]
+    );
+    assert_eq!(records.len(), 8);
+    assert_eq!(*records[3], first_record);
+    assert_eq!(*records[6], second_record);
+
+    // only the history from the client's version is sent
+    request_info.ixfr_serial = Some(middle_serial);
+    let result = authority
+        .search(request_info.clone(), LookupOptions::default())
+        .await
+        .unwrap();
+    let records = result.iter().collect::<Vec<_>>();
+    assert_eq!(records.len(), 5);
+    assert_eq!(*records[3], second_record);
+
+    // an unknown version is answered with the full zone
+    request_info.ixfr_serial = Some(original_serial.wrapping_sub(1));
+    let result = authority
+        .search(request_info, LookupOptions::default())
+        .await
+        .unwrap();
+    assert!(result.iter().count() > 8);
+}
+
+#[tokio::test]
+async fn test_ixfr_without_history() {
+    let mut authority = create_example();
+    authority.set_allow_axfr(true);
+
+    let query = LowerQuery::from(Query::query(
+        Name::from_str("example.com.").unwrap(),
+        RecordType::IXFR,
+    ));
+    let mut request_info = RequestInfo::new(
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 53)),
+        Protocol::Tcp,
+        TEST_HEADER,
+        &query,
+    );
+    request_info.ixfr_serial = Some(1);
+
+    let result = authority
+        .search(request_info, LookupOptions::default())
+        .await
+        .unwrap();
+
+    // without a journal the full zone is sent, as with AXFR
+    assert_eq!(result.iter().count(), 12);
+}
+
+#[tokio::test]
+async fn test_record_sets_at() {
+    let authority = create_example();
+    let www = LowerName::from(Name::from_str("www.example.com.").unwrap());
+    let absent = LowerName::from(Name::from_str("absent.example.com.").unwrap());
+
+    // all the record sets at the names, and only those
+    let record_sets = authority.record_sets_at([&www, &absent]).await;
+    let expected = authority
+        .records()
+        .await
+        .into_iter()
+        .filter(|(key, _)| key.name == www)
+        .map(|(_, rr_set)| rr_set)
+        .collect::<Vec<_>>();
+    assert!(!expected.is_empty());
+    assert_eq!(record_sets, expected);
+}
@@ -0,0 +1,215 @@
+#![cfg(any(feature = "dnssec-ring", feature = "dnssec-openssl"))]
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use tokio::net::TcpListener;
+
+use hickory_client::client::{Client, ClientHandle};
+use hickory_integration::example_authority::create_example;
+use hickory_proto::dnssec::rdata::tsig::TsigAlgorithm;
+use hickory_proto::dnssec::tsig::TSigner;
+use hickory_proto::op::{MessageFinalizer, ResponseCode};
+use hickory_proto::rr::Name;
+use hickory_proto::runtime::TokioRuntimeProvider;
+use hickory_proto::tcp::TcpClientStream;
+use hickory_proto::xfer::{DnsMultiplexer, DnsResponse};
+use hickory_server::authority::{AuthorityObject, Catalog};
+use hickory_server::store::in_memory::InMemoryAuthority;
+use hickory_server::ServerFuture;
+
+fn signer(name: &str) -> TSigner {
+    TSigner::new(
+        b"some secret key".to_vec(),
+        TsigAlgorithm::HmacSha256,
+        Name::from_ascii(name).unwrap(),
+        300,
+    )
+    .unwrap()
+}
+
+fn create_primary() -> InMemoryAuthority {
+    let mut authority = create_example();
+    authority.set_allow_axfr(false);
+    authority.set_axfr_keys(vec![Name::from_ascii("xfr-key.").unwrap()]);
+    authority
+}
+
+async fn spawn_server(
+    authority: Arc<dyn AuthorityObject>,
+    keys: Vec<TSigner>,
+) -> (SocketAddr, ServerFuture<Catalog>) {
+    let mut catalog = Catalog::new();
+    catalog.upsert(authority.origin().clone(), vec![authority]);
+    for key in keys {
+        catalog.add_tsig_key(key);
+    }
+
+    let tcp_listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let addr = tcp_listener.local_addr().unwrap();
+    let mut server = ServerFuture::new(catalog);
+    server.register_listener(tcp_listener, Duration::from_secs(5));
+
+    (addr, server)
+}
+
+async fn connect(addr: SocketAddr, signer: Option<TSigner>) -> Client {
+    let (stream, sender) = TcpClientStream::new(addr, None, None, TokioRuntimeProvider::new());
+    let signer = signer.map(|signer| Arc::new(signer) as Arc<dyn MessageFinalizer>);
+    let multiplexer = DnsMultiplexer::new(stream, sender, signer);
+
+    let (client, driver) = Client::connect(multiplexer)
+        .await
+        .expect("failed to connect");
+    tokio::spawn(driver);
+    client
+}
+
+/// Requests a zone transfer which is refused, returning the response
+///
+/// Only the first response is read, the transfer is not closed by an SOA.
+async fn refused_transfer(client: &mut Client) -> DnsResponse {
+    client
+        .zone_transfer(Name::from_str("example.com.").unwrap(), None)
+        .try_next()
+        .await
+        .expect("zone transfer failed")
+        .expect("no response to zone transfer")
+}
+
+#[tokio::test]
+async fn test_axfr_with_tsig_key() {
+    let (addr, _server) = spawn_server(
+        Arc::new(create_primary()),
+        vec![signer("xfr-key."), signer("other-key.")],
+    )
+    .await;
+
+    // the signature of the response is verified by the client
+    let mut client = connect(addr, Some(signer("xfr-key."))).await;
+    let records = client
+        .zone_transfer(Name::from_str("example.com.").unwrap(), None)
+        .try_collect::<Vec<_>>()
+        .await
+        .expect("zone transfer failed")
+        .into_iter()
+        .flat_map(|response| response.into_message().take_answers())
+        .collect::<Vec<_>>();
+
+    assert!(records.len() > 2);
+}
+
+#[tokio::test]
+async fn test_axfr_refused_without_allowed_key() {
+    let (addr, _server) = spawn_server(
+        Arc::new(create_primary()),
+        vec![signer("xfr-key."), signer("other-key.")],
+    )
+    .await;
+
+    for key in [None, Some(signer("other-key."))] {
+        let mut client = connect(addr, key).await;
+        let response = refused_transfer(&mut client).await;
+        assert_eq!(response.response_code(), ResponseCode::Refused);
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_tsig_key() {
+    let (addr, _server) = spawn_server(Arc::new(create_primary()), vec![signer("xfr-key.")]).await;
+
+    // the server responds with an unsigned BADKEY, which the client must reject
+    let mut client = connect(addr, Some(signer("unknown-key."))).await;
+    let result = client
+        .zone_transfer(Name::from_str("example.com.").unwrap(), None)
+        .try_collect::<Vec<_>>()
+        .await;
+
+    assert!(result.is_err(), "zone transfer succeeded: {result:?}");
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_update_with_tsig_key() {
+    use hickory_proto::rr::rdata::A;
+    use hickory_proto::rr::{RData, Record};
+    use hickory_server::store::sqlite::SqliteAuthority;
+
+    let mut authority = SqliteAuthority::new(create_example(), true, false);
+    authority.set_update_tsig_keys(vec![signer("update-key.")]);
+    let (addr, _server) = spawn_server(
+        Arc::new(authority),
+        vec![signer("update-key."), signer("other-key.")],
+    )
+    .await;
+
+    let record = Record::from_rdata(
+        Name::from_str("new.example.com.").unwrap(),
+        300,
+        RData::A(A::new(10, 0, 0, 1)),
+    );
+    let origin = Name::from_str("example.com.").unwrap();
+
+    let mut client = connect(addr, Some(signer("other-key."))).await;
+    let response = client
+        .create(record.clone(), origin.clone())
+        .await
+        .expect("create failed");
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+
+    let mut client = connect(addr, Some(signer("update-key."))).await;
+    let response = client.create(record, origin).await.expect("create failed");
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+}
+
+#[tokio::test]
+async fn test_secondary_with_tsig_keys() {
+    use hickory_server::authority::{Authority, ZoneType};
+    use hickory_server::store::secondary::{SecondaryAuthority, SecondaryConfig};
+
+    let (primary_addr, _primary) =
+        spawn_server(Arc::new(create_primary()), vec![signer("xfr-key.")]).await;
+
+    let config = SecondaryConfig {
+        primaries: vec![primary_addr],
+        // the transfer without the key fails, it should not hold up the test
+        transfer_timeout: Some(1),
+        allow_notify: vec![],
+        transfer_key: None,
+    };
+    let secondary = |transfer_key| {
+        SecondaryAuthority::try_from_config(
+            Name::from_str("example.com.").unwrap(),
+            ZoneType::Secondary,
+            false,
+            &config,
+            None,
+            vec![Name::from_ascii("xfr-key.").unwrap()],
+            transfer_key,
+        )
+    };
+
+    // the primary only allows signed transfers
+    assert!(!secondary(None).await.unwrap().wait_for_transfer().await);
+    let secondary = secondary(Some(signer("xfr-key."))).await.unwrap();
+    assert!(secondary.wait_for_transfer().await);
+    assert!(!Authority::is_axfr_allowed(&secondary));
+
+    // and the secondary serves the zone to clients with the key
+    let (addr, _server) = spawn_server(Arc::new(secondary), vec![signer("xfr-key.")]).await;
+    let mut client = connect(addr, Some(signer("xfr-key."))).await;
+    let responses = client
+        .zone_transfer(Name::from_str("example.com.").unwrap(), None)
+        .try_collect::<Vec<_>>()
+        .await
+        .expect("zone transfer failed");
+    assert_eq!(responses[0].response_code(), ResponseCode::NoError);
+    assert!(responses[0].answers().len() > 2);
+
+    let mut client = connect(addr, None).await;
+    let response = refused_transfer(&mut client).await;
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+}
@@ -123,25 +123,15 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     for socket_addr in &opts.nameserver {
         roots.push(NameServerConfig {
-            socket_addr: *socket_addr,
-            protocol: Protocol::Tcp,
-            tls_dns_name: None,
-            http_endpoint: None,
             trust_negative_responses: false,
-            #[cfg(feature = "dns-over-rustls")]
-            tls_config: None,
             bind_addr: opts.bind.map(|ip| SocketAddr::new(ip, 0)),
+            ..NameServerConfig::new(*socket_addr, Protocol::Tcp)
         });
 
         roots.push(NameServerConfig {
-            socket_addr: *socket_addr,
-            protocol: Protocol::Udp,
-            tls_dns_name: None,
-            http_endpoint: None,
             trust_negative_responses: false,
-            #[cfg(feature = "dns-over-rustls")]
-            tls_config: None,
             bind_addr: opts.bind.map(|ip| SocketAddr::new(ip, 0)),
+            ..NameServerConfig::new(*socket_addr, Protocol::Udp)
         });
     }
 